@@ -16,8 +16,10 @@ use lightning::ln::channelmanager::ChannelManager;
 use lightning::ln::msgs::{ChannelMessageHandler, RoutingMessageHandler};
 use lightning::ln::peer_handler::{CustomMessageHandler, PeerManager, SocketDescriptor};
 use lightning::routing::network_graph::{NetworkGraph, NetGraphMsgHandler};
+use lightning::routing::scoring::WriteableScore;
 use lightning::util::events::{Event, EventHandler, EventsProvider};
 use lightning::util::logger::Logger;
+use lightning::util::persist::Persister;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
@@ -25,6 +27,13 @@ use std::thread::JoinHandle;
 use std::time::{Duration, Instant};
 use std::ops::Deref;
 
+#[cfg(feature = "futures")]
+use core::future::Future;
+#[cfg(feature = "futures")]
+use core::pin::Pin;
+#[cfg(feature = "futures")]
+use core::task::{Context, Poll};
+
 /// `BackgroundProcessor` takes care of tasks that (1) need to happen periodically to keep
 /// Rust-Lightning running properly, and (2) either can or should be run in the background. Its
 /// responsibilities are:
@@ -35,11 +44,15 @@ use std::ops::Deref;
 /// * Calling [`ChannelManager::timer_tick_occurred`] and [`PeerManager::timer_tick_occurred`]
 ///   at the appropriate intervals.
 /// * Calling [`NetworkGraph::remove_stale_channels`] (if a [`NetGraphMsgHandler`] is provided to
-///   [`BackgroundProcessor::start`]).
+///   [`BackgroundProcessor::start`]) and persisting the pruned [`NetworkGraph`].
+/// * Periodically persisting the scorer, if one is provided to [`BackgroundProcessor::start`].
 ///
 /// It will also call [`PeerManager::process_events`] periodically though this shouldn't be relied
 /// upon as doing so may result in high latency.
 ///
+/// Users running in an async runtime may instead drive the same tasks from a future, without a
+/// dedicated thread, via `process_events_async` when the `futures` feature is enabled.
+///
 /// # Note
 ///
 /// If [`ChannelManager`] persistence fails and the persisted manager becomes out-of-date, then
@@ -73,37 +86,15 @@ const PING_TIMER: u64 = 1;
 /// Prune the network graph of stale entries hourly.
 const NETWORK_PRUNE_TIMER: u64 = 60 * 60;
 
-/// Trait which handles persisting a [`ChannelManager`] to disk.
-///
-/// [`ChannelManager`]: lightning::ln::channelmanager::ChannelManager
-pub trait ChannelManagerPersister<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref>
-where
-	M::Target: 'static + chain::Watch<Signer>,
-	T::Target: 'static + BroadcasterInterface,
-	K::Target: 'static + KeysInterface<Signer = Signer>,
-	F::Target: 'static + FeeEstimator,
-	L::Target: 'static + Logger,
-{
-	/// Persist the given [`ChannelManager`] to disk, returning an error if persistence failed
-	/// (which will cause the [`BackgroundProcessor`] which called this method to exit.
-	///
-	/// [`ChannelManager`]: lightning::ln::channelmanager::ChannelManager
-	fn persist_manager(&self, channel_manager: &ChannelManager<Signer, M, T, K, F, L>) -> Result<(), std::io::Error>;
-}
+#[cfg(not(test))]
+const SCORER_PERSIST_TIMER: u64 = 30;
+#[cfg(test)]
+const SCORER_PERSIST_TIMER: u64 = 1;
 
-impl<Fun, Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref>
-ChannelManagerPersister<Signer, M, T, K, F, L> for Fun where
-	M::Target: 'static + chain::Watch<Signer>,
-	T::Target: 'static + BroadcasterInterface,
-	K::Target: 'static + KeysInterface<Signer = Signer>,
-	F::Target: 'static + FeeEstimator,
-	L::Target: 'static + Logger,
-	Fun: Fn(&ChannelManager<Signer, M, T, K, F, L>) -> Result<(), std::io::Error>,
-{
-	fn persist_manager(&self, channel_manager: &ChannelManager<Signer, M, T, K, F, L>) -> Result<(), std::io::Error> {
-		self(channel_manager)
-	}
-}
+#[cfg(not(test))]
+const FIRST_NETWORK_PRUNE_TIMER: u64 = 60;
+#[cfg(test)]
+const FIRST_NETWORK_PRUNE_TIMER: u64 = 1;
 
 /// Decorates an [`EventHandler`] with common functionality provided by standard [`EventHandler`]s.
 struct DecoratingEventHandler<
@@ -134,25 +125,293 @@ where A::Target: chain::Access, L::Target: Logger {
 	}
 }
 
+macro_rules! define_run_body {
+	($persister: ident, $event_handler: ident, $chain_monitor: ident, $channel_manager: ident,
+	 $net_graph_msg_handler: ident, $peer_manager: ident, $logger: ident, $scorer: ident,
+	 $loop_exit_check: expr, $await: expr, $($persist_await: tt)*)
+	=> { {
+		let event_handler = DecoratingEventHandler { event_handler: $event_handler, net_graph_msg_handler: $net_graph_msg_handler.as_ref().map(|t| t.deref()) };
+
+		log_trace!($logger, "Calling ChannelManager's timer_tick_occurred on startup");
+		$channel_manager.timer_tick_occurred();
+
+		let mut last_freshness_call = Instant::now();
+		let mut last_ping_call = Instant::now();
+		let mut last_prune_call = Instant::now();
+		let mut last_scorer_persist_call = Instant::now();
+		let mut have_pruned = false;
+
+		loop {
+			$peer_manager.process_events(); // Note that this may block on ChannelManager's locking
+			$channel_manager.process_pending_events(&event_handler);
+			$chain_monitor.process_pending_events(&event_handler);
+
+			// We wait up to 100ms, but track how long it takes to detect being put to sleep,
+			// see `await_start`'s use below.
+			let await_start = Instant::now();
+			let updates_available = $await;
+			let await_time = await_start.elapsed();
+
+			if updates_available {
+				log_trace!($logger, "Persisting ChannelManager...");
+				$persister.persist_manager(&*$channel_manager) $($persist_await)* ?;
+				log_trace!($logger, "Done persisting ChannelManager.");
+			}
+			// Exit the loop if the background processor was requested to stop.
+			if $loop_exit_check {
+				log_trace!($logger, "Terminating background processor.");
+				break;
+			}
+			if last_freshness_call.elapsed().as_secs() > FRESHNESS_TIMER {
+				log_trace!($logger, "Calling ChannelManager's timer_tick_occurred");
+				$channel_manager.timer_tick_occurred();
+				last_freshness_call = Instant::now();
+			}
+			if await_time > Duration::from_secs(1) {
+				// On various platforms, we may be starved of CPU cycles for several reasons.
+				// E.g. on iOS, if we've been in the background, we will be entirely paused.
+				// Similarly, if we're on a desktop platform and the device has been asleep, we
+				// may not get any cycles.
+				// We detect this by checking if our max-100ms-sleep, above, ran longer than a
+				// full second, at which point we assume sockets may have been killed (they
+				// appear to be at least on some platforms, even if it has only been a second).
+				// Note that we have to take care to not get here just because user event
+				// processing was slow at the top of the loop. For example, the sample client
+				// may call Bitcoin Core RPCs during event handling, which very often takes
+				// more than a handful of seconds to complete, and shouldn't disconnect all our
+				// peers.
+				log_trace!($logger, "100ms sleep took more than a second, disconnecting peers.");
+				$peer_manager.disconnect_all_peers();
+				last_ping_call = Instant::now();
+			} else if last_ping_call.elapsed().as_secs() > PING_TIMER {
+				log_trace!($logger, "Calling PeerManager's timer_tick_occurred");
+				$peer_manager.timer_tick_occurred();
+				last_ping_call = Instant::now();
+			}
+
+			// Note that we want to run a graph prune once not long after startup before
+			// falling back to our usual hourly prunes. This avoids short-lived clients never
+			// pruning their network graph. We run once 60 seconds after startup before
+			// continuing our normal cadence.
+			if last_prune_call.elapsed().as_secs() > if have_pruned { NETWORK_PRUNE_TIMER } else { FIRST_NETWORK_PRUNE_TIMER } {
+				if let Some(ref handler) = $net_graph_msg_handler {
+					log_trace!($logger, "Pruning network graph of stale entries");
+					handler.network_graph().remove_stale_channels();
+					if let Err(e) = $persister.persist_graph(handler.network_graph()) $($persist_await)* {
+						log_error!($logger, "Error: Failed to persist network graph, check your disk and permissions {}", e);
+					}
+					last_prune_call = Instant::now();
+					have_pruned = true;
+				}
+			}
+
+			if last_scorer_persist_call.elapsed().as_secs() > SCORER_PERSIST_TIMER {
+				if let Some(ref scorer) = $scorer {
+					log_trace!($logger, "Persisting scorer");
+					if let Err(e) = $persister.persist_scorer(&**scorer) $($persist_await)* {
+						log_error!($logger, "Error: Failed to persist scorer, check your disk and permissions {}", e);
+					}
+				}
+				last_scorer_persist_call = Instant::now();
+			}
+		}
+		// After we exit, ensure we persist the ChannelManager one final time - this avoids
+		// some races where users quit while channel updates were in-flight, with
+		// ChannelMonitor update(s) persisted without a corresponding ChannelManager update.
+		$persister.persist_manager(&*$channel_manager) $($persist_await)* ?;
+
+		// Persist Scorer on exit
+		if let Some(ref scorer) = $scorer {
+			$persister.persist_scorer(&**scorer) $($persist_await)* ?;
+		}
+
+		// Persist NetworkGraph on exit
+		if let Some(ref handler) = $net_graph_msg_handler {
+			$persister.persist_graph(handler.network_graph()) $($persist_await)* ?;
+		}
+
+		Ok(())
+	} }
+}
+
+/// The result of an [`AsyncPersister`] call: a boxed [`Future`] which resolves once the given
+/// object has been persisted, or persistence has failed.
+#[cfg(feature = "futures")]
+pub type AsyncPersistResult<'b> = Pin<Box<dyn Future<Output = Result<(), std::io::Error>> + Send + 'b>>;
+
+/// Trait that handles persisting a [`ChannelManager`], [`NetworkGraph`], and [`WriteableScore`]
+/// without blocking the calling task, for use with [`process_events_async`].
+///
+/// This is the async counterpart to [`Persister`] and carries the same semantics. Implementations
+/// will generally want to hand the serialized bytes off to an async file or network client.
+///
+/// [`Persister`]: lightning::util::persist::Persister
+#[cfg(feature = "futures")]
+pub trait AsyncPersister<'a, Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref, S: WriteableScore<'a>>
+	where M::Target: 'static + chain::Watch<Signer>,
+		T::Target: 'static + BroadcasterInterface,
+		K::Target: 'static + KeysInterface<Signer = Signer>,
+		F::Target: 'static + FeeEstimator,
+		L::Target: 'static + Logger,
+{
+	/// Persist the given [`ChannelManager`], resolving to an error if persistence failed.
+	///
+	/// See [`Persister::persist_manager`] for the consequences of a failure.
+	///
+	/// [`Persister::persist_manager`]: lightning::util::persist::Persister::persist_manager
+	fn persist_manager<'b>(&'b self, channel_manager: &'b ChannelManager<Signer, M, T, K, F, L>) -> AsyncPersistResult<'b>;
+
+	/// Persist the given [`NetworkGraph`], resolving to an error if persistence failed.
+	fn persist_graph<'b>(&'b self, network_graph: &'b NetworkGraph) -> AsyncPersistResult<'b>;
+
+	/// Persist the given [`WriteableScore`], resolving to an error if persistence failed.
+	fn persist_scorer<'b>(&'b self, scorer: &'b S) -> AsyncPersistResult<'b>;
+}
+
+#[cfg(feature = "futures")]
+enum SelectorOutput {
+	A, B, C,
+}
+
+/// A simple future which polls three futures in order, completing with the first which does.
+#[cfg(feature = "futures")]
+struct Selector<A: Future<Output = ()> + Unpin, B: Future<Output = ()> + Unpin, C: Future<Output = ()> + Unpin> {
+	a: A,
+	b: B,
+	c: C,
+}
+
+#[cfg(feature = "futures")]
+impl<A: Future<Output = ()> + Unpin, B: Future<Output = ()> + Unpin, C: Future<Output = ()> + Unpin> Future for Selector<A, B, C> {
+	type Output = SelectorOutput;
+	fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<SelectorOutput> {
+		match Pin::new(&mut self.a).poll(ctx) {
+			Poll::Ready(()) => { return Poll::Ready(SelectorOutput::A); },
+			Poll::Pending => {},
+		}
+		match Pin::new(&mut self.b).poll(ctx) {
+			Poll::Ready(()) => { return Poll::Ready(SelectorOutput::B); },
+			Poll::Pending => {},
+		}
+		match Pin::new(&mut self.c).poll(ctx) {
+			Poll::Ready(()) => { return Poll::Ready(SelectorOutput::C); },
+			Poll::Pending => {},
+		}
+		Poll::Pending
+	}
+}
+
+/// Processes background events in a future, performing the same responsibilities as a
+/// [`BackgroundProcessor`] without requiring a dedicated thread.
+///
+/// Rather than blocking on a condition variable, this waits on the [`ChannelManager`]'s
+/// [`get_persistable_update_future`], alongside `sleeper` and `shutdown`. `sleeper` should return
+/// a future which completes after the given [`Duration`] (e.g. `tokio::time::sleep`) and is used
+/// to drive the periodic tasks. Once `shutdown` completes, the [`ChannelManager`], `scorer` and
+/// [`NetworkGraph`] are persisted one final time and the returned future resolves, thus a
+/// [`BackgroundProcessor::stop`]-style shutdown can be triggered with, e.g., a oneshot channel.
+///
+/// Persistence is done via the given [`AsyncPersister`] and the returned future resolves early to
+/// an error if [`AsyncPersister::persist_manager`] fails, exactly as for
+/// [`BackgroundProcessor::start`], whose documentation describes the remaining arguments.
+///
+/// Note that this is only available with the `futures` feature.
+///
+/// [`get_persistable_update_future`]: lightning::ln::channelmanager::ChannelManager::get_persistable_update_future
+#[cfg(feature = "futures")]
+pub async fn process_events_async<
+	'a,
+	Signer: 'static + Sign,
+	CA: 'static + Deref,
+	CF: 'static + Deref,
+	CW: 'static + Deref,
+	T: 'static + Deref,
+	K: 'static + Deref,
+	F: 'static + Deref,
+	G: 'static + Deref<Target = NetworkGraph>,
+	L: 'static + Deref,
+	P: 'static + Deref,
+	Descriptor: 'static + SocketDescriptor,
+	CMH: 'static + Deref,
+	RMH: 'static + Deref,
+	EH: 'static + EventHandler,
+	PS: 'static + Deref,
+	M: 'static + Deref<Target = ChainMonitor<Signer, CF, T, F, L, P>>,
+	CM: 'static + Deref<Target = ChannelManager<Signer, CW, T, K, F, L>>,
+	NG: 'static + Deref<Target = NetGraphMsgHandler<G, CA, L>>,
+	UMH: 'static + Deref,
+	PM: 'static + Deref<Target = PeerManager<Descriptor, CMH, RMH, L, UMH, K>>,
+	S: 'static + Deref<Target = SC>,
+	SC: WriteableScore<'a>,
+	SleepFuture: Future<Output = ()>,
+	Sleeper: Fn(Duration) -> SleepFuture,
+	ShutdownFuture: Future<Output = ()>,
+>(
+	persister: PS, event_handler: EH, chain_monitor: M, channel_manager: CM,
+	net_graph_msg_handler: Option<NG>, peer_manager: PM, logger: L, scorer: Option<S>,
+	sleeper: Sleeper, shutdown: ShutdownFuture,
+) -> Result<(), std::io::Error>
+where
+	CA::Target: 'static + chain::Access,
+	CF::Target: 'static + chain::Filter,
+	CW::Target: 'static + chain::Watch<Signer>,
+	T::Target: 'static + BroadcasterInterface,
+	K::Target: 'static + KeysInterface<Signer = Signer>,
+	F::Target: 'static + FeeEstimator,
+	L::Target: 'static + Logger,
+	P::Target: 'static + Persist<Signer>,
+	CMH::Target: 'static + ChannelMessageHandler,
+	RMH::Target: 'static + RoutingMessageHandler,
+	UMH::Target: 'static + CustomMessageHandler,
+	PS::Target: 'static + AsyncPersister<'a, Signer, CW, T, K, F, L, SC>,
+{
+	let mut should_exit = false;
+	let mut shutdown = Box::pin(shutdown);
+	define_run_body!(persister, event_handler, chain_monitor, channel_manager,
+		net_graph_msg_handler, peer_manager, logger, scorer,
+		should_exit, {
+			let selector = Selector {
+				a: &mut shutdown,
+				b: channel_manager.get_persistable_update_future(),
+				c: Box::pin(sleeper(Duration::from_millis(100))),
+			};
+			match selector.await {
+				SelectorOutput::A => { should_exit = true; false },
+				SelectorOutput::B => true,
+				SelectorOutput::C => false,
+			}
+		}, .await)
+}
+
 impl BackgroundProcessor {
 	/// Start a background thread that takes care of responsibilities enumerated in the [top-level
 	/// documentation].
 	///
 	/// The thread runs indefinitely unless the object is dropped, [`stop`] is called, or
-	/// `persist_manager` returns an error. In case of an error, the error is retrieved by calling
-	/// either [`join`] or [`stop`].
+	/// [`Persister::persist_manager`] returns an error. In case of an error, the error is retrieved
+	/// by calling either [`join`] or [`stop`].
 	///
 	/// # Data Persistence
 	///
-	/// `persist_manager` is responsible for writing out the [`ChannelManager`] to disk, and/or
-	/// uploading to one or more backup services. See [`ChannelManager::write`] for writing out a
-	/// [`ChannelManager`]. See [`FilesystemPersister::persist_manager`] for Rust-Lightning's
-	/// provided implementation.
+	/// [`Persister::persist_manager`] is responsible for writing out the [`ChannelManager`] to disk,
+	/// and/or uploading to one or more backup services. See [`ChannelManager::write`] for writing out
+	/// a [`ChannelManager`]. See the `lightning-persister` crate's [`FilesystemPersister`] for
+	/// Rust-Lightning's provided implementation.
 	///
-	/// Typically, users should either implement [`ChannelManagerPersister`] to never return an
+	/// Typically, users should either implement [`Persister::persist_manager`] to never return an
 	/// error or call [`join`] and handle any error that may arise. For the latter case,
 	/// `BackgroundProcessor` must be restarted by calling `start` again after handling the error.
 	///
+	/// [`Persister::persist_graph`] is called after each prune of the [`NetworkGraph`] (if a
+	/// [`NetGraphMsgHandler`] is given) and [`Persister::persist_scorer`] is called every thirty
+	/// seconds (if a `scorer` is given). Errors returned by either are logged but otherwise
+	/// ignored, as both can be rebuilt from the network if lost. Both are persisted once more,
+	/// alongside the [`ChannelManager`], when the background thread exits, at which point any
+	/// error is returned as for [`Persister::persist_manager`].
+	///
+	/// The `scorer` should be the same one used for routing (e.g., via an `InvoicePayer`'s
+	/// router), so that what is learned about channel liquidity survives restarts.
+	///
 	/// # Event Handling
 	///
 	/// `event_handler` is responsible for handling events that users should be notified of (e.g.,
@@ -165,9 +424,13 @@ impl BackgroundProcessor {
 	/// [`stop`]: Self::stop
 	/// [`ChannelManager`]: lightning::ln::channelmanager::ChannelManager
 	/// [`ChannelManager::write`]: lightning::ln::channelmanager::ChannelManager#impl-Writeable
-	/// [`FilesystemPersister::persist_manager`]: lightning_persister::FilesystemPersister::persist_manager
+	/// [`FilesystemPersister`]: lightning_persister::FilesystemPersister
+	/// [`Persister::persist_manager`]: lightning::util::persist::Persister::persist_manager
+	/// [`Persister::persist_graph`]: lightning::util::persist::Persister::persist_graph
+	/// [`Persister::persist_scorer`]: lightning::util::persist::Persister::persist_scorer
 	/// [`NetworkGraph`]: lightning::routing::network_graph::NetworkGraph
 	pub fn start<
+		'a,
 		Signer: 'static + Sign,
 		CA: 'static + Deref + Send + Sync,
 		CF: 'static + Deref + Send + Sync,
@@ -182,15 +445,17 @@ impl BackgroundProcessor {
 		CMH: 'static + Deref + Send + Sync,
 		RMH: 'static + Deref + Send + Sync,
 		EH: 'static + EventHandler + Send,
-		CMP: 'static + Send + ChannelManagerPersister<Signer, CW, T, K, F, L>,
+		PS: 'static + Deref + Send,
 		M: 'static + Deref<Target = ChainMonitor<Signer, CF, T, F, L, P>> + Send + Sync,
 		CM: 'static + Deref<Target = ChannelManager<Signer, CW, T, K, F, L>> + Send + Sync,
 		NG: 'static + Deref<Target = NetGraphMsgHandler<G, CA, L>> + Send + Sync,
 		UMH: 'static + Deref + Send + Sync,
-		PM: 'static + Deref<Target = PeerManager<Descriptor, CMH, RMH, L, UMH>> + Send + Sync,
+		PM: 'static + Deref<Target = PeerManager<Descriptor, CMH, RMH, L, UMH, K>> + Send + Sync,
+		S: 'static + Deref<Target = SC> + Send + Sync,
+		SC: WriteableScore<'a>,
 	>(
-		persister: CMP, event_handler: EH, chain_monitor: M, channel_manager: CM,
-		net_graph_msg_handler: Option<NG>, peer_manager: PM, logger: L
+		persister: PS, event_handler: EH, chain_monitor: M, channel_manager: CM,
+		net_graph_msg_handler: Option<NG>, peer_manager: PM, logger: L, scorer: Option<S>,
 	) -> Self
 	where
 		CA::Target: 'static + chain::Access,
@@ -204,86 +469,15 @@ impl BackgroundProcessor {
 		CMH::Target: 'static + ChannelMessageHandler,
 		RMH::Target: 'static + RoutingMessageHandler,
 		UMH::Target: 'static + CustomMessageHandler,
+		PS::Target: 'static + Persister<'a, Signer, CW, T, K, F, L, SC>,
 	{
 		let stop_thread = Arc::new(AtomicBool::new(false));
 		let stop_thread_clone = stop_thread.clone();
 		let handle = thread::spawn(move || -> Result<(), std::io::Error> {
-			let event_handler = DecoratingEventHandler { event_handler, net_graph_msg_handler: net_graph_msg_handler.as_ref().map(|t| t.deref()) };
-
-			log_trace!(logger, "Calling ChannelManager's timer_tick_occurred on startup");
-			channel_manager.timer_tick_occurred();
-
-			let mut last_freshness_call = Instant::now();
-			let mut last_ping_call = Instant::now();
-			let mut last_prune_call = Instant::now();
-			let mut have_pruned = false;
-
-			loop {
-				peer_manager.process_events(); // Note that this may block on ChannelManager's locking
-				channel_manager.process_pending_events(&event_handler);
-				chain_monitor.process_pending_events(&event_handler);
-
-				// We wait up to 100ms, but track how long it takes to detect being put to sleep,
-				// see `await_start`'s use below.
-				let await_start = Instant::now();
-				let updates_available =
-					channel_manager.await_persistable_update_timeout(Duration::from_millis(100));
-				let await_time = await_start.elapsed();
-
-				if updates_available {
-					log_trace!(logger, "Persisting ChannelManager...");
-					persister.persist_manager(&*channel_manager)?;
-					log_trace!(logger, "Done persisting ChannelManager.");
-				}
-				// Exit the loop if the background processor was requested to stop.
-				if stop_thread.load(Ordering::Acquire) == true {
-					log_trace!(logger, "Terminating background processor.");
-					break;
-				}
-				if last_freshness_call.elapsed().as_secs() > FRESHNESS_TIMER {
-					log_trace!(logger, "Calling ChannelManager's timer_tick_occurred");
-					channel_manager.timer_tick_occurred();
-					last_freshness_call = Instant::now();
-				}
-				if await_time > Duration::from_secs(1) {
-					// On various platforms, we may be starved of CPU cycles for several reasons.
-					// E.g. on iOS, if we've been in the background, we will be entirely paused.
-					// Similarly, if we're on a desktop platform and the device has been asleep, we
-					// may not get any cycles.
-					// We detect this by checking if our max-100ms-sleep, above, ran longer than a
-					// full second, at which point we assume sockets may have been killed (they
-					// appear to be at least on some platforms, even if it has only been a second).
-					// Note that we have to take care to not get here just because user event
-					// processing was slow at the top of the loop. For example, the sample client
-					// may call Bitcoin Core RPCs during event handling, which very often takes
-					// more than a handful of seconds to complete, and shouldn't disconnect all our
-					// peers.
-					log_trace!(logger, "100ms sleep took more than a second, disconnecting peers.");
-					peer_manager.disconnect_all_peers();
-					last_ping_call = Instant::now();
-				} else if last_ping_call.elapsed().as_secs() > PING_TIMER {
-					log_trace!(logger, "Calling PeerManager's timer_tick_occurred");
-					peer_manager.timer_tick_occurred();
-					last_ping_call = Instant::now();
-				}
-
-				// Note that we want to run a graph prune once not long after startup before
-				// falling back to our usual hourly prunes. This avoids short-lived clients never
-				// pruning their network graph. We run once 60 seconds after startup before
-				// continuing our normal cadence.
-				if last_prune_call.elapsed().as_secs() > if have_pruned { NETWORK_PRUNE_TIMER } else { 60 } {
-					if let Some(ref handler) = net_graph_msg_handler {
-						log_trace!(logger, "Pruning network graph of stale entries");
-						handler.network_graph().remove_stale_channels();
-						last_prune_call = Instant::now();
-						have_pruned = true;
-					}
-				}
-			}
-			// After we exit, ensure we persist the ChannelManager one final time - this avoids
-			// some races where users quit while channel updates were in-flight, with
-			// ChannelMonitor update(s) persisted without a corresponding ChannelManager update.
-			persister.persist_manager(&*channel_manager)
+			define_run_body!(persister, event_handler, chain_monitor, channel_manager,
+				net_graph_msg_handler, peer_manager, logger, scorer,
+				stop_thread.load(Ordering::Acquire),
+				channel_manager.await_persistable_update_timeout(Duration::from_millis(100)),)
 		});
 		Self { stop_thread: stop_thread_clone, thread_handle: Some(handle) }
 	}
@@ -343,22 +537,26 @@ mod tests {
 	use bitcoin::network::constants::Network;
 	use lightning::chain::{BestBlock, Confirm, chainmonitor};
 	use lightning::chain::channelmonitor::ANTI_REORG_DELAY;
-	use lightning::chain::keysinterface::{InMemorySigner, Recipient, KeysInterface, KeysManager};
+	use lightning::chain::chaininterface::{BroadcasterInterface, FeeEstimator};
+	use lightning::chain::keysinterface::{InMemorySigner, KeysInterface, KeysManager, Sign};
 	use lightning::chain::transaction::OutPoint;
-	use lightning::get_event_msg;
+	use lightning::{chain, get_event_msg};
 	use lightning::ln::channelmanager::{BREAKDOWN_TIMEOUT, ChainParameters, ChannelManager, SimpleArcChannelManager};
 	use lightning::ln::features::InitFeatures;
 	use lightning::ln::msgs::{ChannelMessageHandler, Init};
 	use lightning::ln::peer_handler::{PeerManager, MessageHandler, SocketDescriptor, IgnoringMessageHandler};
 	use lightning::routing::network_graph::{NetworkGraph, NetGraphMsgHandler};
+	use lightning::routing::scoring::{FixedPenaltyScorer, WriteableScore};
 	use lightning::util::config::UserConfig;
 	use lightning::util::events::{Event, MessageSendEventsProvider, MessageSendEvent};
+	use lightning::util::logger::Logger;
 	use lightning::util::ser::Writeable;
 	use lightning::util::test_utils;
 	use lightning_invoice::payment::{InvoicePayer, RetryAttempts};
 	use lightning_invoice::utils::DefaultRouter;
 	use lightning_persister::FilesystemPersister;
 	use std::fs;
+	use std::ops::Deref;
 	use std::path::PathBuf;
 	use std::sync::{Arc, Mutex};
 	use std::time::Duration;
@@ -381,13 +579,14 @@ mod tests {
 	struct Node {
 		node: Arc<SimpleArcChannelManager<ChainMonitor, test_utils::TestBroadcaster, test_utils::TestFeeEstimator, test_utils::TestLogger>>,
 		net_graph_msg_handler: Option<Arc<NetGraphMsgHandler<Arc<NetworkGraph>, Arc<test_utils::TestChainSource>, Arc<test_utils::TestLogger>>>>,
-		peer_manager: Arc<PeerManager<TestDescriptor, Arc<test_utils::TestChannelMessageHandler>, Arc<test_utils::TestRoutingMessageHandler>, Arc<test_utils::TestLogger>, IgnoringMessageHandler>>,
+		peer_manager: Arc<PeerManager<TestDescriptor, Arc<test_utils::TestChannelMessageHandler>, Arc<test_utils::TestRoutingMessageHandler>, Arc<test_utils::TestLogger>, IgnoringMessageHandler, Arc<KeysManager>>>,
 		chain_monitor: Arc<ChainMonitor>,
 		persister: Arc<FilesystemPersister>,
 		tx_broadcaster: Arc<test_utils::TestBroadcaster>,
 		network_graph: Arc<NetworkGraph>,
 		logger: Arc<test_utils::TestLogger>,
 		best_block: BestBlock,
+		scorer: Arc<Mutex<FixedPenaltyScorer>>,
 	}
 
 	impl Drop for Node {
@@ -400,6 +599,85 @@ mod tests {
 		}
 	}
 
+	struct Persister {
+		graph_error: Option<(std::io::ErrorKind, &'static str)>,
+		manager_error: Option<(std::io::ErrorKind, &'static str)>,
+		scorer_error: Option<(std::io::ErrorKind, &'static str)>,
+		filesystem_persister: FilesystemPersister,
+	}
+
+	impl Persister {
+		fn new(data_dir: String) -> Self {
+			let filesystem_persister = FilesystemPersister::new(data_dir.clone());
+			Self { graph_error: None, manager_error: None, scorer_error: None, filesystem_persister }
+		}
+
+		fn with_graph_error(self, error: std::io::ErrorKind, message: &'static str) -> Self {
+			Self { graph_error: Some((error, message)), ..self }
+		}
+
+		fn with_manager_error(self, error: std::io::ErrorKind, message: &'static str) -> Self {
+			Self { manager_error: Some((error, message)), ..self }
+		}
+
+		fn with_scorer_error(self, error: std::io::ErrorKind, message: &'static str) -> Self {
+			Self { scorer_error: Some((error, message)), ..self }
+		}
+	}
+
+	impl<'a, Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref, S: WriteableScore<'a>> super::Persister<'a, Signer, M, T, K, F, L, S> for Persister where
+		M::Target: 'static + chain::Watch<Signer>,
+		T::Target: 'static + BroadcasterInterface,
+		K::Target: 'static + KeysInterface<Signer = Signer>,
+		F::Target: 'static + FeeEstimator,
+		L::Target: 'static + Logger,
+	{
+		fn persist_manager(&self, channel_manager: &ChannelManager<Signer, M, T, K, F, L>) -> Result<(), std::io::Error> {
+			match self.manager_error {
+				None => <FilesystemPersister as super::Persister<'a, Signer, M, T, K, F, L, S>>::persist_manager(&self.filesystem_persister, channel_manager),
+				Some((error, message)) => Err(std::io::Error::new(error, message)),
+			}
+		}
+
+		fn persist_graph(&self, network_graph: &NetworkGraph) -> Result<(), std::io::Error> {
+			match self.graph_error {
+				None => <FilesystemPersister as super::Persister<'a, Signer, M, T, K, F, L, S>>::persist_graph(&self.filesystem_persister, network_graph),
+				Some((error, message)) => Err(std::io::Error::new(error, message)),
+			}
+		}
+
+		fn persist_scorer(&self, scorer: &S) -> Result<(), std::io::Error> {
+			match self.scorer_error {
+				None => <FilesystemPersister as super::Persister<'a, Signer, M, T, K, F, L, S>>::persist_scorer(&self.filesystem_persister, scorer),
+				Some((error, message)) => Err(std::io::Error::new(error, message)),
+			}
+		}
+	}
+
+	#[cfg(feature = "futures")]
+	impl<'a, Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref, S: WriteableScore<'a>> super::AsyncPersister<'a, Signer, M, T, K, F, L, S> for Persister where
+		M::Target: 'static + chain::Watch<Signer>,
+		T::Target: 'static + BroadcasterInterface,
+		K::Target: 'static + KeysInterface<Signer = Signer>,
+		F::Target: 'static + FeeEstimator,
+		L::Target: 'static + Logger,
+	{
+		fn persist_manager<'b>(&'b self, channel_manager: &'b ChannelManager<Signer, M, T, K, F, L>) -> super::AsyncPersistResult<'b> {
+			let res = <Self as super::Persister<'a, Signer, M, T, K, F, L, S>>::persist_manager(self, channel_manager);
+			Box::pin(async move { res })
+		}
+
+		fn persist_graph<'b>(&'b self, network_graph: &'b NetworkGraph) -> super::AsyncPersistResult<'b> {
+			let res = <Self as super::Persister<'a, Signer, M, T, K, F, L, S>>::persist_graph(self, network_graph);
+			Box::pin(async move { res })
+		}
+
+		fn persist_scorer<'b>(&'b self, scorer: &'b S) -> super::AsyncPersistResult<'b> {
+			let res = <Self as super::Persister<'a, Signer, M, T, K, F, L, S>>::persist_scorer(self, scorer);
+			Box::pin(async move { res })
+		}
+	}
+
 	fn get_full_filepath(filepath: String, filename: String) -> String {
 		let mut path = PathBuf::from(filepath);
 		path.push(filename);
@@ -426,8 +704,9 @@ mod tests {
 			let network_graph = Arc::new(NetworkGraph::new(genesis_block.header.block_hash()));
 			let net_graph_msg_handler = Some(Arc::new(NetGraphMsgHandler::new(network_graph.clone(), Some(chain_source.clone()), logger.clone())));
 			let msg_handler = MessageHandler { chan_handler: Arc::new(test_utils::TestChannelMessageHandler::new()), route_handler: Arc::new(test_utils::TestRoutingMessageHandler::new() )};
-			let peer_manager = Arc::new(PeerManager::new(msg_handler, keys_manager.get_node_secret(Recipient::Node).unwrap(), &seed, logger.clone(), IgnoringMessageHandler{}));
-			let node = Node { node: manager, net_graph_msg_handler, peer_manager, chain_monitor, persister, tx_broadcaster, network_graph, logger, best_block };
+			let peer_manager = Arc::new(PeerManager::new(msg_handler, keys_manager.clone(), &seed, logger.clone(), IgnoringMessageHandler{}));
+			let scorer = Arc::new(Mutex::new(FixedPenaltyScorer::with_penalty(0)));
+			let node = Node { node: manager, net_graph_msg_handler, peer_manager, chain_monitor, persister, tx_broadcaster, network_graph, logger, best_block, scorer };
 			nodes.push(node);
 		}
 
@@ -523,9 +802,9 @@ mod tests {
 
 		// Initiate the background processors to watch each node.
 		let data_dir = nodes[0].persister.get_data_dir();
-		let persister = move |node: &ChannelManager<InMemorySigner, Arc<ChainMonitor>, Arc<test_utils::TestBroadcaster>, Arc<KeysManager>, Arc<test_utils::TestFeeEstimator>, Arc<test_utils::TestLogger>>| FilesystemPersister::persist_manager(data_dir.clone(), node);
+		let persister = Arc::new(Persister::new(data_dir));
 		let event_handler = |_: &_| {};
-		let bg_processor = BackgroundProcessor::start(persister, event_handler, nodes[0].chain_monitor.clone(), nodes[0].node.clone(), nodes[0].net_graph_msg_handler.clone(), nodes[0].peer_manager.clone(), nodes[0].logger.clone());
+		let bg_processor = BackgroundProcessor::start(persister, event_handler, nodes[0].chain_monitor.clone(), nodes[0].node.clone(), nodes[0].net_graph_msg_handler.clone(), nodes[0].peer_manager.clone(), nodes[0].logger.clone(), Some(nodes[0].scorer.clone()));
 
 		macro_rules! check_persisted_data {
 			($node: expr, $filepath: expr, $expected_bytes: expr) => {
@@ -568,6 +847,16 @@ mod tests {
 			if !nodes[0].node.get_persistence_condvar_value() { break }
 		}
 
+		// Check network graph is persisted
+		let filepath = get_full_filepath("test_background_processor_persister_0".to_string(), "network_graph".to_string());
+		let mut expected_bytes = Vec::new();
+		check_persisted_data!(nodes[0].network_graph, filepath.clone(), expected_bytes);
+
+		// Check scorer is persisted
+		let filepath = get_full_filepath("test_background_processor_persister_0".to_string(), "scorer".to_string());
+		let mut expected_bytes = Vec::new();
+		check_persisted_data!(nodes[0].scorer, filepath.clone(), expected_bytes);
+
 		assert!(bg_processor.stop().is_ok());
 	}
 
@@ -577,9 +866,9 @@ mod tests {
 		// `FRESHNESS_TIMER`.
 		let nodes = create_nodes(1, "test_timer_tick_called".to_string());
 		let data_dir = nodes[0].persister.get_data_dir();
-		let persister = move |node: &ChannelManager<InMemorySigner, Arc<ChainMonitor>, Arc<test_utils::TestBroadcaster>, Arc<KeysManager>, Arc<test_utils::TestFeeEstimator>, Arc<test_utils::TestLogger>>| FilesystemPersister::persist_manager(data_dir.clone(), node);
+		let persister = Arc::new(Persister::new(data_dir));
 		let event_handler = |_: &_| {};
-		let bg_processor = BackgroundProcessor::start(persister, event_handler, nodes[0].chain_monitor.clone(), nodes[0].node.clone(), nodes[0].net_graph_msg_handler.clone(), nodes[0].peer_manager.clone(), nodes[0].logger.clone());
+		let bg_processor = BackgroundProcessor::start(persister, event_handler, nodes[0].chain_monitor.clone(), nodes[0].node.clone(), nodes[0].net_graph_msg_handler.clone(), nodes[0].peer_manager.clone(), nodes[0].logger.clone(), Some(nodes[0].scorer.clone()));
 		loop {
 			let log_entries = nodes[0].logger.lines.lock().unwrap();
 			let desired_log = "Calling ChannelManager's timer_tick_occurred".to_string();
@@ -593,15 +882,44 @@ mod tests {
 		assert!(bg_processor.stop().is_ok());
 	}
 
+	#[cfg(feature = "futures")]
+	#[tokio::test]
+	async fn test_process_events_async() {
+		// Test that the async processor performs the same periodic work as the threaded one,
+		// persisting everything once the shutdown future completes.
+		let nodes = create_nodes(2, "test_process_events_async".to_string());
+		open_channel!(nodes[0], nodes[1], 100000);
+
+		let data_dir = nodes[0].persister.get_data_dir();
+		let persister = Arc::new(Persister::new(data_dir.clone()));
+		let event_handler = |_: &_| {};
+		let shutdown = tokio::time::sleep(Duration::from_secs(3));
+		let res = super::process_events_async(persister, event_handler, nodes[0].chain_monitor.clone(), nodes[0].node.clone(), nodes[0].net_graph_msg_handler.clone(), nodes[0].peer_manager.clone(), nodes[0].logger.clone(), Some(nodes[0].scorer.clone()), |d| tokio::time::sleep(d), shutdown).await;
+		assert!(res.is_ok());
+
+		{
+			let log_entries = nodes[0].logger.lines.lock().unwrap();
+			let desired_log = "Calling ChannelManager's timer_tick_occurred".to_string();
+			assert!(log_entries.get(&("lightning_background_processor".to_string(), desired_log)).is_some());
+			let desired_log = "Terminating background processor.".to_string();
+			assert!(log_entries.get(&("lightning_background_processor".to_string(), desired_log)).is_some());
+		}
+
+		assert_eq!(fs::read(get_full_filepath(data_dir.clone(), "manager".to_string())).unwrap(), nodes[0].node.encode());
+		assert_eq!(fs::read(get_full_filepath(data_dir.clone(), "network_graph".to_string())).unwrap(), nodes[0].network_graph.encode());
+		assert_eq!(fs::read(get_full_filepath(data_dir, "scorer".to_string())).unwrap(), nodes[0].scorer.encode());
+	}
+
 	#[test]
 	fn test_persist_error() {
 		// Test that if we encounter an error during manager persistence, the thread panics.
 		let nodes = create_nodes(2, "test_persist_error".to_string());
 		open_channel!(nodes[0], nodes[1], 100000);
 
-		let persister = |_: &_| Err(std::io::Error::new(std::io::ErrorKind::Other, "test"));
+		let data_dir = nodes[0].persister.get_data_dir();
+		let persister = Arc::new(Persister::new(data_dir).with_manager_error(std::io::ErrorKind::Other, "test"));
 		let event_handler = |_: &_| {};
-		let bg_processor = BackgroundProcessor::start(persister, event_handler, nodes[0].chain_monitor.clone(), nodes[0].node.clone(), nodes[0].net_graph_msg_handler.clone(), nodes[0].peer_manager.clone(), nodes[0].logger.clone());
+		let bg_processor = BackgroundProcessor::start(persister, event_handler, nodes[0].chain_monitor.clone(), nodes[0].node.clone(), nodes[0].net_graph_msg_handler.clone(), nodes[0].peer_manager.clone(), nodes[0].logger.clone(), Some(nodes[0].scorer.clone()));
 		match bg_processor.join() {
 			Ok(_) => panic!("Expected error persisting manager"),
 			Err(e) => {
@@ -611,19 +929,75 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn test_network_graph_persist_error() {
+		// Test that if we encounter an error during network graph persistence, an error gets
+		// returned on shutdown, but the background processor keeps running until then.
+		let nodes = create_nodes(2, "test_persist_network_graph_error".to_string());
+		let data_dir = nodes[0].persister.get_data_dir();
+		let persister = Arc::new(Persister::new(data_dir).with_graph_error(std::io::ErrorKind::Other, "test"));
+		let event_handler = |_: &_| {};
+		let bg_processor = BackgroundProcessor::start(persister, event_handler, nodes[0].chain_monitor.clone(), nodes[0].node.clone(), nodes[0].net_graph_msg_handler.clone(), nodes[0].peer_manager.clone(), nodes[0].logger.clone(), Some(nodes[0].scorer.clone()));
+
+		// Wait for the periodic graph persistence to fail, after which we should still be running.
+		loop {
+			let log_entries = nodes[0].logger.lines.lock().unwrap();
+			let expected_log = "Error: Failed to persist network graph, check your disk and permissions test".to_string();
+			if log_entries.get(&("lightning_background_processor".to_string(), expected_log)).is_some() {
+				break
+			}
+		}
+
+		match bg_processor.stop() {
+			Ok(_) => panic!("Expected error persisting network graph"),
+			Err(e) => {
+				assert_eq!(e.kind(), std::io::ErrorKind::Other);
+				assert_eq!(e.get_ref().unwrap().to_string(), "test");
+			},
+		}
+	}
+
+	#[test]
+	fn test_scorer_persist_error() {
+		// Test that if we encounter an error during scorer persistence, an error gets returned on
+		// shutdown, but the background processor keeps running until then.
+		let nodes = create_nodes(2, "test_persist_scorer_error".to_string());
+		let data_dir = nodes[0].persister.get_data_dir();
+		let persister = Arc::new(Persister::new(data_dir).with_scorer_error(std::io::ErrorKind::Other, "test"));
+		let event_handler = |_: &_| {};
+		let bg_processor = BackgroundProcessor::start(persister, event_handler, nodes[0].chain_monitor.clone(), nodes[0].node.clone(), nodes[0].net_graph_msg_handler.clone(), nodes[0].peer_manager.clone(), nodes[0].logger.clone(), Some(nodes[0].scorer.clone()));
+
+		// Wait for the periodic scorer persistence to fail, after which we should still be running.
+		loop {
+			let log_entries = nodes[0].logger.lines.lock().unwrap();
+			let expected_log = "Error: Failed to persist scorer, check your disk and permissions test".to_string();
+			if log_entries.get(&("lightning_background_processor".to_string(), expected_log)).is_some() {
+				break
+			}
+		}
+
+		match bg_processor.stop() {
+			Ok(_) => panic!("Expected error persisting scorer"),
+			Err(e) => {
+				assert_eq!(e.kind(), std::io::ErrorKind::Other);
+				assert_eq!(e.get_ref().unwrap().to_string(), "test");
+			},
+		}
+	}
+
 	#[test]
 	fn test_background_event_handling() {
 		let mut nodes = create_nodes(2, "test_background_event_handling".to_string());
 		let channel_value = 100000;
 		let data_dir = nodes[0].persister.get_data_dir();
-		let persister = move |node: &_| FilesystemPersister::persist_manager(data_dir.clone(), node);
+		let persister = Arc::new(Persister::new(data_dir));
 
 		// Set up a background event handler for FundingGenerationReady events.
 		let (sender, receiver) = std::sync::mpsc::sync_channel(1);
 		let event_handler = move |event: &Event| {
 			sender.send(handle_funding_generation_ready!(event, channel_value)).unwrap();
 		};
-		let bg_processor = BackgroundProcessor::start(persister.clone(), event_handler, nodes[0].chain_monitor.clone(), nodes[0].node.clone(), nodes[0].net_graph_msg_handler.clone(), nodes[0].peer_manager.clone(), nodes[0].logger.clone());
+		let bg_processor = BackgroundProcessor::start(persister.clone(), event_handler, nodes[0].chain_monitor.clone(), nodes[0].node.clone(), nodes[0].net_graph_msg_handler.clone(), nodes[0].peer_manager.clone(), nodes[0].logger.clone(), Some(nodes[0].scorer.clone()));
 
 		// Open a channel and check that the FundingGenerationReady event was handled.
 		begin_open_channel!(nodes[0], nodes[1], channel_value);
@@ -647,7 +1021,7 @@ mod tests {
 		// Set up a background event handler for SpendableOutputs events.
 		let (sender, receiver) = std::sync::mpsc::sync_channel(1);
 		let event_handler = move |event: &Event| sender.send(event.clone()).unwrap();
-		let bg_processor = BackgroundProcessor::start(persister, event_handler, nodes[0].chain_monitor.clone(), nodes[0].node.clone(), nodes[0].net_graph_msg_handler.clone(), nodes[0].peer_manager.clone(), nodes[0].logger.clone());
+		let bg_processor = BackgroundProcessor::start(persister, event_handler, nodes[0].chain_monitor.clone(), nodes[0].node.clone(), nodes[0].net_graph_msg_handler.clone(), nodes[0].peer_manager.clone(), nodes[0].logger.clone(), Some(nodes[0].scorer.clone()));
 
 		// Force close the channel and check that the SpendableOutputs event was handled.
 		nodes[0].node.force_close_channel(&nodes[0].node.list_channels()[0].channel_id).unwrap();
@@ -671,12 +1045,11 @@ mod tests {
 
 		// Initiate the background processors to watch each node.
 		let data_dir = nodes[0].persister.get_data_dir();
-		let persister = move |node: &ChannelManager<InMemorySigner, Arc<ChainMonitor>, Arc<test_utils::TestBroadcaster>, Arc<KeysManager>, Arc<test_utils::TestFeeEstimator>, Arc<test_utils::TestLogger>>| FilesystemPersister::persist_manager(data_dir.clone(), node);
+		let persister = Arc::new(Persister::new(data_dir));
 		let router = DefaultRouter::new(Arc::clone(&nodes[0].network_graph), Arc::clone(&nodes[0].logger));
-		let scorer = Arc::new(Mutex::new(test_utils::TestScorer::with_penalty(0)));
-		let invoice_payer = Arc::new(InvoicePayer::new(Arc::clone(&nodes[0].node), router, scorer, Arc::clone(&nodes[0].logger), |_: &_| {}, RetryAttempts(2)));
+		let invoice_payer = Arc::new(InvoicePayer::new(Arc::clone(&nodes[0].node), router, Arc::clone(&nodes[0].scorer), Arc::clone(&nodes[0].logger), |_: &_| {}, RetryAttempts(2)));
 		let event_handler = Arc::clone(&invoice_payer);
-		let bg_processor = BackgroundProcessor::start(persister, event_handler, nodes[0].chain_monitor.clone(), nodes[0].node.clone(), nodes[0].net_graph_msg_handler.clone(), nodes[0].peer_manager.clone(), nodes[0].logger.clone());
+		let bg_processor = BackgroundProcessor::start(persister, event_handler, nodes[0].chain_monitor.clone(), nodes[0].node.clone(), nodes[0].net_graph_msg_handler.clone(), nodes[0].peer_manager.clone(), nodes[0].logger.clone(), Some(Arc::clone(&nodes[0].scorer)));
 		assert!(bg_processor.stop().is_ok());
 	}
 }
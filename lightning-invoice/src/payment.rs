@@ -38,9 +38,9 @@
 //! # use lightning::ln::{PaymentHash, PaymentPreimage, PaymentSecret};
 //! # use lightning::ln::channelmanager::{ChannelDetails, PaymentId, PaymentSendFailure};
 //! # use lightning::ln::msgs::LightningError;
-//! # use lightning::routing::scoring::Score;
+//! # use lightning::routing::scoring::{ChannelUsage, Score};
 //! # use lightning::routing::network_graph::NodeId;
-//! # use lightning::routing::router::{Route, RouteHop, RouteParameters};
+//! # use lightning::routing::router::{InFlightHtlcs, Route, RouteHop, RouteParameters};
 //! # use lightning::util::events::{Event, EventHandler, EventsProvider};
 //! # use lightning::util::logger::{Logger, Record};
 //! # use lightning::util::ser::{Writeable, Writer};
@@ -74,13 +74,14 @@
 //! #         &self, route: &Route, payment_id: PaymentId
 //! #     ) -> Result<(), PaymentSendFailure> { unimplemented!() }
 //! #     fn abandon_payment(&self, payment_id: PaymentId) { unimplemented!() }
+//! #     fn inflight_htlcs(&self) -> InFlightHtlcs { unimplemented!() }
 //! # }
 //! #
 //! # struct FakeRouter {}
 //! # impl<S: Score> Router<S> for FakeRouter {
 //! #     fn find_route(
 //! #         &self, payer: &PublicKey, params: &RouteParameters, payment_hash: &PaymentHash,
-//! #         first_hops: Option<&[&ChannelDetails]>, scorer: &S
+//! #         first_hops: Option<&[&ChannelDetails]>, scorer: &S, inflight_htlcs: &InFlightHtlcs
 //! #     ) -> Result<Route, LightningError> { unimplemented!() }
 //! # }
 //! #
@@ -90,7 +91,7 @@
 //! # }
 //! # impl Score for FakeScorer {
 //! #     fn channel_penalty_msat(
-//! #         &self, _short_channel_id: u64, _send_amt: u64, _chan_amt: u64, _source: &NodeId, _target: &NodeId
+//! #         &self, _short_channel_id: u64, _source: &NodeId, _target: &NodeId, _usage: ChannelUsage
 //! #     ) -> u64 { 0 }
 //! #     fn payment_path_failed(&mut self, _path: &[&RouteHop], _short_channel_id: u64) {}
 //! #     fn payment_path_successful(&mut self, _path: &[&RouteHop]) {}
@@ -143,7 +144,7 @@ use lightning::ln::{PaymentHash, PaymentPreimage, PaymentSecret};
 use lightning::ln::channelmanager::{ChannelDetails, PaymentId, PaymentSendFailure};
 use lightning::ln::msgs::LightningError;
 use lightning::routing::scoring::{LockableScore, Score};
-use lightning::routing::router::{PaymentParameters, Route, RouteParameters};
+use lightning::routing::router::{InFlightHtlcs, PaymentParameters, Route, RouteParameters};
 use lightning::util::events::{Event, EventHandler};
 use lightning::util::logger::Logger;
 use crate::sync::Mutex;
@@ -200,14 +201,18 @@ pub trait Payer {
 
 	/// Signals that no further retries for the given payment will occur.
 	fn abandon_payment(&self, payment_id: PaymentId);
+
+	/// Returns the liquidity used by the payer's HTLCs which are currently in flight.
+	fn inflight_htlcs(&self) -> InFlightHtlcs;
 }
 
 /// A trait defining behavior for routing an [`Invoice`] payment.
 pub trait Router<S: Score> {
-	/// Finds a [`Route`] between `payer` and `payee` for a payment with the given values.
+	/// Finds a [`Route`] between `payer` and `payee` for a payment with the given values, avoiding
+	/// the liquidity used by `inflight_htlcs` where possible.
 	fn find_route(
 		&self, payer: &PublicKey, route_params: &RouteParameters, payment_hash: &PaymentHash,
-		first_hops: Option<&[&ChannelDetails]>, scorer: &S
+		first_hops: Option<&[&ChannelDetails]>, scorer: &S, inflight_htlcs: &InFlightHtlcs
 	) -> Result<Route, LightningError>;
 }
 
@@ -355,9 +360,10 @@ where
 
 		let payer = self.payer.node_id();
 		let first_hops = self.payer.first_hops();
+		let inflight_htlcs = self.payer.inflight_htlcs();
 		let route = self.router.find_route(
 			&payer, params, &payment_hash, Some(&first_hops.iter().collect::<Vec<_>>()),
-			&self.scorer.lock()
+			&self.scorer.lock(), &inflight_htlcs
 		).map_err(|e| PaymentError::Routing(e))?;
 
 		match send_payment(&route) {
@@ -419,9 +425,10 @@ where
 
 		let payer = self.payer.node_id();
 		let first_hops = self.payer.first_hops();
+		let inflight_htlcs = self.payer.inflight_htlcs();
 		let route = self.router.find_route(
 			&payer, &params, &payment_hash, Some(&first_hops.iter().collect::<Vec<_>>()),
-			&self.scorer.lock()
+			&self.scorer.lock(), &inflight_htlcs
 		);
 		if route.is_err() {
 			log_trace!(self.logger, "Failed to find a route for payment {}; not retrying (attempts: {})", log_bytes!(payment_hash.0), attempts);
@@ -533,7 +540,8 @@ mod tests {
 	use lightning::ln::functional_test_utils::*;
 	use lightning::ln::msgs::{ChannelMessageHandler, ErrorAction, LightningError};
 	use lightning::routing::network_graph::NodeId;
-	use lightning::routing::router::{PaymentParameters, Route, RouteHop};
+	use lightning::routing::router::{InFlightHtlcs, PaymentParameters, Route, RouteHop};
+	use lightning::routing::scoring::ChannelUsage;
 	use lightning::util::test_utils::TestLogger;
 	use lightning::util::errors::APIError;
 	use lightning::util::events::{Event, EventsProvider, MessageSendEvent, MessageSendEventsProvider};
@@ -1278,7 +1286,7 @@ mod tests {
 	impl<S: Score> Router<S> for TestRouter {
 		fn find_route(
 			&self, _payer: &PublicKey, route_params: &RouteParameters, _payment_hash: &PaymentHash,
-			_first_hops: Option<&[&ChannelDetails]>, _scorer: &S
+			_first_hops: Option<&[&ChannelDetails]>, _scorer: &S, _inflight_htlcs: &InFlightHtlcs
 		) -> Result<Route, LightningError> {
 			Ok(Route {
 				payment_params: Some(route_params.payment_params.clone()), ..Self::route_for_value(route_params.final_value_msat)
@@ -1291,7 +1299,7 @@ mod tests {
 	impl<S: Score> Router<S> for FailingRouter {
 		fn find_route(
 			&self, _payer: &PublicKey, _params: &RouteParameters, _payment_hash: &PaymentHash,
-			_first_hops: Option<&[&ChannelDetails]>, _scorer: &S
+			_first_hops: Option<&[&ChannelDetails]>, _scorer: &S, _inflight_htlcs: &InFlightHtlcs
 		) -> Result<Route, LightningError> {
 			Err(LightningError { err: String::new(), action: ErrorAction::IgnoreError })
 		}
@@ -1327,7 +1335,7 @@ mod tests {
 
 	impl Score for TestScorer {
 		fn channel_penalty_msat(
-			&self, _short_channel_id: u64, _send_amt: u64, _chan_amt: u64, _source: &NodeId, _target: &NodeId
+			&self, _short_channel_id: u64, _source: &NodeId, _target: &NodeId, _usage: ChannelUsage
 		) -> u64 { 0 }
 
 		fn payment_path_failed(&mut self, actual_path: &[&RouteHop], actual_short_channel_id: u64) {
@@ -1486,6 +1494,10 @@ mod tests {
 		}
 
 		fn abandon_payment(&self, _payment_id: PaymentId) { }
+
+		fn inflight_htlcs(&self) -> InFlightHtlcs {
+			InFlightHtlcs::new()
+		}
 	}
 
 	// *** Full Featured Functional Tests with a Real ChannelManager ***
@@ -1494,7 +1506,7 @@ mod tests {
 	impl<S: Score> Router<S> for ManualRouter {
 		fn find_route(
 			&self, _payer: &PublicKey, _params: &RouteParameters, _payment_hash: &PaymentHash,
-			_first_hops: Option<&[&ChannelDetails]>, _scorer: &S
+			_first_hops: Option<&[&ChannelDetails]>, _scorer: &S, _inflight_htlcs: &InFlightHtlcs
 		) -> Result<Route, LightningError> {
 			self.0.borrow_mut().pop_front().unwrap()
 		}
@@ -14,7 +14,7 @@ use lightning::ln::channelmanager::{ChannelDetails, ChannelManager, PaymentId, P
 use lightning::ln::msgs::LightningError;
 use lightning::routing::scoring::Score;
 use lightning::routing::network_graph::{NetworkGraph, RoutingFees};
-use lightning::routing::router::{Route, RouteHint, RouteHintHop, RouteParameters, find_route};
+use lightning::routing::router::{InFlightHtlcs, Route, RouteHint, RouteHintHop, RouteParameters, find_route_with_inflight_htlcs};
 use lightning::util::logger::Logger;
 use secp256k1::key::PublicKey;
 use core::convert::TryInto;
@@ -218,7 +218,7 @@ where
 	}
 }
 
-/// A [`Router`] implemented using [`find_route`].
+/// A [`Router`] implemented using [`find_route_with_inflight_htlcs`].
 pub struct DefaultRouter<G: Deref<Target = NetworkGraph>, L: Deref> where L::Target: Logger {
 	network_graph: G,
 	logger: L,
@@ -235,9 +235,11 @@ impl<G: Deref<Target = NetworkGraph>, L: Deref, S: Score> Router<S> for DefaultR
 where L::Target: Logger {
 	fn find_route(
 		&self, payer: &PublicKey, params: &RouteParameters, _payment_hash: &PaymentHash,
-		first_hops: Option<&[&ChannelDetails]>, scorer: &S
+		first_hops: Option<&[&ChannelDetails]>, scorer: &S, inflight_htlcs: &InFlightHtlcs
 	) -> Result<Route, LightningError> {
-		find_route(payer, params, &*self.network_graph, first_hops, &*self.logger, scorer)
+		find_route_with_inflight_htlcs(
+			payer, params, &*self.network_graph, first_hops, inflight_htlcs, &*self.logger, scorer
+		)
 	}
 }
 
@@ -279,6 +281,10 @@ where
 	fn abandon_payment(&self, payment_id: PaymentId) {
 		self.abandon_payment(payment_id)
 	}
+
+	fn inflight_htlcs(&self) -> InFlightHtlcs {
+		self.compute_inflight_htlcs()
+	}
 }
 
 #[cfg(test)]
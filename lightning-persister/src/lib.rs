@@ -7,13 +7,16 @@
 #[cfg(all(test, feature = "_bench_unstable"))] extern crate test;
 
 mod util;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
 
 extern crate lightning;
 extern crate bitcoin;
 extern crate libc;
+#[cfg(feature = "sqlite")]
+extern crate rusqlite;
 
-use bitcoin::hash_types::{BlockHash, Txid};
-use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::hash_types::BlockHash;
 use crate::util::DiskWriteable;
 use lightning::chain;
 use lightning::chain::chaininterface::{BroadcasterInterface, FeeEstimator};
@@ -22,16 +25,25 @@ use lightning::chain::chainmonitor;
 use lightning::chain::keysinterface::{Sign, KeysInterface};
 use lightning::chain::transaction::OutPoint;
 use lightning::ln::channelmanager::ChannelManager;
+use lightning::routing::network_graph::NetworkGraph;
+use lightning::routing::scoring::WriteableScore;
 use lightning::util::logger::Logger;
-use lightning::util::ser::{ReadableArgs, Writeable};
+use lightning::util::persist::{KVStore, KVStorePersister, Persister, read_channel_monitors};
+use lightning::util::ser::Writeable;
 use std::fs;
-use std::io::{Cursor, Error};
+use std::io::{Error, ErrorKind};
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 
 /// FilesystemPersister persists channel data on disk, where each channel's
 /// data is stored in a file named after its funding outpoint.
 ///
+/// It implements [`KVStore`], storing each namespace as a directory under the data directory
+/// (with the empty namespace being the data directory itself) and each key as a file within it.
+/// Its [`Persister`] and [`Persist`] implementations store data as a [`KVStorePersister`] would,
+/// writing the [`ChannelManager`] to a file called "manager" and each [`ChannelMonitor`] to a file
+/// in the "monitors" directory.
+///
 /// Warning: this module does the best it can with calls to persist data, but it
 /// can only guarantee that the data is passed to the drive. It is up to the
 /// drive manufacturers to do the actual persistence properly, which they often
@@ -41,29 +53,19 @@ use std::path::{Path, PathBuf};
 /// Corollary: especially when dealing with larger amounts of money, it is best
 /// practice to have multiple channel data backups and not rely only on one
 /// FilesystemPersister.
+///
+/// [`Persist`]: lightning::chain::chainmonitor::Persist
+/// [`ChannelManager`]: lightning::ln::channelmanager::ChannelManager
 pub struct FilesystemPersister {
 	path_to_channel_data: String,
 }
 
-impl<Signer: Sign> DiskWriteable for ChannelMonitor<Signer> {
+impl<W: Writeable> DiskWriteable for W {
 	fn write_to_file(&self, writer: &mut fs::File) -> Result<(), Error> {
 		self.write(writer)
 	}
 }
 
-impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> DiskWriteable for ChannelManager<Signer, M, T, K, F, L>
-where
-	M::Target: chain::Watch<Signer>,
-	T::Target: BroadcasterInterface,
-	K::Target: KeysInterface<Signer=Signer>,
-	F::Target: FeeEstimator,
-	L::Target: Logger,
-{
-	fn write_to_file(&self, writer: &mut fs::File) -> Result<(), std::io::Error> {
-		self.write(writer)
-	}
-}
-
 impl FilesystemPersister {
 	/// Initialize a new FilesystemPersister and set the path to the individual channels'
 	/// files.
@@ -78,9 +80,11 @@ impl FilesystemPersister {
 		self.path_to_channel_data.clone()
 	}
 
-	pub(crate) fn path_to_monitor_data(&self) -> PathBuf {
+	fn path_to_namespace(&self, namespace: &str) -> PathBuf {
 		let mut path = PathBuf::from(self.path_to_channel_data.clone());
-		path.push("monitors");
+		if !namespace.is_empty() {
+			path.push(namespace);
+		}
 		path
 	}
 
@@ -101,79 +105,102 @@ impl FilesystemPersister {
 		util::write_to_file(path, "manager".to_string(), manager)
 	}
 
+	/// Writes the provided [`NetworkGraph`] to the path provided at `FilesystemPersister`
+	/// initialization, within a file called "network_graph".
+	pub fn persist_network_graph(data_dir: String, network_graph: &NetworkGraph) -> Result<(), std::io::Error> {
+		let path = PathBuf::from(data_dir);
+		util::write_to_file(path, "network_graph".to_string(), network_graph)
+	}
+
+	/// Writes the provided scorer to the path provided at `FilesystemPersister` initialization,
+	/// within a file called "scorer".
+	pub fn persist_scorer<'a, S: WriteableScore<'a>>(data_dir: String, scorer: &S) -> Result<(), std::io::Error> {
+		let path = PathBuf::from(data_dir);
+		util::write_to_file(path, "scorer".to_string(), scorer)
+	}
+
 	/// Read `ChannelMonitor`s from disk.
 	pub fn read_channelmonitors<Signer: Sign, K: Deref> (
 		&self, keys_manager: K
 	) -> Result<Vec<(BlockHash, ChannelMonitor<Signer>)>, std::io::Error>
 		where K::Target: KeysInterface<Signer=Signer> + Sized,
 	{
-		let path = self.path_to_monitor_data();
-		if !Path::new(&path).exists() {
-			return Ok(Vec::new());
-		}
-		let mut res = Vec::new();
-		for file_option in fs::read_dir(path).unwrap() {
-			let file = file_option.unwrap();
-			let owned_file_name = file.file_name();
-			let filename = owned_file_name.to_str();
-			if !filename.is_some() || !filename.unwrap().is_ascii() || filename.unwrap().len() < 65 {
-				return Err(std::io::Error::new(
-					std::io::ErrorKind::InvalidData,
-					"Invalid ChannelMonitor file name",
-				));
-			}
+		read_channel_monitors(self, keys_manager)
+	}
+}
 
-			let txid = Txid::from_hex(filename.unwrap().split_at(64).0);
-			if txid.is_err() {
-				return Err(std::io::Error::new(
-					std::io::ErrorKind::InvalidData,
-					"Invalid tx ID in filename",
-				));
-			}
+impl<'a, Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref, S: WriteableScore<'a>>
+	Persister<'a, Signer, M, T, K, F, L, S> for FilesystemPersister
+where
+	M::Target: 'static + chain::Watch<Signer>,
+	T::Target: 'static + BroadcasterInterface,
+	K::Target: 'static + KeysInterface<Signer = Signer>,
+	F::Target: 'static + FeeEstimator,
+	L::Target: 'static + Logger,
+{
+	fn persist_manager(&self, channel_manager: &ChannelManager<Signer, M, T, K, F, L>) -> Result<(), std::io::Error> {
+		<KVStorePersister<&Self> as Persister<'a, Signer, M, T, K, F, L, S>>::persist_manager(&KVStorePersister::new(self), channel_manager)
+	}
 
-			let index = filename.unwrap().split_at(65).1.parse();
-			if index.is_err() {
-				return Err(std::io::Error::new(
-					std::io::ErrorKind::InvalidData,
-					"Invalid tx index in filename",
-				));
-			}
+	fn persist_graph(&self, network_graph: &NetworkGraph) -> Result<(), std::io::Error> {
+		<KVStorePersister<&Self> as Persister<'a, Signer, M, T, K, F, L, S>>::persist_graph(&KVStorePersister::new(self), network_graph)
+	}
 
-			let contents = fs::read(&file.path())?;
-			let mut buffer = Cursor::new(&contents);
-			match <(BlockHash, ChannelMonitor<Signer>)>::read(&mut buffer, &*keys_manager) {
-				Ok((blockhash, channel_monitor)) => {
-					if channel_monitor.get_funding_txo().0.txid != txid.unwrap() || channel_monitor.get_funding_txo().0.index != index.unwrap() {
-						return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "ChannelMonitor was stored in the wrong file"));
-					}
-					res.push((blockhash, channel_monitor));
-				}
-				Err(e) => return Err(std::io::Error::new(
-					std::io::ErrorKind::InvalidData,
-					format!("Failed to deserialize ChannelMonitor: {}", e),
-				))
-			}
-		}
-		Ok(res)
+	fn persist_scorer(&self, scorer: &S) -> Result<(), std::io::Error> {
+		<KVStorePersister<&Self> as Persister<'a, Signer, M, T, K, F, L, S>>::persist_scorer(&KVStorePersister::new(self), scorer)
 	}
 }
 
 impl<ChannelSigner: Sign> chainmonitor::Persist<ChannelSigner> for FilesystemPersister {
-	// TODO: We really need a way for the persister to inform the user that its time to crash/shut
-	// down once these start returning failure.
-	// A PermanentFailure implies we need to shut down since we're force-closing channels without
-	// even broadcasting!
-
-	fn persist_new_channel(&self, funding_txo: OutPoint, monitor: &ChannelMonitor<ChannelSigner>, _update_id: chainmonitor::MonitorUpdateId) -> Result<(), chain::ChannelMonitorUpdateErr> {
-		let filename = format!("{}_{}", funding_txo.txid.to_hex(), funding_txo.index);
-		util::write_to_file(self.path_to_monitor_data(), filename, monitor)
-			.map_err(|_| chain::ChannelMonitorUpdateErr::PermanentFailure)
+	fn persist_new_channel(&self, funding_txo: OutPoint, monitor: &ChannelMonitor<ChannelSigner>, update_id: chainmonitor::MonitorUpdateId) -> Result<(), chain::ChannelMonitorUpdateErr> {
+		KVStorePersister::new(self).persist_new_channel(funding_txo, monitor, update_id)
 	}
 
-	fn update_persisted_channel(&self, funding_txo: OutPoint, _update: &Option<ChannelMonitorUpdate>, monitor: &ChannelMonitor<ChannelSigner>, _update_id: chainmonitor::MonitorUpdateId) -> Result<(), chain::ChannelMonitorUpdateErr> {
-		let filename = format!("{}_{}", funding_txo.txid.to_hex(), funding_txo.index);
-		util::write_to_file(self.path_to_monitor_data(), filename, monitor)
-			.map_err(|_| chain::ChannelMonitorUpdateErr::PermanentFailure)
+	fn update_persisted_channel(&self, funding_txo: OutPoint, update: &Option<ChannelMonitorUpdate>, monitor: &ChannelMonitor<ChannelSigner>, update_id: chainmonitor::MonitorUpdateId) -> Result<(), chain::ChannelMonitorUpdateErr> {
+		KVStorePersister::new(self).update_persisted_channel(funding_txo, update, monitor, update_id)
+	}
+
+	fn archive_persisted_channel(&self, funding_txo: OutPoint, monitor: &ChannelMonitor<ChannelSigner>) {
+		KVStorePersister::new(self).archive_persisted_channel(funding_txo, monitor)
+	}
+}
+
+impl KVStore for FilesystemPersister {
+	fn read(&self, namespace: &str, key: &str) -> Result<Vec<u8>, Error> {
+		fs::read(util::get_full_filepath(self.path_to_namespace(namespace), key.to_string()))
+	}
+
+	fn write(&self, namespace: &str, key: &str, buf: &[u8]) -> Result<(), Error> {
+		util::write_to_file(self.path_to_namespace(namespace), key.to_string(), buf)
+	}
+
+	fn remove(&self, namespace: &str, key: &str) -> Result<(), Error> {
+		match fs::remove_file(util::get_full_filepath(self.path_to_namespace(namespace), key.to_string())) {
+			Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(()),
+			res => res,
+		}
+	}
+
+	fn list(&self, namespace: &str) -> Result<Vec<String>, Error> {
+		let path = self.path_to_namespace(namespace);
+		if !Path::new(&path).exists() {
+			return Ok(Vec::new());
+		}
+		let mut keys = Vec::new();
+		for entry in fs::read_dir(path)? {
+			let entry = entry?;
+			// Skip sub-namespaces (i.e. directories) as well as any temporary files left behind
+			// by a write which was interrupted.
+			if !entry.file_type()?.is_file() {
+				continue;
+			}
+			match entry.file_name().to_str() {
+				Some(key) if key.ends_with(".tmp") => continue,
+				Some(key) => keys.push(key.to_string()),
+				None => return Err(Error::new(ErrorKind::InvalidData, "Invalid key in data directory")),
+			}
+		}
+		Ok(keys)
 	}
 }
 
@@ -192,6 +219,7 @@ mod tests {
 	use lightning::ln::features::InitFeatures;
 	use lightning::ln::functional_test_utils::*;
 	use lightning::util::events::{ClosureReason, MessageSendEventsProvider};
+	use lightning::util::persist::KVStore;
 	use lightning::util::test_utils;
 	use std::fs;
 	#[cfg(target_os = "windows")]
@@ -281,6 +309,31 @@ mod tests {
 		check_persisted_data!(11);
 	}
 
+	#[test]
+	fn test_kv_store() {
+		// Test that namespaces map onto directories under the data directory, with the empty
+		// namespace being the data directory itself.
+		let persister = FilesystemPersister::new("test_kv_store".to_string());
+		assert!(persister.list("").unwrap().is_empty());
+		assert_eq!(persister.read("", "key").unwrap_err().kind(), std::io::ErrorKind::NotFound);
+
+		persister.write("", "key", &[1; 4]).unwrap();
+		persister.write("namespace", "other_key", &[2; 8]).unwrap();
+		assert_eq!(persister.read("", "key").unwrap(), vec![1; 4]);
+		assert_eq!(persister.read("namespace", "other_key").unwrap(), vec![2; 8]);
+		assert_eq!(fs::read("test_kv_store/namespace/other_key").unwrap(), vec![2; 8]);
+		assert_eq!(persister.list("").unwrap(), vec!["key".to_string()]);
+		assert_eq!(persister.list("namespace").unwrap(), vec!["other_key".to_string()]);
+
+		persister.write("", "key", &[3; 2]).unwrap();
+		assert_eq!(persister.read("", "key").unwrap(), vec![3; 2]);
+
+		persister.remove("", "key").unwrap();
+		persister.remove("", "key").unwrap();
+		assert!(persister.list("").unwrap().is_empty());
+		assert_eq!(persister.list("namespace").unwrap().len(), 1);
+	}
+
 	// Test that if the persister's path to channel data is read-only, writing a
 	// monitor to it results in the persister returning a PermanentFailure.
 	// Windows ignores the read-only flag for folders, so this test is Unix-only.
@@ -0,0 +1,343 @@
+//! A [`KVStore`] which keeps all persisted Rust-Lightning data in a single SQLite database file.
+
+use bitcoin::hash_types::BlockHash;
+use lightning::chain;
+use lightning::chain::chaininterface::{BroadcasterInterface, FeeEstimator};
+use lightning::chain::chainmonitor::{MonitorUpdateId, Persist};
+use lightning::chain::channelmonitor::{ChannelMonitor, ChannelMonitorUpdate, CLOSED_CHANNEL_UPDATE_ID};
+use lightning::chain::keysinterface::{Sign, KeysInterface};
+use lightning::chain::transaction::OutPoint;
+use lightning::util::logger::Logger;
+use lightning::util::persist::{ARCHIVED_CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, KVStore, MonitorUpdatingPersister, channel_monitor_key, channel_monitor_update_namespace};
+use lightning::util::ser::Writeable;
+use rusqlite::{Connection, OptionalExtension, Transaction, params};
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// The version of the database schema, stored in SQLite's `user_version` field so that future
+/// versions can migrate existing databases.
+const SCHEMA_VERSION: u16 = 1;
+
+/// `SqliteStore` persists channel data in a single SQLite database, rather than in a directory of
+/// files as the [`FilesystemPersister`] does.
+///
+/// It implements [`KVStore`], storing every namespace, key and value as a row in the `kv_store`
+/// table. Wrapped in a [`KVStorePersister`] it thus implements [`Persister`], storing the
+/// [`ChannelManager`] under the key "manager".
+///
+/// It also implements [`Persist`] itself, storing [`ChannelMonitor`]s and
+/// [`ChannelMonitorUpdate`]s in the same layout as a [`MonitorUpdatingPersister`]: each
+/// [`ChannelMonitorUpdate`] is stored as its own row, keyed by its update id, and the full
+/// [`ChannelMonitor`] is only re-written every `maximum_pending_updates` updates. Unlike a
+/// [`MonitorUpdatingPersister`], a full [`ChannelMonitor`] is written and the updates it
+/// consolidates (i.e. those up to its latest update id) are removed in a single transaction, as is
+/// the move of a monitor to the archive. The stored monitors, with their pending updates
+/// replayed, are read back by [`Self::read_channelmonitors`].
+///
+/// Every write is committed in its own transaction and the database is opened in write-ahead-log
+/// mode with `synchronous` set to `FULL`, thus a write has been durably committed once it
+/// returns. Note that in WAL mode recently committed data may still reside in the `-wal` file next
+/// to the database until a checkpoint occurs; backups should be taken via SQLite's backup API
+/// (e.g. the `.backup` command of the `sqlite3` shell) rather than by copying the database file.
+///
+/// [`FilesystemPersister`]: crate::FilesystemPersister
+/// [`KVStorePersister`]: lightning::util::persist::KVStorePersister
+/// [`Persister`]: lightning::util::persist::Persister
+/// [`ChannelManager`]: lightning::ln::channelmanager::ChannelManager
+pub struct SqliteStore {
+	connection: Mutex<Connection>,
+	maximum_pending_updates: u64,
+}
+
+fn sqlite_error(e: rusqlite::Error) -> Error {
+	Error::new(ErrorKind::Other, e)
+}
+
+impl SqliteStore {
+	/// Opens (creating it if it does not yet exist) the SQLite database at `db_file_path`.
+	///
+	/// When used as a [`Persist`]er, a full [`ChannelMonitor`] is written every
+	/// `maximum_pending_updates` updates. Setting it to 0 or 1 results in the full
+	/// [`ChannelMonitor`] being written on every update.
+	pub fn new(db_file_path: PathBuf, maximum_pending_updates: u64) -> Result<Self, Error> {
+		if let Some(parent) = db_file_path.parent() {
+			if !parent.as_os_str().is_empty() {
+				fs::create_dir_all(parent)?;
+			}
+		}
+		let connection = Connection::open(db_file_path).map_err(sqlite_error)?;
+
+		let journal_mode: String = connection.query_row("PRAGMA journal_mode = WAL", params![], |row| row.get(0))
+			.map_err(sqlite_error)?;
+		if !journal_mode.eq_ignore_ascii_case("wal") {
+			return Err(Error::new(ErrorKind::Other, "Failed to enable write-ahead logging"));
+		}
+		connection.execute_batch("PRAGMA synchronous = FULL;").map_err(sqlite_error)?;
+
+		let version: u16 = connection.pragma_query_value(None, "user_version", |row| row.get(0))
+			.map_err(sqlite_error)?;
+		if version > SCHEMA_VERSION {
+			return Err(Error::new(ErrorKind::InvalidData, "Database was written by a newer version"));
+		}
+		connection.execute_batch(
+			"CREATE TABLE IF NOT EXISTS kv_store (
+				namespace TEXT NOT NULL,
+				key TEXT NOT NULL,
+				value BLOB NOT NULL,
+				PRIMARY KEY (namespace, key)
+			);"
+		).map_err(sqlite_error)?;
+		connection.pragma_update(None, "user_version", &SCHEMA_VERSION).map_err(sqlite_error)?;
+
+		Ok(Self { connection: Mutex::new(connection), maximum_pending_updates })
+	}
+
+	/// Read `ChannelMonitor`s from the database, applying any pending `ChannelMonitorUpdate`s to
+	/// them. See [`MonitorUpdatingPersister::read_channelmonitors`].
+	pub fn read_channelmonitors<Signer: Sign, K: Deref, B: Deref, F: Deref, L: Deref> (
+		&self, keys_manager: K, broadcaster: B, fee_estimator: F, logger: L
+	) -> Result<Vec<(BlockHash, ChannelMonitor<Signer>)>, Error>
+		where K::Target: KeysInterface<Signer=Signer> + Sized,
+			B::Target: BroadcasterInterface,
+			F::Target: FeeEstimator,
+			L::Target: Logger,
+	{
+		MonitorUpdatingPersister::new(self, self.maximum_pending_updates)
+			.read_channelmonitors(keys_manager, broadcaster, fee_estimator, logger)
+	}
+
+	/// Writes the full `monitor` and removes the updates consolidated into it in one transaction.
+	fn persist_full_monitor<Signer: Sign>(&self, funding_txo: &OutPoint, monitor: &ChannelMonitor<Signer>) -> Result<(), rusqlite::Error> {
+		let mut connection = self.connection.lock().unwrap();
+		let transaction = connection.transaction()?;
+		write_value(&transaction, CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, &channel_monitor_key(funding_txo), &monitor.encode())?;
+		remove_monitor_updates(&transaction, funding_txo, monitor.get_latest_update_id())?;
+		transaction.commit()
+	}
+}
+
+fn write_value(transaction: &Transaction, namespace: &str, key: &str, buf: &[u8]) -> Result<(), rusqlite::Error> {
+	transaction.execute(
+		"INSERT OR REPLACE INTO kv_store (namespace, key, value) VALUES (?1, ?2, ?3)",
+		params![namespace, key, buf]
+	)?;
+	Ok(())
+}
+
+/// Removes the stored updates for the channel funded by `funding_txo` with update ids up to and
+/// including `up_to_update_id`.
+fn remove_monitor_updates(transaction: &Transaction, funding_txo: &OutPoint, up_to_update_id: u64) -> Result<(), rusqlite::Error> {
+	// SQLite integers are signed 64-bit, thus for larger ids (i.e. `CLOSED_CHANNEL_UPDATE_ID`) we
+	// simply remove all updates.
+	let namespace = channel_monitor_update_namespace(funding_txo);
+	if up_to_update_id > i64::max_value() as u64 {
+		transaction.execute("DELETE FROM kv_store WHERE namespace = ?1", params![namespace])?;
+	} else {
+		transaction.execute(
+			"DELETE FROM kv_store WHERE namespace = ?1 AND CAST(key AS INTEGER) <= ?2",
+			params![namespace, up_to_update_id as i64]
+		)?;
+	}
+	Ok(())
+}
+
+impl KVStore for SqliteStore {
+	fn read(&self, namespace: &str, key: &str) -> Result<Vec<u8>, Error> {
+		let connection = self.connection.lock().unwrap();
+		let value: Option<Vec<u8>> = connection.query_row(
+			"SELECT value FROM kv_store WHERE namespace = ?1 AND key = ?2",
+			params![namespace, key], |row| row.get(0)
+		).optional().map_err(sqlite_error)?;
+		value.ok_or_else(|| Error::new(ErrorKind::NotFound, "Key not found"))
+	}
+
+	fn write(&self, namespace: &str, key: &str, buf: &[u8]) -> Result<(), Error> {
+		let mut connection = self.connection.lock().unwrap();
+		let transaction = connection.transaction().map_err(sqlite_error)?;
+		write_value(&transaction, namespace, key, buf).map_err(sqlite_error)?;
+		transaction.commit().map_err(sqlite_error)
+	}
+
+	fn remove(&self, namespace: &str, key: &str) -> Result<(), Error> {
+		let connection = self.connection.lock().unwrap();
+		connection.execute("DELETE FROM kv_store WHERE namespace = ?1 AND key = ?2", params![namespace, key])
+			.map_err(sqlite_error)?;
+		Ok(())
+	}
+
+	fn list(&self, namespace: &str) -> Result<Vec<String>, Error> {
+		let connection = self.connection.lock().unwrap();
+		let mut statement = connection.prepare("SELECT key FROM kv_store WHERE namespace = ?1")
+			.map_err(sqlite_error)?;
+		let keys = statement.query_map(params![namespace], |row| row.get(0)).map_err(sqlite_error)?;
+		keys.collect::<Result<Vec<String>, _>>().map_err(sqlite_error)
+	}
+}
+
+impl<ChannelSigner: Sign> Persist<ChannelSigner> for SqliteStore {
+	fn persist_new_channel(&self, funding_txo: OutPoint, monitor: &ChannelMonitor<ChannelSigner>, _update_id: MonitorUpdateId) -> Result<(), chain::ChannelMonitorUpdateErr> {
+		self.persist_full_monitor(&funding_txo, monitor)
+			.map_err(|_| chain::ChannelMonitorUpdateErr::PermanentFailure)
+	}
+
+	fn update_persisted_channel(&self, funding_txo: OutPoint, update: &Option<ChannelMonitorUpdate>, monitor: &ChannelMonitor<ChannelSigner>, _update_id: MonitorUpdateId) -> Result<(), chain::ChannelMonitorUpdateErr> {
+		match update {
+			Some(update) if update.update_id != CLOSED_CHANNEL_UPDATE_ID &&
+				self.maximum_pending_updates > 1 && update.update_id % self.maximum_pending_updates != 0 =>
+			{
+				self.write(&channel_monitor_update_namespace(&funding_txo), &update.update_id.to_string(), &update.encode())
+					.map_err(|_| chain::ChannelMonitorUpdateErr::PermanentFailure)
+			},
+			_ => self.persist_full_monitor(&funding_txo, monitor)
+				.map_err(|_| chain::ChannelMonitorUpdateErr::PermanentFailure),
+		}
+	}
+
+	fn archive_persisted_channel(&self, funding_txo: OutPoint, monitor: &ChannelMonitor<ChannelSigner>) {
+		let key = channel_monitor_key(&funding_txo);
+		let mut connection = self.connection.lock().unwrap();
+		let _ = connection.transaction().and_then(|transaction| {
+			write_value(&transaction, ARCHIVED_CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, &key, &monitor.encode())?;
+			transaction.execute("DELETE FROM kv_store WHERE namespace = ?1 AND key = ?2",
+				params![CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, key])?;
+			remove_monitor_updates(&transaction, &funding_txo, u64::max_value())?;
+			transaction.commit()
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::SqliteStore;
+	use bitcoin::blockdata::block::{Block, BlockHeader};
+	use lightning::{check_closed_broadcast, check_closed_event, check_added_monitors};
+	use lightning::ln::features::InitFeatures;
+	use lightning::ln::functional_test_utils::*;
+	use lightning::util::events::{ClosureReason, MessageSendEventsProvider};
+	use lightning::util::persist::{KVStore, channel_monitor_update_namespace};
+	use lightning::util::test_utils;
+	use std::{env, fs, process};
+	use std::path::PathBuf;
+
+	struct TestDb {
+		path: PathBuf,
+	}
+
+	// A database file under the system's temporary directory, removed along with its write-ahead
+	// log files when dropped.
+	impl TestDb {
+		fn new(name: &str) -> Self {
+			let path = env::temp_dir().join(format!("{}_{}.sqlite", name, process::id()));
+			let test_db = Self { path };
+			test_db.remove_files();
+			test_db
+		}
+
+		fn remove_files(&self) {
+			let path = self.path.to_str().unwrap();
+			for suffix in ["", "-wal", "-shm"].iter() {
+				let _ = fs::remove_file(format!("{}{}", path, suffix));
+			}
+		}
+	}
+
+	impl Drop for TestDb {
+		fn drop(&mut self) {
+			self.remove_files();
+		}
+	}
+
+	#[test]
+	fn test_sqlite_kv_store() {
+		let test_db = TestDb::new("test_sqlite_kv_store");
+		{
+			let store = SqliteStore::new(test_db.path.clone(), 0).unwrap();
+			assert!(store.list("").unwrap().is_empty());
+			assert_eq!(store.read("", "key").unwrap_err().kind(), std::io::ErrorKind::NotFound);
+
+			store.write("", "key", &[1; 4]).unwrap();
+			store.write("namespace", "key", &[2; 8]).unwrap();
+			store.write("", "key", &[3; 2]).unwrap();
+			assert_eq!(store.read("", "key").unwrap(), vec![3; 2]);
+			assert_eq!(store.read("namespace", "key").unwrap(), vec![2; 8]);
+			assert_eq!(store.list("namespace").unwrap(), vec!["key".to_string()]);
+		}
+
+		// Data is still there once the database is re-opened.
+		let store = SqliteStore::new(test_db.path.clone(), 0).unwrap();
+		assert_eq!(store.read("", "key").unwrap(), vec![3; 2]);
+		store.remove("", "key").unwrap();
+		store.remove("", "key").unwrap();
+		assert!(store.list("").unwrap().is_empty());
+		assert_eq!(store.list("namespace").unwrap().len(), 1);
+	}
+
+	// Integration-test the SqliteStore as a `Persist`er, relaying a few payments and checking that
+	// the persisted monitors are updated as expected.
+	#[test]
+	fn test_sqlite_monitor_persistence() {
+		let test_db_0 = TestDb::new("test_sqlite_monitor_persistence_0");
+		let test_db_1 = TestDb::new("test_sqlite_monitor_persistence_1");
+		let store_0 = SqliteStore::new(test_db_0.path.clone(), 3).unwrap();
+		let store_1 = SqliteStore::new(test_db_1.path.clone(), 3).unwrap();
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let mut node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let chain_mon_0 = test_utils::TestChainMonitor::new(Some(&chanmon_cfgs[0].chain_source), &chanmon_cfgs[0].tx_broadcaster, &chanmon_cfgs[0].logger, &chanmon_cfgs[0].fee_estimator, &store_0, &node_cfgs[0].keys_manager);
+		let chain_mon_1 = test_utils::TestChainMonitor::new(Some(&chanmon_cfgs[1].chain_source), &chanmon_cfgs[1].tx_broadcaster, &chanmon_cfgs[1].logger, &chanmon_cfgs[1].fee_estimator, &store_1, &node_cfgs[1].keys_manager);
+		node_cfgs[0].chain_monitor = chain_mon_0;
+		node_cfgs[1].chain_monitor = chain_mon_1;
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+		assert!(store_0.read_channelmonitors(nodes[0].keys_manager, nodes[0].tx_broadcaster, &chanmon_cfgs[0].fee_estimator, nodes[0].logger).unwrap().is_empty());
+		assert!(store_1.read_channelmonitors(nodes[1].keys_manager, nodes[1].tx_broadcaster, &chanmon_cfgs[1].fee_estimator, nodes[1].logger).unwrap().is_empty());
+
+		// Checks that the monitors read back, with pending updates replayed, are at the expected
+		// update id and that only the updates since the last full monitor write are stored, given
+		// per node.
+		macro_rules! check_persisted_data {
+			($expected_update_id: expr, $expected_pending_updates: expr) => {
+				let expected_pending_updates: [usize; 2] = $expected_pending_updates;
+				for (idx, (store, node, fee_estimator)) in [(&store_0, &nodes[0], &chanmon_cfgs[0].fee_estimator), (&store_1, &nodes[1], &chanmon_cfgs[1].fee_estimator)].iter().enumerate() {
+					let persisted_chan_data = store.read_channelmonitors(node.keys_manager, node.tx_broadcaster, *fee_estimator, node.logger).unwrap();
+					assert_eq!(persisted_chan_data.len(), 1);
+					for (_, mon) in persisted_chan_data.iter() {
+						assert_eq!(mon.get_latest_update_id(), $expected_update_id);
+						let namespace = channel_monitor_update_namespace(&mon.get_funding_txo().0);
+						assert_eq!(store.list(&namespace).unwrap().len(), expected_pending_updates[idx]);
+					}
+				}
+			}
+		}
+
+		let _ = create_announced_chan_between_nodes(&nodes, 0, 1, InitFeatures::known(), InitFeatures::known());
+		check_persisted_data!(0, [0, 0]);
+
+		send_payment(&nodes[0], &vec!(&nodes[1])[..], 8000000);
+		check_persisted_data!(5, [2, 2]);
+		send_payment(&nodes[1], &vec!(&nodes[0])[..], 4000000);
+		check_persisted_data!(10, [1, 1]);
+
+		nodes[0].node.force_close_channel(&nodes[0].node.list_channels()[0].channel_id).unwrap();
+		check_closed_event!(nodes[0], 1, ClosureReason::HolderForceClosed);
+		check_closed_broadcast!(nodes[0], true);
+		check_added_monitors!(nodes[0], 1);
+
+		// Replaying updates may broadcast transactions, so don't hold the broadcaster's lock.
+		let node_txn = nodes[0].tx_broadcaster.txn_broadcasted.lock().unwrap().clone();
+		assert_eq!(node_txn.len(), 1);
+
+		let header = BlockHeader { version: 0x20000000, prev_blockhash: nodes[0].best_block_hash(), merkle_root: Default::default(), time: 42, bits: 42, nonce: 42 };
+		connect_block(&nodes[1], &Block { header, txdata: vec![node_txn[0].clone(), node_txn[0].clone()]});
+		check_closed_broadcast!(nodes[1], true);
+		check_closed_event!(nodes[1], 1, ClosureReason::CommitmentTxConfirmed);
+		check_added_monitors!(nodes[1], 1);
+
+		// nodes[1] wrote its full monitor on seeing the commitment transaction confirm, so only has the
+		// force-close update pending.
+		check_persisted_data!(11, [2, 1]);
+	}
+}
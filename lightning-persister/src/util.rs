@@ -2,6 +2,7 @@
 extern crate winapi;
 
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 
 #[cfg(not(target_os = "windows"))]
@@ -17,6 +18,12 @@ pub(crate) trait DiskWriteable {
 	fn write_to_file(&self, writer: &mut fs::File) -> Result<(), std::io::Error>;
 }
 
+impl DiskWriteable for [u8] {
+	fn write_to_file(&self, writer: &mut fs::File) -> Result<(), std::io::Error> {
+		writer.write_all(self)
+	}
+}
+
 pub(crate) fn get_full_filepath(mut filepath: PathBuf, filename: String) -> String {
 	filepath.push(filename);
 	filepath.to_str().unwrap().to_string()
@@ -39,7 +46,7 @@ fn path_to_windows_str<T: AsRef<OsStr>>(path: T) -> Vec<winapi::shared::ntdef::W
 }
 
 #[allow(bare_trait_objects)]
-pub(crate) fn write_to_file<D: DiskWriteable>(path: PathBuf, filename: String, data: &D) -> std::io::Result<()> {
+pub(crate) fn write_to_file<D: DiskWriteable + ?Sized>(path: PathBuf, filename: String, data: &D) -> std::io::Result<()> {
 	fs::create_dir_all(path.clone())?;
 	// Do a crazy dance with lots of fsync()s to be overly cautious here...
 	// We never want to end up in a state where we've lost the old data, or end up using the
@@ -112,8 +119,15 @@ mod tests {
 		fs::create_dir_all(path.to_string()).unwrap();
 		let mut perms = fs::metadata(path.to_string()).unwrap().permissions();
 		perms.set_readonly(true);
+		fs::set_permissions(path.to_string(), perms.clone()).unwrap();
+		let res = write_to_file(PathBuf::from(path.to_string()), filename, &test_writeable);
+
+		// Clean up before checking the result, so that nothing is left behind if the write
+		// unexpectedly succeeded, e.g. because we're running as root.
+		perms.set_readonly(false);
 		fs::set_permissions(path.to_string(), perms).unwrap();
-		match write_to_file(PathBuf::from(path.to_string()), filename, &test_writeable) {
+		fs::remove_dir_all(path).unwrap();
+		match res {
 			Err(e) => assert_eq!(e.kind(), io::ErrorKind::PermissionDenied),
 			_ => panic!("Unexpected error message")
 		}
@@ -175,7 +189,7 @@ mod tests {
 		// Create the tmp file and make it a directory.
 		let tmp_path = get_full_filepath(path.clone(), format!("{}.tmp", filename.clone()));
 		fs::create_dir_all(tmp_path).unwrap();
-		match write_to_file(path, filename, &test_writeable) {
+		match write_to_file(path.clone(), filename, &test_writeable) {
 			Err(e) => {
 				#[cfg(not(target_os = "windows"))]
 				assert_eq!(e.raw_os_error(), Some(libc::EISDIR));
@@ -184,5 +198,6 @@ mod tests {
 			}
 			_ => panic!("Unexpected error message")
 		}
+		fs::remove_dir_all(path).unwrap();
 	}
 }
@@ -0,0 +1,321 @@
+use std::collections::{BTreeMap, HashMap};
+
+use lightning::routing::network_graph::{ChannelUpdateInfo, NetworkGraph, NodeId};
+use lightning::util::ser::{BigSize, Writeable};
+
+use crate::processing::{
+	CLTV_EXPIRY_DELTA_FLAG, FEE_BASE_MSAT_FLAG, FEE_PROPORTIONAL_MILLIONTHS_FLAG, GOSSIP_PREFIX,
+	HTLC_MAXIMUM_MSAT_ABSENT, HTLC_MAXIMUM_MSAT_FLAG, HTLC_MINIMUM_MSAT_FLAG,
+};
+
+/// A single directional channel update selected for inclusion in a snapshot.
+struct SnapshotUpdate<'a> {
+	short_channel_id: u64,
+	direction: u8,
+	info: &'a ChannelUpdateInfo,
+}
+
+impl<'a> SnapshotUpdate<'a> {
+	fn htlc_maximum_msat(&self) -> u64 {
+		self.info.htlc_maximum_msat.unwrap_or(HTLC_MAXIMUM_MSAT_ABSENT)
+	}
+}
+
+/// The values which are assumed for any field of a full update that does not explicitly
+/// override them.
+struct UpdateDefaults {
+	cltv_expiry_delta: u16,
+	htlc_minimum_msat: u64,
+	fee_base_msat: u32,
+	fee_proportional_millionths: u32,
+	htlc_maximum_msat: u64,
+}
+
+/// Returns the value that occurs most often, preferring the lowest such value on ties so that the
+/// output is deterministic.
+fn most_common<T: Copy + Ord, I: Iterator<Item = T>>(values: I) -> Option<T> {
+	let mut counts = BTreeMap::new();
+	for value in values {
+		*counts.entry(value).or_insert(0u64) += 1;
+	}
+	let mut best: Option<(T, u64)> = None;
+	for (value, count) in counts {
+		if best.map_or(true, |(_, best_count)| count > best_count) {
+			best = Some((value, count));
+		}
+	}
+	best.map(|(value, _)| value)
+}
+
+/// Serializes the contents of a [`NetworkGraph`] into the compact format understood by
+/// [`update_network_graph`], allowing a server to hand out snapshots to clients which would
+/// otherwise have to sync gossip from their peers.
+///
+/// Only directional channel updates whose timestamp is at least `last_sync_timestamp` are
+/// included, alongside the (partial) announcements for the channels they refer to, making the
+/// output a delta relative to a client which last synced at `last_sync_timestamp`. A
+/// `last_sync_timestamp` of 0 generates a full snapshot. Announcements which the client already
+/// has are ignored when applied, so clients may safely receive a channel more than once.
+///
+/// Signatures are omitted, and updates are always encoded relative to the most common value of
+/// each field across the snapshot, which is typically a fraction of the size of the gossip
+/// messages themselves.
+///
+/// `latest_seen_timestamp` is handed back to clients as the timestamp to use for their next sync
+/// and should be the time at which the server last received gossip.
+///
+/// [`update_network_graph`]: crate::processing::update_network_graph
+pub fn serialize_network_graph(network_graph: &NetworkGraph, last_sync_timestamp: u32, latest_seen_timestamp: u32) -> Vec<u8> {
+	let read_only_graph = network_graph.read_only();
+
+	let mut updates = Vec::new();
+	let mut announced_scids = Vec::new();
+	for (short_channel_id, channel) in read_only_graph.channels().iter() {
+		let mut channel_has_updates = false;
+		let directions = [(0u8, channel.one_to_two.as_ref()), (1u8, channel.two_to_one.as_ref())];
+		for (direction, info) in directions.iter() {
+			if let Some(info) = *info {
+				if info.last_update >= last_sync_timestamp {
+					updates.push(SnapshotUpdate { short_channel_id: *short_channel_id, direction: *direction, info });
+					channel_has_updates = true;
+				}
+			}
+		}
+		if channel_has_updates || last_sync_timestamp == 0 {
+			announced_scids.push(*short_channel_id);
+		}
+	}
+
+	let mut node_ids: Vec<NodeId> = Vec::new();
+	let mut node_id_indices: HashMap<NodeId, u64> = HashMap::new();
+	let mut node_index = |node_id: &NodeId| -> u64 {
+		*node_id_indices.entry(*node_id).or_insert_with(|| {
+			node_ids.push(*node_id);
+			(node_ids.len() - 1) as u64
+		})
+	};
+	let mut announcements = Vec::with_capacity(announced_scids.len());
+	for short_channel_id in announced_scids.iter() {
+		let channel = read_only_graph.channels().get(short_channel_id).unwrap();
+		let node_id_1_index = node_index(&channel.node_one);
+		let node_id_2_index = node_index(&channel.node_two);
+		announcements.push((*short_channel_id, &channel.features, node_id_1_index, node_id_2_index));
+	}
+
+	let mut output = Vec::new();
+	output.extend_from_slice(&GOSSIP_PREFIX);
+	network_graph.get_genesis_hash().write(&mut output).unwrap();
+	latest_seen_timestamp.write(&mut output).unwrap();
+
+	(node_ids.len() as u32).write(&mut output).unwrap();
+	for node_id in node_ids.iter() {
+		node_id.write(&mut output).unwrap();
+	}
+
+	let mut previous_scid = 0;
+	(announcements.len() as u32).write(&mut output).unwrap();
+	for (short_channel_id, features, node_id_1_index, node_id_2_index) in announcements {
+		features.write(&mut output).unwrap();
+		BigSize(short_channel_id - previous_scid).write(&mut output).unwrap();
+		previous_scid = short_channel_id;
+		BigSize(node_id_1_index).write(&mut output).unwrap();
+		BigSize(node_id_2_index).write(&mut output).unwrap();
+	}
+
+	(updates.len() as u32).write(&mut output).unwrap();
+	if updates.is_empty() {
+		return output;
+	}
+
+	let defaults = UpdateDefaults {
+		cltv_expiry_delta: most_common(updates.iter().map(|u| u.info.cltv_expiry_delta)).unwrap(),
+		htlc_minimum_msat: most_common(updates.iter().map(|u| u.info.htlc_minimum_msat)).unwrap(),
+		fee_base_msat: most_common(updates.iter().map(|u| u.info.fees.base_msat)).unwrap(),
+		fee_proportional_millionths: most_common(updates.iter().map(|u| u.info.fees.proportional_millionths)).unwrap(),
+		htlc_maximum_msat: most_common(updates.iter().map(|u| u.htlc_maximum_msat())).unwrap(),
+	};
+	defaults.cltv_expiry_delta.write(&mut output).unwrap();
+	defaults.htlc_minimum_msat.write(&mut output).unwrap();
+	defaults.fee_base_msat.write(&mut output).unwrap();
+	defaults.fee_proportional_millionths.write(&mut output).unwrap();
+	defaults.htlc_maximum_msat.write(&mut output).unwrap();
+
+	previous_scid = 0;
+	for update in updates.iter() {
+		BigSize(update.short_channel_id - previous_scid).write(&mut output).unwrap();
+		previous_scid = update.short_channel_id;
+
+		let mut channel_flags = update.direction;
+		if !update.info.enabled {
+			channel_flags |= 1 << 1;
+		}
+		if update.info.cltv_expiry_delta != defaults.cltv_expiry_delta {
+			channel_flags |= CLTV_EXPIRY_DELTA_FLAG;
+		}
+		if update.info.htlc_minimum_msat != defaults.htlc_minimum_msat {
+			channel_flags |= HTLC_MINIMUM_MSAT_FLAG;
+		}
+		if update.info.fees.base_msat != defaults.fee_base_msat {
+			channel_flags |= FEE_BASE_MSAT_FLAG;
+		}
+		if update.info.fees.proportional_millionths != defaults.fee_proportional_millionths {
+			channel_flags |= FEE_PROPORTIONAL_MILLIONTHS_FLAG;
+		}
+		if update.htlc_maximum_msat() != defaults.htlc_maximum_msat {
+			channel_flags |= HTLC_MAXIMUM_MSAT_FLAG;
+		}
+		channel_flags.write(&mut output).unwrap();
+
+		if channel_flags & CLTV_EXPIRY_DELTA_FLAG != 0 {
+			update.info.cltv_expiry_delta.write(&mut output).unwrap();
+		}
+		if channel_flags & HTLC_MINIMUM_MSAT_FLAG != 0 {
+			update.info.htlc_minimum_msat.write(&mut output).unwrap();
+		}
+		if channel_flags & FEE_BASE_MSAT_FLAG != 0 {
+			update.info.fees.base_msat.write(&mut output).unwrap();
+		}
+		if channel_flags & FEE_PROPORTIONAL_MILLIONTHS_FLAG != 0 {
+			update.info.fees.proportional_millionths.write(&mut output).unwrap();
+		}
+		if channel_flags & HTLC_MAXIMUM_MSAT_FLAG != 0 {
+			update.htlc_maximum_msat().write(&mut output).unwrap();
+		}
+	}
+
+	output
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use bitcoin::blockdata::constants::genesis_block;
+	use bitcoin::hash_types::BlockHash;
+	use bitcoin::network::constants::Network;
+	use bitcoin::secp256k1::key::{PublicKey, SecretKey};
+	use bitcoin::secp256k1::Secp256k1;
+
+	use lightning::ln::features::ChannelFeatures;
+	use lightning::ln::msgs::{OptionalField, UnsignedChannelUpdate};
+	use lightning::routing::network_graph::NetworkGraph;
+
+	use crate::generation::serialize_network_graph;
+	use crate::processing::{update_network_graph, TIMESTAMP_BACKDATE_SECS};
+
+	fn node_pubkey(byte: u8) -> PublicKey {
+		let secp_ctx = Secp256k1::new();
+		PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[byte; 32]).unwrap())
+	}
+
+	fn get_update(chain_hash: BlockHash, short_channel_id: u64, flags: u8, timestamp: u32, fee_base_msat: u32, htlc_maximum_msat: OptionalField<u64>) -> UnsignedChannelUpdate {
+		UnsignedChannelUpdate {
+			chain_hash,
+			short_channel_id,
+			timestamp,
+			flags,
+			cltv_expiry_delta: 144,
+			htlc_minimum_msat: 1000,
+			htlc_maximum_msat,
+			fee_base_msat,
+			fee_proportional_millionths: 100,
+			excess_data: Vec::new(),
+		}
+	}
+
+	/// Builds a graph of three nodes with two channels, where the channels' directions deviate
+	/// from each other in a few fields.
+	pub(crate) fn create_test_graph(chain_hash: BlockHash) -> NetworkGraph {
+		let network_graph = NetworkGraph::new(chain_hash);
+		network_graph.add_channel_from_partial_announcement(42, 1000, ChannelFeatures::empty(), node_pubkey(42), node_pubkey(43)).unwrap();
+		network_graph.add_channel_from_partial_announcement(4242, 1000, ChannelFeatures::empty(), node_pubkey(43), node_pubkey(44)).unwrap();
+
+		network_graph.update_channel_unsigned(&get_update(chain_hash, 42, 0, 1642291000, 1000, OptionalField::Present(100_000_000))).unwrap();
+		network_graph.update_channel_unsigned(&get_update(chain_hash, 42, 1, 1642291900, 1000, OptionalField::Absent)).unwrap();
+		network_graph.update_channel_unsigned(&get_update(chain_hash, 4242, 1 << 1, 1642291500, 5000, OptionalField::Present(100_000_000))).unwrap();
+		network_graph
+	}
+
+	#[test]
+	fn full_snapshot_round_trip() {
+		let chain_hash = genesis_block(Network::Bitcoin).header.block_hash();
+		let source_graph = create_test_graph(chain_hash);
+		let snapshot = serialize_network_graph(&source_graph, 0, 1642291930);
+
+		let network_graph = NetworkGraph::new(chain_hash);
+		assert_eq!(update_network_graph(&network_graph, &snapshot).unwrap(), 1642291930);
+
+		let source = source_graph.read_only();
+		let synced = network_graph.read_only();
+		assert_eq!(source.nodes().len(), synced.nodes().len());
+		assert_eq!(source.channels().len(), synced.channels().len());
+		for (scid, source_channel) in source.channels().iter() {
+			let synced_channel = synced.channels().get(scid).unwrap();
+			assert_eq!(source_channel.node_one, synced_channel.node_one);
+			assert_eq!(source_channel.node_two, synced_channel.node_two);
+			assert_eq!(source_channel.features, synced_channel.features);
+			for direction in 0..2 {
+				let source_info = source_channel.get_directional_info(direction);
+				let synced_info = synced_channel.get_directional_info(direction);
+				assert_eq!(source_info.is_some(), synced_info.is_some());
+				if let (Some(source_info), Some(synced_info)) = (source_info, synced_info) {
+					assert_eq!(source_info.enabled, synced_info.enabled);
+					assert_eq!(source_info.cltv_expiry_delta, synced_info.cltv_expiry_delta);
+					assert_eq!(source_info.htlc_minimum_msat, synced_info.htlc_minimum_msat);
+					assert_eq!(source_info.htlc_maximum_msat, synced_info.htlc_maximum_msat);
+					assert_eq!(source_info.fees, synced_info.fees);
+					assert_eq!(synced_info.last_update, 1642291930 - TIMESTAMP_BACKDATE_SECS);
+					assert!(synced_info.last_update_message.is_none());
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn delta_snapshot_only_includes_recent_updates() {
+		let chain_hash = genesis_block(Network::Bitcoin).header.block_hash();
+		let source_graph = create_test_graph(chain_hash);
+		let full_snapshot = serialize_network_graph(&source_graph, 0, 1642291930);
+		let delta_snapshot = serialize_network_graph(&source_graph, 1642291800, 1642291930);
+		assert!(delta_snapshot.len() < full_snapshot.len());
+
+		let network_graph = NetworkGraph::new(chain_hash);
+		update_network_graph(&network_graph, &delta_snapshot).unwrap();
+		let synced = network_graph.read_only();
+		assert_eq!(synced.channels().len(), 1);
+		let channel = synced.channels().get(&42).unwrap();
+		assert!(channel.get_directional_info(0).is_none());
+		assert!(channel.get_directional_info(1).is_some());
+	}
+
+	#[test]
+	fn snapshot_applies_on_top_of_existing_graph() {
+		let chain_hash = genesis_block(Network::Bitcoin).header.block_hash();
+		let source_graph = create_test_graph(chain_hash);
+		let network_graph = NetworkGraph::new(chain_hash);
+
+		let first_snapshot = serialize_network_graph(&source_graph, 0, 1642291930);
+		update_network_graph(&network_graph, &first_snapshot).unwrap();
+
+		// Announcements we already know about are ignored and later updates supersede ours.
+		source_graph.update_channel_unsigned(&get_update(chain_hash, 42, 0, 1642292000, 2000, OptionalField::Absent)).unwrap();
+		let second_snapshot = serialize_network_graph(&source_graph, 1642291930, 1642292100);
+		assert_eq!(update_network_graph(&network_graph, &second_snapshot).unwrap(), 1642292100);
+
+		let synced = network_graph.read_only();
+		let info = synced.channels().get(&42).unwrap().get_directional_info(0).unwrap();
+		assert_eq!(info.fees.base_msat, 2000);
+		assert_eq!(info.htlc_maximum_msat, None);
+		assert_eq!(network_graph.get_last_rapid_gossip_sync_timestamp(), Some(1642292100));
+	}
+
+	#[test]
+	fn empty_graph_snapshot() {
+		let chain_hash = genesis_block(Network::Bitcoin).header.block_hash();
+		let snapshot = serialize_network_graph(&NetworkGraph::new(chain_hash), 0, 1642291930);
+		// prefix, chain hash, timestamp and three empty counts
+		assert_eq!(snapshot.len(), 4 + 32 + 4 + 4 * 3);
+
+		let network_graph = NetworkGraph::new(chain_hash);
+		assert_eq!(update_network_graph(&network_graph, &snapshot).unwrap(), 1642291930);
+		assert!(network_graph.read_only().channels().is_empty());
+	}
+}
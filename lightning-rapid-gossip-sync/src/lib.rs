@@ -0,0 +1,197 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+#![deny(missing_docs)]
+#![deny(unsafe_code)]
+#![deny(broken_intra_doc_links)]
+#![deny(non_upper_case_globals)]
+#![deny(non_camel_case_types)]
+#![deny(non_snake_case)]
+#![deny(unused_mut)]
+#![deny(unused_variables)]
+#![deny(unused_imports)]
+//! This crate exposes functionality to rapidly sync gossip data, aimed primarily at mobile
+//! devices.
+//!
+//! The server sends a compact response containing differential gossip data. The gossip data is
+//! formatted compactly, omitting signatures and opportunistically incremental where previous
+//! channel updates are known (a mechanism that is enabled when the timestamp of the last known
+//! channel update is communicated). A server may generate such responses from a [`NetworkGraph`]
+//! which it keeps up-to-date via the P2P gossip protocol using
+//! [`generation::serialize_network_graph`].
+//!
+//! The first ever rapid sync should request a snapshot starting at timestamp `0`, with later
+//! syncs using the timestamp returned by (and stored in the [`NetworkGraph`] by) the previous
+//! sync. Once the snapshot has been downloaded, call the network processing function:
+//!
+//! ```
+//! use bitcoin::blockdata::constants::genesis_block;
+//! use bitcoin::network::constants::Network;
+//! use lightning::routing::network_graph::NetworkGraph;
+//!
+//! let block_hash = genesis_block(Network::Bitcoin).header.block_hash();
+//! let network_graph = NetworkGraph::new(block_hash);
+//! let last_sync_timestamp = network_graph.get_last_rapid_gossip_sync_timestamp().unwrap_or(0);
+//! // request a snapshot starting at `last_sync_timestamp` from the server, then:
+//! let new_last_sync_timestamp_result = lightning_rapid_gossip_sync::sync_network_graph_with_file_path(&network_graph, "./rapid_sync.lngossip");
+//! ```
+//!
+//! The primary benefit this syncing mechanism provides is that given a trusted server, a
+//! low-powered client can offload the validation of gossip signatures. This enables a client to
+//! privately calculate routes for payments, and do so much faster and earlier than requiring a full
+//! peer-to-peer gossip sync to complete.
+//!
+//! The reason the rapid sync server requires trust is that it could provide bogus data, though at
+//! worst, all that would result in is a fake network topology, which wouldn't enable the server to
+//! steal or siphon off funds. It could, however, reveal a client's intended payment destinations
+//! as the client may only be able to find routes through channels the server told it about.
+//!
+//! Note that every channel and directional update applied from a snapshot is backdated by a week,
+//! so that any genuine gossip the client later receives from its peers takes precedence.
+//!
+//! [`NetworkGraph`]: lightning::routing::network_graph::NetworkGraph
+//! [`generation::serialize_network_graph`]: crate::generation::serialize_network_graph
+
+// Allow and import test features for benching
+#![cfg_attr(all(test, feature = "_bench_unstable"), feature(test))]
+#[cfg(all(test, feature = "_bench_unstable"))]
+extern crate test;
+
+use std::fs::File;
+
+use lightning::routing::network_graph;
+
+use crate::error::GraphSyncError;
+
+/// Error types that these functions can return
+pub mod error;
+
+/// Core functionality of this crate
+pub mod processing;
+
+/// Generation of rapid sync snapshots from a [`NetworkGraph`]
+///
+/// [`NetworkGraph`]: lightning::routing::network_graph::NetworkGraph
+pub mod generation;
+
+/// Sync gossip data from a file
+/// Returns the last sync timestamp to be used the next time rapid sync data is queried.
+///
+/// `network_graph`: The network graph to apply the updates to
+///
+/// `sync_path`: Path to the file where the gossip update data is located
+///
+pub fn sync_network_graph_with_file_path(
+	network_graph: &network_graph::NetworkGraph,
+	sync_path: &str,
+) -> Result<u32, GraphSyncError> {
+	let mut file = File::open(sync_path)?;
+	processing::update_network_graph_from_byte_stream(&network_graph, &mut file)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use bitcoin::blockdata::constants::genesis_block;
+	use bitcoin::network::constants::Network;
+
+	use lightning::ln::msgs::DecodeError;
+	use lightning::routing::network_graph::NetworkGraph;
+
+	use crate::error::GraphSyncError;
+	use crate::sync_network_graph_with_file_path;
+
+	#[test]
+	fn test_sync_from_file() {
+		struct FileSyncTest {
+			directory: String,
+		}
+
+		impl FileSyncTest {
+			fn new(tmp_directory: &str, valid_response: &[u8]) -> FileSyncTest {
+				let test = FileSyncTest { directory: tmp_directory.to_owned() };
+
+				let graph_sync_test_directory = test.get_test_directory();
+				fs::create_dir_all(graph_sync_test_directory).unwrap();
+
+				let graph_sync_test_file = test.get_test_file_path();
+				fs::write(&graph_sync_test_file, valid_response).unwrap();
+
+				test
+			}
+			fn get_test_directory(&self) -> String {
+				self.directory.clone() + "/graph-sync-tests"
+			}
+			fn get_test_file_path(&self) -> String {
+				self.get_test_directory() + "/test_data.lngossip"
+			}
+		}
+
+		impl Drop for FileSyncTest {
+			fn drop(&mut self) {
+				fs::remove_dir_all(self.directory.clone()).unwrap();
+			}
+		}
+
+		let block_hash = genesis_block(Network::Bitcoin).header.block_hash();
+		let source_graph = crate::generation::tests::create_test_graph(block_hash);
+		let valid_response = crate::generation::serialize_network_graph(&source_graph, 0, 1642291930);
+
+		let tmp_directory = "./rapid-gossip-sync-tests-tmp";
+		let sync_test = FileSyncTest::new(tmp_directory, &valid_response);
+		let graph_sync_test_file = sync_test.get_test_file_path();
+
+		let network_graph = NetworkGraph::new(block_hash);
+		assert_eq!(network_graph.read_only().channels().len(), 0);
+
+		let sync_result = sync_network_graph_with_file_path(&network_graph, &graph_sync_test_file);
+		if sync_result.is_err() {
+			panic!("Unexpected sync result: {:?}", sync_result)
+		}
+
+		assert_eq!(sync_result.unwrap(), 1642291930);
+		assert_eq!(network_graph.read_only().channels().len(), 2);
+		assert_eq!(network_graph.get_last_rapid_gossip_sync_timestamp(), Some(1642291930));
+	}
+
+	#[test]
+	fn test_sync_from_missing_file() {
+		let block_hash = genesis_block(Network::Bitcoin).header.block_hash();
+		let network_graph = NetworkGraph::new(block_hash);
+
+		let sync_result = sync_network_graph_with_file_path(&network_graph, "./rapid-gossip-sync-tests-missing/test_data.lngossip");
+		match sync_result {
+			Err(GraphSyncError::DecodeError(DecodeError::Io(_))) => {},
+			_ => panic!("Unexpected sync result: {:?}", sync_result),
+		}
+		assert!(network_graph.get_last_rapid_gossip_sync_timestamp().is_none());
+	}
+}
+
+#[cfg(all(test, feature = "_bench_unstable"))]
+pub mod bench {
+	use test::Bencher;
+
+	use bitcoin::blockdata::constants::genesis_block;
+	use bitcoin::network::constants::Network;
+
+	use lightning::routing::network_graph::NetworkGraph;
+
+	#[bench]
+	fn bench_reading_snapshot(b: &mut Bencher) {
+		let block_hash = genesis_block(Network::Bitcoin).header.block_hash();
+		let source_graph = crate::generation::tests::create_test_graph(block_hash);
+		let snapshot = crate::generation::serialize_network_graph(&source_graph, 0, 1642291930);
+		b.iter(|| {
+			let network_graph = NetworkGraph::new(block_hash);
+			crate::processing::update_network_graph(&network_graph, &snapshot).unwrap();
+		});
+	}
+}
@@ -0,0 +1,378 @@
+use std::cmp::max;
+use std::io;
+use std::io::Read;
+
+use bitcoin::hash_types::BlockHash;
+use bitcoin::secp256k1::key::PublicKey;
+
+use lightning::ln::msgs::{
+	DecodeError, ErrorAction, LightningError, OptionalField, UnsignedChannelUpdate,
+};
+use lightning::routing::network_graph;
+use lightning::util::ser::{BigSize, Readable};
+
+use crate::error::GraphSyncError;
+
+/// The purpose of this prefix is to identify the serialization format, should other rapid gossip
+/// sync formats arise in the future.
+///
+/// The fourth byte is the protocol version in case our format gets updated.
+pub(crate) const GOSSIP_PREFIX: [u8; 4] = [76, 68, 75, 1];
+
+/// The amount of time (in seconds) the applied timestamps are backdated by, relative to the
+/// latest timestamp seen by the server. This ensures that any genuine gossip received later on
+/// will supersede the data we apply here.
+pub(crate) const TIMESTAMP_BACKDATE_SECS: u32 = 24 * 3600 * 7;
+
+/// Flag in a directional update's channel flags indicating that the update only carries fields
+/// which changed relative to the data the client already has, rather than deviations from the
+/// snapshot's defaults.
+pub(crate) const INCREMENTAL_UPDATE_FLAG: u8 = 0b_1000_0000;
+pub(crate) const CLTV_EXPIRY_DELTA_FLAG: u8 = 0b_0100_0000;
+pub(crate) const HTLC_MINIMUM_MSAT_FLAG: u8 = 0b_0010_0000;
+pub(crate) const FEE_BASE_MSAT_FLAG: u8 = 0b_0001_0000;
+pub(crate) const FEE_PROPORTIONAL_MILLIONTHS_FLAG: u8 = 0b_0000_1000;
+pub(crate) const HTLC_MAXIMUM_MSAT_FLAG: u8 = 0b_0000_0100;
+/// The bits of a directional update's channel flags which are copied into the synthetic
+/// `channel_update` verbatim (ie the direction and disabled bits).
+pub(crate) const STANDARD_CHANNEL_FLAGS_MASK: u8 = 0b_0000_0011;
+
+/// Sentinel `htlc_maximum_msat` value indicating that no maximum was announced.
+pub(crate) const HTLC_MAXIMUM_MSAT_ABSENT: u64 = u64::max_value();
+
+/// We disallow more than this many node ids to be pre-allocated, as the count is read from an
+/// untrusted source.
+const MAX_INITIAL_NODE_ID_VECTOR_CAPACITY: u32 = 50_000;
+
+/// Update network graph from binary data.
+/// Returns the last sync timestamp to be used the next time rapid sync data is queried.
+///
+/// `network_graph`: network graph to be updated
+///
+/// `update_data`: `&[u8]` binary stream that comprises the update data
+pub fn update_network_graph(network_graph: &network_graph::NetworkGraph, update_data: &[u8]) -> Result<u32, GraphSyncError> {
+	let mut read_cursor = io::Cursor::new(update_data);
+	update_network_graph_from_byte_stream(&network_graph, &mut read_cursor)
+}
+
+pub(crate) fn update_network_graph_from_byte_stream<R: Read>(network_graph: &network_graph::NetworkGraph, mut read_cursor: &mut R) -> Result<u32, GraphSyncError> {
+	let mut prefix = [0u8; 4];
+	read_cursor.read_exact(&mut prefix).map_err(DecodeError::from)?;
+
+	match prefix {
+		GOSSIP_PREFIX => {}
+		_ => {
+			return Err(DecodeError::UnknownVersion.into());
+		}
+	};
+
+	let chain_hash: BlockHash = Readable::read(read_cursor)?;
+	if chain_hash != network_graph.get_genesis_hash() {
+		return Err(LightningError {
+			err: "Rapid gossip sync data's chain hash does not match the network graph's".to_owned(),
+			action: ErrorAction::IgnoreError,
+		}.into());
+	}
+
+	let latest_seen_timestamp: u32 = Readable::read(read_cursor)?;
+	// backdate the applied timestamp by a week
+	let backdated_timestamp = latest_seen_timestamp.saturating_sub(TIMESTAMP_BACKDATE_SECS);
+
+	let node_id_count: u32 = Readable::read(read_cursor)?;
+	let mut node_ids: Vec<PublicKey> = Vec::with_capacity(std::cmp::min(
+		node_id_count,
+		MAX_INITIAL_NODE_ID_VECTOR_CAPACITY,
+	) as usize);
+	for _ in 0..node_id_count {
+		let current_node_id = Readable::read(read_cursor)?;
+		node_ids.push(current_node_id);
+	}
+
+	let mut previous_scid: u64 = 0;
+	let announcement_count: u32 = Readable::read(read_cursor)?;
+	for _ in 0..announcement_count {
+		let features = Readable::read(read_cursor)?;
+
+		// handle SCID
+		let scid_delta: BigSize = Readable::read(read_cursor)?;
+		let short_channel_id = previous_scid
+			.checked_add(scid_delta.0)
+			.ok_or(DecodeError::InvalidValue)?;
+		previous_scid = short_channel_id;
+
+		let node_id_1_index: BigSize = Readable::read(read_cursor)?;
+		let node_id_2_index: BigSize = Readable::read(read_cursor)?;
+		if max(node_id_1_index.0, node_id_2_index.0) >= node_id_count as u64 {
+			return Err(DecodeError::InvalidValue.into());
+		};
+		let node_id_1 = node_ids[node_id_1_index.0 as usize];
+		let node_id_2 = node_ids[node_id_2_index.0 as usize];
+
+		let announcement_result = network_graph.add_channel_from_partial_announcement(
+			short_channel_id,
+			backdated_timestamp as u64,
+			features,
+			node_id_1,
+			node_id_2,
+		);
+		if let Err(lightning_error) = announcement_result {
+			if let ErrorAction::IgnoreDuplicateGossip = lightning_error.action {
+				// everything is fine, just a duplicate channel announcement
+			} else {
+				return Err(lightning_error.into());
+			}
+		}
+	}
+
+	previous_scid = 0; // updates start at a new scid
+
+	let update_count: u32 = Readable::read(read_cursor)?;
+	if update_count == 0 {
+		network_graph.set_last_rapid_gossip_sync_timestamp(latest_seen_timestamp);
+		return Ok(latest_seen_timestamp);
+	}
+
+	// obtain default values for non-incremental updates
+	let default_cltv_expiry_delta: u16 = Readable::read(&mut read_cursor)?;
+	let default_htlc_minimum_msat: u64 = Readable::read(&mut read_cursor)?;
+	let default_fee_base_msat: u32 = Readable::read(&mut read_cursor)?;
+	let default_fee_proportional_millionths: u32 = Readable::read(&mut read_cursor)?;
+	let tentative_default_htlc_maximum_msat: u64 = Readable::read(&mut read_cursor)?;
+	let default_htlc_maximum_msat = if tentative_default_htlc_maximum_msat == HTLC_MAXIMUM_MSAT_ABSENT {
+		OptionalField::Absent
+	} else {
+		OptionalField::Present(tentative_default_htlc_maximum_msat)
+	};
+
+	for _ in 0..update_count {
+		let scid_delta: BigSize = Readable::read(read_cursor)?;
+		let short_channel_id = previous_scid
+			.checked_add(scid_delta.0)
+			.ok_or(DecodeError::InvalidValue)?;
+		previous_scid = short_channel_id;
+
+		let channel_flags: u8 = Readable::read(read_cursor)?;
+
+		// flags are always sent in full, and hence always need updating
+		let standard_channel_flags = channel_flags & STANDARD_CHANNEL_FLAGS_MASK;
+
+		let mut synthetic_update = if channel_flags & INCREMENTAL_UPDATE_FLAG == 0 {
+			// full update, field flags will indicate deviations from the default
+			UnsignedChannelUpdate {
+				chain_hash,
+				short_channel_id,
+				timestamp: backdated_timestamp,
+				flags: standard_channel_flags,
+				cltv_expiry_delta: default_cltv_expiry_delta,
+				htlc_minimum_msat: default_htlc_minimum_msat,
+				htlc_maximum_msat: default_htlc_maximum_msat.clone(),
+				fee_base_msat: default_fee_base_msat,
+				fee_proportional_millionths: default_fee_proportional_millionths,
+				excess_data: vec![],
+			}
+		} else {
+			// incremental update, field flags will indicate mutated values
+			let read_only_network_graph = network_graph.read_only();
+			let channel = read_only_network_graph
+				.channels()
+				.get(&short_channel_id)
+				.ok_or(LightningError {
+					err: "Couldn't find channel for update".to_owned(),
+					action: ErrorAction::IgnoreError,
+				})?;
+
+			let directional_info = channel
+				.get_directional_info(channel_flags)
+				.ok_or(LightningError {
+					err: "Couldn't find previous directional data for update".to_owned(),
+					action: ErrorAction::IgnoreError,
+				})?;
+
+			let htlc_maximum_msat =
+				if let Some(htlc_maximum_msat) = directional_info.htlc_maximum_msat {
+					OptionalField::Present(htlc_maximum_msat)
+				} else {
+					OptionalField::Absent
+				};
+
+			UnsignedChannelUpdate {
+				chain_hash,
+				short_channel_id,
+				timestamp: backdated_timestamp,
+				flags: standard_channel_flags,
+				cltv_expiry_delta: directional_info.cltv_expiry_delta,
+				htlc_minimum_msat: directional_info.htlc_minimum_msat,
+				htlc_maximum_msat,
+				fee_base_msat: directional_info.fees.base_msat,
+				fee_proportional_millionths: directional_info.fees.proportional_millionths,
+				excess_data: vec![],
+			}
+		};
+
+		if channel_flags & CLTV_EXPIRY_DELTA_FLAG > 0 {
+			let cltv_expiry_delta: u16 = Readable::read(read_cursor)?;
+			synthetic_update.cltv_expiry_delta = cltv_expiry_delta;
+		}
+
+		if channel_flags & HTLC_MINIMUM_MSAT_FLAG > 0 {
+			let htlc_minimum_msat: u64 = Readable::read(read_cursor)?;
+			synthetic_update.htlc_minimum_msat = htlc_minimum_msat;
+		}
+
+		if channel_flags & FEE_BASE_MSAT_FLAG > 0 {
+			let fee_base_msat: u32 = Readable::read(read_cursor)?;
+			synthetic_update.fee_base_msat = fee_base_msat;
+		}
+
+		if channel_flags & FEE_PROPORTIONAL_MILLIONTHS_FLAG > 0 {
+			let fee_proportional_millionths: u32 = Readable::read(read_cursor)?;
+			synthetic_update.fee_proportional_millionths = fee_proportional_millionths;
+		}
+
+		if channel_flags & HTLC_MAXIMUM_MSAT_FLAG > 0 {
+			let tentative_htlc_maximum_msat: u64 = Readable::read(read_cursor)?;
+			synthetic_update.htlc_maximum_msat = if tentative_htlc_maximum_msat == HTLC_MAXIMUM_MSAT_ABSENT {
+				OptionalField::Absent
+			} else {
+				OptionalField::Present(tentative_htlc_maximum_msat)
+			};
+		}
+
+		match network_graph.update_channel_unsigned(&synthetic_update) {
+			Ok(_) => {},
+			// We may already have received a more recent update for this channel over the P2P
+			// network, in which case the (backdated) synthetic update is simply ignored.
+			Err(LightningError { action: ErrorAction::IgnoreDuplicateGossip, .. }) => {},
+			Err(LightningError { action: ErrorAction::IgnoreAndLog(_), .. }) => {},
+			Err(e) => return Err(e.into()),
+		}
+	}
+
+	network_graph.set_last_rapid_gossip_sync_timestamp(latest_seen_timestamp);
+	Ok(latest_seen_timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+	use bitcoin::blockdata::constants::genesis_block;
+	use bitcoin::network::constants::Network;
+
+	use lightning::ln::msgs::DecodeError;
+	use lightning::routing::network_graph::NetworkGraph;
+	use lightning::util::ser::{BigSize, Writeable};
+
+	use crate::error::GraphSyncError;
+	use crate::generation::serialize_network_graph;
+	use crate::generation::tests::create_test_graph;
+	use crate::processing::{update_network_graph, GOSSIP_PREFIX, INCREMENTAL_UPDATE_FLAG, FEE_BASE_MSAT_FLAG};
+
+	#[test]
+	fn network_graph_fails_to_update_from_clipped_input() {
+		let chain_hash = genesis_block(Network::Bitcoin).header.block_hash();
+		let snapshot = serialize_network_graph(&create_test_graph(chain_hash), 0, 1642291930);
+
+		for clipped_length in 0..snapshot.len() {
+			let network_graph = NetworkGraph::new(chain_hash);
+			let update_result = update_network_graph(&network_graph, &snapshot[..clipped_length]);
+			match update_result {
+				Err(GraphSyncError::DecodeError(DecodeError::ShortRead)) => {},
+				_ => panic!("Unexpected update result for length {}: {:?}", clipped_length, update_result),
+			}
+			assert!(network_graph.get_last_rapid_gossip_sync_timestamp().is_none());
+		}
+	}
+
+	#[test]
+	fn network_graph_rejects_unknown_version() {
+		let chain_hash = genesis_block(Network::Bitcoin).header.block_hash();
+		let mut snapshot = serialize_network_graph(&create_test_graph(chain_hash), 0, 1642291930);
+		snapshot[3] = GOSSIP_PREFIX[3] + 1;
+
+		let network_graph = NetworkGraph::new(chain_hash);
+		match update_network_graph(&network_graph, &snapshot) {
+			Err(GraphSyncError::DecodeError(DecodeError::UnknownVersion)) => {},
+			res => panic!("Unexpected update result: {:?}", res),
+		}
+	}
+
+	#[test]
+	fn network_graph_rejects_other_chains() {
+		let snapshot = serialize_network_graph(&create_test_graph(genesis_block(Network::Testnet).header.block_hash()), 0, 1642291930);
+
+		let network_graph = NetworkGraph::new(genesis_block(Network::Bitcoin).header.block_hash());
+		match update_network_graph(&network_graph, &snapshot) {
+			Err(GraphSyncError::LightningError(_)) => {},
+			res => panic!("Unexpected update result: {:?}", res),
+		}
+		assert!(network_graph.read_only().channels().is_empty());
+	}
+
+	/// Builds a snapshot without announcements carrying a single update for channel 42's first
+	/// direction.
+	fn single_update_snapshot(network_graph: &NetworkGraph, channel_flags: u8, fee_base_msat: u32) -> Vec<u8> {
+		let mut snapshot = Vec::new();
+		snapshot.extend_from_slice(&GOSSIP_PREFIX);
+		network_graph.get_genesis_hash().write(&mut snapshot).unwrap();
+		1642292930u32.write(&mut snapshot).unwrap();
+		0u32.write(&mut snapshot).unwrap(); // node ids
+		0u32.write(&mut snapshot).unwrap(); // announcements
+		1u32.write(&mut snapshot).unwrap(); // updates
+		// defaults
+		0u16.write(&mut snapshot).unwrap();
+		0u64.write(&mut snapshot).unwrap();
+		0u32.write(&mut snapshot).unwrap();
+		0u32.write(&mut snapshot).unwrap();
+		u64::max_value().write(&mut snapshot).unwrap();
+		BigSize(42).write(&mut snapshot).unwrap();
+		(channel_flags | FEE_BASE_MSAT_FLAG).write(&mut snapshot).unwrap();
+		fee_base_msat.write(&mut snapshot).unwrap();
+		snapshot
+	}
+
+	#[test]
+	fn incremental_update_keeps_previous_values() {
+		let chain_hash = genesis_block(Network::Bitcoin).header.block_hash();
+		let network_graph = NetworkGraph::new(chain_hash);
+		update_network_graph(&network_graph, &serialize_network_graph(&create_test_graph(chain_hash), 0, 1642291930)).unwrap();
+
+		let snapshot = single_update_snapshot(&network_graph, INCREMENTAL_UPDATE_FLAG, 7000);
+		assert_eq!(update_network_graph(&network_graph, &snapshot).unwrap(), 1642292930);
+
+		let read_only_graph = network_graph.read_only();
+		let info = read_only_graph.channels().get(&42).unwrap().get_directional_info(0).unwrap();
+		assert_eq!(info.fees.base_msat, 7000);
+		assert_eq!(info.fees.proportional_millionths, 100);
+		assert_eq!(info.cltv_expiry_delta, 144);
+		assert_eq!(info.htlc_maximum_msat, Some(100_000_000));
+	}
+
+	#[test]
+	fn full_update_uses_snapshot_defaults() {
+		let chain_hash = genesis_block(Network::Bitcoin).header.block_hash();
+		let network_graph = NetworkGraph::new(chain_hash);
+		update_network_graph(&network_graph, &serialize_network_graph(&create_test_graph(chain_hash), 0, 1642291930)).unwrap();
+
+		let snapshot = single_update_snapshot(&network_graph, 0, 7000);
+		update_network_graph(&network_graph, &snapshot).unwrap();
+
+		let read_only_graph = network_graph.read_only();
+		let info = read_only_graph.channels().get(&42).unwrap().get_directional_info(0).unwrap();
+		assert_eq!(info.fees.base_msat, 7000);
+		assert_eq!(info.fees.proportional_millionths, 0);
+		assert_eq!(info.cltv_expiry_delta, 0);
+		assert_eq!(info.htlc_maximum_msat, None);
+	}
+
+	#[test]
+	fn incremental_update_requires_known_channel() {
+		let chain_hash = genesis_block(Network::Bitcoin).header.block_hash();
+		let network_graph = NetworkGraph::new(chain_hash);
+
+		let snapshot = single_update_snapshot(&network_graph, INCREMENTAL_UPDATE_FLAG, 7000);
+		match update_network_graph(&network_graph, &snapshot) {
+			Err(GraphSyncError::LightningError(_)) => {},
+			res => panic!("Unexpected update result: {:?}", res),
+		}
+		assert!(network_graph.get_last_rapid_gossip_sync_timestamp().is_none());
+	}
+}
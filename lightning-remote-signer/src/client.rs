@@ -0,0 +1,408 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! The client side of the remote signer protocol, implementing [`KeysInterface`] and [`Sign`] by
+//! forwarding all requests to a [`SignerServer`].
+//!
+//! [`SignerServer`]: crate::SignerServer
+
+use bitcoin::bech32::u5;
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::hashes::sha256::Hash as Sha256;
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::Transaction;
+use bitcoin::secp256k1;
+use bitcoin::secp256k1::ecdh::SharedSecret;
+use bitcoin::secp256k1::key::{PublicKey, SecretKey};
+use bitcoin::secp256k1::recovery::{RecoverableSignature, RecoveryId};
+use bitcoin::secp256k1::{Secp256k1, Signature};
+
+use lightning::chain::keysinterface::{BaseSign, KeyMaterial, KeysInterface, Recipient, Sign, SignerError};
+use lightning::ln::chan_utils::{ChannelPublicKeys, ChannelTransactionParameters, ClosingTransaction, CommitmentTransaction, HolderCommitmentTransaction, HTLCOutputInCommitment};
+use lightning::ln::msgs::{DecodeError, UnsignedChannelAnnouncement, UnsignedGossipMessage};
+use lightning::ln::script::ShutdownScript;
+use lightning::ln::PaymentPreimage;
+use lightning::util::ser::{Readable, Writeable, Writer};
+
+use crate::protocol::{self, Preimages, Request, Response, PROTOCOL_VERSION};
+
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const SERIALIZATION_VERSION: u8 = 1;
+
+/// A connection to a [`SignerServer`], shared between a [`RemoteKeysManager`] and all of the
+/// [`RemoteSigner`]s it hands out.
+///
+/// [`SignerServer`]: crate::SignerServer
+struct SignerConnection<S: Read + Write> {
+	stream: Mutex<S>,
+}
+
+impl<S: Read + Write> SignerConnection<S> {
+	/// Sends the given request and waits for the server's response.
+	fn call(&self, request: &Request) -> Result<Response, io::Error> {
+		let mut stream = self.stream.lock().unwrap();
+		protocol::write_message(&mut *stream, request)?;
+		protocol::read_message(&mut *stream)
+	}
+
+	/// Sends a request for which the [`BaseSign`] or [`KeysInterface`] method being implemented
+	/// cannot return an error, panicking if the server could not be reached or refused it.
+	fn call_infallible(&self, request: &Request) -> Response {
+		match self.call(request) {
+			Ok(Response::Error {}) => panic!("Remote signer failed to handle an infallible request"),
+			Ok(response) => response,
+			Err(e) => panic!("Failed to reach remote signer: {}", e),
+		}
+	}
+}
+
+fn encode_recipient(recipient: Recipient) -> u8 {
+	match recipient {
+		Recipient::Node => 0,
+		Recipient::PhantomNode => 1,
+	}
+}
+
+/// A [`KeysInterface`] which holds no secrets itself, instead forwarding all requests to a
+/// [`SignerServer`], which may run in a separate, hardened process.
+///
+/// The connection to the server may be any stream, e.g. a [`std::net::TcpStream`] or a
+/// [`std::os::unix::net::UnixStream`]. Requests are sent one at a time over it, so it should not
+/// be used for anything else.
+///
+/// The inbound payment key material and a seed for [`KeysInterface::get_secure_random_bytes`]
+/// are fetched once, when connecting, so those methods never need to reach the server. Other
+/// [`KeysInterface`] and [`BaseSign`] methods which can return an error (e.g. script and signing
+/// methods) return an `Err` if the server cannot be reached or refuses the request. The few which
+/// cannot ([`KeysInterface::get_channel_signer`], [`BaseSign::get_per_commitment_point`] and
+/// [`BaseSign::ready_channel`]) will panic, as there is no way to continue operating a channel
+/// without its signer.
+///
+/// [`SignerServer`]: crate::SignerServer
+pub struct RemoteKeysManager<S: Read + Write> {
+	connection: Arc<SignerConnection<S>>,
+	inbound_payment_key: KeyMaterial,
+	rand_bytes_seed: [u8; 32],
+	rand_bytes_index: AtomicU64,
+}
+
+impl<S: Read + Write> RemoteKeysManager<S> {
+	/// Constructs a new [`RemoteKeysManager`] speaking to a [`SignerServer`] over `stream`,
+	/// checking that the server speaks the same protocol version.
+	///
+	/// [`SignerServer`]: crate::SignerServer
+	pub fn new(stream: S) -> Result<Self, io::Error> {
+		let connection = SignerConnection { stream: Mutex::new(stream) };
+		match connection.call(&Request::Init { version: PROTOCOL_VERSION })? {
+			Response::Init { version } if version == PROTOCOL_VERSION => {},
+			Response::Init { .. } => return Err(io::Error::new(io::ErrorKind::Other, "Remote signer speaks an incompatible protocol version")),
+			_ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected response from remote signer")),
+		}
+		let fetch_bytes = |request| match connection.call(&request)? {
+			Response::Bytes { bytes } => Ok(bytes),
+			_ => Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected response from remote signer")),
+		};
+		let inbound_payment_key = KeyMaterial(fetch_bytes(Request::GetInboundPaymentKeyMaterial {})?);
+		let rand_bytes_seed = fetch_bytes(Request::GetSecureRandomBytes {})?;
+		Ok(Self {
+			connection: Arc::new(connection),
+			inbound_payment_key,
+			rand_bytes_seed,
+			rand_bytes_index: AtomicU64::new(0),
+		})
+	}
+}
+
+impl<S: Read + Write> KeysInterface for RemoteKeysManager<S> {
+	type Signer = RemoteSigner<S>;
+
+	fn get_node_id(&self, recipient: Recipient) -> Result<PublicKey, ()> {
+		match self.connection.call(&Request::GetNodeId { recipient: encode_recipient(recipient) }) {
+			Ok(Response::PublicKey { key }) => Ok(key),
+			_ => Err(()),
+		}
+	}
+
+	fn ecdh(&self, recipient: Recipient, other_key: &PublicKey) -> Result<SharedSecret, ()> {
+		match self.connection.call(&Request::Ecdh { recipient: encode_recipient(recipient), other_key: *other_key }) {
+			Ok(Response::Bytes { bytes }) => Ok(SharedSecret::from(bytes)),
+			_ => Err(()),
+		}
+	}
+
+	fn get_destination_script(&self) -> Result<Script, ()> {
+		match self.connection.call(&Request::GetDestinationScript {}) {
+			Ok(Response::Script { script }) => Ok(script),
+			_ => Err(()),
+		}
+	}
+
+	fn get_shutdown_scriptpubkey(&self) -> Result<ShutdownScript, ()> {
+		match self.connection.call(&Request::GetShutdownScriptpubkey {}) {
+			Ok(Response::ShutdownScript { script }) => Ok(script),
+			_ => Err(()),
+		}
+	}
+
+	fn get_channel_signer(&self, inbound: bool, channel_value_satoshis: u64) -> Self::Signer {
+		match self.connection.call_infallible(&Request::GetChannelSigner { inbound, channel_value_satoshis }) {
+			Response::ChannelSigner { channel_keys_id, pubkeys } => RemoteSigner {
+				connection: Arc::clone(&self.connection),
+				channel_keys_id,
+				channel_value_satoshis,
+				pubkeys,
+				channel_parameters: None,
+			},
+			_ => panic!("Unexpected response from remote signer"),
+		}
+	}
+
+	fn get_secure_random_bytes(&self) -> [u8; 32] {
+		let index = self.rand_bytes_index.fetch_add(1, Ordering::AcqRel);
+		let mut engine = Sha256::engine();
+		engine.input(&self.rand_bytes_seed);
+		engine.input(&index.to_be_bytes());
+		Sha256::from_engine(engine).into_inner()
+	}
+
+	fn read_chan_signer(&self, mut reader: &[u8]) -> Result<Self::Signer, DecodeError> {
+		let version: u8 = Readable::read(&mut reader)?;
+		if version > SERIALIZATION_VERSION {
+			return Err(DecodeError::UnknownVersion);
+		}
+		let channel_keys_id: [u8; 32] = Readable::read(&mut reader)?;
+		let channel_value_satoshis: u64 = Readable::read(&mut reader)?;
+		let pubkeys: ChannelPublicKeys = Readable::read(&mut reader)?;
+		let channel_parameters: Option<ChannelTransactionParameters> = Readable::read(&mut reader)?;
+		if !reader.is_empty() {
+			return Err(DecodeError::InvalidValue);
+		}
+
+		let request = Request::LoadChannelSigner {
+			channel_keys_id, channel_value_satoshis, channel_parameters: channel_parameters.clone(),
+		};
+		match self.connection.call(&request) {
+			Ok(Response::ChannelSigner { channel_keys_id: loaded_keys_id, pubkeys: loaded_pubkeys }) => {
+				if loaded_keys_id != channel_keys_id || loaded_pubkeys != pubkeys {
+					return Err(DecodeError::InvalidValue);
+				}
+			},
+			Ok(_) => return Err(DecodeError::InvalidValue),
+			Err(e) => return Err(DecodeError::Io(e.kind())),
+		}
+		Ok(RemoteSigner {
+			connection: Arc::clone(&self.connection),
+			channel_keys_id,
+			channel_value_satoshis,
+			pubkeys,
+			channel_parameters,
+		})
+	}
+
+	fn sign_invoice(&self, hrp_bytes: &[u8], invoice_data: &[u5], recipient: Recipient) -> Result<RecoverableSignature, ()> {
+		let request = Request::SignInvoice {
+			hrp_bytes: hrp_bytes.to_vec(),
+			invoice_data: invoice_data.iter().map(|c| c.to_u8()).collect(),
+			recipient: encode_recipient(recipient),
+		};
+		match self.connection.call(&request) {
+			Ok(Response::RecoverableSignature { recovery_id, signature }) => {
+				let recovery_id = RecoveryId::from_i32(recovery_id as i32).map_err(|_| ())?;
+				RecoverableSignature::from_compact(&signature, recovery_id).map_err(|_| ())
+			},
+			_ => Err(()),
+		}
+	}
+
+	fn sign_gossip_message(&self, msg: UnsignedGossipMessage) -> Result<Signature, ()> {
+		let request = match msg {
+			UnsignedGossipMessage::ChannelAnnouncement(msg) => Request::SignGossipChannelAnnouncement { msg: msg.clone() },
+			UnsignedGossipMessage::ChannelUpdate(msg) => Request::SignGossipChannelUpdate { msg: msg.clone() },
+			UnsignedGossipMessage::NodeAnnouncement(msg) => Request::SignGossipNodeAnnouncement { msg: msg.clone() },
+		};
+		match self.connection.call(&request) {
+			Ok(Response::Signature { signature }) => Ok(signature),
+			_ => Err(()),
+		}
+	}
+
+	fn sign_message(&self, msg: &[u8]) -> Result<String, ()> {
+		match self.connection.call(&Request::SignMessage { msg: msg.to_vec() }) {
+			Ok(Response::ZBase32Signature { signature }) => Ok(signature),
+			_ => Err(()),
+		}
+	}
+
+	fn get_inbound_payment_key_material(&self) -> KeyMaterial {
+		self.inbound_payment_key.clone()
+	}
+}
+
+/// A [`Sign`] implementation which forwards all requests to the [`SignerServer`] holding the
+/// channel's secrets. See [`RemoteKeysManager`] for details.
+///
+/// Only the channel's public keys and parameters are kept (and serialized) locally.
+///
+/// [`SignerServer`]: crate::SignerServer
+pub struct RemoteSigner<S: Read + Write> {
+	connection: Arc<SignerConnection<S>>,
+	channel_keys_id: [u8; 32],
+	channel_value_satoshis: u64,
+	pubkeys: ChannelPublicKeys,
+	channel_parameters: Option<ChannelTransactionParameters>,
+}
+
+impl<S: Read + Write> Clone for RemoteSigner<S> {
+	fn clone(&self) -> Self {
+		Self {
+			connection: Arc::clone(&self.connection),
+			channel_keys_id: self.channel_keys_id,
+			channel_value_satoshis: self.channel_value_satoshis,
+			pubkeys: self.pubkeys.clone(),
+			channel_parameters: self.channel_parameters.clone(),
+		}
+	}
+}
+
+impl<S: Read + Write> RemoteSigner<S> {
+	fn signature(&self, request: &Request) -> Result<Signature, ()> {
+		match self.connection.call(request) {
+			Ok(Response::Signature { signature }) => Ok(signature),
+			_ => Err(()),
+		}
+	}
+
+	fn signatures(&self, request: &Request) -> Result<(Signature, Vec<Signature>), ()> {
+		match self.connection.call(request) {
+			Ok(Response::Signatures { signature, htlc_signatures }) => Ok((signature, htlc_signatures)),
+			_ => Err(()),
+		}
+	}
+
+	fn validation(&self, request: &Request) -> Result<(), ()> {
+		match self.connection.call(request) {
+			Ok(Response::Ok {}) => Ok(()),
+			_ => Err(()),
+		}
+	}
+}
+
+impl<S: Read + Write> BaseSign for RemoteSigner<S> {
+	fn get_per_commitment_point(&self, idx: u64, _secp_ctx: &Secp256k1<secp256k1::All>) -> PublicKey {
+		match self.connection.call_infallible(&Request::GetPerCommitmentPoint { channel_keys_id: self.channel_keys_id, idx }) {
+			Response::PublicKey { key } => key,
+			_ => panic!("Unexpected response from remote signer"),
+		}
+	}
+
+	fn release_commitment_secret(&self, idx: u64) -> Result<[u8; 32], ()> {
+		match self.connection.call(&Request::ReleaseCommitmentSecret { channel_keys_id: self.channel_keys_id, idx }) {
+			Ok(Response::Bytes { bytes }) => Ok(bytes),
+			_ => Err(()),
+		}
+	}
+
+	fn validate_holder_commitment(&self, holder_tx: &HolderCommitmentTransaction, preimages: Vec<PaymentPreimage>) -> Result<(), ()> {
+		self.validation(&Request::ValidateHolderCommitment {
+			channel_keys_id: self.channel_keys_id, holder_tx: holder_tx.clone(), preimages: Preimages(preimages),
+		})
+	}
+
+	fn pubkeys(&self) -> &ChannelPublicKeys { &self.pubkeys }
+
+	fn channel_keys_id(&self) -> [u8; 32] { self.channel_keys_id }
+
+	fn sign_counterparty_commitment(&self, commitment_tx: &CommitmentTransaction, preimages: Vec<PaymentPreimage>, _secp_ctx: &Secp256k1<secp256k1::All>) -> Result<(Signature, Vec<Signature>), SignerError> {
+		let request = Request::SignCounterpartyCommitment {
+			channel_keys_id: self.channel_keys_id, commitment_tx: commitment_tx.clone(), preimages: Preimages(preimages),
+		};
+		// If the server cannot currently be reached we may be able to sign later, so only a
+		// refusal from the server is treated as a rejection.
+		match self.connection.call(&request) {
+			Ok(Response::Signatures { signature, htlc_signatures }) => Ok((signature, htlc_signatures)),
+			Ok(Response::Unavailable {}) | Err(_) => Err(SignerError::Unavailable),
+			Ok(_) => Err(SignerError::Rejected),
+		}
+	}
+
+	fn validate_counterparty_revocation(&self, idx: u64, secret: &SecretKey) -> Result<(), ()> {
+		self.validation(&Request::ValidateCounterpartyRevocation { channel_keys_id: self.channel_keys_id, idx, secret: *secret })
+	}
+
+	fn sign_holder_commitment_and_htlcs(&self, commitment_tx: &HolderCommitmentTransaction, _secp_ctx: &Secp256k1<secp256k1::All>) -> Result<(Signature, Vec<Signature>), ()> {
+		self.signatures(&Request::SignHolderCommitmentAndHtlcs { channel_keys_id: self.channel_keys_id, commitment_tx: commitment_tx.clone() })
+	}
+
+	#[cfg(feature = "unsafe_revoked_tx_signing")]
+	fn unsafe_sign_holder_commitment_and_htlcs(&self, commitment_tx: &HolderCommitmentTransaction, _secp_ctx: &Secp256k1<secp256k1::All>) -> Result<(Signature, Vec<Signature>), ()> {
+		self.signatures(&Request::UnsafeSignHolderCommitmentAndHtlcs { channel_keys_id: self.channel_keys_id, commitment_tx: commitment_tx.clone() })
+	}
+
+	fn sign_justice_revoked_output(&self, justice_tx: &Transaction, input: usize, amount: u64, per_commitment_key: &SecretKey, _secp_ctx: &Secp256k1<secp256k1::All>) -> Result<Signature, ()> {
+		self.signature(&Request::SignJusticeRevokedOutput {
+			channel_keys_id: self.channel_keys_id, justice_tx: justice_tx.clone(), input: input as u64, amount,
+			per_commitment_key: *per_commitment_key,
+		})
+	}
+
+	fn sign_justice_revoked_htlc(&self, justice_tx: &Transaction, input: usize, amount: u64, per_commitment_key: &SecretKey, htlc: &HTLCOutputInCommitment, _secp_ctx: &Secp256k1<secp256k1::All>) -> Result<Signature, ()> {
+		self.signature(&Request::SignJusticeRevokedHtlc {
+			channel_keys_id: self.channel_keys_id, justice_tx: justice_tx.clone(), input: input as u64, amount,
+			per_commitment_key: *per_commitment_key, htlc: htlc.clone(),
+		})
+	}
+
+	fn sign_counterparty_htlc_transaction(&self, htlc_tx: &Transaction, input: usize, amount: u64, per_commitment_point: &PublicKey, htlc: &HTLCOutputInCommitment, _secp_ctx: &Secp256k1<secp256k1::All>) -> Result<Signature, ()> {
+		self.signature(&Request::SignCounterpartyHtlcTransaction {
+			channel_keys_id: self.channel_keys_id, htlc_tx: htlc_tx.clone(), input: input as u64, amount,
+			per_commitment_point: *per_commitment_point, htlc: htlc.clone(),
+		})
+	}
+
+	fn sign_closing_transaction(&self, closing_tx: &ClosingTransaction, _secp_ctx: &Secp256k1<secp256k1::All>) -> Result<Signature, ()> {
+		self.signature(&Request::SignClosingTransaction {
+			channel_keys_id: self.channel_keys_id,
+			to_holder_value_sat: closing_tx.to_holder_value_sat(),
+			to_counterparty_value_sat: closing_tx.to_counterparty_value_sat(),
+			to_holder_script: closing_tx.to_holder_script().clone(),
+			to_counterparty_script: closing_tx.to_counterparty_script().clone(),
+		})
+	}
+
+	fn sign_channel_announcement(&self, msg: &UnsignedChannelAnnouncement, _secp_ctx: &Secp256k1<secp256k1::All>) -> Result<(Signature, Signature), ()> {
+		match self.connection.call(&Request::SignChannelAnnouncement { channel_keys_id: self.channel_keys_id, msg: msg.clone() }) {
+			Ok(Response::SignaturePair { first, second }) => Ok((first, second)),
+			_ => Err(()),
+		}
+	}
+
+	fn ready_channel(&mut self, channel_parameters: &ChannelTransactionParameters) {
+		match self.connection.call_infallible(&Request::ReadyChannel { channel_keys_id: self.channel_keys_id, channel_parameters: channel_parameters.clone() }) {
+			Response::Ok {} => {},
+			_ => panic!("Unexpected response from remote signer"),
+		}
+		self.channel_parameters = Some(channel_parameters.clone());
+	}
+}
+
+impl<S: Read + Write> Sign for RemoteSigner<S> {}
+
+impl<S: Read + Write> Writeable for RemoteSigner<S> {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+		SERIALIZATION_VERSION.write(writer)?;
+		self.channel_keys_id.write(writer)?;
+		self.channel_value_satoshis.write(writer)?;
+		self.pubkeys.write(writer)?;
+		self.channel_parameters.write(writer)?;
+		Ok(())
+	}
+}
@@ -0,0 +1,173 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+#![deny(missing_docs)]
+#![deny(unsafe_code)]
+#![deny(broken_intra_doc_links)]
+#![deny(non_upper_case_globals)]
+#![deny(non_camel_case_types)]
+#![deny(non_snake_case)]
+#![deny(unused_mut)]
+#![deny(unused_variables)]
+#![deny(unused_imports)]
+//! This crate allows the keys and signing operations of a Lightning node to live in a separate
+//! process (or on a separate machine) from the rest of the node.
+//!
+//! The node uses a [`RemoteKeysManager`] as its [`KeysInterface`], which forwards each request,
+//! including all signing requests made by the [`RemoteSigner`]s it hands out, to a
+//! [`SignerServer`] over any [`Read`] + [`Write`] stream, e.g. a `TcpStream` or `UnixStream`.
+//! The [`SignerServer`] answers them using a [`KeysManager`] built from the node's seed. Neither
+//! the node secret nor any channel secrets ever leave the signer process.
+//!
+//! [`KeysInterface`]: lightning::chain::keysinterface::KeysInterface
+//! [`KeysManager`]: lightning::chain::keysinterface::KeysManager
+//! [`Read`]: std::io::Read
+//! [`Write`]: std::io::Write
+
+mod client;
+mod protocol;
+mod server;
+
+pub use client::{RemoteKeysManager, RemoteSigner};
+pub use protocol::PROTOCOL_VERSION;
+pub use server::SignerServer;
+
+#[cfg(all(test, unix))]
+mod tests {
+	use crate::{RemoteKeysManager, SignerServer};
+
+	use bitcoin::blockdata::script::Builder;
+	use bitcoin::hash_types::Txid;
+	use bitcoin::hashes::Hash;
+	use bitcoin::secp256k1::Secp256k1;
+	use bitcoin::secp256k1::key::{PublicKey, SecretKey};
+
+	use lightning::chain::keysinterface::{BaseSign, KeysInterface, KeysManager, Recipient};
+	use lightning::chain::transaction::OutPoint;
+	use lightning::ln::chan_utils::{ChannelTransactionParameters, ClosingTransaction, CounterpartyChannelTransactionParameters};
+	use lightning::util::ser::Writeable;
+
+	use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+	use std::sync::Arc;
+	use std::thread;
+
+	const SEED: [u8; 32] = [42; 32];
+
+	fn start_server() -> (RemoteKeysManager<UnixStream>, thread::JoinHandle<Result<(), std::io::Error>>) {
+		let (client_stream, server_stream) = UnixStream::pair().unwrap();
+		let server = SignerServer::new(Arc::new(KeysManager::new(&SEED, 42, 42)));
+		let handle = thread::spawn(move || server.handle_connection(server_stream));
+		(RemoteKeysManager::new(client_stream).unwrap(), handle)
+	}
+
+	fn channel_parameters(holder: &KeysManager, holder_signer_keys_id: &[u8; 32]) -> ChannelTransactionParameters {
+		let holder_signer = holder.derive_channel_keys(1_000_000, holder_signer_keys_id);
+		let counterparty_signer = KeysManager::new(&[43; 32], 42, 42).get_channel_signer(true, 1_000_000);
+		ChannelTransactionParameters {
+			holder_pubkeys: holder_signer.pubkeys().clone(),
+			holder_selected_contest_delay: 144,
+			is_outbound_from_holder: true,
+			counterparty_parameters: Some(CounterpartyChannelTransactionParameters {
+				pubkeys: counterparty_signer.pubkeys().clone(),
+				selected_contest_delay: 144,
+			}),
+			funding_outpoint: Some(OutPoint { txid: Txid::from_slice(&[1; 32]).unwrap(), index: 0 }),
+			opt_anchors: None,
+		}
+	}
+
+	#[test]
+	fn test_remote_keys_match_local() {
+		let (remote, handle) = start_server();
+		let local = KeysManager::new(&SEED, 42, 42);
+		let secp_ctx = Secp256k1::new();
+
+		let other_key = PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[1; 32]).unwrap());
+		assert_eq!(remote.get_node_id(Recipient::Node), local.get_node_id(Recipient::Node));
+		assert!(remote.get_node_id(Recipient::PhantomNode).is_err());
+		assert_eq!(remote.ecdh(Recipient::Node, &other_key), local.ecdh(Recipient::Node, &other_key));
+		assert!(remote.ecdh(Recipient::PhantomNode, &other_key).is_err());
+		assert_eq!(remote.sign_message(b"remote message"), local.sign_message(b"remote message"));
+		assert_eq!(remote.get_destination_script().unwrap(), local.get_destination_script().unwrap());
+		assert!(remote.get_shutdown_scriptpubkey().unwrap() == local.get_shutdown_scriptpubkey().unwrap());
+		assert_eq!(remote.get_inbound_payment_key_material().0, local.get_inbound_payment_key_material().0);
+
+		let remote_signer = remote.get_channel_signer(false, 1_000_000);
+		let local_signer = local.derive_channel_keys(1_000_000, &remote_signer.channel_keys_id());
+		assert!(remote_signer.pubkeys() == local_signer.pubkeys());
+		assert_eq!(remote_signer.get_per_commitment_point(1234, &secp_ctx), local_signer.get_per_commitment_point(1234, &secp_ctx));
+		assert_eq!(remote_signer.release_commitment_secret(1234).unwrap(), local_signer.release_commitment_secret(1234).unwrap());
+
+		drop(remote_signer);
+		drop(remote);
+		handle.join().unwrap().unwrap();
+	}
+
+	#[test]
+	fn test_unreachable_server() {
+		let (client_stream, server_stream) = UnixStream::pair().unwrap();
+		let server = SignerServer::new(Arc::new(KeysManager::new(&SEED, 42, 42)));
+		let handle = thread::spawn(move || server.handle_connection(server_stream));
+		let remote = RemoteKeysManager::new(client_stream.try_clone().unwrap()).unwrap();
+		let local = KeysManager::new(&SEED, 42, 42);
+		let remote_signer = remote.get_channel_signer(false, 1_000_000);
+
+		client_stream.shutdown(Shutdown::Both).unwrap();
+		handle.join().unwrap().unwrap();
+
+		// Methods which can fail return an error once the server is gone, while those which were
+		// served at connection time keep working.
+		assert!(remote.get_destination_script().is_err());
+		assert!(remote.get_shutdown_scriptpubkey().is_err());
+		assert!(remote.get_node_id(Recipient::Node).is_err());
+		assert!(remote_signer.release_commitment_secret(1234).is_err());
+		assert_eq!(remote.get_inbound_payment_key_material().0, local.get_inbound_payment_key_material().0);
+		assert_ne!(remote.get_secure_random_bytes(), remote.get_secure_random_bytes());
+	}
+
+	#[test]
+	fn test_remote_signing() {
+		let (remote, handle) = start_server();
+		let local = KeysManager::new(&SEED, 42, 42);
+		let secp_ctx = Secp256k1::new();
+
+		let mut remote_signer = remote.get_channel_signer(false, 1_000_000);
+		let mut local_signer = local.derive_channel_keys(1_000_000, &remote_signer.channel_keys_id());
+		let params = channel_parameters(&local, &remote_signer.channel_keys_id());
+		let closing_tx = ClosingTransaction::new(400_000, 600_000,
+			Builder::new().push_int(0).push_slice(&[2; 20]).into_script(),
+			Builder::new().push_int(0).push_slice(&[3; 20]).into_script(),
+			params.funding_outpoint.unwrap().into_bitcoin_outpoint());
+
+		// The server refuses to sign until it knows the channel parameters.
+		assert!(remote_signer.sign_closing_transaction(&closing_tx, &secp_ctx).is_err());
+
+		remote_signer.ready_channel(&params);
+		local_signer.ready_channel(&params);
+		assert_eq!(remote_signer.sign_closing_transaction(&closing_tx, &secp_ctx),
+			local_signer.sign_closing_transaction(&closing_tx, &secp_ctx));
+
+		// A signer re-loaded from its serialization signs identically.
+		let reloaded_signer = remote.read_chan_signer(&remote_signer.encode()).unwrap();
+		assert!(reloaded_signer.pubkeys() == local_signer.pubkeys());
+		assert_eq!(reloaded_signer.sign_closing_transaction(&closing_tx, &secp_ctx),
+			local_signer.sign_closing_transaction(&closing_tx, &secp_ctx));
+
+		// Signers which the server could not have handed out are rejected.
+		let mut bogus_signer = remote_signer.encode();
+		bogus_signer[1..9].copy_from_slice(&[0xff; 8]);
+		assert!(remote.read_chan_signer(&bogus_signer).is_err());
+
+		drop(remote_signer);
+		drop(reloaded_signer);
+		drop(remote);
+		handle.join().unwrap().unwrap();
+	}
+}
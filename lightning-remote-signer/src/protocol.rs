@@ -0,0 +1,266 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! The messages exchanged between a [`RemoteKeysManager`] and a [`SignerServer`], and their
+//! framing on the wire.
+//!
+//! Each message is sent as a frame consisting of a 4-byte big-endian length followed by that many
+//! bytes of message data. The message data starts with a 2-byte big-endian message type, followed
+//! by the message's fields, each serialized using [`Writeable`].
+//!
+//! Every connection starts with the client sending an [`Request::Init`] message carrying the
+//! [`PROTOCOL_VERSION`] it speaks, to which the server responds with a [`Response::Init`] carrying
+//! its own. If the two differ the server closes the connection. After this, the client sends
+//! requests one at a time, each of which is answered by exactly one response.
+//!
+//! [`RemoteKeysManager`]: crate::RemoteKeysManager
+//! [`SignerServer`]: crate::SignerServer
+
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::Transaction;
+use bitcoin::secp256k1::key::{PublicKey, SecretKey};
+use bitcoin::secp256k1::Signature;
+
+use lightning::ln::chan_utils::{ChannelPublicKeys, ChannelTransactionParameters, CommitmentTransaction, HolderCommitmentTransaction, HTLCOutputInCommitment};
+use lightning::ln::msgs::{DecodeError, UnsignedChannelAnnouncement, UnsignedChannelUpdate, UnsignedNodeAnnouncement};
+use lightning::ln::script::ShutdownScript;
+use lightning::ln::PaymentPreimage;
+use lightning::util::ser::{Readable, Writeable, Writer};
+
+use std::io::{self, Read, Write};
+
+/// The version of the protocol spoken by this version of the crate.
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// The maximum length of a single message frame. Anything larger is rejected to avoid allocating
+/// arbitrary amounts of memory on behalf of the other side.
+const MAX_FRAME_LEN: u32 = 16 * 1024 * 1024;
+
+/// A list of [`PaymentPreimage`]s, as passed to the commitment-related signing requests.
+pub struct Preimages(pub Vec<PaymentPreimage>);
+
+impl Writeable for Preimages {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		(self.0.len() as u16).write(w)?;
+		for preimage in self.0.iter() {
+			preimage.write(w)?;
+		}
+		Ok(())
+	}
+}
+
+impl Readable for Preimages {
+	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let len: u16 = Readable::read(r)?;
+		let mut preimages = Vec::with_capacity(len as usize);
+		for _ in 0..len {
+			preimages.push(Readable::read(r)?);
+		}
+		Ok(Preimages(preimages))
+	}
+}
+
+macro_rules! impl_message_enum {
+	($(#[$attr: meta])* $name: ident, $($(#[$variant_attr: meta])* ($type: literal, $variant: ident { $($(#[$field_attr: meta])* $field: ident : $fieldty: ty),* $(,)* })),* $(,)*) => {
+		$(#[$attr])*
+		pub enum $name {
+			$(
+				$(#[$variant_attr])*
+				$variant {
+					$($(#[$field_attr])* $field: $fieldty),*
+				}
+			),*
+		}
+
+		impl Writeable for $name {
+			fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+				match self {
+					$($name::$variant { $($field),* } => {
+						($type as u16).write(w)?;
+						$($field.write(w)?;)*
+					}),*
+				}
+				Ok(())
+			}
+		}
+
+		impl Readable for $name {
+			fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+				let message_type: u16 = Readable::read(r)?;
+				match message_type {
+					$($type => Ok($name::$variant { $($field: Readable::read(r)?),* }),)*
+					_ => Err(DecodeError::UnknownRequiredFeature),
+				}
+			}
+		}
+	}
+}
+
+impl_message_enum!(
+	/// A request sent from the client to the [`SignerServer`].
+	///
+	/// Requests relating to a specific channel's signer identify it by its `channel_keys_id`, see
+	/// [`BaseSign::channel_keys_id`]. Each maps directly onto the [`KeysInterface`] or [`BaseSign`]
+	/// method of the same name.
+	///
+	/// [`SignerServer`]: crate::SignerServer
+	/// [`BaseSign::channel_keys_id`]: lightning::chain::keysinterface::BaseSign::channel_keys_id
+	/// [`KeysInterface`]: lightning::chain::keysinterface::KeysInterface
+	/// [`BaseSign`]: lightning::chain::keysinterface::BaseSign
+	Request,
+	/// Sent once at the start of each connection.
+	(1, Init { version: u16 }),
+	/// `recipient` is 0 for [`Recipient::Node`] and 1 for [`Recipient::PhantomNode`].
+	///
+	/// [`Recipient::Node`]: lightning::chain::keysinterface::Recipient::Node
+	/// [`Recipient::PhantomNode`]: lightning::chain::keysinterface::Recipient::PhantomNode
+	(2, GetNodeId { recipient: u8 }),
+	(3, GetDestinationScript {}),
+	(4, GetShutdownScriptpubkey {}),
+	(5, GetChannelSigner { inbound: bool, channel_value_satoshis: u64 }),
+	(6, GetSecureRandomBytes {}),
+	/// Re-creates a channel signer previously handed out by [`Request::GetChannelSigner`], e.g.
+	/// after either side restarted.
+	(7, LoadChannelSigner {
+		channel_keys_id: [u8; 32],
+		channel_value_satoshis: u64,
+		channel_parameters: Option<ChannelTransactionParameters>,
+	}),
+	/// The `invoice_data` holds one base32 character per byte and `recipient` is encoded as in
+	/// [`Request::GetNodeId`].
+	(8, SignInvoice { hrp_bytes: Vec<u8>, invoice_data: Vec<u8>, recipient: u8 }),
+	(9, GetInboundPaymentKeyMaterial {}),
+	/// `recipient` is encoded as in [`Request::GetNodeId`].
+	(10, Ecdh { recipient: u8, other_key: PublicKey }),
+	/// The three variants of [`UnsignedGossipMessage`] are sent as separate requests.
+	///
+	/// [`UnsignedGossipMessage`]: lightning::ln::msgs::UnsignedGossipMessage
+	(11, SignGossipChannelAnnouncement { msg: UnsignedChannelAnnouncement }),
+	(12, SignGossipChannelUpdate { msg: UnsignedChannelUpdate }),
+	(13, SignGossipNodeAnnouncement { msg: UnsignedNodeAnnouncement }),
+	(14, SignMessage { msg: Vec<u8> }),
+	(20, GetPerCommitmentPoint { channel_keys_id: [u8; 32], idx: u64 }),
+	(21, ReleaseCommitmentSecret { channel_keys_id: [u8; 32], idx: u64 }),
+	(22, ValidateHolderCommitment {
+		channel_keys_id: [u8; 32],
+		holder_tx: HolderCommitmentTransaction,
+		preimages: Preimages,
+	}),
+	(23, SignCounterpartyCommitment {
+		channel_keys_id: [u8; 32],
+		commitment_tx: CommitmentTransaction,
+		preimages: Preimages,
+	}),
+	(24, ValidateCounterpartyRevocation { channel_keys_id: [u8; 32], idx: u64, secret: SecretKey }),
+	(25, SignHolderCommitmentAndHtlcs { channel_keys_id: [u8; 32], commitment_tx: HolderCommitmentTransaction }),
+	/// Only served if the server was built with the `unsafe_revoked_tx_signing` feature.
+	(26, UnsafeSignHolderCommitmentAndHtlcs { channel_keys_id: [u8; 32], commitment_tx: HolderCommitmentTransaction }),
+	(27, SignJusticeRevokedOutput {
+		channel_keys_id: [u8; 32],
+		justice_tx: Transaction,
+		input: u64,
+		amount: u64,
+		per_commitment_key: SecretKey,
+	}),
+	(28, SignJusticeRevokedHtlc {
+		channel_keys_id: [u8; 32],
+		justice_tx: Transaction,
+		input: u64,
+		amount: u64,
+		per_commitment_key: SecretKey,
+		htlc: HTLCOutputInCommitment,
+	}),
+	(29, SignCounterpartyHtlcTransaction {
+		channel_keys_id: [u8; 32],
+		htlc_tx: Transaction,
+		input: u64,
+		amount: u64,
+		per_commitment_point: PublicKey,
+		htlc: HTLCOutputInCommitment,
+	}),
+	/// The closing transaction is re-built by the server from its outputs and the channel's
+	/// funding outpoint.
+	(30, SignClosingTransaction {
+		channel_keys_id: [u8; 32],
+		to_holder_value_sat: u64,
+		to_counterparty_value_sat: u64,
+		to_holder_script: Script,
+		to_counterparty_script: Script,
+	}),
+	(31, SignChannelAnnouncement { channel_keys_id: [u8; 32], msg: UnsignedChannelAnnouncement }),
+	(32, ReadyChannel { channel_keys_id: [u8; 32], channel_parameters: ChannelTransactionParameters }),
+);
+
+impl_message_enum!(
+	/// A response sent from the [`SignerServer`] to the client.
+	///
+	/// [`SignerServer`]: crate::SignerServer
+	Response,
+	/// Sent in response to [`Request::Init`].
+	(1, Init { version: u16 }),
+	/// Sent if the request failed, e.g. because the signer refused to sign.
+	(2, Error {}),
+	/// Sent if the request succeeded but has no data to return.
+	(3, Ok {}),
+	/// Sent if the signer is not yet able to provide a requested counterparty commitment
+	/// signature, see [`SignerError::Unavailable`].
+	///
+	/// [`SignerError::Unavailable`]: lightning::chain::keysinterface::SignerError::Unavailable
+	(4, Unavailable {}),
+	(5, Script { script: Script }),
+	(6, ChannelSigner { channel_keys_id: [u8; 32], pubkeys: ChannelPublicKeys }),
+	(7, Bytes { bytes: [u8; 32] }),
+	(8, PublicKey { key: PublicKey }),
+	(9, Signature { signature: Signature }),
+	(10, Signatures { signature: Signature, htlc_signatures: Vec<Signature> }),
+	(11, SignaturePair { first: Signature, second: Signature }),
+	/// A recoverable signature, in compact form.
+	(12, RecoverableSignature { recovery_id: u8, signature: [u8; 64] }),
+	/// A zbase32-encoded signature over an arbitrary message, see
+	/// [`KeysInterface::sign_message`].
+	///
+	/// [`KeysInterface::sign_message`]: lightning::chain::keysinterface::KeysInterface::sign_message
+	(13, ZBase32Signature { signature: String }),
+	(14, ShutdownScript { script: ShutdownScript }),
+);
+
+/// Writes the given message to `stream` as a single frame.
+pub(crate) fn write_message<S: Write, M: Writeable>(stream: &mut S, message: &M) -> Result<(), io::Error> {
+	let data = message.encode();
+	if data.len() > MAX_FRAME_LEN as usize {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput, "Message too long"));
+	}
+	let mut frame = Vec::with_capacity(4 + data.len());
+	frame.extend_from_slice(&(data.len() as u32).to_be_bytes());
+	frame.extend_from_slice(&data);
+	stream.write_all(&frame)?;
+	stream.flush()
+}
+
+/// Reads a single frame from `stream`, decoding the message in it.
+///
+/// Returns an [`io::ErrorKind::UnexpectedEof`] error if the stream was closed before a new frame
+/// started.
+pub(crate) fn read_message<S: Read, M: Readable>(stream: &mut S) -> Result<M, io::Error> {
+	let mut len_bytes = [0; 4];
+	stream.read_exact(&mut len_bytes)?;
+	let len = u32::from_be_bytes(len_bytes);
+	if len > MAX_FRAME_LEN {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, "Message too long"));
+	}
+	let mut data = vec![0; len as usize];
+	stream.read_exact(&mut data)?;
+	let mut reader = io::Cursor::new(&data);
+	let message = M::read(&mut reader)
+		.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Failed to decode message"))?;
+	if reader.position() != data.len() as u64 {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, "Trailing data after message"));
+	}
+	Ok(message)
+}
@@ -0,0 +1,338 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! The server side of the remote signer protocol, serving requests from [`RemoteKeysManager`]s
+//! using a [`KeysManager`].
+//!
+//! [`RemoteKeysManager`]: crate::RemoteKeysManager
+
+use bitcoin::bech32::u5;
+use bitcoin::secp256k1;
+use bitcoin::secp256k1::Secp256k1;
+
+use lightning::chain::keysinterface::{BaseSign, InMemorySigner, KeysInterface, KeysManager, Recipient, SignerError};
+use lightning::ln::chan_utils::ClosingTransaction;
+use lightning::ln::msgs::UnsignedGossipMessage;
+
+use crate::protocol::{self, Request, Response, PROTOCOL_VERSION};
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::ops::Deref;
+use std::sync::Mutex;
+
+/// A channel signer which has been handed out to (or re-loaded by) a client.
+struct ServedSigner {
+	signer: InMemorySigner,
+	/// Whether [`BaseSign::ready_channel`] has been called on the signer. Most signing operations
+	/// require the channel parameters to be known, and [`InMemorySigner`] panics if they're not.
+	ready: bool,
+}
+
+fn decode_recipient(recipient: u8) -> Result<Recipient, ()> {
+	match recipient {
+		0 => Ok(Recipient::Node),
+		1 => Ok(Recipient::PhantomNode),
+		_ => Err(()),
+	}
+}
+
+/// Serves the remote signer protocol to [`RemoteKeysManager`] clients, answering their requests
+/// using the wrapped [`KeysManager`] and the [`InMemorySigner`]s it derives.
+///
+/// The server is intended to run in a separate, hardened process (or on a separate machine),
+/// such that the node's secrets never enter the process running the [`ChannelManager`]. Each
+/// connection should be handed to [`Self::handle_connection`], e.g. on its own thread. Multiple
+/// connections may be served concurrently.
+///
+/// Channel signers are kept in memory once handed out. After a restart of the server, clients
+/// re-load them when they deserialize their [`RemoteSigner`]s on startup.
+///
+/// [`RemoteKeysManager`]: crate::RemoteKeysManager
+/// [`RemoteSigner`]: crate::RemoteSigner
+/// [`ChannelManager`]: lightning::ln::channelmanager::ChannelManager
+pub struct SignerServer<K: Deref<Target = KeysManager>> {
+	keys_manager: K,
+	signers: Mutex<HashMap<[u8; 32], ServedSigner>>,
+	secp_ctx: Secp256k1<secp256k1::All>,
+}
+
+impl<K: Deref<Target = KeysManager>> SignerServer<K> {
+	/// Constructs a new [`SignerServer`] serving keys from the given [`KeysManager`].
+	pub fn new(keys_manager: K) -> Self {
+		let mut secp_ctx = Secp256k1::new();
+		secp_ctx.seeded_randomize(&keys_manager.get_secure_random_bytes());
+		Self { keys_manager, signers: Mutex::new(HashMap::new()), secp_ctx }
+	}
+
+	/// Serves requests from a single client connected via `stream` until it disconnects.
+	///
+	/// Returns `Ok(())` once the client closes the connection, or an error if the client speaks
+	/// an incompatible protocol version or the connection fails otherwise.
+	pub fn handle_connection<S: Read + Write>(&self, mut stream: S) -> Result<(), io::Error> {
+		match protocol::read_message(&mut stream)? {
+			Request::Init { version } => {
+				protocol::write_message(&mut stream, &Response::Init { version: PROTOCOL_VERSION })?;
+				if version != PROTOCOL_VERSION {
+					return Err(io::Error::new(io::ErrorKind::Other, "Client speaks an incompatible protocol version"));
+				}
+			},
+			_ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Client did not send an Init message")),
+		}
+
+		loop {
+			let request = match protocol::read_message(&mut stream) {
+				Ok(request) => request,
+				Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+				Err(e) => return Err(e),
+			};
+			let response = self.handle_request(request);
+			protocol::write_message(&mut stream, &response)?;
+		}
+	}
+
+	fn handle_request(&self, request: Request) -> Response {
+		match request {
+			Request::Init { .. } => Response::Error {},
+			Request::GetNodeId { recipient } => {
+				match decode_recipient(recipient).and_then(|recipient| self.keys_manager.get_node_id(recipient)) {
+					Ok(key) => Response::PublicKey { key },
+					Err(()) => Response::Error {},
+				}
+			},
+			Request::GetDestinationScript {} => {
+				match self.keys_manager.get_destination_script() {
+					Ok(script) => Response::Script { script },
+					Err(()) => Response::Error {},
+				}
+			},
+			Request::GetShutdownScriptpubkey {} => {
+				match self.keys_manager.get_shutdown_scriptpubkey() {
+					Ok(script) => Response::ShutdownScript { script },
+					Err(()) => Response::Error {},
+				}
+			},
+			Request::GetChannelSigner { inbound, channel_value_satoshis } => {
+				let signer = self.keys_manager.get_channel_signer(inbound, channel_value_satoshis);
+				self.add_signer(signer, false)
+			},
+			Request::GetSecureRandomBytes {} => {
+				Response::Bytes { bytes: self.keys_manager.get_secure_random_bytes() }
+			},
+			Request::LoadChannelSigner { channel_keys_id, channel_value_satoshis, channel_parameters } => {
+				// `KeysManager::derive_channel_keys` panics if given an id it could not have
+				// generated, so check it first.
+				let mut child_index = [0; 8];
+				child_index.copy_from_slice(&channel_keys_id[..8]);
+				if u64::from_be_bytes(child_index) > std::u32::MAX as u64 {
+					return Response::Error {};
+				}
+				let mut signer = self.keys_manager.derive_channel_keys(channel_value_satoshis, &channel_keys_id);
+				match channel_parameters {
+					Some(channel_parameters) => {
+						if !channel_parameters.is_populated() {
+							return Response::Error {};
+						}
+						signer.ready_channel(&channel_parameters);
+						self.add_signer(signer, true)
+					},
+					None => self.add_signer(signer, false),
+				}
+			},
+			Request::SignInvoice { hrp_bytes, invoice_data, recipient } => {
+				let recipient = match decode_recipient(recipient) {
+					Ok(recipient) => recipient,
+					Err(()) => return Response::Error {},
+				};
+				let invoice_data: Result<Vec<u5>, _> = invoice_data.iter().map(|c| u5::try_from_u8(*c)).collect();
+				let invoice_data = match invoice_data {
+					Ok(invoice_data) => invoice_data,
+					Err(_) => return Response::Error {},
+				};
+				match self.keys_manager.sign_invoice(&hrp_bytes, &invoice_data, recipient) {
+					Ok(signature) => {
+						let (recovery_id, signature) = signature.serialize_compact();
+						Response::RecoverableSignature { recovery_id: recovery_id.to_i32() as u8, signature }
+					},
+					Err(()) => Response::Error {},
+				}
+			},
+			Request::GetInboundPaymentKeyMaterial {} => {
+				Response::Bytes { bytes: self.keys_manager.get_inbound_payment_key_material().0 }
+			},
+			Request::Ecdh { recipient, other_key } => {
+				match decode_recipient(recipient).and_then(|recipient| self.keys_manager.ecdh(recipient, &other_key)) {
+					Ok(shared_secret) => {
+						let mut bytes = [0; 32];
+						bytes.copy_from_slice(&shared_secret[..]);
+						Response::Bytes { bytes }
+					},
+					Err(()) => Response::Error {},
+				}
+			},
+			Request::SignGossipChannelAnnouncement { msg } => {
+				self.sign_gossip_message(UnsignedGossipMessage::ChannelAnnouncement(&msg))
+			},
+			Request::SignGossipChannelUpdate { msg } => {
+				self.sign_gossip_message(UnsignedGossipMessage::ChannelUpdate(&msg))
+			},
+			Request::SignGossipNodeAnnouncement { msg } => {
+				self.sign_gossip_message(UnsignedGossipMessage::NodeAnnouncement(&msg))
+			},
+			Request::SignMessage { msg } => {
+				match self.keys_manager.sign_message(&msg) {
+					Ok(signature) => Response::ZBase32Signature { signature },
+					Err(()) => Response::Error {},
+				}
+			},
+			Request::GetPerCommitmentPoint { channel_keys_id, idx } => {
+				self.with_signer(&channel_keys_id, false, |signer| {
+					Response::PublicKey { key: signer.signer.get_per_commitment_point(idx, &self.secp_ctx) }
+				})
+			},
+			Request::ReleaseCommitmentSecret { channel_keys_id, idx } => {
+				self.with_signer(&channel_keys_id, false, |signer| {
+					match signer.signer.release_commitment_secret(idx) {
+						Ok(bytes) => Response::Bytes { bytes },
+						Err(()) => Response::Error {},
+					}
+				})
+			},
+			Request::ValidateHolderCommitment { channel_keys_id, holder_tx, preimages } => {
+				self.with_signer(&channel_keys_id, true, |signer| {
+					match signer.signer.validate_holder_commitment(&holder_tx, preimages.0) {
+						Ok(()) => Response::Ok {},
+						Err(()) => Response::Error {},
+					}
+				})
+			},
+			Request::SignCounterpartyCommitment { channel_keys_id, commitment_tx, preimages } => {
+				self.with_signer(&channel_keys_id, true, |signer| {
+					match signer.signer.sign_counterparty_commitment(&commitment_tx, preimages.0, &self.secp_ctx) {
+						Ok((signature, htlc_signatures)) => Response::Signatures { signature, htlc_signatures },
+						Err(SignerError::Unavailable) => Response::Unavailable {},
+						Err(SignerError::Rejected) => Response::Error {},
+					}
+				})
+			},
+			Request::ValidateCounterpartyRevocation { channel_keys_id, idx, secret } => {
+				self.with_signer(&channel_keys_id, true, |signer| {
+					match signer.signer.validate_counterparty_revocation(idx, &secret) {
+						Ok(()) => Response::Ok {},
+						Err(()) => Response::Error {},
+					}
+				})
+			},
+			Request::SignHolderCommitmentAndHtlcs { channel_keys_id, commitment_tx } => {
+				self.with_signer(&channel_keys_id, true, |signer| {
+					match signer.signer.sign_holder_commitment_and_htlcs(&commitment_tx, &self.secp_ctx) {
+						Ok((signature, htlc_signatures)) => Response::Signatures { signature, htlc_signatures },
+						Err(()) => Response::Error {},
+					}
+				})
+			},
+			#[cfg(feature = "unsafe_revoked_tx_signing")]
+			Request::UnsafeSignHolderCommitmentAndHtlcs { channel_keys_id, commitment_tx } => {
+				self.with_signer(&channel_keys_id, true, |signer| {
+					match signer.signer.unsafe_sign_holder_commitment_and_htlcs(&commitment_tx, &self.secp_ctx) {
+						Ok((signature, htlc_signatures)) => Response::Signatures { signature, htlc_signatures },
+						Err(()) => Response::Error {},
+					}
+				})
+			},
+			#[cfg(not(feature = "unsafe_revoked_tx_signing"))]
+			Request::UnsafeSignHolderCommitmentAndHtlcs { .. } => Response::Error {},
+			Request::SignJusticeRevokedOutput { channel_keys_id, justice_tx, input, amount, per_commitment_key } => {
+				if input >= justice_tx.input.len() as u64 {
+					return Response::Error {};
+				}
+				self.with_signer(&channel_keys_id, true, |signer| {
+					match signer.signer.sign_justice_revoked_output(&justice_tx, input as usize, amount, &per_commitment_key, &self.secp_ctx) {
+						Ok(signature) => Response::Signature { signature },
+						Err(()) => Response::Error {},
+					}
+				})
+			},
+			Request::SignJusticeRevokedHtlc { channel_keys_id, justice_tx, input, amount, per_commitment_key, htlc } => {
+				if input >= justice_tx.input.len() as u64 {
+					return Response::Error {};
+				}
+				self.with_signer(&channel_keys_id, true, |signer| {
+					match signer.signer.sign_justice_revoked_htlc(&justice_tx, input as usize, amount, &per_commitment_key, &htlc, &self.secp_ctx) {
+						Ok(signature) => Response::Signature { signature },
+						Err(()) => Response::Error {},
+					}
+				})
+			},
+			Request::SignCounterpartyHtlcTransaction { channel_keys_id, htlc_tx, input, amount, per_commitment_point, htlc } => {
+				if input >= htlc_tx.input.len() as u64 {
+					return Response::Error {};
+				}
+				self.with_signer(&channel_keys_id, true, |signer| {
+					match signer.signer.sign_counterparty_htlc_transaction(&htlc_tx, input as usize, amount, &per_commitment_point, &htlc, &self.secp_ctx) {
+						Ok(signature) => Response::Signature { signature },
+						Err(()) => Response::Error {},
+					}
+				})
+			},
+			Request::SignClosingTransaction { channel_keys_id, to_holder_value_sat, to_counterparty_value_sat, to_holder_script, to_counterparty_script } => {
+				self.with_signer(&channel_keys_id, true, |signer| {
+					let closing_tx = ClosingTransaction::new(to_holder_value_sat, to_counterparty_value_sat,
+						to_holder_script, to_counterparty_script, signer.signer.funding_outpoint().into_bitcoin_outpoint());
+					match signer.signer.sign_closing_transaction(&closing_tx, &self.secp_ctx) {
+						Ok(signature) => Response::Signature { signature },
+						Err(()) => Response::Error {},
+					}
+				})
+			},
+			Request::SignChannelAnnouncement { channel_keys_id, msg } => {
+				self.with_signer(&channel_keys_id, true, |signer| {
+					match signer.signer.sign_channel_announcement(&msg, &self.secp_ctx) {
+						Ok((first, second)) => Response::SignaturePair { first, second },
+						Err(()) => Response::Error {},
+					}
+				})
+			},
+			Request::ReadyChannel { channel_keys_id, channel_parameters } => {
+				self.with_signer(&channel_keys_id, false, |signer| {
+					// The channel parameters may never change once set.
+					if signer.ready || !channel_parameters.is_populated() {
+						return Response::Error {};
+					}
+					signer.signer.ready_channel(&channel_parameters);
+					signer.ready = true;
+					Response::Ok {}
+				})
+			},
+		}
+	}
+
+	fn add_signer(&self, signer: InMemorySigner, ready: bool) -> Response {
+		let response = Response::ChannelSigner { channel_keys_id: signer.channel_keys_id(), pubkeys: signer.pubkeys().clone() };
+		self.signers.lock().unwrap().insert(signer.channel_keys_id(), ServedSigner { signer, ready });
+		response
+	}
+
+	fn sign_gossip_message(&self, msg: UnsignedGossipMessage) -> Response {
+		match self.keys_manager.sign_gossip_message(msg) {
+			Ok(signature) => Response::Signature { signature },
+			Err(()) => Response::Error {},
+		}
+	}
+
+	/// Calls `f` with the signer identified by `channel_keys_id`, responding with an error if no
+	/// such signer is known or if `require_ready` is set and the signer is not ready yet.
+	fn with_signer<F: FnOnce(&mut ServedSigner) -> Response>(&self, channel_keys_id: &[u8; 32], require_ready: bool, f: F) -> Response {
+		let mut signers = self.signers.lock().unwrap();
+		match signers.get_mut(channel_keys_id) {
+			Some(signer) if signer.ready || !require_ready => f(signer),
+			_ => Response::Error {},
+		}
+	}
+}
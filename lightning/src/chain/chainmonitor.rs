@@ -143,6 +143,19 @@ pub trait Persist<ChannelSigner: Sign> {
 	///
 	/// [`Writeable::write`]: crate::util::ser::Writeable::write
 	fn update_persisted_channel(&self, channel_id: OutPoint, update: &Option<ChannelMonitorUpdate>, data: &ChannelMonitor<ChannelSigner>, update_id: MonitorUpdateId) -> Result<(), ChannelMonitorUpdateErr>;
+
+	/// Archive a fully resolved channel's data, called by
+	/// [`ChainMonitor::archive_fully_resolved_channel_monitors`] right before the [`ChannelMonitor`]
+	/// is removed from the [`ChainMonitor`].
+	///
+	/// The data should be moved out of the set of [`ChannelMonitor`]s which are loaded on startup,
+	/// e.g. into cold storage, as it is no longer needed for the node's operation. It may still be
+	/// useful for accounting or debugging, so implementations may wish to avoid deleting it.
+	///
+	/// As the channel is fully resolved, failing to archive it is harmless - the [`ChannelMonitor`]
+	/// will simply be loaded again on the next startup and be archived again thereafter. Thus, by
+	/// default, this does nothing.
+	fn archive_persisted_channel(&self, _channel_id: OutPoint, _data: &ChannelMonitor<ChannelSigner>) {}
 }
 
 struct MonitorHolder<ChannelSigner: Sign> {
@@ -181,6 +194,12 @@ struct MonitorHolder<ChannelSigner: Sign> {
 }
 
 impl<ChannelSigner: Sign> MonitorHolder<ChannelSigner> {
+	/// Checks whether the channel is fully resolved and all updates to it have been persisted, in
+	/// which case the monitor can be archived.
+	fn is_fully_resolved(&self) -> bool {
+		self.monitor.is_fully_resolved() && self.pending_monitor_updates.lock().unwrap().is_empty()
+	}
+
 	fn has_pending_offchain_updates(&self, pending_monitor_updates_lock: &MutexGuard<Vec<MonitorUpdateId>>) -> bool {
 		pending_monitor_updates_lock.iter().any(|update_id|
 			if let UpdateOrigin::OffChain(_) = update_id.contents { true } else { false })
@@ -403,11 +422,38 @@ where C::Target: chain::Filter,
 	/// Lists the funding outpoint of each [`ChannelMonitor`] being monitored.
 	///
 	/// Note that [`ChannelMonitor`]s are not removed when a channel is closed as they are always
-	/// monitoring for on-chain state resolutions.
+	/// monitoring for on-chain state resolutions. Instead, they are removed once fully resolved by
+	/// [`Self::archive_fully_resolved_channel_monitors`].
 	pub fn list_monitors(&self) -> Vec<OutPoint> {
 		self.monitors.read().unwrap().keys().map(|outpoint| *outpoint).collect()
 	}
 
+	/// Lists the funding outpoint of each [`ChannelMonitor`] whose channel has been fully resolved
+	/// on-chain and which may thus be archived, see [`ChannelMonitor::is_fully_resolved`].
+	pub fn list_fully_resolved_monitors(&self) -> Vec<OutPoint> {
+		let monitors = self.monitors.read().unwrap();
+		monitors.iter().filter_map(|(funding_outpoint, monitor_state)| {
+			if monitor_state.is_fully_resolved() { Some(*funding_outpoint) } else { None }
+		}).collect()
+	}
+
+	/// Archives the [`ChannelMonitor`]s of all fully resolved channels, moving them to cold storage
+	/// via [`Persist::archive_persisted_channel`] and no longer monitoring the chain for them.
+	///
+	/// This keeps node startup and block processing from scaling with the number of historical
+	/// channels. It should be called regularly, e.g. once per block.
+	pub fn archive_fully_resolved_channel_monitors(&self) {
+		let mut monitors = self.monitors.write().unwrap();
+		let fully_resolved: Vec<OutPoint> = monitors.iter().filter_map(|(funding_outpoint, monitor_state)| {
+			if monitor_state.is_fully_resolved() { Some(*funding_outpoint) } else { None }
+		}).collect();
+		for funding_outpoint in fully_resolved {
+			let monitor_state = monitors.remove(&funding_outpoint).unwrap();
+			log_info!(self.logger, "Archiving fully resolved ChannelMonitor for channel {}", log_funding_info!(monitor_state.monitor));
+			self.persister.archive_persisted_channel(funding_outpoint, &monitor_state.monitor);
+		}
+	}
+
 	#[cfg(test)]
 	pub fn remove_monitor(&self, funding_txo: &OutPoint) -> ChannelMonitor<ChannelSigner> {
 		self.monitors.write().unwrap().remove(funding_txo).unwrap().monitor
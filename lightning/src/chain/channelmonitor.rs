@@ -208,6 +208,12 @@ pub(crate) const LATENCY_GRACE_PERIOD_BLOCKS: u32 = 3;
 // solved by a previous claim tx. What we want to avoid is reorg evicting our claim tx and us not
 // keep bumping another claim tx to solve the outpoint.
 pub const ANTI_REORG_DELAY: u32 = 6;
+/// Number of blocks for which a closed channel's [`ChannelMonitor`] must have had no claimable
+/// balances before it is considered fully resolved and may be archived. This is well beyond any
+/// reorg we'd expect to see, roughly two weeks of blocks.
+///
+/// See [`ChannelMonitor::is_fully_resolved`] for more details.
+pub const ARCHIVAL_DELAY_BLOCKS: u32 = 4032;
 /// Number of blocks before confirmation at which we fail back an un-relayed HTLC or at which we
 /// refuse to accept a new HTLC.
 ///
@@ -698,6 +704,10 @@ pub(crate) struct ChannelMonitorImpl<Signer: Sign> {
 	/// spending CSV for revocable outputs).
 	htlcs_resolved_on_chain: Vec<IrrevocablyResolvedHTLC>,
 
+	/// The height at which we first found no claimable balances left in this (closed) channel,
+	/// reset if balances re-appear, e.g. due to a reorg.
+	balances_empty_height: Option<u32>,
+
 	// We simply modify best_block in Channel's block_connected so that serialization is
 	// consistent but hopefully the users' copy handles block_connected in a consistent way.
 	// (we do *not*, however, update them in update_monitor to ensure any local user copies keep
@@ -759,7 +769,8 @@ impl<Signer: Sign> PartialEq for ChannelMonitorImpl<Signer> {
 			self.holder_tx_signed != other.holder_tx_signed ||
 			self.funding_spend_seen != other.funding_spend_seen ||
 			self.funding_spend_confirmed != other.funding_spend_confirmed ||
-			self.htlcs_resolved_on_chain != other.htlcs_resolved_on_chain
+			self.htlcs_resolved_on_chain != other.htlcs_resolved_on_chain ||
+			self.balances_empty_height != other.balances_empty_height
 		{
 			false
 		} else {
@@ -934,6 +945,7 @@ impl<Signer: Sign> Writeable for ChannelMonitorImpl<Signer> {
 			(3, self.htlcs_resolved_on_chain, vec_type),
 			(5, self.pending_monitor_events, vec_type),
 			(7, self.funding_spend_seen, required),
+			(9, self.balances_empty_height, option),
 		});
 
 		Ok(())
@@ -1035,6 +1047,7 @@ impl<Signer: Sign> ChannelMonitor<Signer> {
 				funding_spend_seen: false,
 				funding_spend_confirmed: None,
 				htlcs_resolved_on_chain: Vec::new(),
+				balances_empty_height: None,
 
 				best_block,
 
@@ -1358,155 +1371,24 @@ impl<Signer: Sign> ChannelMonitor<Signer> {
 	/// See [`Balance`] for additional details on the types of claimable balances which
 	/// may be returned here and their meanings.
 	pub fn get_claimable_balances(&self) -> Vec<Balance> {
-		let mut res = Vec::new();
-		let us = self.inner.lock().unwrap();
-
-		let mut confirmed_txid = us.funding_spend_confirmed;
-		let mut pending_commitment_tx_conf_thresh = None;
-		let funding_spend_pending = us.onchain_events_awaiting_threshold_conf.iter().find_map(|event| {
-			if let OnchainEvent::FundingSpendConfirmation { .. } = event.event {
-				Some((event.txid, event.confirmation_threshold()))
-			} else { None }
-		});
-		if let Some((txid, conf_thresh)) = funding_spend_pending {
-			debug_assert!(us.funding_spend_confirmed.is_none(),
-				"We have a pending funding spend awaiting anti-reorg confirmation, we can't have confirmed it already!");
-			confirmed_txid = Some(txid);
-			pending_commitment_tx_conf_thresh = Some(conf_thresh);
-		}
-
-		macro_rules! walk_htlcs {
-			($holder_commitment: expr, $htlc_iter: expr) => {
-				for htlc in $htlc_iter {
-					if let Some(htlc_input_idx) = htlc.transaction_output_index {
-						if us.htlcs_resolved_on_chain.iter().any(|v| v.input_idx == htlc_input_idx) {
-							assert!(us.funding_spend_confirmed.is_some());
-						} else if htlc.offered == $holder_commitment {
-							// If the payment was outbound, check if there's an HTLCUpdate
-							// indicating we have spent this HTLC with a timeout, claiming it back
-							// and awaiting confirmations on it.
-							let htlc_update_pending = us.onchain_events_awaiting_threshold_conf.iter().find_map(|event| {
-								if let OnchainEvent::HTLCUpdate { input_idx: Some(input_idx), .. } = event.event {
-									if input_idx == htlc_input_idx { Some(event.confirmation_threshold()) } else { None }
-								} else { None }
-							});
-							if let Some(conf_thresh) = htlc_update_pending {
-								res.push(Balance::ClaimableAwaitingConfirmations {
-									claimable_amount_satoshis: htlc.amount_msat / 1000,
-									confirmation_height: conf_thresh,
-								});
-							} else {
-								res.push(Balance::MaybeClaimableHTLCAwaitingTimeout {
-									claimable_amount_satoshis: htlc.amount_msat / 1000,
-									claimable_height: htlc.cltv_expiry,
-								});
-							}
-						} else if us.payment_preimages.get(&htlc.payment_hash).is_some() {
-							// Otherwise (the payment was inbound), only expose it as claimable if
-							// we know the preimage.
-							// Note that if there is a pending claim, but it did not use the
-							// preimage, we lost funds to our counterparty! We will then continue
-							// to show it as ContentiousClaimable until ANTI_REORG_DELAY.
-							let htlc_spend_pending = us.onchain_events_awaiting_threshold_conf.iter().find_map(|event| {
-								if let OnchainEvent::HTLCSpendConfirmation { input_idx, preimage, .. } = event.event {
-									if input_idx == htlc_input_idx {
-										Some((event.confirmation_threshold(), preimage.is_some()))
-									} else { None }
-								} else { None }
-							});
-							if let Some((conf_thresh, true)) = htlc_spend_pending {
-								res.push(Balance::ClaimableAwaitingConfirmations {
-									claimable_amount_satoshis: htlc.amount_msat / 1000,
-									confirmation_height: conf_thresh,
-								});
-							} else {
-								res.push(Balance::ContentiousClaimable {
-									claimable_amount_satoshis: htlc.amount_msat / 1000,
-									timeout_height: htlc.cltv_expiry,
-								});
-							}
-						}
-					}
-				}
-			}
-		}
+		self.inner.lock().unwrap().get_claimable_balances()
+	}
 
-		if let Some(txid) = confirmed_txid {
-			let mut found_commitment_tx = false;
-			if Some(txid) == us.current_counterparty_commitment_txid || Some(txid) == us.prev_counterparty_commitment_txid {
-				walk_htlcs!(false, us.counterparty_claimable_outpoints.get(&txid).unwrap().iter().map(|(a, _)| a));
-				if let Some(conf_thresh) = pending_commitment_tx_conf_thresh {
-					if let Some(value) = us.onchain_events_awaiting_threshold_conf.iter().find_map(|event| {
-						if let OnchainEvent::MaturingOutput {
-							descriptor: SpendableOutputDescriptor::StaticPaymentOutput(descriptor)
-						} = &event.event {
-							Some(descriptor.output.value)
-						} else { None }
-					}) {
-						res.push(Balance::ClaimableAwaitingConfirmations {
-							claimable_amount_satoshis: value,
-							confirmation_height: conf_thresh,
-						});
-					} else {
-						// If a counterparty commitment transaction is awaiting confirmation, we
-						// should either have a StaticPaymentOutput MaturingOutput event awaiting
-						// confirmation with the same height or have never met our dust amount.
-					}
-				}
-				found_commitment_tx = true;
-			} else if txid == us.current_holder_commitment_tx.txid {
-				walk_htlcs!(true, us.current_holder_commitment_tx.htlc_outputs.iter().map(|(a, _, _)| a));
-				if let Some(conf_thresh) = pending_commitment_tx_conf_thresh {
-					res.push(Balance::ClaimableAwaitingConfirmations {
-						claimable_amount_satoshis: us.current_holder_commitment_tx.to_self_value_sat,
-						confirmation_height: conf_thresh,
-					});
-				}
-				found_commitment_tx = true;
-			} else if let Some(prev_commitment) = &us.prev_holder_signed_commitment_tx {
-				if txid == prev_commitment.txid {
-					walk_htlcs!(true, prev_commitment.htlc_outputs.iter().map(|(a, _, _)| a));
-					if let Some(conf_thresh) = pending_commitment_tx_conf_thresh {
-						res.push(Balance::ClaimableAwaitingConfirmations {
-							claimable_amount_satoshis: prev_commitment.to_self_value_sat,
-							confirmation_height: conf_thresh,
-						});
-					}
-					found_commitment_tx = true;
-				}
-			}
-			if !found_commitment_tx {
-				if let Some(conf_thresh) = pending_commitment_tx_conf_thresh {
-					// We blindly assume this is a cooperative close transaction here, and that
-					// neither us nor our counterparty misbehaved. At worst we've under-estimated
-					// the amount we can claim as we'll punish a misbehaving counterparty.
-					res.push(Balance::ClaimableAwaitingConfirmations {
-						claimable_amount_satoshis: us.current_holder_commitment_tx.to_self_value_sat,
-						confirmation_height: conf_thresh,
-					});
-				}
-			}
-			// TODO: Add logic to provide claimable balances for counterparty broadcasting revoked
-			// outputs.
-		} else {
-			let mut claimable_inbound_htlc_value_sat = 0;
-			for (htlc, _, _) in us.current_holder_commitment_tx.htlc_outputs.iter() {
-				if htlc.transaction_output_index.is_none() { continue; }
-				if htlc.offered {
-					res.push(Balance::MaybeClaimableHTLCAwaitingTimeout {
-						claimable_amount_satoshis: htlc.amount_msat / 1000,
-						claimable_height: htlc.cltv_expiry,
-					});
-				} else if us.payment_preimages.get(&htlc.payment_hash).is_some() {
-					claimable_inbound_htlc_value_sat += htlc.amount_msat / 1000;
-				}
-			}
-			res.push(Balance::ClaimableOnChannelClose {
-				claimable_amount_satoshis: us.current_holder_commitment_tx.to_self_value_sat + claimable_inbound_htlc_value_sat,
-			});
+	/// Checks whether this channel is closed and all of its on-chain outputs have been
+	/// irrevocably resolved, in which case the monitor no longer needs to watch the chain and may
+	/// be archived, e.g. via [`ChainMonitor::archive_fully_resolved_channel_monitors`].
+	///
+	/// This is the case once the funding output has been spent and [`Self::get_claimable_balances`]
+	/// has been empty for at least [`ARCHIVAL_DELAY_BLOCKS`], as tracked (and persisted) while
+	/// blocks are connected.
+	///
+	/// [`ChainMonitor::archive_fully_resolved_channel_monitors`]: crate::chain::chainmonitor::ChainMonitor::archive_fully_resolved_channel_monitors
+	pub fn is_fully_resolved(&self) -> bool {
+		let us = self.inner.lock().unwrap();
+		match us.balances_empty_height {
+			Some(height) => us.best_block.height() >= height + ARCHIVAL_DELAY_BLOCKS,
+			None => false,
 		}
-
-		res
 	}
 
 	/// Gets the set of outbound HTLCs which are pending resolution in this channel.
@@ -1682,6 +1564,175 @@ macro_rules! fail_unbroadcast_htlcs {
 }
 
 impl<Signer: Sign> ChannelMonitorImpl<Signer> {
+	fn get_claimable_balances(&self) -> Vec<Balance> {
+		let mut res = Vec::new();
+		let us = self;
+
+		let mut confirmed_txid = us.funding_spend_confirmed;
+		let mut pending_commitment_tx_conf_thresh = None;
+		let funding_spend_pending = us.onchain_events_awaiting_threshold_conf.iter().find_map(|event| {
+			if let OnchainEvent::FundingSpendConfirmation { .. } = event.event {
+				Some((event.txid, event.confirmation_threshold()))
+			} else { None }
+		});
+		if let Some((txid, conf_thresh)) = funding_spend_pending {
+			debug_assert!(us.funding_spend_confirmed.is_none(),
+				"We have a pending funding spend awaiting anti-reorg confirmation, we can't have confirmed it already!");
+			confirmed_txid = Some(txid);
+			pending_commitment_tx_conf_thresh = Some(conf_thresh);
+		}
+
+		macro_rules! walk_htlcs {
+			($holder_commitment: expr, $htlc_iter: expr) => {
+				for htlc in $htlc_iter {
+					if let Some(htlc_input_idx) = htlc.transaction_output_index {
+						if us.htlcs_resolved_on_chain.iter().any(|v| v.input_idx == htlc_input_idx) {
+							assert!(us.funding_spend_confirmed.is_some());
+						} else if htlc.offered == $holder_commitment {
+							// If the payment was outbound, check if there's an HTLCUpdate
+							// indicating we have spent this HTLC with a timeout, claiming it back
+							// and awaiting confirmations on it.
+							let htlc_update_pending = us.onchain_events_awaiting_threshold_conf.iter().find_map(|event| {
+								if let OnchainEvent::HTLCUpdate { input_idx: Some(input_idx), .. } = event.event {
+									if input_idx == htlc_input_idx { Some(event.confirmation_threshold()) } else { None }
+								} else { None }
+							});
+							if let Some(conf_thresh) = htlc_update_pending {
+								res.push(Balance::ClaimableAwaitingConfirmations {
+									claimable_amount_satoshis: htlc.amount_msat / 1000,
+									confirmation_height: conf_thresh,
+								});
+							} else {
+								res.push(Balance::MaybeClaimableHTLCAwaitingTimeout {
+									claimable_amount_satoshis: htlc.amount_msat / 1000,
+									claimable_height: htlc.cltv_expiry,
+								});
+							}
+						} else if us.payment_preimages.get(&htlc.payment_hash).is_some() {
+							// Otherwise (the payment was inbound), only expose it as claimable if
+							// we know the preimage.
+							// Note that if there is a pending claim, but it did not use the
+							// preimage, we lost funds to our counterparty! We will then continue
+							// to show it as ContentiousClaimable until ANTI_REORG_DELAY.
+							let htlc_spend_pending = us.onchain_events_awaiting_threshold_conf.iter().find_map(|event| {
+								if let OnchainEvent::HTLCSpendConfirmation { input_idx, preimage, .. } = event.event {
+									if input_idx == htlc_input_idx {
+										Some((event.confirmation_threshold(), preimage.is_some()))
+									} else { None }
+								} else { None }
+							});
+							if let Some((conf_thresh, true)) = htlc_spend_pending {
+								res.push(Balance::ClaimableAwaitingConfirmations {
+									claimable_amount_satoshis: htlc.amount_msat / 1000,
+									confirmation_height: conf_thresh,
+								});
+							} else {
+								res.push(Balance::ContentiousClaimable {
+									claimable_amount_satoshis: htlc.amount_msat / 1000,
+									timeout_height: htlc.cltv_expiry,
+								});
+							}
+						}
+					}
+				}
+			}
+		}
+
+		if let Some(txid) = confirmed_txid {
+			let mut found_commitment_tx = false;
+			if Some(txid) == us.current_counterparty_commitment_txid || Some(txid) == us.prev_counterparty_commitment_txid {
+				walk_htlcs!(false, us.counterparty_claimable_outpoints.get(&txid).unwrap().iter().map(|(a, _)| a));
+				if let Some(conf_thresh) = pending_commitment_tx_conf_thresh {
+					if let Some(value) = us.onchain_events_awaiting_threshold_conf.iter().find_map(|event| {
+						if let OnchainEvent::MaturingOutput {
+							descriptor: SpendableOutputDescriptor::StaticPaymentOutput(descriptor)
+						} = &event.event {
+							Some(descriptor.output.value)
+						} else { None }
+					}) {
+						res.push(Balance::ClaimableAwaitingConfirmations {
+							claimable_amount_satoshis: value,
+							confirmation_height: conf_thresh,
+						});
+					} else {
+						// If a counterparty commitment transaction is awaiting confirmation, we
+						// should either have a StaticPaymentOutput MaturingOutput event awaiting
+						// confirmation with the same height or have never met our dust amount.
+					}
+				}
+				found_commitment_tx = true;
+			} else if txid == us.current_holder_commitment_tx.txid {
+				walk_htlcs!(true, us.current_holder_commitment_tx.htlc_outputs.iter().map(|(a, _, _)| a));
+				if let Some(conf_thresh) = pending_commitment_tx_conf_thresh {
+					res.push(Balance::ClaimableAwaitingConfirmations {
+						claimable_amount_satoshis: us.current_holder_commitment_tx.to_self_value_sat,
+						confirmation_height: conf_thresh,
+					});
+				}
+				found_commitment_tx = true;
+			} else if let Some(prev_commitment) = &us.prev_holder_signed_commitment_tx {
+				if txid == prev_commitment.txid {
+					walk_htlcs!(true, prev_commitment.htlc_outputs.iter().map(|(a, _, _)| a));
+					if let Some(conf_thresh) = pending_commitment_tx_conf_thresh {
+						res.push(Balance::ClaimableAwaitingConfirmations {
+							claimable_amount_satoshis: prev_commitment.to_self_value_sat,
+							confirmation_height: conf_thresh,
+						});
+					}
+					found_commitment_tx = true;
+				}
+			}
+			if !found_commitment_tx {
+				if let Some(conf_thresh) = pending_commitment_tx_conf_thresh {
+					// We blindly assume this is a cooperative close transaction here, and that
+					// neither us nor our counterparty misbehaved. At worst we've under-estimated
+					// the amount we can claim as we'll punish a misbehaving counterparty.
+					res.push(Balance::ClaimableAwaitingConfirmations {
+						claimable_amount_satoshis: us.current_holder_commitment_tx.to_self_value_sat,
+						confirmation_height: conf_thresh,
+					});
+				}
+			}
+			// TODO: Add logic to provide claimable balances for counterparty broadcasting revoked
+			// outputs.
+		} else {
+			let mut claimable_inbound_htlc_value_sat = 0;
+			for (htlc, _, _) in us.current_holder_commitment_tx.htlc_outputs.iter() {
+				if htlc.transaction_output_index.is_none() { continue; }
+				if htlc.offered {
+					res.push(Balance::MaybeClaimableHTLCAwaitingTimeout {
+						claimable_amount_satoshis: htlc.amount_msat / 1000,
+						claimable_height: htlc.cltv_expiry,
+					});
+				} else if us.payment_preimages.get(&htlc.payment_hash).is_some() {
+					claimable_inbound_htlc_value_sat += htlc.amount_msat / 1000;
+				}
+			}
+			res.push(Balance::ClaimableOnChannelClose {
+				claimable_amount_satoshis: us.current_holder_commitment_tx.to_self_value_sat + claimable_inbound_htlc_value_sat,
+			});
+		}
+
+		res
+	}
+
+	/// Tracks the height at which we first found no claimable balances left in this (closed)
+	/// channel, see [`ChannelMonitor::is_fully_resolved`]. Called whenever our view of the chain
+	/// changes.
+	fn update_balances_empty_height(&mut self) {
+		let height = self.best_block.height();
+		// While a (possibly different, after a reorg) funding spend is awaiting its anti-reorg
+		// confirmations we still have balances to claim, and the balance calculation below expects
+		// this not to overlap with a confirmed funding spend, so we check for it first.
+		let funding_spend_pending = self.onchain_events_awaiting_threshold_conf.iter()
+			.any(|event| if let OnchainEvent::FundingSpendConfirmation { .. } = event.event { true } else { false });
+		if self.funding_spend_confirmed.is_none() || funding_spend_pending || !self.get_claimable_balances().is_empty() {
+			self.balances_empty_height = None;
+		} else if self.balances_empty_height.map_or(true, |empty_height| empty_height > height) {
+			self.balances_empty_height = Some(height);
+		}
+	}
+
 	/// Inserts a revocation secret into this channel monitor. Prunes old preimages if neither
 	/// needed by holder commitment transactions HTCLs nor by counterparty ones. Unless we haven't already seen
 	/// counterparty commitment transaction's secret, they are de facto pruned (we can use revocation key).
@@ -2325,6 +2376,7 @@ impl<Signer: Sign> ChannelMonitorImpl<Signer> {
 			self.best_block = BestBlock::new(block_hash, height);
 			self.onchain_events_awaiting_threshold_conf.retain(|ref entry| entry.height <= height);
 			self.onchain_tx_handler.block_disconnected(height + 1, broadcaster, fee_estimator, logger);
+			self.update_balances_empty_height();
 			Vec::new()
 		} else { Vec::new() }
 	}
@@ -2554,6 +2606,7 @@ impl<Signer: Sign> ChannelMonitorImpl<Signer> {
 				}
 			}
 		}
+		self.update_balances_empty_height();
 		watch_outputs
 	}
 
@@ -2572,6 +2625,7 @@ impl<Signer: Sign> ChannelMonitorImpl<Signer> {
 		self.onchain_tx_handler.block_disconnected(height, broadcaster, fee_estimator, logger);
 
 		self.best_block = BestBlock::new(header.prev_blockhash, height - 1);
+		self.update_balances_empty_height();
 	}
 
 	fn transaction_unconfirmed<B: Deref, F: Deref, L: Deref>(
@@ -2587,6 +2641,7 @@ impl<Signer: Sign> ChannelMonitorImpl<Signer> {
 	{
 		self.onchain_events_awaiting_threshold_conf.retain(|ref entry| entry.txid != *txid);
 		self.onchain_tx_handler.transaction_unconfirmed(txid, broadcaster, fee_estimator, logger);
+		self.update_balances_empty_height();
 	}
 
 	/// Filters a block's `txdata` for transactions spending watched outputs or for any child
@@ -3219,11 +3274,13 @@ impl<'a, Signer: Sign, K: KeysInterface<Signer = Signer>> ReadableArgs<&'a K>
 		let mut funding_spend_confirmed = None;
 		let mut htlcs_resolved_on_chain = Some(Vec::new());
 		let mut funding_spend_seen = Some(false);
+		let mut balances_empty_height = None;
 		read_tlv_fields!(reader, {
 			(1, funding_spend_confirmed, option),
 			(3, htlcs_resolved_on_chain, vec_type),
 			(5, pending_monitor_events, vec_type),
 			(7, funding_spend_seen, option),
+			(9, balances_empty_height, option),
 		});
 
 		let mut secp_ctx = Secp256k1::new();
@@ -3276,6 +3333,7 @@ impl<'a, Signer: Sign, K: KeysInterface<Signer = Signer>> ReadableArgs<&'a K>
 				funding_spend_seen: funding_spend_seen.unwrap(),
 				funding_spend_confirmed,
 				htlcs_resolved_on_chain: htlcs_resolved_on_chain.unwrap(),
+				balances_empty_height,
 
 				best_block,
 
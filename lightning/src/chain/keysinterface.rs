@@ -15,7 +15,8 @@ use bitcoin::blockdata::transaction::{Transaction, TxOut, TxIn, SigHashType};
 use bitcoin::blockdata::script::{Script, Builder};
 use bitcoin::blockdata::opcodes;
 use bitcoin::network::constants::Network;
-use bitcoin::util::bip32::{ExtendedPrivKey, ExtendedPubKey, ChildNumber};
+use bitcoin::util::bip32::{ExtendedPrivKey, ExtendedPubKey, ChildNumber, DerivationPath, KeySource};
+use bitcoin::util::psbt::PartiallySignedTransaction;
 use bitcoin::util::bip143;
 
 use bitcoin::bech32::u5;
@@ -27,20 +28,22 @@ use bitcoin::hash_types::WPubkeyHash;
 
 use bitcoin::secp256k1::key::{SecretKey, PublicKey};
 use bitcoin::secp256k1::{Secp256k1, Signature, Signing};
+use bitcoin::secp256k1::ecdh::SharedSecret;
 use bitcoin::secp256k1::recovery::RecoverableSignature;
 use bitcoin::secp256k1;
 
-use util::{byte_utils, transaction_utils};
+use util::{byte_utils, message_signing, transaction_utils};
 use util::crypto::hkdf_extract_expand_twice;
 use util::ser::{Writeable, Writer, Readable, ReadableArgs};
 
 use chain::transaction::OutPoint;
 use ln::{chan_utils, PaymentPreimage};
 use ln::chan_utils::{HTLCOutputInCommitment, make_funding_redeemscript, ChannelPublicKeys, HolderCommitmentTransaction, ChannelTransactionParameters, CommitmentTransaction, ClosingTransaction};
-use ln::msgs::UnsignedChannelAnnouncement;
+use ln::msgs::{UnsignedChannelAnnouncement, UnsignedGossipMessage};
 use ln::script::ShutdownScript;
 
 use prelude::*;
+use core::cmp;
 use core::sync::atomic::{AtomicUsize, Ordering};
 use io::{self, Error};
 use ln::msgs::{DecodeError, MAX_VALUE_MSAT};
@@ -214,13 +217,13 @@ pub trait BaseSign {
 	/// Gets the commitment secret for a specific commitment number as part of the revocation process
 	///
 	/// An external signer implementation should error here if the commitment was already signed
-	/// and should refuse to sign it in the future.
+	/// and should refuse to sign it in the future. If an `Err` is returned the channel will be
+	/// force-closed.
 	///
 	/// May be called more than once for the same index.
 	///
 	/// Note that the commitment number starts at (1 << 48) - 1 and counts backwards.
-	// TODO: return a Result so we can signal a validation error
-	fn release_commitment_secret(&self, idx: u64) -> [u8; 32];
+	fn release_commitment_secret(&self, idx: u64) -> Result<[u8; 32], ()>;
 	/// Validate the counterparty's signatures on the holder commitment transaction and HTLCs.
 	///
 	/// This is required in order for the signer to make sure that releasing a commitment
@@ -244,7 +247,16 @@ pub trait BaseSign {
 
 	/// Create a signature for a counterparty's commitment transaction and associated HTLC transactions.
 	///
-	/// Note that if signing fails or is rejected, the channel will be force-closed.
+	/// Returning [`SignerError::Unavailable`] indicates that the signature is not (yet) available,
+	/// e.g. because it is being produced by a remote signer. The channel will stop making
+	/// progress, holding any commitment update (and any `revoke_and_ack` which has to follow it)
+	/// until [`ChannelManager::signer_unblocked`] is called, at which point this will be called
+	/// again with the same commitment. Returning [`SignerError::Rejected`] instead causes the
+	/// channel to be force-closed. Note that for the initial commitment transaction, signed during
+	/// funding, any `Err` causes the channel to be closed.
+	///
+	/// Other methods on this trait (including those used to build a `revoke_and_ack`) must return
+	/// immediately.
 	///
 	/// Policy checks should be implemented in this function, including checking the amount
 	/// sent to us and checking the HTLCs.
@@ -255,9 +267,11 @@ pub trait BaseSign {
 	///
 	/// NOTE: all the relevant preimages will be provided, but there may also be additional
 	/// irrelevant or duplicate preimages.
+	///
+	/// [`ChannelManager::signer_unblocked`]: crate::ln::channelmanager::ChannelManager::signer_unblocked
 	//
 	// TODO: Document the things someone using this interface should enforce before signing.
-	fn sign_counterparty_commitment(&self, commitment_tx: &CommitmentTransaction, preimages: Vec<PaymentPreimage>, secp_ctx: &Secp256k1<secp256k1::All>) -> Result<(Signature, Vec<Signature>), ()>;
+	fn sign_counterparty_commitment(&self, commitment_tx: &CommitmentTransaction, preimages: Vec<PaymentPreimage>, secp_ctx: &Secp256k1<secp256k1::All>) -> Result<(Signature, Vec<Signature>), SignerError>;
 	/// Validate the counterparty's revocation.
 	///
 	/// This is required in order for the signer to make sure that the state has moved
@@ -380,6 +394,16 @@ pub trait BaseSign {
 pub trait Sign: BaseSign + Writeable + Clone {
 }
 
+/// The reason a [`BaseSign`] was unable to provide a requested signature.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SignerError {
+	/// The signature is not yet available, e.g. because it is being produced by a remote signer
+	/// which has not yet responded. The request will be retried later.
+	Unavailable,
+	/// The signer refused to provide the signature, e.g. because a policy check failed.
+	Rejected,
+}
+
 /// Specifies the recipient of an invoice, to indicate to [`KeysInterface::sign_invoice`] what node
 /// secret key should be used to sign the invoice.
 pub enum Recipient {
@@ -397,21 +421,35 @@ pub trait KeysInterface {
 	/// A type which implements Sign which will be returned by get_channel_signer.
 	type Signer : Sign;
 
-	/// Get node secret key (aka node_id or network_key) based on the provided [`Recipient`].
+	/// Get the node id (aka network public key) based on the provided [`Recipient`].
 	///
 	/// This method must return the same value each time it is called with a given `Recipient`
 	/// parameter.
-	fn get_node_secret(&self, recipient: Recipient) -> Result<SecretKey, ()>;
+	fn get_node_id(&self, recipient: Recipient) -> Result<PublicKey, ()>;
+	/// Computes the ECDH shared secret between the node secret key of the provided [`Recipient`]
+	/// and `other_key`. This is used in the BOLT 8 handshake with our peers and to decrypt the
+	/// onion packets of HTLCs forwarded through or paid to us.
+	///
+	/// As all uses of the node secret key go through this method, [`Self::sign_gossip_message`],
+	/// [`Self::sign_message`] and [`Self::sign_invoice`], implementors never need to reveal the
+	/// node secret key itself, allowing it to be kept in an HSM.
+	fn ecdh(&self, recipient: Recipient, other_key: &PublicKey) -> Result<SharedSecret, ()>;
 	/// Get a script pubkey which we send funds to when claiming on-chain contestable outputs.
 	///
 	/// This method should return a different value each time it is called, to avoid linking
 	/// on-chain funds across channels as controlled to the same user.
-	fn get_destination_script(&self) -> Script;
+	///
+	/// Errors if the script could not be obtained, in which case the channel it was requested
+	/// for will not be opened.
+	fn get_destination_script(&self) -> Result<Script, ()>;
 	/// Get a script pubkey which we will send funds to when closing a channel.
 	///
 	/// This method should return a different value each time it is called, to avoid linking
 	/// on-chain funds across channels as controlled to the same user.
-	fn get_shutdown_scriptpubkey(&self) -> ShutdownScript;
+	///
+	/// Errors if the script could not be obtained, in which case the channel it was requested
+	/// for will not be opened or, if it is being closed, will be force-closed instead.
+	fn get_shutdown_scriptpubkey(&self) -> Result<ShutdownScript, ()>;
 	/// Get a new set of Sign for per-channel secrets. These MUST be unique even if you
 	/// restarted with some stale data!
 	///
@@ -441,6 +479,21 @@ pub trait KeysInterface {
 	/// The secret key used to sign the invoice is dependent on the [`Recipient`].
 	fn sign_invoice(&self, hrp_bytes: &[u8], invoice_data: &[u5], receipient: Recipient) -> Result<RecoverableSignature, ()>;
 
+	/// Sign a gossip message with the node secret key of [`Recipient::Node`].
+	///
+	/// Note that the `channel_announcement`s of our own channels are instead signed by their
+	/// channel signers, see [`BaseSign::sign_channel_announcement`].
+	fn sign_gossip_message(&self, msg: UnsignedGossipMessage) -> Result<Signature, ()>;
+
+	/// Sign an arbitrary message with the node secret key of [`Recipient::Node`], returning the
+	/// zbase32-encoded signature compatible with lnd's and c-lightning's `signmessage`.
+	///
+	/// This is used to prove ownership of our node id to third parties. Implementors may simply
+	/// call [`message_signing::sign`], which defines the exact format.
+	///
+	/// [`message_signing::sign`]: crate::util::message_signing::sign
+	fn sign_message(&self, msg: &[u8]) -> Result<String, ()>;
+
 	/// Get secret key material as bytes for use in encrypting and decrypting inbound payment data.
 	///
 	/// If the implementor of this trait supports [phantom node payments], then every node that is
@@ -644,8 +697,8 @@ impl BaseSign for InMemorySigner {
 		PublicKey::from_secret_key(secp_ctx, &commitment_secret)
 	}
 
-	fn release_commitment_secret(&self, idx: u64) -> [u8; 32] {
-		chan_utils::build_commitment_secret(&self.commitment_seed, idx)
+	fn release_commitment_secret(&self, idx: u64) -> Result<[u8; 32], ()> {
+		Ok(chan_utils::build_commitment_secret(&self.commitment_seed, idx))
 	}
 
 	fn validate_holder_commitment(&self, _holder_tx: &HolderCommitmentTransaction, _preimages: Vec<PaymentPreimage>) -> Result<(), ()> {
@@ -655,7 +708,7 @@ impl BaseSign for InMemorySigner {
 	fn pubkeys(&self) -> &ChannelPublicKeys { &self.holder_channel_pubkeys }
 	fn channel_keys_id(&self) -> [u8; 32] { self.channel_keys_id }
 
-	fn sign_counterparty_commitment(&self, commitment_tx: &CommitmentTransaction, _preimages: Vec<PaymentPreimage>, secp_ctx: &Secp256k1<secp256k1::All>) -> Result<(Signature, Vec<Signature>), ()> {
+	fn sign_counterparty_commitment(&self, commitment_tx: &CommitmentTransaction, _preimages: Vec<PaymentPreimage>, secp_ctx: &Secp256k1<secp256k1::All>) -> Result<(Signature, Vec<Signature>), SignerError> {
 		let trusted_tx = commitment_tx.trust();
 		let keys = trusted_tx.keys();
 
@@ -672,7 +725,7 @@ impl BaseSign for InMemorySigner {
 			let htlc_redeemscript = chan_utils::get_htlc_redeemscript(&htlc, self.opt_anchors(), &keys);
 			let htlc_sighashtype = if self.opt_anchors() { SigHashType::SinglePlusAnyoneCanPay } else { SigHashType::All };
 			let htlc_sighash = hash_to_message!(&bip143::SigHashCache::new(&htlc_tx).signature_hash(0, &htlc_redeemscript, htlc.amount_msat / 1000, htlc_sighashtype)[..]);
-			let holder_htlc_key = chan_utils::derive_private_key(&secp_ctx, &keys.per_commitment_point, &self.htlc_base_key).map_err(|_| ())?;
+			let holder_htlc_key = chan_utils::derive_private_key(&secp_ctx, &keys.per_commitment_point, &self.htlc_base_key).map_err(|_| SignerError::Rejected)?;
 			htlc_sigs.push(secp_ctx.sign(&htlc_sighash, &holder_htlc_key));
 		}
 
@@ -828,6 +881,171 @@ impl ReadableArgs<SecretKey> for InMemorySigner {
 	}
 }
 
+/// Builds an unsigned transaction spending the given descriptors to the given outputs, plus an
+/// output to the given change destination (if sufficient change value remains), returning it along
+/// with its expected maximum weight once signed.
+///
+/// See [`KeysManager::spend_spendable_outputs`] for when this fails.
+fn build_spend_transaction(descriptors: &[&SpendableOutputDescriptor], outputs: Vec<TxOut>, change_destination_script: Script, feerate_sat_per_1000_weight: u32) -> Result<(Transaction, usize), ()> {
+	let mut input = Vec::new();
+	let mut input_value = 0;
+	let mut witness_weight = 0;
+	let mut output_set = HashSet::with_capacity(descriptors.len());
+	for outp in descriptors {
+		match outp {
+			SpendableOutputDescriptor::StaticPaymentOutput(descriptor) => {
+				input.push(TxIn {
+					previous_output: descriptor.outpoint.into_bitcoin_outpoint(),
+					script_sig: Script::new(),
+					sequence: 0,
+					witness: Vec::new(),
+				});
+				witness_weight += StaticPaymentOutputDescriptor::MAX_WITNESS_LENGTH;
+				input_value += descriptor.output.value;
+				if !output_set.insert(descriptor.outpoint) { return Err(()); }
+			},
+			SpendableOutputDescriptor::DelayedPaymentOutput(descriptor) => {
+				input.push(TxIn {
+					previous_output: descriptor.outpoint.into_bitcoin_outpoint(),
+					script_sig: Script::new(),
+					sequence: descriptor.to_self_delay as u32,
+					witness: Vec::new(),
+				});
+				witness_weight += DelayedPaymentOutputDescriptor::MAX_WITNESS_LENGTH;
+				input_value += descriptor.output.value;
+				if !output_set.insert(descriptor.outpoint) { return Err(()); }
+			},
+			SpendableOutputDescriptor::StaticOutput { ref outpoint, ref output } => {
+				input.push(TxIn {
+					previous_output: outpoint.into_bitcoin_outpoint(),
+					script_sig: Script::new(),
+					sequence: 0,
+					witness: Vec::new(),
+				});
+				witness_weight += 1 + 73 + 34;
+				input_value += output.value;
+				if !output_set.insert(*outpoint) { return Err(()); }
+			}
+		}
+		if input_value > MAX_VALUE_MSAT / 1000 { return Err(()); }
+	}
+	let mut spend_tx = Transaction {
+		version: 2,
+		lock_time: 0,
+		input,
+		output: outputs,
+	};
+	let expected_max_weight =
+		transaction_utils::maybe_add_change_output(&mut spend_tx, input_value, witness_weight, feerate_sat_per_1000_weight, change_destination_script)?;
+	Ok((spend_tx, expected_max_weight))
+}
+
+/// The key material needed to create and sign transactions spending [`SpendableOutputDescriptor`]s
+/// generated by channels using a given [`KeysInterface`].
+trait SpendableOutputKeys {
+	/// Re-derives the signer of the channel with the given `channel_keys_id`.
+	fn channel_signer(&self, channel_value_satoshis: u64, channel_keys_id: &[u8; 32]) -> InMemorySigner;
+	/// Gets the secret key of the given [`SpendableOutputDescriptor::StaticOutput`] script, along
+	/// with the BIP 32 origin of its public key, if the script is ours.
+	fn static_output_key(&self, script_pubkey: &Script) -> Option<(SecretKey, KeySource)>;
+	/// Gets the BIP 32 origin of the payment key of the channel with the given `channel_keys_id`,
+	/// if it was derived using BIP 32.
+	fn payment_key_source(&self, channel_keys_id: &[u8; 32]) -> Option<KeySource>;
+}
+
+fn create_spendable_outputs_psbt<K: SpendableOutputKeys, C: Signing>(keys: &K, descriptors: &[&SpendableOutputDescriptor], outputs: Vec<TxOut>, change_destination_script: Script, feerate_sat_per_1000_weight: u32, secp_ctx: &Secp256k1<C>) -> Result<(PartiallySignedTransaction, usize), ()> {
+	let (spend_tx, expected_max_weight) = build_spend_transaction(descriptors, outputs, change_destination_script, feerate_sat_per_1000_weight)?;
+	let mut psbt = PartiallySignedTransaction::from_unsigned_tx(spend_tx).map_err(|_| ())?;
+	for (psbt_input, outp) in psbt.inputs.iter_mut().zip(descriptors.iter()) {
+		psbt_input.sighash_type = Some(SigHashType::All);
+		match outp {
+			SpendableOutputDescriptor::StaticPaymentOutput(descriptor) => {
+				if let Some(key_source) = keys.payment_key_source(&descriptor.channel_keys_id) {
+					let signer = keys.channel_signer(descriptor.channel_value_satoshis, &descriptor.channel_keys_id);
+					let payment_pubkey = bitcoin::PublicKey { compressed: true, key: signer.pubkeys().payment_point };
+					psbt_input.bip32_derivation.insert(payment_pubkey, key_source);
+				}
+				psbt_input.witness_utxo = Some(descriptor.output.clone());
+			},
+			SpendableOutputDescriptor::DelayedPaymentOutput(descriptor) => {
+				let signer = keys.channel_signer(descriptor.channel_value_satoshis, &descriptor.channel_keys_id);
+				let delayed_payment_pubkey = chan_utils::derive_public_key(secp_ctx, &descriptor.per_commitment_point, &signer.pubkeys().delayed_payment_basepoint)
+					.map_err(|_| ())?;
+				psbt_input.witness_script = Some(chan_utils::get_revokeable_redeemscript(&descriptor.revocation_pubkey, descriptor.to_self_delay, &delayed_payment_pubkey));
+				psbt_input.witness_utxo = Some(descriptor.output.clone());
+			},
+			SpendableOutputDescriptor::StaticOutput { ref output, .. } => {
+				if let Some((secret, key_source)) = keys.static_output_key(&output.script_pubkey) {
+					let pubkey = bitcoin::PublicKey { compressed: true, key: PublicKey::from_secret_key(secp_ctx, &secret) };
+					psbt_input.bip32_derivation.insert(pubkey, key_source);
+				}
+				psbt_input.witness_utxo = Some(output.clone());
+			},
+		}
+	}
+	Ok((psbt, expected_max_weight))
+}
+
+fn sign_spendable_outputs_psbt<K: SpendableOutputKeys, C: Signing>(keys: &K, descriptors: &[&SpendableOutputDescriptor], mut psbt: PartiallySignedTransaction, secp_ctx: &Secp256k1<C>) -> Result<PartiallySignedTransaction, ()> {
+	let mut keys_cache: Option<(InMemorySigner, [u8; 32])> = None;
+	for outp in descriptors {
+		let outpoint = match outp {
+			SpendableOutputDescriptor::StaticPaymentOutput(descriptor) => descriptor.outpoint,
+			SpendableOutputDescriptor::DelayedPaymentOutput(descriptor) => descriptor.outpoint,
+			SpendableOutputDescriptor::StaticOutput { outpoint, .. } => *outpoint,
+		};
+		let input_idx = psbt.global.unsigned_tx.input.iter()
+			.position(|input| input.previous_output == outpoint.into_bitcoin_outpoint())
+			.ok_or(())?;
+		let witness = match outp {
+			SpendableOutputDescriptor::StaticPaymentOutput(descriptor) => {
+				if keys_cache.is_none() || keys_cache.as_ref().unwrap().1 != descriptor.channel_keys_id {
+					keys_cache = Some((
+						keys.channel_signer(descriptor.channel_value_satoshis, &descriptor.channel_keys_id),
+						descriptor.channel_keys_id));
+				}
+				keys_cache.as_ref().unwrap().0.sign_counterparty_payment_input(&psbt.global.unsigned_tx, input_idx, &descriptor, &secp_ctx)?
+			},
+			SpendableOutputDescriptor::DelayedPaymentOutput(descriptor) => {
+				if keys_cache.is_none() || keys_cache.as_ref().unwrap().1 != descriptor.channel_keys_id {
+					keys_cache = Some((
+						keys.channel_signer(descriptor.channel_value_satoshis, &descriptor.channel_keys_id),
+						descriptor.channel_keys_id));
+				}
+				keys_cache.as_ref().unwrap().0.sign_dynamic_p2wsh_input(&psbt.global.unsigned_tx, input_idx, &descriptor, &secp_ctx)?
+			},
+			SpendableOutputDescriptor::StaticOutput { ref output, .. } => {
+				let (secret, _) = keys.static_output_key(&output.script_pubkey).ok_or(())?;
+				let spend_tx = &psbt.global.unsigned_tx;
+				if !spend_tx.input[input_idx].script_sig.is_empty() { return Err(()); }
+				let pubkey = bitcoin::PublicKey { compressed: true, key: PublicKey::from_secret_key(&secp_ctx, &secret) };
+				let witness_script = bitcoin::Address::p2pkh(&pubkey, Network::Testnet).script_pubkey();
+				let sighash = hash_to_message!(&bip143::SigHashCache::new(spend_tx).signature_hash(input_idx, &witness_script, output.value, SigHashType::All)[..]);
+				let sig = secp_ctx.sign(&sighash, &secret);
+				let mut witness = Vec::with_capacity(2);
+				witness.push(sig.serialize_der().to_vec());
+				witness[0].push(SigHashType::All as u8);
+				witness.push(pubkey.key.serialize().to_vec());
+				witness
+			},
+		};
+		psbt.inputs[input_idx].final_script_witness = Some(witness);
+	}
+	Ok(psbt)
+}
+
+fn spend_spendable_outputs<K: SpendableOutputKeys, C: Signing>(keys: &K, descriptors: &[&SpendableOutputDescriptor], outputs: Vec<TxOut>, change_destination_script: Script, feerate_sat_per_1000_weight: u32, secp_ctx: &Secp256k1<C>) -> Result<Transaction, ()> {
+	let (psbt, expected_max_weight) = create_spendable_outputs_psbt(keys, descriptors, outputs, change_destination_script, feerate_sat_per_1000_weight, secp_ctx)?;
+	let spend_tx = sign_spendable_outputs_psbt(keys, descriptors, psbt, secp_ctx)?.extract_tx();
+
+	debug_assert!(expected_max_weight >= spend_tx.get_weight());
+	// Note that witnesses with a signature vary somewhat in size, so allow
+	// `expected_max_weight` to overshoot by up to 3 bytes per input.
+	debug_assert!(expected_max_weight <= spend_tx.get_weight() + descriptors.len() * 3);
+
+	Ok(spend_tx)
+}
+
 /// Simple KeysInterface implementor that takes a 32-byte seed for use as a BIP 32 extended key
 /// and derives keys from that.
 ///
@@ -1003,129 +1221,91 @@ impl KeysManager {
 	/// May panic if the `SpendableOutputDescriptor`s were not generated by Channels which used
 	/// this KeysManager or one of the `InMemorySigner` created by this KeysManager.
 	pub fn spend_spendable_outputs<C: Signing>(&self, descriptors: &[&SpendableOutputDescriptor], outputs: Vec<TxOut>, change_destination_script: Script, feerate_sat_per_1000_weight: u32, secp_ctx: &Secp256k1<C>) -> Result<Transaction, ()> {
-		let mut input = Vec::new();
-		let mut input_value = 0;
-		let mut witness_weight = 0;
-		let mut output_set = HashSet::with_capacity(descriptors.len());
-		for outp in descriptors {
-			match outp {
-				SpendableOutputDescriptor::StaticPaymentOutput(descriptor) => {
-					input.push(TxIn {
-						previous_output: descriptor.outpoint.into_bitcoin_outpoint(),
-						script_sig: Script::new(),
-						sequence: 0,
-						witness: Vec::new(),
-					});
-					witness_weight += StaticPaymentOutputDescriptor::MAX_WITNESS_LENGTH;
-					input_value += descriptor.output.value;
-					if !output_set.insert(descriptor.outpoint) { return Err(()); }
-				},
-				SpendableOutputDescriptor::DelayedPaymentOutput(descriptor) => {
-					input.push(TxIn {
-						previous_output: descriptor.outpoint.into_bitcoin_outpoint(),
-						script_sig: Script::new(),
-						sequence: descriptor.to_self_delay as u32,
-						witness: Vec::new(),
-					});
-					witness_weight += DelayedPaymentOutputDescriptor::MAX_WITNESS_LENGTH;
-					input_value += descriptor.output.value;
-					if !output_set.insert(descriptor.outpoint) { return Err(()); }
-				},
-				SpendableOutputDescriptor::StaticOutput { ref outpoint, ref output } => {
-					input.push(TxIn {
-						previous_output: outpoint.into_bitcoin_outpoint(),
-						script_sig: Script::new(),
-						sequence: 0,
-						witness: Vec::new(),
-					});
-					witness_weight += 1 + 73 + 34;
-					input_value += output.value;
-					if !output_set.insert(*outpoint) { return Err(()); }
-				}
-			}
-			if input_value > MAX_VALUE_MSAT / 1000 { return Err(()); }
-		}
-		let mut spend_tx = Transaction {
-			version: 2,
-			lock_time: 0,
-			input,
-			output: outputs,
+		spend_spendable_outputs(self, descriptors, outputs, change_destination_script, feerate_sat_per_1000_weight, secp_ctx)
+	}
+
+	/// Creates a [`PartiallySignedTransaction`] which spends the given descriptors to the given
+	/// outputs, plus an output to the given change destination (if sufficient change value
+	/// remains), along with the expected maximum weight of the transaction once our inputs are
+	/// signed. The transaction will have a feerate, at least, of the given value.
+	///
+	/// Each input spending a descriptor is populated with its witness UTXO, witness script (where
+	/// applicable), sighash type and, for keys derived using BIP 32, the origin of its key. Before
+	/// signing, other parties may add their own inputs and outputs, e.g. to coin-select from
+	/// another wallet, after which [`Self::sign_spendable_outputs_psbt`] signs the inputs spending
+	/// the descriptors.
+	///
+	/// Returns `Err(())` in the same cases as [`Self::spend_spendable_outputs`].
+	pub fn create_spendable_outputs_psbt<C: Signing>(&self, descriptors: &[&SpendableOutputDescriptor], outputs: Vec<TxOut>, change_destination_script: Script, feerate_sat_per_1000_weight: u32, secp_ctx: &Secp256k1<C>) -> Result<(PartiallySignedTransaction, usize), ()> {
+		create_spendable_outputs_psbt(self, descriptors, outputs, change_destination_script, feerate_sat_per_1000_weight, secp_ctx)
+	}
+
+	/// Signs the inputs of the given [`PartiallySignedTransaction`] which spend the given
+	/// descriptors, setting their final witnesses. Any other inputs are left untouched, so that
+	/// they may be signed by their own wallet.
+	///
+	/// As our signatures commit to all inputs and outputs, this must only be called once the
+	/// transaction is otherwise complete.
+	///
+	/// Returns `Err(())` if an input spending one of the descriptors is missing or if a
+	/// descriptor's `script_pubkey` does not match the one we can spend.
+	///
+	/// May panic if the `SpendableOutputDescriptor`s were not generated by Channels which used
+	/// this KeysManager or one of the `InMemorySigner` created by this KeysManager.
+	pub fn sign_spendable_outputs_psbt<C: Signing>(&self, descriptors: &[&SpendableOutputDescriptor], psbt: PartiallySignedTransaction, secp_ctx: &Secp256k1<C>) -> Result<PartiallySignedTransaction, ()> {
+		sign_spendable_outputs_psbt(self, descriptors, psbt, secp_ctx)
+	}
+}
+
+impl SpendableOutputKeys for KeysManager {
+	fn channel_signer(&self, channel_value_satoshis: u64, channel_keys_id: &[u8; 32]) -> InMemorySigner {
+		self.derive_channel_keys(channel_value_satoshis, channel_keys_id)
+	}
+
+	fn static_output_key(&self, script_pubkey: &Script) -> Option<(SecretKey, KeySource)> {
+		let derivation_idx = if *script_pubkey == self.destination_script {
+			1
+		} else {
+			2
 		};
-		let expected_max_weight =
-			transaction_utils::maybe_add_change_output(&mut spend_tx, input_value, witness_weight, feerate_sat_per_1000_weight, change_destination_script)?;
-
-		let mut keys_cache: Option<(InMemorySigner, [u8; 32])> = None;
-		let mut input_idx = 0;
-		for outp in descriptors {
-			match outp {
-				SpendableOutputDescriptor::StaticPaymentOutput(descriptor) => {
-					if keys_cache.is_none() || keys_cache.as_ref().unwrap().1 != descriptor.channel_keys_id {
-						keys_cache = Some((
-							self.derive_channel_keys(descriptor.channel_value_satoshis, &descriptor.channel_keys_id),
-							descriptor.channel_keys_id));
-					}
-					spend_tx.input[input_idx].witness = keys_cache.as_ref().unwrap().0.sign_counterparty_payment_input(&spend_tx, input_idx, &descriptor, &secp_ctx)?;
-				},
-				SpendableOutputDescriptor::DelayedPaymentOutput(descriptor) => {
-					if keys_cache.is_none() || keys_cache.as_ref().unwrap().1 != descriptor.channel_keys_id {
-						keys_cache = Some((
-							self.derive_channel_keys(descriptor.channel_value_satoshis, &descriptor.channel_keys_id),
-							descriptor.channel_keys_id));
-					}
-					spend_tx.input[input_idx].witness = keys_cache.as_ref().unwrap().0.sign_dynamic_p2wsh_input(&spend_tx, input_idx, &descriptor, &secp_ctx)?;
-				},
-				SpendableOutputDescriptor::StaticOutput { ref output, .. } => {
-					let derivation_idx = if output.script_pubkey == self.destination_script {
-						1
-					} else {
-						2
-					};
-					let secret = {
-						// Note that when we aren't serializing the key, network doesn't matter
-						match ExtendedPrivKey::new_master(Network::Testnet, &self.seed) {
-							Ok(master_key) => {
-								match master_key.ckd_priv(&secp_ctx, ChildNumber::from_hardened_idx(derivation_idx).expect("key space exhausted")) {
-									Ok(key) => key,
-									Err(_) => panic!("Your RNG is busted"),
-								}
-							}
-							Err(_) => panic!("Your rng is busted"),
-						}
-					};
-					let pubkey = ExtendedPubKey::from_private(&secp_ctx, &secret).public_key;
-					if derivation_idx == 2 {
-						assert_eq!(pubkey.key, self.shutdown_pubkey);
-					}
-					let witness_script = bitcoin::Address::p2pkh(&pubkey, Network::Testnet).script_pubkey();
-					let payment_script = bitcoin::Address::p2wpkh(&pubkey, Network::Testnet).expect("uncompressed key found").script_pubkey();
-
-					if payment_script != output.script_pubkey { return Err(()); };
-
-					let sighash = hash_to_message!(&bip143::SigHashCache::new(&spend_tx).signature_hash(input_idx, &witness_script, output.value, SigHashType::All)[..]);
-					let sig = secp_ctx.sign(&sighash, &secret.private_key.key);
-					spend_tx.input[input_idx].witness.push(sig.serialize_der().to_vec());
-					spend_tx.input[input_idx].witness[0].push(SigHashType::All as u8);
-					spend_tx.input[input_idx].witness.push(pubkey.key.serialize().to_vec());
-				},
-			}
-			input_idx += 1;
+		// Note that when we aren't serializing the key, network doesn't matter
+		let master_key = match ExtendedPrivKey::new_master(Network::Testnet, &self.seed) {
+			Ok(master_key) => master_key,
+			Err(_) => panic!("Your rng is busted"),
+		};
+		let child_number = ChildNumber::from_hardened_idx(derivation_idx).expect("key space exhausted");
+		let secret = match master_key.ckd_priv(&self.secp_ctx, child_number) {
+			Ok(key) => key,
+			Err(_) => panic!("Your RNG is busted"),
+		};
+		let pubkey = ExtendedPubKey::from_private(&self.secp_ctx, &secret).public_key;
+		if derivation_idx == 2 {
+			assert_eq!(pubkey.key, self.shutdown_pubkey);
 		}
+		let payment_script = bitcoin::Address::p2wpkh(&pubkey, Network::Testnet).expect("uncompressed key found").script_pubkey();
+		if payment_script != *script_pubkey { return None; }
 
-		debug_assert!(expected_max_weight >= spend_tx.get_weight());
-		// Note that witnesses with a signature vary somewhat in size, so allow
-		// `expected_max_weight` to overshoot by up to 3 bytes per input.
-		debug_assert!(expected_max_weight <= spend_tx.get_weight() + descriptors.len() * 3);
+		Some((secret.private_key.key, (master_key.fingerprint(&self.secp_ctx), DerivationPath::from(vec![child_number]))))
+	}
 
-		Ok(spend_tx)
+	fn payment_key_source(&self, _channel_keys_id: &[u8; 32]) -> Option<KeySource> {
+		None
 	}
 }
 
 impl KeysInterface for KeysManager {
 	type Signer = InMemorySigner;
 
-	fn get_node_secret(&self, recipient: Recipient) -> Result<SecretKey, ()> {
+	fn get_node_id(&self, recipient: Recipient) -> Result<PublicKey, ()> {
 		match recipient {
-			Recipient::Node => Ok(self.node_secret.clone()),
+			Recipient::Node => Ok(PublicKey::from_secret_key(&self.secp_ctx, &self.node_secret)),
+			Recipient::PhantomNode => Err(())
+		}
+	}
+
+	fn ecdh(&self, recipient: Recipient, other_key: &PublicKey) -> Result<SharedSecret, ()> {
+		match recipient {
+			Recipient::Node => Ok(SharedSecret::new(other_key, &self.node_secret)),
 			Recipient::PhantomNode => Err(())
 		}
 	}
@@ -1134,12 +1314,12 @@ impl KeysInterface for KeysManager {
 		self.inbound_payment_key.clone()
 	}
 
-	fn get_destination_script(&self) -> Script {
-		self.destination_script.clone()
+	fn get_destination_script(&self) -> Result<Script, ()> {
+		Ok(self.destination_script.clone())
 	}
 
-	fn get_shutdown_scriptpubkey(&self) -> ShutdownScript {
-		ShutdownScript::new_p2wpkh_from_pubkey(self.shutdown_pubkey.clone())
+	fn get_shutdown_scriptpubkey(&self) -> Result<ShutdownScript, ()> {
+		Ok(ShutdownScript::new_p2wpkh_from_pubkey(self.shutdown_pubkey.clone()))
 	}
 
 	fn get_channel_signer(&self, _inbound: bool, channel_value_satoshis: u64) -> Self::Signer {
@@ -1170,10 +1350,19 @@ impl KeysInterface for KeysManager {
 	fn sign_invoice(&self, hrp_bytes: &[u8], invoice_data: &[u5], recipient: Recipient) -> Result<RecoverableSignature, ()> {
 		let preimage = construct_invoice_preimage(&hrp_bytes, &invoice_data);
 		let secret = match recipient {
-			Recipient::Node => self.get_node_secret(Recipient::Node)?,
+			Recipient::Node => &self.node_secret,
 			Recipient::PhantomNode => return Err(()),
 		};
-		Ok(self.secp_ctx.sign_recoverable(&hash_to_message!(&Sha256::hash(&preimage)), &secret))
+		Ok(self.secp_ctx.sign_recoverable(&hash_to_message!(&Sha256::hash(&preimage)), secret))
+	}
+
+	fn sign_gossip_message(&self, msg: UnsignedGossipMessage) -> Result<Signature, ()> {
+		let msg_hash = hash_to_message!(&Sha256dHash::hash(&msg.encode()[..])[..]);
+		Ok(self.secp_ctx.sign(&msg_hash, &self.node_secret))
+	}
+
+	fn sign_message(&self, msg: &[u8]) -> Result<String, ()> {
+		message_signing::sign(msg, &self.node_secret).map_err(|_| ())
 	}
 }
 
@@ -1207,10 +1396,17 @@ pub struct PhantomKeysManager {
 impl KeysInterface for PhantomKeysManager {
 	type Signer = InMemorySigner;
 
-	fn get_node_secret(&self, recipient: Recipient) -> Result<SecretKey, ()> {
+	fn get_node_id(&self, recipient: Recipient) -> Result<PublicKey, ()> {
+		match recipient {
+			Recipient::Node => self.inner.get_node_id(Recipient::Node),
+			Recipient::PhantomNode => Ok(PublicKey::from_secret_key(&self.inner.secp_ctx, &self.phantom_secret)),
+		}
+	}
+
+	fn ecdh(&self, recipient: Recipient, other_key: &PublicKey) -> Result<SharedSecret, ()> {
 		match recipient {
-			Recipient::Node => self.inner.get_node_secret(Recipient::Node),
-			Recipient::PhantomNode => Ok(self.phantom_secret.clone()),
+			Recipient::Node => self.inner.ecdh(Recipient::Node, other_key),
+			Recipient::PhantomNode => Ok(SharedSecret::new(other_key, &self.phantom_secret)),
 		}
 	}
 
@@ -1218,11 +1414,11 @@ impl KeysInterface for PhantomKeysManager {
 		self.inbound_payment_key.clone()
 	}
 
-	fn get_destination_script(&self) -> Script {
+	fn get_destination_script(&self) -> Result<Script, ()> {
 		self.inner.get_destination_script()
 	}
 
-	fn get_shutdown_scriptpubkey(&self) -> ShutdownScript {
+	fn get_shutdown_scriptpubkey(&self) -> Result<ShutdownScript, ()> {
 		self.inner.get_shutdown_scriptpubkey()
 	}
 
@@ -1240,8 +1436,19 @@ impl KeysInterface for PhantomKeysManager {
 
 	fn sign_invoice(&self, hrp_bytes: &[u8], invoice_data: &[u5], recipient: Recipient) -> Result<RecoverableSignature, ()> {
 		let preimage = construct_invoice_preimage(&hrp_bytes, &invoice_data);
-		let secret = self.get_node_secret(recipient)?;
-		Ok(self.inner.secp_ctx.sign_recoverable(&hash_to_message!(&Sha256::hash(&preimage)), &secret))
+		let secret = match recipient {
+			Recipient::Node => &self.inner.node_secret,
+			Recipient::PhantomNode => &self.phantom_secret,
+		};
+		Ok(self.inner.secp_ctx.sign_recoverable(&hash_to_message!(&Sha256::hash(&preimage)), secret))
+	}
+
+	fn sign_gossip_message(&self, msg: UnsignedGossipMessage) -> Result<Signature, ()> {
+		self.inner.sign_gossip_message(msg)
+	}
+
+	fn sign_message(&self, msg: &[u8]) -> Result<String, ()> {
+		self.inner.sign_message(msg)
 	}
 }
 
@@ -1271,14 +1478,483 @@ impl PhantomKeysManager {
 		self.inner.spend_spendable_outputs(descriptors, outputs, change_destination_script, feerate_sat_per_1000_weight, secp_ctx)
 	}
 
+	/// See [`KeysManager::create_spendable_outputs_psbt`] for documentation on this method.
+	pub fn create_spendable_outputs_psbt<C: Signing>(&self, descriptors: &[&SpendableOutputDescriptor], outputs: Vec<TxOut>, change_destination_script: Script, feerate_sat_per_1000_weight: u32, secp_ctx: &Secp256k1<C>) -> Result<(PartiallySignedTransaction, usize), ()> {
+		self.inner.create_spendable_outputs_psbt(descriptors, outputs, change_destination_script, feerate_sat_per_1000_weight, secp_ctx)
+	}
+
+	/// See [`KeysManager::sign_spendable_outputs_psbt`] for documentation on this method.
+	pub fn sign_spendable_outputs_psbt<C: Signing>(&self, descriptors: &[&SpendableOutputDescriptor], psbt: PartiallySignedTransaction, secp_ctx: &Secp256k1<C>) -> Result<PartiallySignedTransaction, ()> {
+		self.inner.sign_spendable_outputs_psbt(descriptors, psbt, secp_ctx)
+	}
+
 	/// See [`KeysManager::derive_channel_keys`] for documentation on this method.
 	pub fn derive_channel_keys(&self, channel_value_satoshis: u64, params: &[u8; 32]) -> InMemorySigner {
 		self.inner.derive_channel_keys(channel_value_satoshis, params)
 	}
 }
 
+/// The number of unused child indices past the last one handed out which are searched when
+/// looking for the key of a [`SpendableOutputDescriptor::StaticOutput`] in
+/// [`Bip32KeysManager::spend_spendable_outputs`]. This matches the gap limit commonly used by
+/// BIP 32 wallets.
+pub const BIP32_GAP_LIMIT: u32 = 20;
+
+/// A KeysInterface implementor which derives all of its keys from a BIP 32 extended private key
+/// for a single account, e.g. one derived at `m/1017'/0'/0'` from a wallet's seed.
+///
+/// Unlike [`KeysManager`], each channel uses its own destination and shutdown scripts, and all
+/// keys which control funds LDK may hand back to us are derived at non-hardened paths below the
+/// account key. Thus a wallet restored from the account key alone (or a watch-only wallet using
+/// the [`Self::descriptors`]) can find them on-chain without any of our serialized state:
+///
+/// account/0' is your node_id
+/// account/1/i are the keys of the scripts returned by [`KeysInterface::get_destination_script`]
+/// account/2/i are the keys of the scripts returned by [`KeysInterface::get_shutdown_scriptpubkey`]
+/// account/3/i are the payment keys of our channels, to which our counterparty's commitment
+/// transactions pay our balance (see [`SpendableOutputDescriptor::StaticPaymentOutput`])
+///
+/// The remaining channel secrets are derived from account/4'/i' together with the starting time
+/// passed to [`Self::new`], while account/5' and account/6' are used for secure random bytes and
+/// inbound payment key material, respectively.
+///
+/// Note that on channels which use anchor outputs our counterparty's commitment transactions pay
+/// our balance to a P2WSH script rather than directly to the payment key.
+pub struct Bip32KeysManager {
+	secp_ctx: Secp256k1<secp256k1::All>,
+	account_key: ExtendedPrivKey,
+	node_secret: SecretKey,
+	inbound_payment_key: KeyMaterial,
+
+	destination_master_key: ExtendedPrivKey,
+	destination_child_index: AtomicUsize,
+	shutdown_master_key: ExtendedPrivKey,
+	shutdown_child_index: AtomicUsize,
+	payment_master_key: ExtendedPrivKey,
+	channel_master_key: ExtendedPrivKey,
+	channel_child_index: AtomicUsize,
+
+	rand_bytes_master_key: ExtendedPrivKey,
+	rand_bytes_child_index: AtomicUsize,
+	rand_bytes_unique_start: Sha256State,
+
+	starting_time_secs: u64,
+	starting_time_nanos: u32,
+}
+
+impl Bip32KeysManager {
+	/// Constructs a Bip32KeysManager from the extended private key of an account.
+	///
+	/// `next_child_index` is the first child index which has not yet been handed out for a
+	/// destination script, shutdown script or channel. As it is not persisted by this struct, you
+	/// should either persist [`Self::next_child_index`] or, when restoring from a backup, find the
+	/// first index after the last one used on-chain, scanning the [`Self::descriptors`] with a gap
+	/// limit as is usual for BIP 32 wallets. Re-using an index does not put funds at risk, but
+	/// links the outputs or channels using it on-chain.
+	///
+	/// See [`KeysManager::new`] for more information on `starting_time_secs` and
+	/// `starting_time_nanos`, which must be unique to each run.
+	pub fn new(account_key: &ExtendedPrivKey, next_child_index: u32, starting_time_secs: u64, starting_time_nanos: u32) -> Self {
+		let secp_ctx = Secp256k1::new();
+		let derive_child = |child_number: ChildNumber| account_key.ckd_priv(&secp_ctx, child_number).expect("Your RNG is busted");
+		let node_secret = derive_child(ChildNumber::from_hardened_idx(0).unwrap()).private_key.key;
+		let destination_master_key = derive_child(ChildNumber::from_normal_idx(1).unwrap());
+		let shutdown_master_key = derive_child(ChildNumber::from_normal_idx(2).unwrap());
+		let payment_master_key = derive_child(ChildNumber::from_normal_idx(3).unwrap());
+		let channel_master_key = derive_child(ChildNumber::from_hardened_idx(4).unwrap());
+		let rand_bytes_master_key = derive_child(ChildNumber::from_hardened_idx(5).unwrap());
+		let inbound_payment_key = derive_child(ChildNumber::from_hardened_idx(6).unwrap()).private_key.key;
+		let mut inbound_pmt_key_bytes = [0; 32];
+		inbound_pmt_key_bytes.copy_from_slice(&inbound_payment_key[..]);
+
+		let mut rand_bytes_unique_start = Sha256::engine();
+		rand_bytes_unique_start.input(&byte_utils::be64_to_array(starting_time_secs));
+		rand_bytes_unique_start.input(&byte_utils::be32_to_array(starting_time_nanos));
+		rand_bytes_unique_start.input(&account_key.private_key.key[..]);
+
+		let mut res = Bip32KeysManager {
+			secp_ctx,
+			account_key: *account_key,
+			node_secret,
+			inbound_payment_key: KeyMaterial(inbound_pmt_key_bytes),
+
+			destination_master_key,
+			destination_child_index: AtomicUsize::new(next_child_index as usize),
+			shutdown_master_key,
+			shutdown_child_index: AtomicUsize::new(next_child_index as usize),
+			payment_master_key,
+			channel_master_key,
+			channel_child_index: AtomicUsize::new(next_child_index as usize),
+
+			rand_bytes_master_key,
+			rand_bytes_child_index: AtomicUsize::new(0),
+			rand_bytes_unique_start,
+
+			starting_time_secs,
+			starting_time_nanos,
+		};
+		let secp_seed = res.get_secure_random_bytes();
+		res.secp_ctx.seeded_randomize(&secp_seed);
+		res
+	}
+
+	/// Gets the first child index which has not yet been handed out, to be passed to
+	/// [`Self::new`] on the next start.
+	pub fn next_child_index(&self) -> u32 {
+		let destination_index = self.destination_child_index.load(Ordering::Acquire);
+		let shutdown_index = self.shutdown_child_index.load(Ordering::Acquire);
+		let channel_index = self.channel_child_index.load(Ordering::Acquire);
+		cmp::max(destination_index, cmp::max(shutdown_index, channel_index)) as u32
+	}
+
+	/// Gets output descriptors describing all destination scripts, shutdown scripts and payment
+	/// keys handed out by this struct, for use by a watch-only wallet.
+	///
+	/// The descriptors use the extended public key of the account, and thus are only valid for
+	/// the network the account key was created for.
+	pub fn descriptors(&self) -> Vec<String> {
+		let account_pubkey = ExtendedPubKey::from_private(&self.secp_ctx, &self.account_key);
+		vec![
+			format!("wpkh({}/1/*)", account_pubkey),
+			format!("wpkh({}/2/*)", account_pubkey),
+			format!("wpkh({}/3/*)", account_pubkey),
+		]
+	}
+
+	fn derive_normal_child(&self, master_key: &ExtendedPrivKey, child_index: usize) -> SecretKey {
+		assert!(child_index < (1 << 31));
+		master_key.ckd_priv(&self.secp_ctx, ChildNumber::from_normal_idx(child_index as u32).unwrap())
+			.expect("Your RNG is busted").private_key.key
+	}
+
+	fn p2wpkh_script(&self, key: &SecretKey) -> Script {
+		let wpubkey_hash = WPubkeyHash::hash(&PublicKey::from_secret_key(&self.secp_ctx, key).serialize());
+		Builder::new().push_opcode(opcodes::all::OP_PUSHBYTES_0)
+		              .push_slice(&wpubkey_hash.into_inner())
+		              .into_script()
+	}
+
+	/// Derive an old Sign containing per-channel secrets based on a key derivation parameters.
+	///
+	/// See [`KeysManager::derive_channel_keys`] for more information. Only the payment key is
+	/// derived solely from the child index in `params`; the remaining secrets also depend on the
+	/// starting time of the run which created the channel, keeping them unique even if the same
+	/// child index is handed out again after restarting with stale data.
+	pub fn derive_channel_keys(&self, channel_value_satoshis: u64, params: &[u8; 32]) -> InMemorySigner {
+		let chan_id = byte_utils::slice_to_be64(&params[0..8]);
+		assert!(chan_id < (1 << 31)); // Otherwise the params field wasn't created by us
+		let payment_key = self.derive_normal_child(&self.payment_master_key, chan_id as usize);
+
+		let mut unique_start = Sha256::engine();
+		unique_start.input(params);
+		let child_privkey = self.channel_master_key.ckd_priv(&self.secp_ctx, ChildNumber::from_hardened_idx(chan_id as u32).unwrap()).expect("Your RNG is busted");
+		unique_start.input(&child_privkey.private_key.key[..]);
+		let seed = Sha256::from_engine(unique_start).into_inner();
+
+		let commitment_seed = {
+			let mut sha = Sha256::engine();
+			sha.input(&seed);
+			sha.input(&b"commitment seed"[..]);
+			Sha256::from_engine(sha).into_inner()
+		};
+		macro_rules! key_step {
+			($info: expr, $prev_key: expr) => {{
+				let mut sha = Sha256::engine();
+				sha.input(&seed);
+				sha.input(&$prev_key[..]);
+				sha.input(&$info[..]);
+				SecretKey::from_slice(&Sha256::from_engine(sha).into_inner()).expect("SHA-256 is busted")
+			}}
+		}
+		let funding_key = key_step!(b"funding key", commitment_seed);
+		let revocation_base_key = key_step!(b"revocation base key", funding_key);
+		let delayed_payment_base_key = key_step!(b"delayed payment base key", revocation_base_key);
+		let htlc_base_key = key_step!(b"HTLC base key", delayed_payment_base_key);
+
+		InMemorySigner::new(
+			&self.secp_ctx,
+			self.node_secret,
+			funding_key,
+			revocation_base_key,
+			payment_key,
+			delayed_payment_base_key,
+			htlc_base_key,
+			commitment_seed,
+			channel_value_satoshis,
+			params.clone()
+		)
+	}
+
+	/// Creates a Transaction which spends the given descriptors to the given outputs, plus an
+	/// output to the given change destination (if sufficient change value remains). The
+	/// transaction will have a feerate, at least, of the given value.
+	///
+	/// See [`KeysManager::spend_spendable_outputs`] for when this fails.
+	///
+	/// May panic if the `SpendableOutputDescriptor`s were not generated by Channels which used
+	/// this Bip32KeysManager or one of the `InMemorySigner` created by this Bip32KeysManager.
+	pub fn spend_spendable_outputs<C: Signing>(&self, descriptors: &[&SpendableOutputDescriptor], outputs: Vec<TxOut>, change_destination_script: Script, feerate_sat_per_1000_weight: u32, secp_ctx: &Secp256k1<C>) -> Result<Transaction, ()> {
+		spend_spendable_outputs(self, descriptors, outputs, change_destination_script, feerate_sat_per_1000_weight, secp_ctx)
+	}
+
+	/// See [`KeysManager::create_spendable_outputs_psbt`] for documentation on this method.
+	///
+	/// The key origins of destination scripts, shutdown scripts and payment keys are given
+	/// relative to the account key, i.e. with its fingerprint and a path below it.
+	pub fn create_spendable_outputs_psbt<C: Signing>(&self, descriptors: &[&SpendableOutputDescriptor], outputs: Vec<TxOut>, change_destination_script: Script, feerate_sat_per_1000_weight: u32, secp_ctx: &Secp256k1<C>) -> Result<(PartiallySignedTransaction, usize), ()> {
+		create_spendable_outputs_psbt(self, descriptors, outputs, change_destination_script, feerate_sat_per_1000_weight, secp_ctx)
+	}
+
+	/// See [`KeysManager::sign_spendable_outputs_psbt`] for documentation on this method.
+	pub fn sign_spendable_outputs_psbt<C: Signing>(&self, descriptors: &[&SpendableOutputDescriptor], psbt: PartiallySignedTransaction, secp_ctx: &Secp256k1<C>) -> Result<PartiallySignedTransaction, ()> {
+		sign_spendable_outputs_psbt(self, descriptors, psbt, secp_ctx)
+	}
+
+	fn account_key_source(&self, path: [u32; 2]) -> KeySource {
+		let path: Vec<ChildNumber> = path.iter().map(|idx| ChildNumber::from_normal_idx(*idx).unwrap()).collect();
+		(self.account_key.fingerprint(&self.secp_ctx), DerivationPath::from(path))
+	}
+}
+
+impl SpendableOutputKeys for Bip32KeysManager {
+	fn channel_signer(&self, channel_value_satoshis: u64, channel_keys_id: &[u8; 32]) -> InMemorySigner {
+		self.derive_channel_keys(channel_value_satoshis, channel_keys_id)
+	}
+
+	/// Searches the destination and shutdown scripts up to [`BIP32_GAP_LIMIT`] indices past the
+	/// last one handed out.
+	fn static_output_key(&self, script_pubkey: &Script) -> Option<(SecretKey, KeySource)> {
+		let master_keys = [
+			(1, &self.destination_master_key, &self.destination_child_index),
+			(2, &self.shutdown_master_key, &self.shutdown_child_index),
+		];
+		for (master_idx, master_key, child_index) in master_keys.iter() {
+			let max_index = cmp::min(child_index.load(Ordering::Acquire) + BIP32_GAP_LIMIT as usize, 1 << 31);
+			for index in 0..max_index {
+				let key = self.derive_normal_child(master_key, index);
+				if self.p2wpkh_script(&key) == *script_pubkey {
+					return Some((key, self.account_key_source([*master_idx, index as u32])));
+				}
+			}
+		}
+		None
+	}
+
+	fn payment_key_source(&self, channel_keys_id: &[u8; 32]) -> Option<KeySource> {
+		let chan_id = byte_utils::slice_to_be64(&channel_keys_id[0..8]);
+		if chan_id >= (1 << 31) { return None; }
+		Some(self.account_key_source([3, chan_id as u32]))
+	}
+}
+
+impl KeysInterface for Bip32KeysManager {
+	type Signer = InMemorySigner;
+
+	fn get_node_id(&self, recipient: Recipient) -> Result<PublicKey, ()> {
+		match recipient {
+			Recipient::Node => Ok(PublicKey::from_secret_key(&self.secp_ctx, &self.node_secret)),
+			Recipient::PhantomNode => Err(())
+		}
+	}
+
+	fn ecdh(&self, recipient: Recipient, other_key: &PublicKey) -> Result<SharedSecret, ()> {
+		match recipient {
+			Recipient::Node => Ok(SharedSecret::new(other_key, &self.node_secret)),
+			Recipient::PhantomNode => Err(())
+		}
+	}
+
+	fn get_inbound_payment_key_material(&self) -> KeyMaterial {
+		self.inbound_payment_key.clone()
+	}
+
+	fn get_destination_script(&self) -> Result<Script, ()> {
+		let child_ix = self.destination_child_index.fetch_add(1, Ordering::AcqRel);
+		Ok(self.p2wpkh_script(&self.derive_normal_child(&self.destination_master_key, child_ix)))
+	}
+
+	fn get_shutdown_scriptpubkey(&self) -> Result<ShutdownScript, ()> {
+		let child_ix = self.shutdown_child_index.fetch_add(1, Ordering::AcqRel);
+		let shutdown_key = self.derive_normal_child(&self.shutdown_master_key, child_ix);
+		Ok(ShutdownScript::new_p2wpkh_from_pubkey(PublicKey::from_secret_key(&self.secp_ctx, &shutdown_key)))
+	}
+
+	fn get_channel_signer(&self, _inbound: bool, channel_value_satoshis: u64) -> Self::Signer {
+		let child_ix = self.channel_child_index.fetch_add(1, Ordering::AcqRel);
+		assert!(child_ix < (1 << 31));
+		let mut id = [0; 32];
+		id[0..8].copy_from_slice(&byte_utils::be64_to_array(child_ix as u64));
+		id[8..16].copy_from_slice(&byte_utils::be64_to_array(self.starting_time_nanos as u64));
+		id[16..24].copy_from_slice(&byte_utils::be64_to_array(self.starting_time_secs));
+		self.derive_channel_keys(channel_value_satoshis, &id)
+	}
+
+	fn get_secure_random_bytes(&self) -> [u8; 32] {
+		let mut sha = self.rand_bytes_unique_start.clone();
+
+		let child_ix = self.rand_bytes_child_index.fetch_add(1, Ordering::AcqRel);
+		let child_privkey = self.rand_bytes_master_key.ckd_priv(&self.secp_ctx, ChildNumber::from_hardened_idx(child_ix as u32).expect("key space exhausted")).expect("Your RNG is busted");
+		sha.input(&child_privkey.private_key.key[..]);
+
+		sha.input(b"Unique Secure Random Bytes Salt");
+		Sha256::from_engine(sha).into_inner()
+	}
+
+	fn read_chan_signer(&self, reader: &[u8]) -> Result<Self::Signer, DecodeError> {
+		InMemorySigner::read(&mut io::Cursor::new(reader), self.node_secret.clone())
+	}
+
+	fn sign_invoice(&self, hrp_bytes: &[u8], invoice_data: &[u5], recipient: Recipient) -> Result<RecoverableSignature, ()> {
+		let preimage = construct_invoice_preimage(&hrp_bytes, &invoice_data);
+		let secret = match recipient {
+			Recipient::Node => &self.node_secret,
+			Recipient::PhantomNode => return Err(()),
+		};
+		Ok(self.secp_ctx.sign_recoverable(&hash_to_message!(&Sha256::hash(&preimage)), secret))
+	}
+
+	fn sign_gossip_message(&self, msg: UnsignedGossipMessage) -> Result<Signature, ()> {
+		let msg_hash = hash_to_message!(&Sha256dHash::hash(&msg.encode()[..])[..]);
+		Ok(self.secp_ctx.sign(&msg_hash, &self.node_secret))
+	}
+
+	fn sign_message(&self, msg: &[u8]) -> Result<String, ()> {
+		message_signing::sign(msg, &self.node_secret).map_err(|_| ())
+	}
+}
+
 // Ensure that BaseSign can have a vtable
 #[test]
 pub fn dyn_sign() {
 	let _signer: Box<dyn BaseSign>;
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn account_key() -> ExtendedPrivKey {
+		let secp_ctx = Secp256k1::new();
+		let path = [ChildNumber::from_hardened_idx(1017).unwrap(), ChildNumber::from_hardened_idx(1).unwrap(), ChildNumber::from_hardened_idx(0).unwrap()];
+		ExtendedPrivKey::new_master(Network::Testnet, &[42; 32]).unwrap().derive_priv(&secp_ctx, &path).unwrap()
+	}
+
+	#[test]
+	fn test_bip32_keys_manager_is_deterministic() {
+		let secp_ctx = Secp256k1::new();
+		let keys_manager = Bip32KeysManager::new(&account_key(), 0, 1, 2);
+		let destination_script = keys_manager.get_destination_script().unwrap();
+		assert_ne!(keys_manager.get_destination_script().unwrap(), destination_script);
+		let shutdown_script = keys_manager.get_shutdown_scriptpubkey().unwrap();
+		assert!(keys_manager.get_shutdown_scriptpubkey().unwrap() != shutdown_script);
+		let signer = keys_manager.get_channel_signer(false, 1_000_000);
+		assert_eq!(keys_manager.next_child_index(), 2);
+
+		// A manager restored from the account key hands out the same scripts and payment keys,
+		// while the remaining channel keys differ as it was started at a different time.
+		let restored = Bip32KeysManager::new(&account_key(), 0, 3, 4);
+		assert_eq!(restored.get_node_id(Recipient::Node), keys_manager.get_node_id(Recipient::Node));
+		assert_eq!(restored.get_destination_script().unwrap(), destination_script);
+		assert!(restored.get_shutdown_scriptpubkey().unwrap() == shutdown_script);
+		let restored_signer = restored.get_channel_signer(false, 1_000_000);
+		assert_eq!(restored_signer.pubkeys().payment_point, signer.pubkeys().payment_point);
+		assert_ne!(restored_signer.pubkeys().funding_pubkey, signer.pubkeys().funding_pubkey);
+		assert_ne!(restored_signer.pubkeys().revocation_basepoint, signer.pubkeys().revocation_basepoint);
+		assert_ne!(restored_signer.channel_keys_id(), signer.channel_keys_id());
+		assert!(restored.derive_channel_keys(1_000_000, &signer.channel_keys_id()).pubkeys() == signer.pubkeys());
+
+		// The payment keys can be found using only the account's extended public key.
+		let account_pubkey = ExtendedPubKey::from_private(&secp_ctx, &account_key());
+		let payment_pubkey = account_pubkey.ckd_pub(&secp_ctx, ChildNumber::from_normal_idx(3).unwrap()).unwrap()
+			.ckd_pub(&secp_ctx, ChildNumber::from_normal_idx(0).unwrap()).unwrap();
+		assert_eq!(payment_pubkey.public_key.key, signer.pubkeys().payment_point);
+		assert_eq!(keys_manager.descriptors()[2], format!("wpkh({}/3/*)", account_pubkey));
+	}
+
+	#[test]
+	fn test_bip32_keys_manager_spends_static_outputs() {
+		let secp_ctx = Secp256k1::new();
+		let keys_manager = Bip32KeysManager::new(&account_key(), 0, 1, 2);
+		// Skip an index to make sure scripts past the first are found as well.
+		keys_manager.get_destination_script().unwrap();
+		let tx = Transaction {
+			version: 2,
+			lock_time: 0,
+			input: Vec::new(),
+			output: vec![
+				TxOut { value: 10_000, script_pubkey: keys_manager.get_destination_script().unwrap() },
+				TxOut { value: 20_000, script_pubkey: keys_manager.get_shutdown_scriptpubkey().unwrap().into_inner() },
+				TxOut { value: 30_000, script_pubkey: Builder::new().push_int(0).push_slice(&[42; 20]).into_script() },
+			],
+		};
+		let descriptors: Vec<_> = tx.output.iter().enumerate().map(|(idx, output)| SpendableOutputDescriptor::StaticOutput {
+			outpoint: OutPoint { txid: tx.txid(), index: idx as u16 },
+			output: output.clone(),
+		}).collect();
+		let change_script = Builder::new().push_opcode(opcodes::all::OP_RETURN).into_script();
+
+		// The outputs can be spent by a manager restored from the account key alone.
+		let restored = Bip32KeysManager::new(&account_key(), 0, 3, 4);
+		let spend_tx = restored.spend_spendable_outputs(&[&descriptors[0], &descriptors[1]], Vec::new(), change_script.clone(), 253, &secp_ctx).unwrap();
+		spend_tx.verify(|outpoint| {
+			if outpoint.txid == tx.txid() { tx.output.get(outpoint.vout as usize).cloned() } else { None }
+		}).unwrap();
+
+		// Outputs to scripts we never handed out cannot be spent.
+		assert!(restored.spend_spendable_outputs(&[&descriptors[2]], Vec::new(), change_script, 253, &secp_ctx).is_err());
+	}
+
+	#[test]
+	fn test_spendable_outputs_psbt() {
+		let secp_ctx = Secp256k1::new();
+		let keys_manager = KeysManager::new(&[42; 32], 1, 2);
+		let tx = Transaction {
+			version: 2,
+			lock_time: 0,
+			input: Vec::new(),
+			output: vec![
+				TxOut { value: 10_000, script_pubkey: keys_manager.get_destination_script().unwrap() },
+				TxOut { value: 20_000, script_pubkey: keys_manager.get_shutdown_scriptpubkey().unwrap().into_inner() },
+			],
+		};
+		let descriptors: Vec<_> = tx.output.iter().enumerate().map(|(idx, output)| SpendableOutputDescriptor::StaticOutput {
+			outpoint: OutPoint { txid: tx.txid(), index: idx as u16 },
+			output: output.clone(),
+		}).collect();
+		let change_script = Builder::new().push_opcode(opcodes::all::OP_RETURN).into_script();
+
+		let (mut psbt, _) = keys_manager.create_spendable_outputs_psbt(&[&descriptors[0], &descriptors[1]], Vec::new(), change_script.clone(), 253, &secp_ctx).unwrap();
+		assert_eq!(psbt.inputs.len(), 2);
+		for (idx, psbt_input) in psbt.inputs.iter().enumerate() {
+			assert_eq!(psbt_input.witness_utxo, Some(tx.output[idx].clone()));
+			assert_eq!(psbt_input.sighash_type, Some(SigHashType::All));
+			let (_, path) = psbt_input.bip32_derivation.values().next().unwrap();
+			assert_eq!(*path, DerivationPath::from(vec![ChildNumber::from_hardened_idx(idx as u32 + 1).unwrap()]));
+		}
+
+		// Add an input from another wallet, which must be left for it to sign.
+		psbt.global.unsigned_tx.input.push(TxIn {
+			previous_output: bitcoin::OutPoint { txid: tx.txid(), vout: 2 },
+			script_sig: Script::new(),
+			sequence: 0,
+			witness: Vec::new(),
+		});
+		psbt.inputs.push(Default::default());
+
+		let psbt = keys_manager.sign_spendable_outputs_psbt(&[&descriptors[1], &descriptors[0]], psbt, &secp_ctx).unwrap();
+		assert!(psbt.inputs[2].final_script_witness.is_none());
+		let spend_tx = psbt.extract_tx();
+		let serialized_tx = bitcoin::consensus::encode::serialize(&spend_tx);
+		for idx in 0..2 {
+			tx.output[idx].script_pubkey.verify(idx, tx.output[idx].value, &serialized_tx).unwrap();
+		}
+
+		// Inputs spending descriptors which aren't part of the transaction can't be signed.
+		let other_descriptor = SpendableOutputDescriptor::StaticOutput {
+			outpoint: OutPoint { txid: tx.txid(), index: 3 },
+			output: tx.output[0].clone(),
+		};
+		let (psbt, _) = keys_manager.create_spendable_outputs_psbt(&[&descriptors[0]], Vec::new(), change_script, 253, &secp_ctx).unwrap();
+		assert!(keys_manager.sign_spendable_outputs_psbt(&[&other_descriptor], psbt, &secp_ctx).is_err());
+	}
+}
@@ -0,0 +1,133 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Tests for asynchronous signing, i.e. a signer which is not always able to immediately provide
+//! the signatures we ask it for.
+
+use ln::features::InitFeatures;
+use ln::msgs::ChannelMessageHandler;
+use util::events::{ClosureReason, MessageSendEvent, MessageSendEventsProvider};
+
+use ln::functional_test_utils::*;
+
+fn set_signer_available<'a, 'b, 'c>(node: &Node<'a, 'b, 'c>, channel_id: &[u8; 32], available: bool) {
+	let chan_lock = node.node.channel_state.lock().unwrap();
+	let chan = chan_lock.by_id.get(channel_id).unwrap();
+	chan.get_signer().get_enforcement_state().signer_unavailable = !available;
+}
+
+fn do_test_async_commitment_signature_for_payment(disconnect: bool) {
+	// Test that if our signer is unable to sign a new counterparty commitment when we send a
+	// payment, we hold the update_add_htlc and commitment_signed until the signer is unblocked,
+	// optionally with a disconnect in between.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let channel_id = create_announced_chan_between_nodes(&nodes, 0, 1, InitFeatures::known(), InitFeatures::known()).2;
+
+	let (route, payment_hash, payment_preimage, payment_secret) = get_route_and_payment_hash!(&nodes[0], nodes[1], 1_000_000);
+
+	set_signer_available(&nodes[0], &channel_id, false);
+	nodes[0].node.send_payment(&route, payment_hash, &Some(payment_secret)).unwrap();
+	// The monitor is still told about the new commitment, but nothing may be sent yet.
+	check_added_monitors!(nodes[0], 1);
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+
+	// Unblocking while the signer is still unavailable doesn't do anything.
+	nodes[0].node.signer_unblocked(Some(channel_id));
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+
+	if disconnect {
+		nodes[0].node.peer_disconnected(&nodes[1].node.get_our_node_id(), false);
+		nodes[1].node.peer_disconnected(&nodes[0].node.get_our_node_id(), false);
+		// On reconnect the commitment update still can't be regenerated, so only the
+		// funding_locked messages are resent.
+		reconnect_nodes(&nodes[0], &nodes[1], (true, true), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (false, false));
+	}
+
+	set_signer_available(&nodes[0], &channel_id, true);
+	nodes[0].node.signer_unblocked(None);
+	check_added_monitors!(nodes[0], 0);
+
+	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	let payment_event = SendEvent::from_event(events.pop().unwrap());
+	assert_eq!(payment_event.node_id, nodes[1].node.get_our_node_id());
+	assert_eq!(payment_event.msgs.len(), 1);
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], payment_event.commitment_msg, false);
+
+	expect_pending_htlcs_forwardable!(nodes[1]);
+	expect_payment_received!(nodes[1], payment_hash, payment_secret, 1_000_000);
+
+	claim_payment(&nodes[0], &[&nodes[1]], payment_preimage);
+}
+
+#[test]
+fn test_async_commitment_signature_for_payment() {
+	do_test_async_commitment_signature_for_payment(false);
+	do_test_async_commitment_signature_for_payment(true);
+}
+
+#[test]
+fn test_async_commitment_signature_for_claim() {
+	// Test that if our signer is unable to sign the commitment for an update_fulfill_htlc, the
+	// fulfill is sent along with the commitment_signed once the signer is unblocked.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let channel_id = create_announced_chan_between_nodes(&nodes, 0, 1, InitFeatures::known(), InitFeatures::known()).2;
+
+	let (payment_preimage, _, _) = route_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+
+	set_signer_available(&nodes[1], &channel_id, false);
+	assert!(nodes[1].node.claim_funds(payment_preimage));
+	check_added_monitors!(nodes[1], 1);
+	assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+
+	set_signer_available(&nodes[1], &channel_id, true);
+	nodes[1].node.signer_unblocked(Some(channel_id));
+
+	let updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	assert_eq!(updates.update_fulfill_htlcs.len(), 1);
+	nodes[0].node.handle_update_fulfill_htlc(&nodes[1].node.get_our_node_id(), &updates.update_fulfill_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], updates.commitment_signed, false);
+	expect_payment_sent!(nodes[0], payment_preimage);
+}
+
+#[test]
+fn test_async_signer_rejection_closes_channel() {
+	// Test that if our signer, rather than merely being unavailable, rejects a commitment we've
+	// been waiting on it to sign, the channel is force-closed instead of being left stuck.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let channel_id = create_announced_chan_between_nodes(&nodes, 0, 1, InitFeatures::known(), InitFeatures::known()).2;
+
+	let (route, payment_hash, _, payment_secret) = get_route_and_payment_hash!(&nodes[0], nodes[1], 1_000_000);
+
+	set_signer_available(&nodes[0], &channel_id, false);
+	nodes[0].node.send_payment(&route, payment_hash, &Some(payment_secret)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+
+	{
+		let chan_lock = nodes[0].node.channel_state.lock().unwrap();
+		let chan = chan_lock.by_id.get(&channel_id).unwrap();
+		chan.get_signer().get_enforcement_state().signer_rejects = true;
+	}
+	nodes[0].node.signer_unblocked(Some(channel_id));
+	check_closed_broadcast!(nodes[0], true);
+	check_added_monitors!(nodes[0], 1);
+	check_closed_event!(nodes[0], 1, ClosureReason::ProcessingError { err: "Failed to get signatures for new commitment_signed".to_string() });
+	assert!(nodes[0].node.list_channels().is_empty());
+}
@@ -34,7 +34,7 @@ use chain::BestBlock;
 use chain::chaininterface::{FeeEstimator,ConfirmationTarget};
 use chain::channelmonitor::{ChannelMonitor, ChannelMonitorUpdate, ChannelMonitorUpdateStep, LATENCY_GRACE_PERIOD_BLOCKS};
 use chain::transaction::{OutPoint, TransactionData};
-use chain::keysinterface::{Sign, KeysInterface};
+use chain::keysinterface::{Sign, KeysInterface, SignerError};
 use util::events::ClosureReason;
 use util::ser::{Readable, ReadableArgs, Writeable, Writer, VecWriter};
 use util::logger::Logger;
@@ -434,6 +434,13 @@ pub(super) struct ReestablishResponses {
 	pub shutdown_msg: Option<msgs::Shutdown>,
 }
 
+/// The return value of `signer_maybe_unblocked`
+pub(super) struct SignerResumeUpdates {
+	pub commitment_update: Option<msgs::CommitmentUpdate>,
+	pub raa: Option<msgs::RevokeAndACK>,
+	pub order: RAACommitmentOrder,
+}
+
 /// If the majority of the channels funds are to the fundee and the initiator holds only just
 /// enough funds to cover their reserve value, channels are at risk of getting "stuck". Because the
 /// initiator controls the feerate, if they then go to increase the channel fee, they may have no
@@ -539,6 +546,15 @@ pub(super) struct Channel<Signer: Sign> {
 	monitor_pending_failures: Vec<(HTLCSource, PaymentHash, HTLCFailReason)>,
 	monitor_pending_finalized_fulfills: Vec<HTLCSource>,
 
+	/// If we went to send a commitment update but our signer was not yet able to provide the
+	/// signature for it, this is set and the update is regenerated once the signer is unblocked
+	/// (see `signer_maybe_unblocked`). None of the messages in the update are sent until then.
+	signer_pending_commitment_update: bool,
+	/// If a revoke_and_ack needed to be sent after a commitment update which is still waiting on
+	/// our signer (i.e. `resend_order` is `CommitmentFirst`), we hold it until the commitment
+	/// update can be sent.
+	signer_pending_revoke_and_ack: bool,
+
 	// pending_update_fee is filled when sending and receiving update_fee.
 	//
 	// Because it follows the same commitment flow as HTLCs, `FeeUpdateState` is either `Outbound`
@@ -838,7 +854,10 @@ impl<Signer: Sign> Channel<Signer> {
 		secp_ctx.seeded_randomize(&keys_provider.get_secure_random_bytes());
 
 		let shutdown_scriptpubkey = if config.channel_options.commit_upfront_shutdown_pubkey {
-			Some(keys_provider.get_shutdown_scriptpubkey())
+			match keys_provider.get_shutdown_scriptpubkey() {
+				Ok(scriptpubkey) => Some(scriptpubkey),
+				Err(_) => return Err(APIError::ChannelUnavailable { err: "Failed to get shutdown scriptpubkey".to_owned() }),
+			}
 		} else { None };
 
 		if let Some(shutdown_scriptpubkey) = &shutdown_scriptpubkey {
@@ -847,6 +866,11 @@ impl<Signer: Sign> Channel<Signer> {
 			}
 		}
 
+		let destination_script = match keys_provider.get_destination_script() {
+			Ok(script) => script,
+			Err(_) => return Err(APIError::ChannelUnavailable { err: "Failed to get destination script".to_owned() }),
+		};
+
 		Ok(Channel {
 			user_id,
 			config: config.channel_options.clone(),
@@ -862,7 +886,7 @@ impl<Signer: Sign> Channel<Signer> {
 
 			holder_signer,
 			shutdown_scriptpubkey,
-			destination_script: keys_provider.get_destination_script(),
+			destination_script,
 
 			cur_holder_commitment_transaction_number: INITIAL_COMMITMENT_NUMBER,
 			cur_counterparty_commitment_transaction_number: INITIAL_COMMITMENT_NUMBER,
@@ -886,6 +910,9 @@ impl<Signer: Sign> Channel<Signer> {
 			monitor_pending_failures: Vec::new(),
 			monitor_pending_finalized_fulfills: Vec::new(),
 
+			signer_pending_commitment_update: false,
+			signer_pending_revoke_and_ack: false,
+
 			#[cfg(debug_assertions)]
 			holder_max_commitment_tx_output: Mutex::new((channel_value_satoshis * 1000 - push_msat, push_msat)),
 			#[cfg(debug_assertions)]
@@ -1137,7 +1164,10 @@ impl<Signer: Sign> Channel<Signer> {
 		} else { None };
 
 		let shutdown_scriptpubkey = if config.channel_options.commit_upfront_shutdown_pubkey {
-			Some(keys_provider.get_shutdown_scriptpubkey())
+			match keys_provider.get_shutdown_scriptpubkey() {
+				Ok(scriptpubkey) => Some(scriptpubkey),
+				Err(_) => return Err(ChannelError::Close("Failed to get upfront shutdown scriptpubkey".to_owned())),
+			}
 		} else { None };
 
 		if let Some(shutdown_scriptpubkey) = &shutdown_scriptpubkey {
@@ -1146,6 +1176,11 @@ impl<Signer: Sign> Channel<Signer> {
 			}
 		}
 
+		let destination_script = match keys_provider.get_destination_script() {
+			Ok(script) => script,
+			Err(_) => return Err(ChannelError::Close("Failed to get destination script".to_owned())),
+		};
+
 		let mut secp_ctx = Secp256k1::new();
 		secp_ctx.seeded_randomize(&keys_provider.get_secure_random_bytes());
 
@@ -1163,7 +1198,7 @@ impl<Signer: Sign> Channel<Signer> {
 
 			holder_signer,
 			shutdown_scriptpubkey,
-			destination_script: keys_provider.get_destination_script(),
+			destination_script,
 
 			cur_holder_commitment_transaction_number: INITIAL_COMMITMENT_NUMBER,
 			cur_counterparty_commitment_transaction_number: INITIAL_COMMITMENT_NUMBER,
@@ -1187,6 +1222,9 @@ impl<Signer: Sign> Channel<Signer> {
 			monitor_pending_failures: Vec::new(),
 			monitor_pending_finalized_fulfills: Vec::new(),
 
+			signer_pending_commitment_update: false,
+			signer_pending_revoke_and_ack: false,
+
 			#[cfg(debug_assertions)]
 			holder_max_commitment_tx_output: Mutex::new((msg.push_msat, msg.funding_satoshis * 1000 - msg.push_msat)),
 			#[cfg(debug_assertions)]
@@ -1737,7 +1775,10 @@ impl<Signer: Sign> Channel<Signer> {
 				// strictly increasing by one, so decrement it here.
 				self.latest_monitor_update_id = monitor_update.update_id;
 				monitor_update.updates.append(&mut additional_update.updates);
-				Ok(UpdateFulfillCommitFetch::NewClaim { monitor_update, htlc_value_msat, msgs: Some((update_fulfill_htlc, commitment)) })
+				// If our signer is still working on the commitment signature, the fulfill will be
+				// sent along with the rest of the commitment update once it is unblocked.
+				let msgs = commitment.map(|commitment| (update_fulfill_htlc, commitment));
+				Ok(UpdateFulfillCommitFetch::NewClaim { monitor_update, htlc_value_msat, msgs })
 			},
 			UpdateFulfillFetch::NewClaim { monitor_update, htlc_value_msat, msg: None } =>
 				Ok(UpdateFulfillCommitFetch::NewClaim { monitor_update, htlc_value_msat, msgs: None }),
@@ -2692,7 +2733,9 @@ impl<Signer: Sign> Channel<Signer> {
 		Ok(())
 	}
 
-	pub fn commitment_signed<L: Deref>(&mut self, msg: &msgs::CommitmentSigned, logger: &L) -> Result<(msgs::RevokeAndACK, Option<msgs::CommitmentSigned>, ChannelMonitorUpdate), (Option<ChannelMonitorUpdate>, ChannelError)>
+	/// The returned revoke_and_ack may be `None` if it has to wait on a commitment_signed which our
+	/// signer has not yet provided, in which case both are regenerated by `signer_maybe_unblocked`.
+	pub fn commitment_signed<L: Deref>(&mut self, msg: &msgs::CommitmentSigned, logger: &L) -> Result<(Option<msgs::RevokeAndACK>, Option<msgs::CommitmentSigned>, ChannelMonitorUpdate), (Option<ChannelMonitorUpdate>, ChannelError)>
 		where L::Target: Logger
 	{
 		if (self.channel_state & (ChannelState::ChannelFunded as u32)) != (ChannelState::ChannelFunded as u32) {
@@ -2794,7 +2837,8 @@ impl<Signer: Sign> Channel<Signer> {
 		let next_per_commitment_point = self.holder_signer.get_per_commitment_point(self.cur_holder_commitment_transaction_number - 1, &self.secp_ctx);
 		self.holder_signer.validate_holder_commitment(&holder_commitment_tx, commitment_stats.preimages)
 			.map_err(|_| (None, ChannelError::Close("Failed to validate our commitment".to_owned())))?;
-		let per_commitment_secret = self.holder_signer.release_commitment_secret(self.cur_holder_commitment_transaction_number + 1);
+		let per_commitment_secret = self.holder_signer.release_commitment_secret(self.cur_holder_commitment_transaction_number + 1)
+			.map_err(|_| (None, ChannelError::Close("Failed to release our commitment secret".to_owned())))?;
 
 		// Update state now that we've passed all the can-fail calls...
 		let mut need_commitment = false;
@@ -2871,23 +2915,33 @@ impl<Signer: Sign> Channel<Signer> {
 			// strictly increasing by one, so decrement it here.
 			self.latest_monitor_update_id = monitor_update.update_id;
 			monitor_update.updates.append(&mut additional_update.updates);
-			Some(msg)
+			msg
 		} else { None };
 
-		log_debug!(logger, "Received valid commitment_signed from peer in channel {}, updating HTLC state and responding with{} a revoke_and_ack.",
-			log_bytes!(self.channel_id()), if commitment_signed.is_some() { " our own commitment_signed and" } else { "" });
+		let revoke_and_ack = if self.raa_blocked_on_signer() {
+			// We owe the peer a commitment_signed which our signer hasn't yet provided and which
+			// must be delivered before this revoke_and_ack.
+			log_debug!(logger, "Received valid commitment_signed from peer in channel {}, updated HTLC state but holding our revoke_and_ack until our signer is unblocked.",
+				log_bytes!(self.channel_id()));
+			self.signer_pending_revoke_and_ack = true;
+			None
+		} else {
+			log_debug!(logger, "Received valid commitment_signed from peer in channel {}, updating HTLC state and responding with{} a revoke_and_ack.",
+				log_bytes!(self.channel_id()), if commitment_signed.is_some() { " our own commitment_signed and" } else { "" });
+			Some(msgs::RevokeAndACK {
+				channel_id: self.channel_id,
+				per_commitment_secret,
+				next_per_commitment_point,
+			})
+		};
 
-		Ok((msgs::RevokeAndACK {
-			channel_id: self.channel_id,
-			per_commitment_secret,
-			next_per_commitment_point,
-		}, commitment_signed, monitor_update))
+		Ok((revoke_and_ack, commitment_signed, monitor_update))
 	}
 
 	/// Public version of the below, checking relevant preconditions first.
 	/// If we're not in a state where freeing the holding cell makes sense, this is a no-op and
 	/// returns `(None, Vec::new())`.
-	pub fn maybe_free_holding_cell_htlcs<L: Deref>(&mut self, logger: &L) -> Result<(Option<(msgs::CommitmentUpdate, ChannelMonitorUpdate)>, Vec<(HTLCSource, PaymentHash)>), ChannelError> where L::Target: Logger {
+	pub fn maybe_free_holding_cell_htlcs<L: Deref>(&mut self, logger: &L) -> Result<(Option<(Option<msgs::CommitmentUpdate>, ChannelMonitorUpdate)>, Vec<(HTLCSource, PaymentHash)>), ChannelError> where L::Target: Logger {
 		if self.channel_state >= ChannelState::ChannelFunded as u32 &&
 		   (self.channel_state & (ChannelState::AwaitingRemoteRevoke as u32 | ChannelState::PeerDisconnected as u32 | ChannelState::MonitorUpdateFailed as u32)) == 0 {
 			self.free_holding_cell_htlcs(logger)
@@ -2896,7 +2950,9 @@ impl<Signer: Sign> Channel<Signer> {
 
 	/// Used to fulfill holding_cell_htlcs when we get a remote ack (or implicitly get it by them
 	/// fulfilling or failing the last pending HTLC)
-	fn free_holding_cell_htlcs<L: Deref>(&mut self, logger: &L) -> Result<(Option<(msgs::CommitmentUpdate, ChannelMonitorUpdate)>, Vec<(HTLCSource, PaymentHash)>), ChannelError> where L::Target: Logger {
+	/// The returned commitment update is `None` if our signer could not yet sign it, in which case
+	/// the monitor update must still be applied.
+	fn free_holding_cell_htlcs<L: Deref>(&mut self, logger: &L) -> Result<(Option<(Option<msgs::CommitmentUpdate>, ChannelMonitorUpdate)>, Vec<(HTLCSource, PaymentHash)>), ChannelError> where L::Target: Logger {
 		assert_eq!(self.channel_state & ChannelState::MonitorUpdateFailed as u32, 0);
 		if self.holding_cell_htlc_updates.len() != 0 || self.holding_cell_update_fee.is_some() {
 			log_trace!(logger, "Freeing holding cell with {} HTLC updates{} in channel {}", self.holding_cell_htlc_updates.len(),
@@ -2995,14 +3051,15 @@ impl<Signer: Sign> Channel<Signer> {
 				log_bytes!(self.channel_id()), if update_fee.is_some() { "a fee update, " } else { "" },
 				update_add_htlcs.len(), update_fulfill_htlcs.len(), update_fail_htlcs.len());
 
-			Ok((Some((msgs::CommitmentUpdate {
+			let commitment_update = commitment_signed.map(|commitment_signed| msgs::CommitmentUpdate {
 				update_add_htlcs,
 				update_fulfill_htlcs,
 				update_fail_htlcs,
 				update_fail_malformed_htlcs: Vec::new(),
 				update_fee,
 				commitment_signed,
-			}, monitor_update)), htlcs_to_fail))
+			});
+			Ok((Some((commitment_update, monitor_update)), htlcs_to_fail))
 		} else {
 			Ok((None, Vec::new()))
 		}
@@ -3220,13 +3277,17 @@ impl<Signer: Sign> Channel<Signer> {
 
 		match self.free_holding_cell_htlcs(logger)? {
 			(Some((mut commitment_update, mut additional_update)), htlcs_to_fail) => {
-				commitment_update.update_fail_htlcs.reserve(update_fail_htlcs.len());
-				for fail_msg in update_fail_htlcs.drain(..) {
-					commitment_update.update_fail_htlcs.push(fail_msg);
-				}
-				commitment_update.update_fail_malformed_htlcs.reserve(update_fail_malformed_htlcs.len());
-				for fail_msg in update_fail_malformed_htlcs.drain(..) {
-					commitment_update.update_fail_malformed_htlcs.push(fail_msg);
+				// If our signer is still working on the commitment, the failures will be regenerated
+				// along with the rest of the update once it is unblocked.
+				if let Some(ref mut commitment_update) = commitment_update {
+					commitment_update.update_fail_htlcs.reserve(update_fail_htlcs.len());
+					for fail_msg in update_fail_htlcs.drain(..) {
+						commitment_update.update_fail_htlcs.push(fail_msg);
+					}
+					commitment_update.update_fail_malformed_htlcs.reserve(update_fail_malformed_htlcs.len());
+					for fail_msg in update_fail_malformed_htlcs.drain(..) {
+						commitment_update.update_fail_malformed_htlcs.push(fail_msg);
+					}
 				}
 
 				// free_holding_cell_htlcs may bump latest_monitor_id multiple times but we want them to be
@@ -3235,7 +3296,7 @@ impl<Signer: Sign> Channel<Signer> {
 				monitor_update.updates.append(&mut additional_update.updates);
 
 				Ok(RAAUpdates {
-					commitment_update: Some(commitment_update),
+					commitment_update,
 					finalized_claimed_htlcs,
 					accepted_htlcs: to_forward_infos,
 					failed_htlcs: revoked_htlcs,
@@ -3255,7 +3316,7 @@ impl<Signer: Sign> Channel<Signer> {
 					log_debug!(logger, "Received a valid revoke_and_ack for channel {}. Responding with a commitment update with {} HTLCs failed.",
 						log_bytes!(self.channel_id()), update_fail_htlcs.len() + update_fail_malformed_htlcs.len());
 					Ok(RAAUpdates {
-						commitment_update: Some(msgs::CommitmentUpdate {
+						commitment_update: commitment_signed.map(|commitment_signed| msgs::CommitmentUpdate {
 							update_add_htlcs: Vec::new(),
 							update_fulfill_htlcs: Vec::new(),
 							update_fail_htlcs,
@@ -3335,7 +3396,9 @@ impl<Signer: Sign> Channel<Signer> {
 		})
 	}
 
-	pub fn send_update_fee_and_commit<L: Deref>(&mut self, feerate_per_kw: u32, logger: &L) -> Result<Option<(msgs::UpdateFee, msgs::CommitmentSigned, ChannelMonitorUpdate)>, ChannelError> where L::Target: Logger {
+	/// If our signer is not yet able to sign the new commitment, the returned `CommitmentSigned` is
+	/// `None` and the update_fee must not be sent either, see [`Self::send_commitment`].
+	pub fn send_update_fee_and_commit<L: Deref>(&mut self, feerate_per_kw: u32, logger: &L) -> Result<Option<(msgs::UpdateFee, Option<msgs::CommitmentSigned>, ChannelMonitorUpdate)>, ChannelError> where L::Target: Logger {
 		match self.send_update_fee(feerate_per_kw, logger) {
 			Some(update_fee) => {
 				let (commitment_signed, monitor_update) = self.send_commitment_no_status_check(logger)?;
@@ -3411,6 +3474,11 @@ impl<Signer: Sign> Channel<Signer> {
 			}
 		}
 
+		// Anything we were waiting on our signer to generate will be regenerated as required when
+		// we handle the peer's channel_reestablish.
+		self.signer_pending_commitment_update = false;
+		self.signer_pending_revoke_and_ack = false;
+
 		self.channel_state |= ChannelState::PeerDisconnected as u32;
 		log_trace!(logger, "Peer disconnection resulted in {} remote-announced HTLC drops on channel {}", inbound_drop_count, log_bytes!(self.channel_id()));
 	}
@@ -3478,12 +3546,35 @@ impl<Signer: Sign> Channel<Signer> {
 			};
 		}
 
-		let raa = if self.monitor_pending_revoke_and_ack {
-			Some(self.get_last_revoke_and_ack())
+		let mut raa = if self.monitor_pending_revoke_and_ack || self.signer_pending_revoke_and_ack {
+			match self.get_last_revoke_and_ack() {
+				Ok(raa) => Some(raa),
+				Err(_) => {
+					// As with the commitment update below, the failure will be hit again, and the
+					// channel closed, on reconnection.
+					log_error!(logger, "Signer refused to re-release our last commitment secret in channel {}", log_bytes!(self.channel_id()));
+					None
+				},
+			}
 		} else { None };
-		let commitment_update = if self.monitor_pending_commitment_signed {
-			Some(self.get_last_commitment_update(logger))
+		let commitment_update = if self.monitor_pending_commitment_signed || self.signer_pending_commitment_update {
+			match self.get_last_commitment_update(logger) {
+				Ok(update) => update,
+				Err(_) => {
+					// We can't close the channel from here, so leave the update pending on the
+					// signer. The rejection will be hit again, and the channel closed, on the next
+					// call to `signer_maybe_unblocked` or on reconnection.
+					log_error!(logger, "Signer rejected the latest commitment transaction in channel {}", log_bytes!(self.channel_id()));
+					self.signer_pending_commitment_update = true;
+					None
+				},
+			}
 		} else { None };
+		self.signer_pending_revoke_and_ack = false;
+		if raa.is_some() && self.raa_blocked_on_signer() {
+			self.signer_pending_revoke_and_ack = true;
+			raa = None;
+		}
 
 		self.monitor_pending_revoke_and_ack = false;
 		self.monitor_pending_commitment_signed = false;
@@ -3531,17 +3622,27 @@ impl<Signer: Sign> Channel<Signer> {
 		Ok(())
 	}
 
-	fn get_last_revoke_and_ack(&self) -> msgs::RevokeAndACK {
+	fn get_last_revoke_and_ack(&self) -> Result<msgs::RevokeAndACK, ChannelError> {
 		let next_per_commitment_point = self.holder_signer.get_per_commitment_point(self.cur_holder_commitment_transaction_number, &self.secp_ctx);
-		let per_commitment_secret = self.holder_signer.release_commitment_secret(self.cur_holder_commitment_transaction_number + 2);
-		msgs::RevokeAndACK {
+		let per_commitment_secret = self.holder_signer.release_commitment_secret(self.cur_holder_commitment_transaction_number + 2)
+			.map_err(|_| ChannelError::Close("Failed to release our commitment secret".to_owned()))?;
+		Ok(msgs::RevokeAndACK {
 			channel_id: self.channel_id,
 			per_commitment_secret,
 			next_per_commitment_point,
-		}
+		})
+	}
+
+	/// Whether a revoke_and_ack we're about to send has to wait on a commitment_signed which we
+	/// generated before it but which our signer has not yet provided.
+	fn raa_blocked_on_signer(&self) -> bool {
+		self.signer_pending_commitment_update && self.resend_order == RAACommitmentOrder::CommitmentFirst
 	}
 
-	fn get_last_commitment_update<L: Deref>(&self, logger: &L) -> msgs::CommitmentUpdate where L::Target: Logger {
+	/// Regenerates the latest commitment update we sent (or tried to send). Returns `None`, and
+	/// marks the update as pending on our signer, if the signer cannot yet sign the commitment,
+	/// and fails if it rejects it outright.
+	fn get_last_commitment_update<L: Deref>(&mut self, logger: &L) -> Result<Option<msgs::CommitmentUpdate>, ChannelError> where L::Target: Logger {
 		let mut update_add_htlcs = Vec::new();
 		let mut update_fulfill_htlcs = Vec::new();
 		let mut update_fail_htlcs = Vec::new();
@@ -3596,13 +3697,45 @@ impl<Signer: Sign> Channel<Signer> {
 			})
 		} else { None };
 
+		let commitment_signed = match self.send_commitment_no_state_update(logger)?.0 {
+			Some(commitment_signed) => commitment_signed,
+			None => {
+				log_debug!(logger, "Unable to regenerate the latest commitment update in channel {} as our signer is not yet ready",
+					log_bytes!(self.channel_id()));
+				self.signer_pending_commitment_update = true;
+				return Ok(None);
+			},
+		};
+		self.signer_pending_commitment_update = false;
+
 		log_trace!(logger, "Regenerated latest commitment update in channel {} with{} {} update_adds, {} update_fulfills, {} update_fails, and {} update_fail_malformeds",
 				log_bytes!(self.channel_id()), if update_fee.is_some() { " update_fee," } else { "" },
 				update_add_htlcs.len(), update_fulfill_htlcs.len(), update_fail_htlcs.len(), update_fail_malformed_htlcs.len());
-		msgs::CommitmentUpdate {
+		Ok(Some(msgs::CommitmentUpdate {
 			update_add_htlcs, update_fulfill_htlcs, update_fail_htlcs, update_fail_malformed_htlcs, update_fee,
-			commitment_signed: self.send_commitment_no_state_update(logger).expect("It looks like we failed to re-generate a commitment_signed we had previously sent?").0,
-		}
+			commitment_signed,
+		}))
+	}
+
+	/// Indicates that our signer may now be able to provide signatures it previously could not.
+	/// Returns the commitment update (and any revoke_and_ack which had to wait on it) which we
+	/// were previously unable to send, if the signer has now signed it, or an error if the signer
+	/// has instead rejected it.
+	pub fn signer_maybe_unblocked<L: Deref>(&mut self, logger: &L) -> Result<SignerResumeUpdates, ChannelError> where L::Target: Logger {
+		// If we're disconnected or waiting on a monitor update, the messages will be regenerated
+		// on channel_reestablish or monitor_updating_restored, respectively.
+		if !self.signer_pending_commitment_update ||
+				self.channel_state & (ChannelState::PeerDisconnected as u32 | ChannelState::MonitorUpdateFailed as u32) != 0 {
+			return Ok(SignerResumeUpdates { commitment_update: None, raa: None, order: self.resend_order.clone() });
+		}
+		let commitment_update = self.get_last_commitment_update(logger)?;
+		let raa = if commitment_update.is_some() && self.signer_pending_revoke_and_ack {
+			self.signer_pending_revoke_and_ack = false;
+			Some(self.get_last_revoke_and_ack()?)
+		} else { None };
+		log_debug!(logger, "Signer unblocked in channel {} resulting in {} commitment update and {} RAA",
+			log_bytes!(self.channel_id()), if commitment_update.is_some() { "a" } else { "no" }, if raa.is_some() { "an" } else { "no" });
+		Ok(SignerResumeUpdates { commitment_update, raa, order: self.resend_order.clone() })
 	}
 
 	/// May panic if some calls other than message-handling calls (which will all Err immediately)
@@ -3695,7 +3828,7 @@ impl<Signer: Sign> Channel<Signer> {
 				self.monitor_pending_revoke_and_ack = true;
 				None
 			} else {
-				Some(self.get_last_revoke_and_ack())
+				Some(self.get_last_revoke_and_ack()?)
 			}
 		} else {
 			return Err(ChannelError::Close("Peer attempted to reestablish channel with a very old local commitment transaction".to_owned()));
@@ -3736,7 +3869,7 @@ impl<Signer: Sign> Channel<Signer> {
 						Ok(ReestablishResponses {
 							funding_locked, shutdown_msg, announcement_sigs,
 							raa: required_revoke,
-							commitment_update: Some(commitment_update),
+							commitment_update,
 							order: self.resend_order.clone(),
 							mon_update: Some(monitor_update),
 							holding_cell_failed_htlcs,
@@ -3779,10 +3912,15 @@ impl<Signer: Sign> Channel<Signer> {
 					holding_cell_failed_htlcs: Vec::new(),
 				})
 			} else {
+				let commitment_update = self.get_last_commitment_update(logger)?;
+				let raa = if required_revoke.is_some() && self.raa_blocked_on_signer() {
+					self.signer_pending_revoke_and_ack = true;
+					None
+				} else { required_revoke };
 				Ok(ReestablishResponses {
 					funding_locked, shutdown_msg, announcement_sigs,
-					raa: required_revoke,
-					commitment_update: Some(self.get_last_commitment_update(logger)),
+					raa,
+					commitment_update,
 					order: self.resend_order.clone(),
 					mon_update: None,
 					holding_cell_failed_htlcs: Vec::new(),
@@ -3950,7 +4088,10 @@ impl<Signer: Sign> Channel<Signer> {
 			Some(_) => false,
 			None => {
 				assert!(send_shutdown);
-				let shutdown_scriptpubkey = keys_provider.get_shutdown_scriptpubkey();
+				let shutdown_scriptpubkey = match keys_provider.get_shutdown_scriptpubkey() {
+					Ok(scriptpubkey) => scriptpubkey,
+					Err(_) => return Err(ChannelError::Close("Failed to get shutdown scriptpubkey".to_owned())),
+				};
 				if !shutdown_scriptpubkey.is_compatible(their_features) {
 					return Err(ChannelError::Close(format!("Provided a scriptpubkey format not accepted by peer: {}", shutdown_scriptpubkey)));
 				}
@@ -4296,6 +4437,20 @@ impl<Signer: Sign> Channel<Signer> {
 		self.cur_counterparty_commitment_transaction_number + 2
 	}
 
+	/// Returns the sources of the outbound HTLCs which are still in flight over this channel,
+	/// including those in the holding cell but excluding those already removed by our counterparty.
+	pub fn inflight_htlc_sources(&self) -> impl Iterator<Item=&HTLCSource> {
+		self.holding_cell_htlc_updates.iter()
+			.filter_map(|htlc_update| match htlc_update {
+				HTLCUpdateAwaitingACK::AddHTLC { source, .. } => Some(source),
+				_ => None,
+			})
+			.chain(self.pending_outbound_htlcs.iter().filter_map(|htlc| match htlc.state {
+				OutboundHTLCState::LocalAnnounced(_) | OutboundHTLCState::Committed => Some(&htlc.source),
+				_ => None,
+			}))
+	}
+
 	#[cfg(test)]
 	pub fn get_signer(&self) -> &Signer {
 		&self.holder_signer
@@ -5135,7 +5290,12 @@ impl<Signer: Sign> Channel<Signer> {
 	/// Always returns a ChannelError::Close if an immediately-preceding (read: the
 	/// last call to this Channel) send_htlc returned Ok(Some(_)) and there is an Err.
 	/// May panic if called except immediately after a successful, Ok(Some(_))-returning send_htlc.
-	pub fn send_commitment<L: Deref>(&mut self, logger: &L) -> Result<(msgs::CommitmentSigned, ChannelMonitorUpdate), ChannelError> where L::Target: Logger {
+	///
+	/// If our signer is not yet able to sign the commitment transaction, the returned
+	/// `CommitmentSigned` is `None`. The `ChannelMonitorUpdate` must still be applied, but none of
+	/// the updates covered by the commitment may be sent to the peer. The full commitment update is
+	/// instead returned by `signer_maybe_unblocked` once the signer has the signature ready.
+	pub fn send_commitment<L: Deref>(&mut self, logger: &L) -> Result<(Option<msgs::CommitmentSigned>, ChannelMonitorUpdate), ChannelError> where L::Target: Logger {
 		if (self.channel_state & (ChannelState::ChannelFunded as u32)) != (ChannelState::ChannelFunded as u32) {
 			panic!("Cannot create commitment tx until channel is fully established");
 		}
@@ -5167,7 +5327,7 @@ impl<Signer: Sign> Channel<Signer> {
 		self.send_commitment_no_status_check(logger)
 	}
 	/// Only fails in case of bad keys
-	fn send_commitment_no_status_check<L: Deref>(&mut self, logger: &L) -> Result<(msgs::CommitmentSigned, ChannelMonitorUpdate), ChannelError> where L::Target: Logger {
+	fn send_commitment_no_status_check<L: Deref>(&mut self, logger: &L) -> Result<(Option<msgs::CommitmentSigned>, ChannelMonitorUpdate), ChannelError> where L::Target: Logger {
 		log_trace!(logger, "Updating HTLC state for a newly-sent commitment_signed...");
 		// We can upgrade the status of some HTLCs that are waiting on a commitment, even if we
 		// fail to generate this, we still are at least at a position where upgrading their status
@@ -5209,6 +5369,7 @@ impl<Signer: Sign> Channel<Signer> {
 			},
 			Err(e) => return Err(e),
 		};
+		self.signer_pending_commitment_update = res.is_none();
 
 		if self.announcement_sigs_state == AnnouncementSigsState::MessageSent {
 			self.announcement_sigs_state = AnnouncementSigsState::Committed;
@@ -5228,9 +5389,10 @@ impl<Signer: Sign> Channel<Signer> {
 		Ok((res, monitor_update))
 	}
 
-	/// Only fails in case of bad keys. Used for channel_reestablish commitment_signed generation
-	/// when we shouldn't change HTLC/channel state.
-	fn send_commitment_no_state_update<L: Deref>(&self, logger: &L) -> Result<(msgs::CommitmentSigned, (Txid, Vec<(HTLCOutputInCommitment, Option<&HTLCSource>)>)), ChannelError> where L::Target: Logger {
+	/// Only fails in case of bad keys or if our signer rejects the commitment. Used for
+	/// channel_reestablish commitment_signed generation when we shouldn't change HTLC/channel state.
+	/// Returns no `CommitmentSigned` if our signer is not yet able to sign the commitment.
+	fn send_commitment_no_state_update<L: Deref>(&self, logger: &L) -> Result<(Option<msgs::CommitmentSigned>, (Txid, Vec<(HTLCOutputInCommitment, Option<&HTLCSource>)>)), ChannelError> where L::Target: Logger {
 		let counterparty_keys = self.build_remote_transaction_keys()?;
 		let commitment_stats = self.build_commitment_transaction(self.cur_counterparty_commitment_transaction_number, &counterparty_keys, false, true, logger);
 		let counterparty_commitment_txid = commitment_stats.tx.trust().txid();
@@ -5260,8 +5422,15 @@ impl<Signer: Sign> Channel<Signer> {
 				htlcs.push(htlc);
 			}
 
-			let res = self.holder_signer.sign_counterparty_commitment(&commitment_stats.tx, commitment_stats.preimages, &self.secp_ctx)
-				.map_err(|_| ChannelError::Close("Failed to get signatures for new commitment_signed".to_owned()))?;
+			let res = match self.holder_signer.sign_counterparty_commitment(&commitment_stats.tx, commitment_stats.preimages, &self.secp_ctx) {
+				Ok(res) => res,
+				Err(SignerError::Unavailable) => {
+					log_debug!(logger, "Signer is not yet able to sign remote commitment tx {} in channel {}",
+						&counterparty_commitment_txid, log_bytes!(self.channel_id()));
+					return Ok((None, (counterparty_commitment_txid, commitment_stats.htlcs_included)));
+				},
+				Err(SignerError::Rejected) => return Err(ChannelError::Close("Failed to get signatures for new commitment_signed".to_owned())),
+			};
 			signature = res.0;
 			htlc_signatures = res.1;
 
@@ -5279,18 +5448,18 @@ impl<Signer: Sign> Channel<Signer> {
 			}
 		}
 
-		Ok((msgs::CommitmentSigned {
+		Ok((Some(msgs::CommitmentSigned {
 			channel_id: self.channel_id,
 			signature,
 			htlc_signatures,
-		}, (counterparty_commitment_txid, commitment_stats.htlcs_included)))
+		}), (counterparty_commitment_txid, commitment_stats.htlcs_included)))
 	}
 
 	/// Adds a pending outbound HTLC to this channel, and creates a signed commitment transaction
 	/// to send to the remote peer in one go.
 	/// Shorthand for calling send_htlc() followed by send_commitment(), see docs on those for
 	/// more info.
-	pub fn send_htlc_and_commit<L: Deref>(&mut self, amount_msat: u64, payment_hash: PaymentHash, cltv_expiry: u32, source: HTLCSource, onion_routing_packet: msgs::OnionPacket, logger: &L) -> Result<Option<(msgs::UpdateAddHTLC, msgs::CommitmentSigned, ChannelMonitorUpdate)>, ChannelError> where L::Target: Logger {
+	pub fn send_htlc_and_commit<L: Deref>(&mut self, amount_msat: u64, payment_hash: PaymentHash, cltv_expiry: u32, source: HTLCSource, onion_routing_packet: msgs::OnionPacket, logger: &L) -> Result<Option<(msgs::UpdateAddHTLC, Option<msgs::CommitmentSigned>, ChannelMonitorUpdate)>, ChannelError> where L::Target: Logger {
 		match self.send_htlc(amount_msat, payment_hash, cltv_expiry, source, onion_routing_packet, logger)? {
 			Some(update_add_htlc) => {
 				let (commitment_signed, monitor_update) = self.send_commitment_no_status_check(logger)?;
@@ -5344,7 +5513,10 @@ impl<Signer: Sign> Channel<Signer> {
 		let update_shutdown_script = match self.shutdown_scriptpubkey {
 			Some(_) => false,
 			None => {
-				let shutdown_scriptpubkey = keys_provider.get_shutdown_scriptpubkey();
+				let shutdown_scriptpubkey = match keys_provider.get_shutdown_scriptpubkey() {
+					Ok(scriptpubkey) => scriptpubkey,
+					Err(_) => return Err(APIError::ChannelUnavailable { err: "Failed to get shutdown scriptpubkey".to_owned() }),
+				};
 				if !shutdown_scriptpubkey.is_compatible(their_features) {
 					return Err(APIError::IncompatibleShutdownScript { script: shutdown_scriptpubkey.clone() });
 				}
@@ -6109,6 +6281,9 @@ impl<'a, Signer: Sign, K: Deref> ReadableArgs<(&'a K, u32)> for Channel<Signer>
 			monitor_pending_failures,
 			monitor_pending_finalized_fulfills: monitor_pending_finalized_fulfills.unwrap(),
 
+			signer_pending_commitment_update: false,
+			signer_pending_revoke_and_ack: false,
+
 			pending_update_fee,
 			holding_cell_update_fee,
 			next_holder_htlc_id,
@@ -6193,6 +6368,7 @@ mod tests {
 	use ln::channel::{Channel,InboundHTLCOutput,OutboundHTLCOutput,InboundHTLCState,OutboundHTLCState,HTLCOutputInCommitment,HTLCCandidate,HTLCInitiator,TxCreationKeys};
 	use ln::channel::MAX_FUNDING_SATOSHIS;
 	use ln::features::InitFeatures;
+	use ln::msgs;
 	use ln::msgs::{ChannelUpdate, DataLossProtect, DecodeError, OptionalField, UnsignedChannelUpdate};
 	use ln::script::ShutdownScript;
 	use ln::chan_utils;
@@ -6210,6 +6386,7 @@ mod tests {
 	use bitcoin::secp256k1::{Secp256k1, Message, Signature, All};
 	use bitcoin::secp256k1::ffi::Signature as FFISignature;
 	use bitcoin::secp256k1::key::{SecretKey,PublicKey};
+	use bitcoin::secp256k1::ecdh::SharedSecret;
 	use bitcoin::secp256k1::recovery::RecoverableSignature;
 	use bitcoin::hashes::sha256::Hash as Sha256;
 	use bitcoin::hashes::Hash;
@@ -6247,19 +6424,20 @@ mod tests {
 	impl KeysInterface for Keys {
 		type Signer = InMemorySigner;
 
-		fn get_node_secret(&self, _recipient: Recipient) -> Result<SecretKey, ()> { panic!(); }
+		fn get_node_id(&self, _recipient: Recipient) -> Result<PublicKey, ()> { panic!(); }
+		fn ecdh(&self, _recipient: Recipient, _other_key: &PublicKey) -> Result<SharedSecret, ()> { panic!(); }
 		fn get_inbound_payment_key_material(&self) -> KeyMaterial { panic!(); }
-		fn get_destination_script(&self) -> Script {
+		fn get_destination_script(&self) -> Result<Script, ()> {
 			let secp_ctx = Secp256k1::signing_only();
 			let channel_monitor_claim_key = SecretKey::from_slice(&hex::decode("0fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff").unwrap()[..]).unwrap();
 			let channel_monitor_claim_key_hash = WPubkeyHash::hash(&PublicKey::from_secret_key(&secp_ctx, &channel_monitor_claim_key).serialize());
-			Builder::new().push_opcode(opcodes::all::OP_PUSHBYTES_0).push_slice(&channel_monitor_claim_key_hash[..]).into_script()
+			Ok(Builder::new().push_opcode(opcodes::all::OP_PUSHBYTES_0).push_slice(&channel_monitor_claim_key_hash[..]).into_script())
 		}
 
-		fn get_shutdown_scriptpubkey(&self) -> ShutdownScript {
+		fn get_shutdown_scriptpubkey(&self) -> Result<ShutdownScript, ()> {
 			let secp_ctx = Secp256k1::signing_only();
 			let channel_close_key = SecretKey::from_slice(&hex::decode("0fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff").unwrap()[..]).unwrap();
-			ShutdownScript::new_p2wpkh_from_pubkey(PublicKey::from_secret_key(&secp_ctx, &channel_close_key))
+			Ok(ShutdownScript::new_p2wpkh_from_pubkey(PublicKey::from_secret_key(&secp_ctx, &channel_close_key)))
 		}
 
 		fn get_channel_signer(&self, _inbound: bool, _channel_value_satoshis: u64) -> InMemorySigner {
@@ -6268,6 +6446,8 @@ mod tests {
 		fn get_secure_random_bytes(&self) -> [u8; 32] { [0; 32] }
 		fn read_chan_signer(&self, _data: &[u8]) -> Result<Self::Signer, DecodeError> { panic!(); }
 		fn sign_invoice(&self, _hrp_bytes: &[u8], _invoice_data: &[u5], _recipient: Recipient) -> Result<RecoverableSignature, ()> { panic!(); }
+		fn sign_gossip_message(&self, _msg: msgs::UnsignedGossipMessage) -> Result<Signature, ()> { panic!(); }
+		fn sign_message(&self, _msg: &[u8]) -> Result<String, ()> { panic!(); }
 	}
 
 	fn public_from_secret_hex(secp_ctx: &Secp256k1<All>, hex: &str) -> PublicKey {
@@ -25,12 +25,10 @@ use bitcoin::network::constants::Network;
 
 use bitcoin::hashes::{Hash, HashEngine};
 use bitcoin::hashes::sha256::Hash as Sha256;
-use bitcoin::hashes::sha256d::Hash as Sha256dHash;
 use bitcoin::hash_types::{BlockHash, Txid};
 
 use bitcoin::secp256k1::key::{SecretKey,PublicKey};
 use bitcoin::secp256k1::Secp256k1;
-use bitcoin::secp256k1::ecdh::SharedSecret;
 use bitcoin::secp256k1;
 
 use chain;
@@ -43,7 +41,7 @@ use chain::transaction::{OutPoint, TransactionData};
 use ln::{PaymentHash, PaymentPreimage, PaymentSecret};
 use ln::channel::{Channel, ChannelError, ChannelUpdateStatus, UpdateFulfillCommitFetch};
 use ln::features::{InitFeatures, NodeFeatures};
-use routing::router::{PaymentParameters, Route, RouteHop, RoutePath, RouteParameters};
+use routing::router::{InFlightHtlcs, PaymentParameters, Route, RouteHop, RoutePath, RouteParameters};
 use ln::msgs;
 use ln::msgs::NetAddress;
 use ln::onion_utils;
@@ -51,25 +49,23 @@ use ln::msgs::{ChannelMessageHandler, DecodeError, LightningError, MAX_VALUE_MSA
 use chain::keysinterface::{Sign, KeysInterface, KeysManager, InMemorySigner, Recipient};
 use util::config::UserConfig;
 use util::events::{EventHandler, EventsProvider, MessageSendEvent, MessageSendEventsProvider, ClosureReason};
-use util::{byte_utils, events};
+use util::{byte_utils, events, message_signing};
 use util::scid_utils::fake_scid;
 use util::ser::{BigSize, FixedLengthReader, Readable, ReadableArgs, MaybeReadable, Writeable, Writer};
 use util::logger::{Level, Logger};
 use util::errors::APIError;
+use util::wakers::{Future, Notifier};
 
 use io;
 use prelude::*;
 use core::{cmp, mem};
 use core::cell::RefCell;
 use io::Read;
-use sync::{Arc, Condvar, Mutex, MutexGuard, RwLock, RwLockReadGuard};
+use sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard};
 use core::sync::atomic::{AtomicUsize, Ordering};
 use core::time::Duration;
 use core::ops::Deref;
 
-#[cfg(any(test, feature = "std"))]
-use std::time::Instant;
-
 mod inbound_payment {
 	use alloc::string::ToString;
 	use bitcoin::hashes::{Hash, HashEngine};
@@ -727,6 +723,10 @@ pub(crate) enum PendingOutboundPayment {
 		total_msat: u64,
 		/// Our best known block height at the time this payment was initiated.
 		starting_block_height: u32,
+		/// Whether this is a circular payment to ourselves sent via
+		/// [`ChannelManager::send_rebalance`], used to rebuild the set of pending rebalances on
+		/// restart.
+		is_rebalance: bool,
 	},
 	/// When a pending payment is fulfilled, we continue tracking it until all pending HTLCs have
 	/// been resolved. This ensures we don't look up pending payments in ChannelMonitors on restart
@@ -969,7 +969,21 @@ pub struct ChannelManager<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref,
 	/// Locked *after* channel_state.
 	pending_outbound_payments: Mutex<HashMap<PaymentId, PendingOutboundPayment>>,
 
-	our_network_key: SecretKey,
+	/// The payment hashes of circular payments sent to ourselves via [`Self::send_rebalance`]
+	/// which have not yet completed. Upon receipt, such payments are claimed automatically rather
+	/// than generating an [`Event::PaymentReceived`], and upon completion an
+	/// [`Event::RebalanceCompleted`] is generated in place of an [`Event::PaymentSent`].
+	///
+	/// This is rebuilt on restart from the `is_rebalance` flag of the corresponding
+	/// [`PendingOutboundPayment`]s.
+	///
+	/// Locked *after* all other locks held at the same time.
+	///
+	/// [`Event::PaymentReceived`]: events::Event::PaymentReceived
+	/// [`Event::RebalanceCompleted`]: events::Event::RebalanceCompleted
+	/// [`Event::PaymentSent`]: events::Event::PaymentSent
+	pending_rebalances: Mutex<HashSet<PaymentHash>>,
+
 	our_network_pubkey: PublicKey,
 
 	inbound_payment_key: inbound_payment::ExpandedKey,
@@ -1007,10 +1021,10 @@ pub struct ChannelManager<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref,
 	/// Taken first everywhere where we are making changes before any other locks.
 	/// When acquiring this lock in read mode, rather than acquiring it directly, call
 	/// `PersistenceNotifierGuard::notify_on_drop(..)` and pass the lock to it, to ensure the
-	/// PersistenceNotifier the lock contains sends out a notification when the lock is released.
+	/// Notifier the lock contains sends out a notification when the lock is released.
 	total_consistency_lock: RwLock<()>,
 
-	persistence_notifier: PersistenceNotifier,
+	persistence_notifier: Notifier,
 
 	keys_manager: K,
 
@@ -1050,18 +1064,18 @@ enum NotifyOption {
 /// notify or not based on whether relevant changes have been made, providing a closure to
 /// `optionally_notify` which returns a `NotifyOption`.
 struct PersistenceNotifierGuard<'a, F: Fn() -> NotifyOption> {
-	persistence_notifier: &'a PersistenceNotifier,
+	persistence_notifier: &'a Notifier,
 	should_persist: F,
 	// We hold onto this result so the lock doesn't get released immediately.
 	_read_guard: RwLockReadGuard<'a, ()>,
 }
 
 impl<'a> PersistenceNotifierGuard<'a, fn() -> NotifyOption> { // We don't care what the concrete F is here, it's unused
-	fn notify_on_drop(lock: &'a RwLock<()>, notifier: &'a PersistenceNotifier) -> PersistenceNotifierGuard<'a, impl Fn() -> NotifyOption> {
+	fn notify_on_drop(lock: &'a RwLock<()>, notifier: &'a Notifier) -> PersistenceNotifierGuard<'a, impl Fn() -> NotifyOption> {
 		PersistenceNotifierGuard::optionally_notify(lock, notifier, || -> NotifyOption { NotifyOption::DoPersist })
 	}
 
-	fn optionally_notify<F: Fn() -> NotifyOption>(lock: &'a RwLock<()>, notifier: &'a PersistenceNotifier, persist_check: F) -> PersistenceNotifierGuard<'a, F> {
+	fn optionally_notify<F: Fn() -> NotifyOption>(lock: &'a RwLock<()>, notifier: &'a Notifier, persist_check: F) -> PersistenceNotifierGuard<'a, F> {
 		let read_guard = lock.read().unwrap();
 
 		PersistenceNotifierGuard {
@@ -1112,6 +1126,11 @@ pub(super) const CLTV_FAR_FAR_AWAY: u32 = 6 * 24 * 7; //TODO?
 // a payment was being routed, so we add an extra block to be safe.
 pub const MIN_FINAL_CLTV_EXPIRY: u32 = HTLC_FAIL_BACK_BUFFER + 3;
 
+/// The number of seconds after which the inbound payment registered for a rebalance sent via
+/// [`ChannelManager::send_rebalance`] expires, measured against the highest block timestamp we've
+/// seen.
+const REBALANCE_EXPIRY_DELTA_SECS: u32 = 60 * 60 * 24;
+
 // Check that our CLTV_EXPIRY is at least CLTV_CLAIM_BUFFER + ANTI_REORG_DELAY + LATENCY_GRACE_PERIOD_BLOCKS,
 // ie that if the next-hop peer fails the HTLC within
 // LATENCY_GRACE_PERIOD_BLOCKS then we'll still have CLTV_CLAIM_BUFFER left to timeout it onchain,
@@ -1562,8 +1581,9 @@ macro_rules! handle_chan_restoration_locked {
 				assert!($funding_locked.is_none());
 				// A channel monitor update makes no sense without either a funding_locked or a
 				// commitment update to process after it. Since we can't have a funding_locked, we
-				// only bother to handle the monitor-update + commitment_update case below.
-				assert!($commitment_update.is_some());
+				// only bother to handle the monitor-update + commitment_update case below. Note
+				// that the commitment update may be missing if our signer has not yet provided the
+				// signature for it, in which case it'll be sent once the signer is unblocked.
 			}
 
 			if let Some(msg) = $funding_locked {
@@ -1593,8 +1613,8 @@ macro_rules! handle_chan_restoration_locked {
 				assert!(funding_broadcastable.is_none());
 				// Given we were just reconnected or finished updating a channel monitor, the
 				// only case where we can get a new ChannelMonitorUpdate would be if we also
-				// have some commitment updates to send as well.
-				assert!($commitment_update.is_some());
+				// have some commitment updates to send as well (or are waiting on our signer to
+				// sign them).
 				if let Err(e) = $self.chain_monitor.update_channel($channel_entry.get().get_funding_txo().unwrap(), monitor_update) {
 					// channel_reestablish doesn't guarantee the order it returns is sensical
 					// for the messages it returns, but if we're setting what messages to
@@ -1603,7 +1623,7 @@ macro_rules! handle_chan_restoration_locked {
 					if $raa.is_none() {
 						order = RAACommitmentOrder::CommitmentFirst;
 					}
-					break handle_monitor_err!($self, e, $channel_state, $channel_entry, order, $raa.is_some(), true);
+					break handle_monitor_err!($self, e, $channel_state, $channel_entry, order, $raa.is_some(), $commitment_update.is_some());
 				}
 			}
 
@@ -1705,9 +1725,9 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 			}),
 			pending_inbound_payments: Mutex::new(HashMap::new()),
 			pending_outbound_payments: Mutex::new(HashMap::new()),
+			pending_rebalances: Mutex::new(HashSet::new()),
 
-			our_network_key: keys_manager.get_node_secret(Recipient::Node).unwrap(),
-			our_network_pubkey: PublicKey::from_secret_key(&secp_ctx, &keys_manager.get_node_secret(Recipient::Node).unwrap()),
+			our_network_pubkey: keys_manager.get_node_id(Recipient::Node).unwrap(),
 			secp_ctx,
 
 			inbound_payment_key: expanded_inbound_key,
@@ -1721,7 +1741,7 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 			pending_events: Mutex::new(Vec::new()),
 			pending_background_events: Mutex::new(Vec::new()),
 			total_consistency_lock: RwLock::new(()),
-			persistence_notifier: PersistenceNotifier::new(),
+			persistence_notifier: Notifier::new(),
 
 			keys_manager,
 
@@ -1866,6 +1886,27 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 		self.list_channels_with_filter(|&(_, ref channel)| channel.is_live())
 	}
 
+	/// Gets the liquidity used by the HTLCs of our outbound payments which are still pending in
+	/// our channels, across the channels of each payment path.
+	///
+	/// May be passed to [`find_route_with_inflight_htlcs`] so that concurrent payments (or
+	/// concurrently retried parts of a multi-path payment) avoid relying on the same liquidity.
+	///
+	/// [`find_route_with_inflight_htlcs`]: crate::routing::router::find_route_with_inflight_htlcs
+	pub fn compute_inflight_htlcs(&self) -> InFlightHtlcs {
+		let mut inflight_htlcs = InFlightHtlcs::new();
+		let our_node_id = self.get_our_node_id();
+		let channel_state = self.channel_state.lock().unwrap();
+		for chan in channel_state.by_id.values() {
+			for htlc_source in chan.inflight_htlc_sources() {
+				if let HTLCSource::OutboundRoute { path, .. } = htlc_source {
+					inflight_htlcs.process_path(path, our_node_id);
+				}
+			}
+		}
+		inflight_htlcs
+	}
+
 	/// Helper function that issues the channel close events
 	fn issue_channel_close_events(&self, channel: &Channel<Signer>, closure_reason: ClosureReason) {
 		let mut pending_events_lock = self.pending_events.lock().unwrap();
@@ -2071,6 +2112,64 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 		}
 	}
 
+	/// Indicates that our channel signer may now be able to provide signatures which it previously
+	/// could not, see [`BaseSign::sign_counterparty_commitment`].
+	///
+	/// If `channel_id` is `Some`, only the given channel is checked, otherwise all channels are.
+	/// Any commitment updates (and `revoke_and_ack`s ordered after them) which were waiting on the
+	/// signer are regenerated and sent to the counterparty via [`MessageSendEvent`]s. If the
+	/// signer is still not ready the channel keeps waiting and this may be called again later,
+	/// while if it now rejects the commitment the channel is force-closed.
+	///
+	/// [`BaseSign::sign_counterparty_commitment`]: crate::chain::keysinterface::BaseSign::sign_counterparty_commitment
+	pub fn signer_unblocked(&self, channel_id: Option<[u8; 32]>) {
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(&self.total_consistency_lock, &self.persistence_notifier);
+
+		let mut handle_errors: Vec<(PublicKey, Result<(), _>)> = Vec::new();
+		{
+			let mut channel_state_lock = self.channel_state.lock().unwrap();
+			let channel_state = &mut *channel_state_lock;
+			let short_to_id = &mut channel_state.short_to_id;
+			let pending_msg_events = &mut channel_state.pending_msg_events;
+			channel_state.by_id.retain(|chan_id, chan| {
+				if channel_id.is_some() && channel_id != Some(*chan_id) { return true; }
+				let updates = match chan.signer_maybe_unblocked(&self.logger) {
+					Ok(updates) => updates,
+					Err(e) => {
+						let (close_channel, res) = convert_chan_err!(self, e, short_to_id, chan, chan_id);
+						handle_errors.push((chan.get_counterparty_node_id(), Err(res)));
+						// ChannelClosed event is generated by handle_error for us
+						return !close_channel;
+					},
+				};
+				let counterparty_node_id = chan.get_counterparty_node_id();
+				let cu_msg = updates.commitment_update.map(|updates| events::MessageSendEvent::UpdateHTLCs {
+					node_id: counterparty_node_id,
+					updates,
+				});
+				let raa_msg = updates.raa.map(|msg| events::MessageSendEvent::SendRevokeAndACK {
+					node_id: counterparty_node_id,
+					msg,
+				});
+				match updates.order {
+					RAACommitmentOrder::CommitmentFirst => {
+						if let Some(msg) = cu_msg { pending_msg_events.push(msg); }
+						if let Some(msg) = raa_msg { pending_msg_events.push(msg); }
+					},
+					RAACommitmentOrder::RevokeAndACKFirst => {
+						if let Some(msg) = raa_msg { pending_msg_events.push(msg); }
+						if let Some(msg) = cu_msg { pending_msg_events.push(msg); }
+					},
+				}
+				true
+			});
+		}
+
+		for (counterparty_node_id, err) in handle_errors.drain(..) {
+			let _ = handle_error!(self, err, counterparty_node_id);
+		}
+	}
+
 	fn construct_recv_pending_htlc_info(&self, hop_data: msgs::OnionHopData, shared_secret: [u8; 32],
 		payment_hash: PaymentHash, amt_msat: u64, cltv_expiry: u32) -> Result<PendingHTLCInfo, ReceiveError>
 	{
@@ -2186,10 +2285,22 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 			return_malformed_err!("invalid ephemeral pubkey", 0x8000 | 0x4000 | 6);
 		}
 
-		let shared_secret = {
-			let mut arr = [0; 32];
-			arr.copy_from_slice(&SharedSecret::new(&msg.onion_routing_packet.public_key.unwrap(), &self.our_network_key)[..]);
-			arr
+		let shared_secret = match self.keys_manager.ecdh(Recipient::Node, &msg.onion_routing_packet.public_key.unwrap()) {
+			Ok(ss) => {
+				let mut arr = [0; 32];
+				arr.copy_from_slice(&ss[..]);
+				arr
+			},
+			Err(()) => {
+				// Without the shared secret we cannot encrypt the failure for the sender, so it
+				// will not be able to attribute it, but the HTLC is still failed back promptly.
+				log_info!(self.logger, "Failed to accept/forward incoming HTLC: unable to compute the onion shared secret");
+				return (PendingHTLCStatus::Fail(HTLCFailureMsg::Relay(msgs::UpdateFailHTLC {
+					channel_id: msg.channel_id,
+					htlc_id: msg.htlc_id,
+					reason: onion_utils::build_first_hop_failure_packet(&[0; 32], 0x2000 | 2, &[0; 0]),
+				})), self.channel_state.lock().unwrap());
+			},
 		};
 
 		if msg.onion_routing_packet.version != 0 {
@@ -2415,7 +2526,7 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 			Some(id) => id,
 		};
 
-		let were_node_one = PublicKey::from_secret_key(&self.secp_ctx, &self.our_network_key).serialize()[..] < chan.get_counterparty_node_id().serialize()[..];
+		let were_node_one = self.our_network_pubkey.serialize()[..] < chan.get_counterparty_node_id().serialize()[..];
 
 		let unsigned = msgs::UnsignedChannelUpdate {
 			chain_hash: self.genesis_hash,
@@ -2430,8 +2541,10 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 			excess_data: Vec::new(),
 		};
 
-		let msg_hash = Sha256dHash::hash(&unsigned.encode()[..]);
-		let sig = self.secp_ctx.sign(&hash_to_message!(&msg_hash[..]), &self.our_network_key);
+		let sig = match self.keys_manager.sign_gossip_message(msgs::UnsignedGossipMessage::ChannelUpdate(&unsigned)) {
+			Ok(sig) => sig,
+			Err(()) => return Err(LightningError{err: "Failed to generate signature for channel_update".to_owned(), action: msgs::ErrorAction::IgnoreError}),
+		};
 
 		Ok(msgs::ChannelUpdate {
 			signature: sig,
@@ -2484,6 +2597,7 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 						payment_secret: *payment_secret,
 						starting_block_height: self.best_block.read().unwrap().height(),
 						total_msat: total_value,
+						is_rebalance: self.pending_rebalances.lock().unwrap().contains(payment_hash),
 					});
 					assert!(payment.insert(session_priv_bytes, path));
 				}
@@ -2511,7 +2625,7 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 				} {
 					Some((update_add, commitment_signed, monitor_update)) => {
 						if let Err(e) = self.chain_monitor.update_channel(chan.get().get_funding_txo().unwrap(), monitor_update) {
-							maybe_break_monitor_err!(self, e, channel_state, chan, RAACommitmentOrder::CommitmentFirst, false, true);
+							maybe_break_monitor_err!(self, e, channel_state, chan, RAACommitmentOrder::CommitmentFirst, false, commitment_signed.is_some());
 							// Note that MonitorUpdateFailed here indicates (per function docs)
 							// that we will resend the commitment update once monitor updating
 							// is restored. Therefore, we must return an error indicating that
@@ -2522,18 +2636,22 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 						}
 						insert_outbound_payment!();
 
-						log_debug!(self.logger, "Sending payment along path resulted in a commitment_signed for channel {}", log_bytes!(chan.get().channel_id()));
-						channel_state.pending_msg_events.push(events::MessageSendEvent::UpdateHTLCs {
-							node_id: path.first().unwrap().pubkey,
-							updates: msgs::CommitmentUpdate {
-								update_add_htlcs: vec![update_add],
-								update_fulfill_htlcs: Vec::new(),
-								update_fail_htlcs: Vec::new(),
-								update_fail_malformed_htlcs: Vec::new(),
-								update_fee: None,
-								commitment_signed,
-							},
-						});
+						if let Some(commitment_signed) = commitment_signed {
+							log_debug!(self.logger, "Sending payment along path resulted in a commitment_signed for channel {}", log_bytes!(chan.get().channel_id()));
+							channel_state.pending_msg_events.push(events::MessageSendEvent::UpdateHTLCs {
+								node_id: path.first().unwrap().pubkey,
+								updates: msgs::CommitmentUpdate {
+									update_add_htlcs: vec![update_add],
+									update_fulfill_htlcs: Vec::new(),
+									update_fail_htlcs: Vec::new(),
+									update_fail_malformed_htlcs: Vec::new(),
+									update_fee: None,
+									commitment_signed,
+								},
+							});
+						} else {
+							log_debug!(self.logger, "Sending payment along path is waiting on our signer for channel {}", log_bytes!(chan.get().channel_id()));
+						}
 					},
 					None => { insert_outbound_payment!(); },
 				}
@@ -2758,10 +2876,9 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 		if let hash_map::Entry::Occupied(mut payment) = outbounds.entry(payment_id) {
 			if let Ok(()) = payment.get_mut().mark_abandoned() {
 				if payment.get().remaining_parts() == 0 {
-					self.pending_events.lock().unwrap().push(events::Event::PaymentFailed {
-						payment_id,
-						payment_hash: payment.get().payment_hash().expect("PendingOutboundPayments::RetriesExceeded always has a payment hash set"),
-					});
+					let payment_hash = payment.get().payment_hash().expect("PendingOutboundPayments::RetriesExceeded always has a payment hash set");
+					self.pending_events.lock().unwrap().push(events::Event::PaymentFailed { payment_id, payment_hash });
+					self.pending_rebalances.lock().unwrap().remove(&payment_hash);
 					payment.remove();
 				}
 			}
@@ -2794,6 +2911,47 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 		}
 	}
 
+	/// Send a circular payment to ourselves along the given route, moving liquidity out of the
+	/// channel used by the first hop of each path and back into the channel used by its last hop.
+	/// Such a route can be found with [`find_rebalance_route`].
+	///
+	/// The payment hash and secret are generated internally. Once all parts of the payment reach
+	/// us it is claimed automatically, without an [`Event::PaymentReceived`] being generated, and
+	/// once the claim completes an [`Event::RebalanceCompleted`] is generated in place of an
+	/// [`Event::PaymentSent`], reporting the fees paid to move the liquidity.
+	///
+	/// Pending rebalances are persisted along with the [`ChannelManager`], so a rebalance which
+	/// completes after a restart is still claimed automatically and reported via
+	/// [`Event::RebalanceCompleted`].
+	///
+	/// See [`send_payment`] documentation for more details on the return value of this function.
+	///
+	/// [`find_rebalance_route`]: crate::routing::router::find_rebalance_route
+	/// [`send_payment`]: Self::send_payment
+	/// [`Event::PaymentReceived`]: events::Event::PaymentReceived
+	/// [`Event::RebalanceCompleted`]: events::Event::RebalanceCompleted
+	/// [`Event::PaymentSent`]: events::Event::PaymentSent
+	pub fn send_rebalance(&self, route: &Route) -> Result<(PaymentHash, PaymentId), PaymentSendFailure> {
+		let our_node_id = self.get_our_node_id();
+		if route.paths.iter().any(|path| path.last().map_or(true, |hop| hop.pubkey != our_node_id)) {
+			return Err(PaymentSendFailure::ParameterError(APIError::RouteError{err: "All paths of a rebalance must end at our own node"}));
+		}
+		let (payment_hash, payment_secret) = self.create_inbound_payment(Some(route.get_total_amount()), REBALANCE_EXPIRY_DELTA_SECS)
+			.map_err(|()| PaymentSendFailure::ParameterError(APIError::APIMisuseError{err: "Rebalance amount is greater than total bitcoin supply".to_owned()}))?;
+
+		self.pending_rebalances.lock().unwrap().insert(payment_hash);
+		match self.send_payment_internal(route, payment_hash, &Some(payment_secret), None, None, None) {
+			Ok(payment_id) => Ok((payment_hash, payment_id)),
+			Err(e) => {
+				if let PaymentSendFailure::PartialFailure { .. } = e {} else {
+					// No HTLCs are in flight, so the rebalance can't complete.
+					self.pending_rebalances.lock().unwrap().remove(&payment_hash);
+				}
+				Err(e)
+			}
+		}
+	}
+
 	/// Handles the generation of a funding transaction, optionally (for tests) with a function
 	/// which checks the correctness of the funding transaction given the associated channel.
 	fn funding_transaction_generated_intern<FundingOutput: Fn(&Channel<Signer>, &Transaction) -> Result<OutPoint, APIError>>
@@ -2905,15 +3063,15 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 
 	#[allow(dead_code)]
 	// Messages of up to 64KB should never end up more than half full with addresses, as that would
-	// be absurd. We ensure this by checking that at least 500 (our stated public contract on when
+	// be absurd. We ensure this by checking that at least 100 (our stated public contract on when
 	// broadcast_node_announcement panics) of the maximum-length addresses would fit in a 64KB
 	// message...
 	const HALF_MESSAGE_IS_ADDRS: u32 = ::core::u16::MAX as u32 / (NetAddress::MAX_LEN as u32 + 1) / 2;
 	#[deny(const_err)]
 	#[allow(dead_code)]
 	// ...by failing to compile if the number of addresses that would be half of a message is
-	// smaller than 500:
-	const STATIC_ASSERT: u32 = Self::HALF_MESSAGE_IS_ADDRS - 500;
+	// smaller than 100:
+	const STATIC_ASSERT: u32 = Self::HALF_MESSAGE_IS_ADDRS - 100;
 
 	/// Regenerates channel_announcements and generates a signed node_announcement from the given
 	/// arguments, providing them in corresponding events via
@@ -2928,17 +3086,22 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 	/// `addresses` represent the set (possibly empty) of socket addresses on which this node
 	/// accepts incoming connections. These will be included in the node_announcement, publicly
 	/// tying these addresses together and to this node. If you wish to preserve user privacy,
-	/// addresses should likely contain only Tor Onion addresses.
+	/// addresses should likely contain only Tor Onion addresses. DNS hostnames may be included via
+	/// [`NetAddress::Hostname`], though at most one should be given.
 	///
-	/// Panics if `addresses` is absurdly large (more than 500).
+	/// Panics if `addresses` is absurdly large (more than 100) or if any address is not
+	/// [`NetAddress::is_valid`], e.g. a hostname which is empty, too long, or not printable ASCII.
 	///
 	/// [`get_and_clear_pending_msg_events`]: MessageSendEventsProvider::get_and_clear_pending_msg_events
 	pub fn broadcast_node_announcement(&self, rgb: [u8; 3], alias: [u8; 32], mut addresses: Vec<NetAddress>) {
 		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(&self.total_consistency_lock, &self.persistence_notifier);
 
-		if addresses.len() > 500 {
+		if addresses.len() > 100 {
 			panic!("More than half the message size was taken up by public addresses!");
 		}
+		if addresses.iter().any(|addr| !addr.is_valid()) {
+			panic!("Node announcement addresses must be valid to be encoded!");
+		}
 
 		// While all existing nodes handle unsorted addresses just fine, the spec requires that
 		// addresses be sorted for future compatibility.
@@ -2952,8 +3115,13 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 			excess_address_data: Vec::new(),
 			excess_data: Vec::new(),
 		};
-		let msghash = hash_to_message!(&Sha256dHash::hash(&announcement.encode()[..])[..]);
-		let node_announce_sig = self.secp_ctx.sign(&msghash, &self.our_network_key);
+		let node_announce_sig = match self.keys_manager.sign_gossip_message(msgs::UnsignedGossipMessage::NodeAnnouncement(&announcement)) {
+			Ok(sig) => sig,
+			Err(()) => {
+				log_error!(self.logger, "Failed to generate signature for node_announcement");
+				return;
+			},
+		};
 
 		let mut channel_state_lock = self.channel_state.lock().unwrap();
 		let channel_state = &mut *channel_state_lock;
@@ -3029,11 +3197,13 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 												}
 											}
 											if let PendingHTLCRouting::Forward { onion_packet, .. } = routing {
-												let phantom_secret_res = self.keys_manager.get_node_secret(Recipient::PhantomNode);
-												if phantom_secret_res.is_ok() && fake_scid::is_valid_phantom(&self.fake_scid_rand_bytes, short_chan_id) {
+												let phantom_shared_secret = if fake_scid::is_valid_phantom(&self.fake_scid_rand_bytes, short_chan_id) {
+													self.keys_manager.ecdh(Recipient::PhantomNode, &onion_packet.public_key.unwrap()).ok()
+												} else { None };
+												if let Some(phantom_shared_secret) = phantom_shared_secret {
 													let shared_secret = {
 														let mut arr = [0; 32];
-														arr.copy_from_slice(&SharedSecret::new(&onion_packet.public_key.unwrap(), &phantom_secret_res.unwrap())[..]);
+														arr.copy_from_slice(&phantom_shared_secret[..]);
 														arr
 													};
 													let next_hop = match onion_utils::decode_next_hop(shared_secret, &onion_packet.hop_data, onion_packet.hmac, payment_hash) {
@@ -3181,9 +3351,17 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 								}
 							};
 							if let Err(e) = self.chain_monitor.update_channel(chan.get().get_funding_txo().unwrap(), monitor_update) {
-								handle_errors.push((chan.get().get_counterparty_node_id(), handle_monitor_err!(self, e, channel_state, chan, RAACommitmentOrder::CommitmentFirst, false, true)));
+								handle_errors.push((chan.get().get_counterparty_node_id(), handle_monitor_err!(self, e, channel_state, chan, RAACommitmentOrder::CommitmentFirst, false, commitment_msg.is_some())));
 								continue;
 							}
+							let commitment_msg = match commitment_msg {
+								Some(msg) => msg,
+								None => {
+									log_debug!(self.logger, "Forwarding HTLCs for channel {} is waiting on our signer to provide a commitment signature",
+										log_bytes!(chan.get().channel_id()));
+									continue;
+								},
+							};
 							log_debug!(self.logger, "Forwarding HTLCs resulted in a commitment update with {} HTLCs added and {} HTLCs failed for channel {}",
 								add_htlc_msgs.len(), fail_htlc_msgs.len(), log_bytes!(chan.get().channel_id()));
 							channel_state.pending_msg_events.push(events::MessageSendEvent::UpdateHTLCs {
@@ -3380,6 +3558,29 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 			let _ = handle_error!(self, err, counterparty_node_id);
 		}
 
+		// Claim any rebalances we sent to ourselves which have now been fully received rather than
+		// surfacing them to the user.
+		let mut rebalance_preimages = Vec::new();
+		{
+			let pending_rebalances = self.pending_rebalances.lock().unwrap();
+			if !pending_rebalances.is_empty() {
+				new_events.retain(|event| {
+					if let events::Event::PaymentReceived { payment_hash, purpose: events::PaymentPurpose::InvoicePayment { payment_preimage: Some(payment_preimage), .. }, .. } = event {
+						if pending_rebalances.contains(payment_hash) {
+							rebalance_preimages.push(*payment_preimage);
+							return false;
+						}
+					}
+					true
+				});
+			}
+		}
+		for payment_preimage in rebalance_preimages.drain(..) {
+			if !self.claim_received_payment(payment_preimage) {
+				log_error!(self.logger, "Failed to claim rebalance with payment_preimage {}", log_bytes!(payment_preimage.0));
+			}
+		}
+
 		if new_events.is_empty() { return }
 		let mut events = self.pending_events.lock().unwrap();
 		events.append(&mut new_events);
@@ -3444,21 +3645,23 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 		let ret_err = match res {
 			Ok(Some((update_fee, commitment_signed, monitor_update))) => {
 				if let Err(e) = self.chain_monitor.update_channel(chan.get_funding_txo().unwrap(), monitor_update) {
-					let (res, drop) = handle_monitor_err!(self, e, short_to_id, chan, RAACommitmentOrder::CommitmentFirst, false, true, Vec::new(), Vec::new(), Vec::new(), chan_id);
+					let (res, drop) = handle_monitor_err!(self, e, short_to_id, chan, RAACommitmentOrder::CommitmentFirst, false, commitment_signed.is_some(), Vec::new(), Vec::new(), Vec::new(), chan_id);
 					if drop { retain_channel = false; }
 					res
 				} else {
-					pending_msg_events.push(events::MessageSendEvent::UpdateHTLCs {
-						node_id: chan.get_counterparty_node_id(),
-						updates: msgs::CommitmentUpdate {
-							update_add_htlcs: Vec::new(),
-							update_fulfill_htlcs: Vec::new(),
-							update_fail_htlcs: Vec::new(),
-							update_fail_malformed_htlcs: Vec::new(),
-							update_fee: Some(update_fee),
-							commitment_signed,
-						},
-					});
+					if let Some(commitment_signed) = commitment_signed {
+						pending_msg_events.push(events::MessageSendEvent::UpdateHTLCs {
+							node_id: chan.get_counterparty_node_id(),
+							updates: msgs::CommitmentUpdate {
+								update_add_htlcs: Vec::new(),
+								update_fulfill_htlcs: Vec::new(),
+								update_fail_htlcs: Vec::new(),
+								update_fail_malformed_htlcs: Vec::new(),
+								update_fee: Some(update_fee),
+								commitment_signed,
+							},
+						});
+					}
 					Ok(())
 				}
 			},
@@ -3650,10 +3853,9 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 								error_data: None,
 							});
 							if payment.get().abandoned() && payment.get().remaining_parts() == 0 {
-								pending_events.push(events::Event::PaymentFailed {
-									payment_id,
-									payment_hash: payment.get().payment_hash().expect("PendingOutboundPayments::RetriesExceeded always has a payment hash set"),
-								});
+								let payment_hash = payment.get().payment_hash().expect("PendingOutboundPayments::RetriesExceeded always has a payment hash set");
+								self.pending_rebalances.lock().unwrap().remove(&payment_hash);
+								pending_events.push(events::Event::PaymentFailed { payment_id, payment_hash });
 								payment.remove();
 							}
 						}
@@ -3699,10 +3901,9 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 					if payment.get().remaining_parts() == 0 {
 						all_paths_failed = true;
 						if payment.get().abandoned() {
-							full_failure_ev = Some(events::Event::PaymentFailed {
-								payment_id,
-								payment_hash: payment.get().payment_hash().expect("PendingOutboundPayments::RetriesExceeded always has a payment hash set"),
-							});
+							let payment_hash = payment.get().payment_hash().expect("PendingOutboundPayments::RetriesExceeded always has a payment hash set");
+							self.pending_rebalances.lock().unwrap().remove(&payment_hash);
+							full_failure_ev = Some(events::Event::PaymentFailed { payment_id, payment_hash });
 							payment.remove();
 						}
 					}
@@ -3830,9 +4031,14 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 	/// [`create_inbound_payment_for_hash`]: Self::create_inbound_payment_for_hash
 	/// [`get_and_clear_pending_msg_events`]: MessageSendEventsProvider::get_and_clear_pending_msg_events
 	pub fn claim_funds(&self, payment_preimage: PaymentPreimage) -> bool {
-		let payment_hash = PaymentHash(Sha256::hash(&payment_preimage.0).into_inner());
-
 		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(&self.total_consistency_lock, &self.persistence_notifier);
+		self.claim_received_payment(payment_preimage)
+	}
+
+	/// Claims the HTLCs of a received payment, as in [`Self::claim_funds`]. Must be called while
+	/// holding the `total_consistency_lock` read lock.
+	fn claim_received_payment(&self, payment_preimage: PaymentPreimage) -> bool {
+		let payment_hash = PaymentHash(Sha256::hash(&payment_preimage.0).into_inner());
 
 		let mut channel_state = Some(self.channel_state.lock().unwrap());
 		let removed_source = channel_state.as_mut().unwrap().claimable_htlcs.remove(&payment_hash);
@@ -4003,14 +4209,24 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 					if !payment.get().is_fulfilled() {
 						let payment_hash = PaymentHash(Sha256::hash(&payment_preimage.0).into_inner());
 						let fee_paid_msat = payment.get().get_pending_fee_msat();
-						pending_events.push(
-							events::Event::PaymentSent {
-								payment_id: Some(payment_id),
-								payment_preimage,
-								payment_hash,
-								fee_paid_msat,
-							}
-						);
+						if self.pending_rebalances.lock().unwrap().remove(&payment_hash) {
+							pending_events.push(
+								events::Event::RebalanceCompleted {
+									payment_id,
+									payment_hash,
+									fee_paid_msat,
+								}
+							);
+						} else {
+							pending_events.push(
+								events::Event::PaymentSent {
+									payment_id: Some(payment_id),
+									payment_preimage,
+									payment_hash,
+									fee_paid_msat,
+								}
+							);
+						}
 						payment.get_mut().mark_fulfilled();
 					}
 
@@ -4097,6 +4313,21 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 		self.our_network_pubkey.clone()
 	}
 
+	/// Signs the given message with our node key, returning a zbase32-encoded signature which is
+	/// compatible with the `signmessage` commands of lnd and c-lightning.
+	///
+	/// The node secret never leaves the [`KeysInterface`], see [`KeysInterface::sign_message`].
+	pub fn sign_message(&self, msg: &[u8]) -> Result<String, ()> {
+		self.keys_manager.sign_message(msg)
+	}
+
+	/// Checks that the given zbase32-encoded signature over `msg` (as generated by
+	/// [`Self::sign_message`] or the `signmessage` commands of lnd and c-lightning) was made by the
+	/// node with the given `node_id`.
+	pub fn verify_message(&self, msg: &[u8], sig: &str, node_id: &PublicKey) -> bool {
+		message_signing::verify(msg, sig, node_id)
+	}
+
 	fn channel_monitor_updated(&self, funding_txo: &OutPoint, highest_applied_update_id: u64) {
 		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(&self.total_consistency_lock, &self.persistence_notifier);
 
@@ -4595,12 +4826,14 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 						Ok(res) => res
 					};
 				if let Err(e) = self.chain_monitor.update_channel(chan.get().get_funding_txo().unwrap(), monitor_update) {
-					return_monitor_err!(self, e, channel_state, chan, RAACommitmentOrder::RevokeAndACKFirst, true, commitment_signed.is_some());
+					return_monitor_err!(self, e, channel_state, chan, RAACommitmentOrder::RevokeAndACKFirst, revoke_and_ack.is_some(), commitment_signed.is_some());
+				}
+				if let Some(msg) = revoke_and_ack {
+					channel_state.pending_msg_events.push(events::MessageSendEvent::SendRevokeAndACK {
+						node_id: counterparty_node_id.clone(),
+						msg,
+					});
 				}
-				channel_state.pending_msg_events.push(events::MessageSendEvent::SendRevokeAndACK {
-					node_id: counterparty_node_id.clone(),
-					msg: revoke_and_ack,
-				});
 				if let Some(msg) = commitment_signed {
 					channel_state.pending_msg_events.push(events::MessageSendEvent::UpdateHTLCs {
 						node_id: counterparty_node_id.clone(),
@@ -4946,10 +5179,10 @@ impl<Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref> ChannelMana
 						if let Some((commitment_update, monitor_update)) = commitment_opt {
 							if let Err(e) = self.chain_monitor.update_channel(chan.get_funding_txo().unwrap(), monitor_update) {
 								has_monitor_update = true;
-								let (res, close_channel) = handle_monitor_err!(self, e, short_to_id, chan, RAACommitmentOrder::CommitmentFirst, false, true, Vec::new(), Vec::new(), Vec::new(), channel_id);
+								let (res, close_channel) = handle_monitor_err!(self, e, short_to_id, chan, RAACommitmentOrder::CommitmentFirst, false, commitment_update.is_some(), Vec::new(), Vec::new(), Vec::new(), channel_id);
 								handle_errors.push((chan.get_counterparty_node_id(), res));
 								if close_channel { return false; }
-							} else {
+							} else if let Some(commitment_update) = commitment_update {
 								pending_msg_events.push(events::MessageSendEvent::UpdateHTLCs {
 									node_id: chan.get_counterparty_node_id(),
 									updates: commitment_update,
@@ -5439,6 +5672,7 @@ where
 					pending_events.push(events::Event::PaymentFailed {
 						payment_id: *payment_id, payment_hash: *payment_hash,
 					});
+					self.pending_rebalances.lock().unwrap().remove(payment_hash);
 					false
 				} else { true }
 			} else { true }
@@ -5608,12 +5842,22 @@ where
 		self.persistence_notifier.wait()
 	}
 
+	/// Gets a [`Future`] that completes when a persistable update is available. Note that
+	/// callbacks registered on the [`Future`] MUST NOT call back into this [`ChannelManager`] and
+	/// should instead register actions to be taken later.
+	///
+	/// Only one listener on the returned [`Future`], [`await_persistable_update`] or
+	/// [`await_persistable_update_timeout`] is guaranteed to be woken up.
+	///
+	/// [`await_persistable_update`]: Self::await_persistable_update
+	/// [`await_persistable_update_timeout`]: Self::await_persistable_update_timeout
+	pub fn get_persistable_update_future(&self) -> Future {
+		self.persistence_notifier.get_future()
+	}
+
 	#[cfg(any(test, feature = "_test_utils"))]
 	pub fn get_persistence_condvar_value(&self) -> bool {
-		let mutcond = &self.persistence_notifier.persistence_lock;
-		let &(ref mtx, _) = mutcond;
-		let guard = mtx.lock().unwrap();
-		*guard
+		self.persistence_notifier.notify_pending()
 	}
 
 	/// Gets the latest best block which was connected either via the [`chain::Listen`] or
@@ -5859,77 +6103,6 @@ impl<Signer: Sign, M: Deref , T: Deref , K: Deref , F: Deref , L: Deref >
 	}
 }
 
-/// Used to signal to the ChannelManager persister that the manager needs to be re-persisted to
-/// disk/backups, through `await_persistable_update_timeout` and `await_persistable_update`.
-struct PersistenceNotifier {
-	/// Users won't access the persistence_lock directly, but rather wait on its bool using
-	/// `wait_timeout` and `wait`.
-	persistence_lock: (Mutex<bool>, Condvar),
-}
-
-impl PersistenceNotifier {
-	fn new() -> Self {
-		Self {
-			persistence_lock: (Mutex::new(false), Condvar::new()),
-		}
-	}
-
-	fn wait(&self) {
-		loop {
-			let &(ref mtx, ref cvar) = &self.persistence_lock;
-			let mut guard = mtx.lock().unwrap();
-			if *guard {
-				*guard = false;
-				return;
-			}
-			guard = cvar.wait(guard).unwrap();
-			let result = *guard;
-			if result {
-				*guard = false;
-				return
-			}
-		}
-	}
-
-	#[cfg(any(test, feature = "std"))]
-	fn wait_timeout(&self, max_wait: Duration) -> bool {
-		let current_time = Instant::now();
-		loop {
-			let &(ref mtx, ref cvar) = &self.persistence_lock;
-			let mut guard = mtx.lock().unwrap();
-			if *guard {
-				*guard = false;
-				return true;
-			}
-			guard = cvar.wait_timeout(guard, max_wait).unwrap().0;
-			// Due to spurious wakeups that can happen on `wait_timeout`, here we need to check if the
-			// desired wait time has actually passed, and if not then restart the loop with a reduced wait
-			// time. Note that this logic can be highly simplified through the use of
-			// `Condvar::wait_while` and `Condvar::wait_timeout_while`, if and when our MSRV is raised to
-			// 1.42.0.
-			let elapsed = current_time.elapsed();
-			let result = *guard;
-			if result || elapsed >= max_wait {
-				*guard = false;
-				return result;
-			}
-			match max_wait.checked_sub(elapsed) {
-				None => return result,
-				Some(_) => continue
-			}
-		}
-	}
-
-	// Signal to the ChannelManager persister that there are updates necessitating persisting to disk.
-	fn notify(&self) {
-		let &(ref persist_mtx, ref cnd) = &self.persistence_lock;
-		let mut persistence_lock = persist_mtx.lock().unwrap();
-		*persistence_lock = true;
-		mem::drop(persistence_lock);
-		cnd.notify_all();
-	}
-}
-
 const SERIALIZATION_VERSION: u8 = 1;
 const MIN_SERIALIZATION_VERSION: u8 = 1;
 
@@ -6242,6 +6415,7 @@ impl_writeable_tlv_based_enum_upgradable!(PendingOutboundPayment,
 		(6, total_msat, required),
 		(8, pending_amt_msat, required),
 		(10, starting_block_height, required),
+		(11, is_rebalance, (default_value, false)),
 	},
 	(3, Abandoned) => {
 		(0, session_privs, required),
@@ -6724,6 +6898,7 @@ impl<'a, Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref>
 										pending_fee_msat: Some(path_fee),
 										total_msat: path_amt,
 										starting_block_height: best_block_height,
+										is_rebalance: false,
 									});
 									log_info!(args.logger, "Added a pending payment for {} msat with payment hash {} for path with session priv {}",
 										path_amt, log_bytes!(htlc.payment_hash.0),  log_bytes!(session_priv_bytes));
@@ -6742,11 +6917,10 @@ impl<'a, Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref>
 			pending_events_read.append(&mut channel_closures);
 		}
 
-		let our_network_key = match args.keys_manager.get_node_secret(Recipient::Node) {
+		let our_network_pubkey = match args.keys_manager.get_node_id(Recipient::Node) {
 			Ok(key) => key,
 			Err(()) => return Err(DecodeError::InvalidValue)
 		};
-		let our_network_pubkey = PublicKey::from_secret_key(&secp_ctx, &our_network_key);
 		if let Some(network_pubkey) = received_network_pubkey {
 			if network_pubkey != our_network_pubkey {
 				log_error!(args.logger, "Key that was generated does not match the existing key.");
@@ -6754,6 +6928,11 @@ impl<'a, Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref>
 			}
 		}
 
+		let pending_rebalances = pending_outbound_payments.as_ref().unwrap().values().filter_map(|payment| match payment {
+			PendingOutboundPayment::Retryable { payment_hash, is_rebalance: true, .. } => Some(*payment_hash),
+			_ => None,
+		}).collect();
+
 		let inbound_pmt_key_material = args.keys_manager.get_inbound_payment_key_material();
 		let expanded_inbound_key = inbound_payment::ExpandedKey::new(&inbound_pmt_key_material);
 		let channel_manager = ChannelManager {
@@ -6774,9 +6953,9 @@ impl<'a, Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref>
 			inbound_payment_key: expanded_inbound_key,
 			pending_inbound_payments: Mutex::new(pending_inbound_payments),
 			pending_outbound_payments: Mutex::new(pending_outbound_payments.unwrap()),
+			pending_rebalances: Mutex::new(pending_rebalances),
 			fake_scid_rand_bytes: fake_scid_rand_bytes.unwrap(),
 
-			our_network_key,
 			our_network_pubkey,
 			secp_ctx,
 
@@ -6788,7 +6967,7 @@ impl<'a, Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref>
 			pending_events: Mutex::new(pending_events_read),
 			pending_background_events: Mutex::new(pending_background_events_read),
 			total_consistency_lock: RwLock::new(()),
-			persistence_notifier: PersistenceNotifier::new(),
+			persistence_notifier: Notifier::new(),
 
 			keys_manager: args.keys_manager,
 			logger: args.logger,
@@ -6819,59 +6998,12 @@ mod tests {
 	use ln::functional_test_utils::*;
 	use ln::msgs;
 	use ln::msgs::ChannelMessageHandler;
-	use routing::router::{PaymentParameters, RouteParameters, find_route};
+	use routing::network_graph::NodeId;
+	use routing::router::{InFlightHtlcs, PaymentParameters, RouteParameters, find_route};
 	use util::errors::APIError;
 	use util::events::{Event, MessageSendEvent, MessageSendEventsProvider};
 	use util::test_utils;
 
-	#[cfg(feature = "std")]
-	#[test]
-	fn test_wait_timeout() {
-		use ln::channelmanager::PersistenceNotifier;
-		use sync::Arc;
-		use core::sync::atomic::AtomicBool;
-		use std::thread;
-
-		let persistence_notifier = Arc::new(PersistenceNotifier::new());
-		let thread_notifier = Arc::clone(&persistence_notifier);
-
-		let exit_thread = Arc::new(AtomicBool::new(false));
-		let exit_thread_clone = exit_thread.clone();
-		thread::spawn(move || {
-			loop {
-				let &(ref persist_mtx, ref cnd) = &thread_notifier.persistence_lock;
-				let mut persistence_lock = persist_mtx.lock().unwrap();
-				*persistence_lock = true;
-				cnd.notify_all();
-
-				if exit_thread_clone.load(Ordering::SeqCst) {
-					break
-				}
-			}
-		});
-
-		// Check that we can block indefinitely until updates are available.
-		let _ = persistence_notifier.wait();
-
-		// Check that the PersistenceNotifier will return after the given duration if updates are
-		// available.
-		loop {
-			if persistence_notifier.wait_timeout(Duration::from_millis(100)) {
-				break
-			}
-		}
-
-		exit_thread.store(true, Ordering::SeqCst);
-
-		// Check that the PersistenceNotifier will return after the given duration even if no updates
-		// are available.
-		loop {
-			if !persistence_notifier.wait_timeout(Duration::from_millis(100)) {
-				break
-			}
-		}
-	}
-
 	#[test]
 	fn test_notify_limits() {
 		// Check that a few cases which don't require the persistence of a new ChannelManager,
@@ -7276,6 +7408,49 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn test_compute_inflight_htlcs() {
+		let chanmon_cfgs = create_chanmon_cfgs(3);
+		let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+		let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+		let chan_1_id = create_announced_chan_between_nodes(&nodes, 0, 1, InitFeatures::known(), InitFeatures::known()).0.contents.short_channel_id;
+		let chan_2_id = create_announced_chan_between_nodes(&nodes, 1, 2, InitFeatures::known(), InitFeatures::known()).0.contents.short_channel_id;
+		let node_0_id = NodeId::from_pubkey(&nodes[0].node.get_our_node_id());
+		let node_1_id = NodeId::from_pubkey(&nodes[1].node.get_our_node_id());
+		let node_2_id = NodeId::from_pubkey(&nodes[2].node.get_our_node_id());
+
+		assert_eq!(nodes[0].node.compute_inflight_htlcs(), InFlightHtlcs::new());
+
+		let (route, payment_hash, payment_preimage, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[2], 100_000);
+		send_along_route_with_secret(&nodes[0], route.clone(), &[&[&nodes[1], &nodes[2]]], 100_000, payment_hash, payment_secret);
+
+		// Only the payer tracks the HTLC along the full path, including the fee paid to nodes[1].
+		let inflight_htlcs = nodes[0].node.compute_inflight_htlcs();
+		assert_eq!(inflight_htlcs.used_liquidity_msat(&node_0_id, &node_1_id, chan_1_id), Some(route.get_total_fees() + 100_000));
+		assert_eq!(inflight_htlcs.used_liquidity_msat(&node_1_id, &node_2_id, chan_2_id), Some(100_000));
+		assert_eq!(inflight_htlcs.used_liquidity_msat(&node_1_id, &node_0_id, chan_1_id), None);
+		assert_eq!(nodes[1].node.compute_inflight_htlcs(), InFlightHtlcs::new());
+
+		claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_preimage);
+		assert_eq!(nodes[0].node.compute_inflight_htlcs(), InFlightHtlcs::new());
+	}
+
+	#[test]
+	fn test_sign_and_verify_message() {
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+		let msg = b"Test message to be signed with our node key";
+		let sig = nodes[0].node.sign_message(msg).unwrap();
+		assert!(nodes[0].node.verify_message(msg, &sig, &nodes[0].node.get_our_node_id()));
+		assert!(nodes[1].node.verify_message(msg, &sig, &nodes[0].node.get_our_node_id()));
+		assert!(!nodes[1].node.verify_message(msg, &sig, &nodes[1].node.get_our_node_id()));
+		assert!(!nodes[1].node.verify_message(b"Some other message", &sig, &nodes[0].node.get_our_node_id()));
+	}
+
 	#[test]
 	fn bad_inbound_payment_hash() {
 		// Add coverage for checking that a user-provided payment hash matches the payment secret.
@@ -658,6 +658,11 @@ impl<T: sealed::Context> Features<T> {
 		}
 	}
 
+	/// Returns true if the given feature bit is set, regardless of whether it is known.
+	pub(crate) fn supports_bit(&self, bit: u16) -> bool {
+		self.flags.get(bit as usize / 8).map_or(false, |byte| byte & (1 << (bit % 8)) != 0)
+	}
+
 	pub(crate) fn supports_any_optional_bits(&self) -> bool {
 		self.flags.iter().any(|&byte| (byte & 0b10_10_10_10) != 0)
 	}
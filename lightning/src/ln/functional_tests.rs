@@ -1464,7 +1464,7 @@ fn test_fee_spike_violation_fails_htlc() {
 
 		let pubkeys = chan_signer.pubkeys();
 		(pubkeys.revocation_basepoint, pubkeys.htlc_basepoint,
-		 chan_signer.release_commitment_secret(INITIAL_COMMITMENT_NUMBER),
+		 chan_signer.release_commitment_secret(INITIAL_COMMITMENT_NUMBER).unwrap(),
 		 chan_signer.get_per_commitment_point(INITIAL_COMMITMENT_NUMBER - 2, &secp_ctx),
 		 chan_signer.pubkeys().funding_pubkey)
 	};
@@ -8122,15 +8122,15 @@ fn test_counterparty_raa_skip_no_crash() {
 
 	// Make signer believe we got a counterparty signature, so that it allows the revocation
 	keys.get_enforcement_state().last_holder_commitment -= 1;
-	let per_commitment_secret = keys.release_commitment_secret(INITIAL_COMMITMENT_NUMBER);
+	let per_commitment_secret = keys.release_commitment_secret(INITIAL_COMMITMENT_NUMBER).unwrap();
 
 	// Must revoke without gaps
 	keys.get_enforcement_state().last_holder_commitment -= 1;
-	keys.release_commitment_secret(INITIAL_COMMITMENT_NUMBER - 1);
+	keys.release_commitment_secret(INITIAL_COMMITMENT_NUMBER - 1).unwrap();
 
 	keys.get_enforcement_state().last_holder_commitment -= 1;
 	let next_per_commitment_point = PublicKey::from_secret_key(&Secp256k1::new(),
-		&SecretKey::from_slice(&keys.release_commitment_secret(INITIAL_COMMITMENT_NUMBER - 2)).unwrap());
+		&SecretKey::from_slice(&keys.release_commitment_secret(INITIAL_COMMITMENT_NUMBER - 2).unwrap()).unwrap());
 
 	nodes[1].node.handle_revoke_and_ack(&nodes[0].node.get_our_node_id(),
 		&msgs::RevokeAndACK { channel_id, per_commitment_secret, next_per_commitment_point });
@@ -9,18 +9,22 @@
 
 //! Further functional tests which test blockchain reorganizations.
 
-use chain::channelmonitor::{ANTI_REORG_DELAY, Balance};
+use chain::channelmonitor::{ANTI_REORG_DELAY, ARCHIVAL_DELAY_BLOCKS, Balance, ChannelMonitor};
 use chain::transaction::OutPoint;
 use ln::channel;
 use ln::channelmanager::BREAKDOWN_TIMEOUT;
 use ln::features::InitFeatures;
 use ln::msgs::ChannelMessageHandler;
+use util::enforcing_trait_impls::EnforcingSigner;
 use util::events::{Event, MessageSendEvent, MessageSendEventsProvider, ClosureReason};
+use util::ser::{ReadableArgs, Writeable};
 
 use bitcoin::blockdata::script::Builder;
 use bitcoin::blockdata::opcodes;
+use bitcoin::hash_types::BlockHash;
 use bitcoin::secp256k1::Secp256k1;
 
+use io;
 use prelude::*;
 
 use ln::functional_test_utils::*;
@@ -176,6 +180,59 @@ fn chanmon_claim_value_coop_close() {
 	check_closed_event!(nodes[1], 1, ClosureReason::CooperativeClosure);
 }
 
+#[test]
+fn test_archive_fully_resolved_monitors() {
+	// Tests that `ChainMonitor::archive_fully_resolved_channel_monitors` only archives a closed
+	// channel's monitor once it has had no claimable balances for `ARCHIVAL_DELAY_BLOCKS`.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	let (_, _, chan_id, funding_tx) =
+		create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 1_000_000, InitFeatures::known(), InitFeatures::known());
+	let funding_outpoint = OutPoint { txid: funding_tx.txid(), index: 0 };
+
+	// Open channels are never archived.
+	nodes[0].chain_monitor.chain_monitor.archive_fully_resolved_channel_monitors();
+	assert_eq!(nodes[0].chain_monitor.chain_monitor.list_monitors(), vec![funding_outpoint]);
+
+	let (_, _, closing_tx) = close_channel(&nodes[0], &nodes[1], &chan_id, funding_tx, true);
+	check_closed_event!(nodes[0], 1, ClosureReason::CooperativeClosure);
+	check_closed_event!(nodes[1], 1, ClosureReason::CooperativeClosure);
+	mine_transaction(&nodes[0], &closing_tx);
+	connect_blocks(&nodes[0], ANTI_REORG_DELAY - 1);
+	assert_eq!(nodes[0].chain_monitor.chain_monitor.get_and_clear_pending_events().len(), 1);
+	assert!(nodes[0].chain_monitor.chain_monitor.get_monitor(funding_outpoint).unwrap().get_claimable_balances().is_empty());
+
+	// The countdown towards archival starts once the balances are gone...
+	assert!(nodes[0].chain_monitor.chain_monitor.list_fully_resolved_monitors().is_empty());
+	nodes[0].chain_monitor.chain_monitor.archive_fully_resolved_channel_monitors();
+	assert_eq!(nodes[0].chain_monitor.chain_monitor.list_monitors(), vec![funding_outpoint]);
+
+	connect_blocks(&nodes[0], ARCHIVAL_DELAY_BLOCKS - 1);
+	nodes[0].chain_monitor.chain_monitor.archive_fully_resolved_channel_monitors();
+	assert_eq!(nodes[0].chain_monitor.chain_monitor.list_monitors(), vec![funding_outpoint]);
+	assert!(chanmon_cfgs[0].persister.archived_channels.lock().unwrap().is_empty());
+
+	// ...and once it has elapsed the monitor is archived.
+	connect_blocks(&nodes[0], 1);
+	assert_eq!(nodes[0].chain_monitor.chain_monitor.list_fully_resolved_monitors(), vec![funding_outpoint]);
+	// The countdown is persisted with the monitor.
+	let serialized_monitor = nodes[0].chain_monitor.chain_monitor.get_monitor(funding_outpoint).unwrap().encode();
+	let (_, reloaded_monitor) = <(BlockHash, ChannelMonitor<EnforcingSigner>)>::read(
+		&mut io::Cursor::new(&serialized_monitor), nodes[0].keys_manager).unwrap();
+	assert!(reloaded_monitor.is_fully_resolved());
+	nodes[0].chain_monitor.chain_monitor.archive_fully_resolved_channel_monitors();
+	assert!(nodes[0].chain_monitor.chain_monitor.list_monitors().is_empty());
+	assert!(chanmon_cfgs[0].persister.archived_channels.lock().unwrap().contains(&funding_outpoint));
+
+	// `chain::Filter` has no way to unregister the archived monitor's outputs, but a restart would
+	// no longer register them, which is what our reload check on drop compares against.
+	nodes[0].chain_source.watched_txn.lock().unwrap().clear();
+	nodes[0].chain_source.watched_outputs.lock().unwrap().clear();
+}
+
 fn sorted_vec<T: Ord>(mut v: Vec<T>) -> Vec<T> {
 	v.sort_unstable();
 	v
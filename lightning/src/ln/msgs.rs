@@ -434,6 +434,16 @@ pub enum NetAddress {
 		/// The port on which the node is listening
 		port: u16,
 	},
+	/// A DNS hostname/port on which the peer is listening.
+	///
+	/// The hostname must be between 1 and 255 bytes of printable ASCII, see
+	/// [`NetAddress::is_valid`].
+	Hostname {
+		/// The hostname on which the node is listening
+		hostname: String,
+		/// The port on which the node is listening
+		port: u16,
+	},
 }
 impl NetAddress {
 	/// Gets the ID of this address type. Addresses in node_announcement messages should be sorted
@@ -444,6 +454,7 @@ impl NetAddress {
 			&NetAddress::IPv6 {..} => { 2 },
 			&NetAddress::OnionV2(_) => { 3 },
 			&NetAddress::OnionV3 {..} => { 4 },
+			&NetAddress::Hostname {..} => { 5 },
 		}
 	}
 
@@ -454,11 +465,54 @@ impl NetAddress {
 			&NetAddress::IPv6 { .. } => { 18 },
 			&NetAddress::OnionV2(_) => { 12 },
 			&NetAddress::OnionV3 { .. } => { 37 },
+			// Hostname length byte + hostname + port
+			&NetAddress::Hostname { ref hostname, .. } => { 1 + hostname.len() as u16 + 2 },
 		}
 	}
 
 	/// The maximum length of any address descriptor, not including the 1-byte type
-	pub(crate) const MAX_LEN: u16 = 37;
+	pub(crate) const MAX_LEN: u16 = 258;
+
+	/// Returns true if this address can be encoded in a node_announcement. Only hostnames can be
+	/// invalid: they must be between 1 and 255 bytes long and consist only of printable ASCII
+	/// characters.
+	pub fn is_valid(&self) -> bool {
+		match self {
+			&NetAddress::Hostname { ref hostname, .. } => is_valid_hostname(hostname.as_bytes()),
+			_ => true,
+		}
+	}
+
+	/// Converts this address into a [`SocketAddr`] which can be connected to directly.
+	///
+	/// Returns `None` for Tor onion addresses and DNS hostnames, which need to be resolved via a
+	/// proxy or a DNS lookup, respectively. For hostnames, see [`NetAddress::to_host_string`].
+	///
+	/// [`SocketAddr`]: std::net::SocketAddr
+	#[cfg(feature = "std")]
+	pub fn to_socket_addr(&self) -> Option<::std::net::SocketAddr> {
+		use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+		match self {
+			&NetAddress::IPv4 { addr, port } => Some(SocketAddr::new(Ipv4Addr::from(addr).into(), port)),
+			&NetAddress::IPv6 { addr, port } => Some(SocketAddr::new(Ipv6Addr::from(addr).into(), port)),
+			_ => None,
+		}
+	}
+
+	/// Returns a `"hostname:port"` string for DNS hostname addresses, suitable for passing to a
+	/// resolver such as [`ToSocketAddrs`]. Returns `None` for any other address type.
+	///
+	/// [`ToSocketAddrs`]: https://doc.rust-lang.org/std/net/trait.ToSocketAddrs.html
+	pub fn to_host_string(&self) -> Option<String> {
+		match self {
+			&NetAddress::Hostname { ref hostname, port } => Some(format!("{}:{}", hostname, port)),
+			_ => None,
+		}
+	}
+}
+
+fn is_valid_hostname(hostname: &[u8]) -> bool {
+	!hostname.is_empty() && hostname.len() <= 255 && hostname.iter().all(|b| b.is_ascii_graphic())
 }
 
 impl Writeable for NetAddress {
@@ -484,7 +538,14 @@ impl Writeable for NetAddress {
 				checksum.write(writer)?;
 				version.write(writer)?;
 				port.write(writer)?;
-			}
+			},
+			&NetAddress::Hostname { ref hostname, ref port } => {
+				debug_assert!(is_valid_hostname(hostname.as_bytes()));
+				5u8.write(writer)?;
+				(hostname.len() as u8).write(writer)?;
+				writer.write_all(hostname.as_bytes())?;
+				port.write(writer)?;
+			},
 		}
 		Ok(())
 	}
@@ -515,6 +576,19 @@ impl Readable for Result<NetAddress, u8> {
 					port: Readable::read(reader)?,
 				}))
 			},
+			5 => {
+				let hostname_len: u8 = Readable::read(reader)?;
+				let mut hostname = vec![0; hostname_len as usize];
+				reader.read_exact(&mut hostname)?;
+				if !is_valid_hostname(&hostname) {
+					return Err(DecodeError::InvalidValue);
+				}
+				Ok(Ok(NetAddress::Hostname {
+					// is_valid_hostname only accepts ASCII, so this cannot fail.
+					hostname: String::from_utf8(hostname).map_err(|_| DecodeError::InvalidValue)?,
+					port: Readable::read(reader)?,
+				}))
+			},
 			_ => return Ok(Err(byte)),
 		}
 	}
@@ -622,7 +696,10 @@ pub struct UnsignedChannelUpdate {
 	pub fee_base_msat: u32,
 	/// The amount to fee multiplier, in micro-satoshi
 	pub fee_proportional_millionths: u32,
-	pub(crate) excess_data: Vec<u8>,
+	/// Excess data which was signed as a part of the message which we do not (yet) understand how
+	/// to decode. This is stored to ensure forward-compatibility as new fields are added to the
+	/// lightning gossip
+	pub excess_data: Vec<u8>,
 }
 /// A channel_update message to be sent or received from a peer
 #[derive(Clone, Debug, PartialEq)]
@@ -633,6 +710,20 @@ pub struct ChannelUpdate {
 	pub contents: UnsignedChannelUpdate,
 }
 
+/// The unsigned part of a gossip message which is signed with our node secret key, as passed to
+/// [`KeysInterface::sign_gossip_message`].
+///
+/// [`KeysInterface::sign_gossip_message`]: crate::chain::keysinterface::KeysInterface::sign_gossip_message
+#[derive(Clone, Debug, PartialEq)]
+pub enum UnsignedGossipMessage<'a> {
+	/// An unsigned channel announcement.
+	ChannelAnnouncement(&'a UnsignedChannelAnnouncement),
+	/// An unsigned channel update.
+	ChannelUpdate(&'a UnsignedChannelUpdate),
+	/// An unsigned node announcement.
+	NodeAnnouncement(&'a UnsignedNodeAnnouncement),
+}
+
 /// A query_channel_range message is used to query a peer for channel
 /// UTXOs in a range of blocks. The recipient of a query makes a best
 /// effort to reply to the query using one or more reply_channel_range
@@ -1437,6 +1528,16 @@ impl Readable for Pong {
 	}
 }
 
+impl<'a> Writeable for UnsignedGossipMessage<'a> {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		match self {
+			UnsignedGossipMessage::ChannelAnnouncement(msg) => msg.write(w),
+			UnsignedGossipMessage::ChannelUpdate(msg) => msg.write(w),
+			UnsignedGossipMessage::NodeAnnouncement(msg) => msg.write(w),
+		}
+	}
+}
+
 impl Writeable for UnsignedChannelAnnouncement {
 	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
 		self.features.write(w)?;
@@ -2054,6 +2155,57 @@ mod tests {
 		do_encoding_node_announcement(false, false, true, false, true, false, false);
 	}
 
+	#[test]
+	fn encoding_hostname_net_address() {
+		let hostname = msgs::NetAddress::Hostname { hostname: String::from("host.com"), port: 9735 };
+		assert!(hostname.is_valid());
+		assert_eq!(hostname.to_host_string(), Some(String::from("host.com:9735")));
+		#[cfg(feature = "std")]
+		assert_eq!(hostname.to_socket_addr(), None);
+
+		let encoded = hostname.encode();
+		assert_eq!(encoded, hex::decode("0508686f73742e636f6d2607").unwrap());
+		let decoded: msgs::NetAddress = Readable::read(&mut Cursor::new(&encoded)).unwrap();
+		assert_eq!(decoded, hostname);
+
+		#[cfg(feature = "std")]
+		{
+			let ipv4 = msgs::NetAddress::IPv4 { addr: [127, 0, 0, 1], port: 9735 };
+			assert_eq!(ipv4.to_socket_addr(), Some("127.0.0.1:9735".parse().unwrap()));
+			assert_eq!(ipv4.to_host_string(), None);
+		}
+
+		// Empty hostnames, or ones containing non-printable characters, are invalid
+		assert!(!msgs::NetAddress::Hostname { hostname: String::new(), port: 9735 }.is_valid());
+		assert!(!msgs::NetAddress::Hostname { hostname: String::from("host com"), port: 9735 }.is_valid());
+		let empty_hostname = hex::decode("05002607").unwrap();
+		let res: Result<msgs::NetAddress, _> = Readable::read(&mut Cursor::new(&empty_hostname));
+		assert_eq!(res, Err(msgs::DecodeError::InvalidValue));
+
+		// ...and cause the node_announcement containing them to be rejected
+		let secp_ctx = Secp256k1::new();
+		let (_, pubkey) = get_keys_from!("0101010101010101010101010101010101010101010101010101010101010101", secp_ctx);
+		let announcement = msgs::UnsignedNodeAnnouncement {
+			features: NodeFeatures::known(),
+			timestamp: 20190119,
+			node_id: pubkey,
+			rgb: [32; 3],
+			alias: [16; 32],
+			addresses: vec![hostname],
+			excess_address_data: Vec::new(),
+			excess_data: Vec::new(),
+		};
+		let mut encoded_announcement = announcement.encode();
+		let decoded_announcement: msgs::UnsignedNodeAnnouncement = Readable::read(&mut Cursor::new(&encoded_announcement)).unwrap();
+		assert_eq!(decoded_announcement, announcement);
+		// Replace the '.' in "host.com" with a space
+		let dot_pos = encoded_announcement.len() - 6;
+		assert_eq!(encoded_announcement[dot_pos], b'.');
+		encoded_announcement[dot_pos] = b' ';
+		let res: Result<msgs::UnsignedNodeAnnouncement, _> = Readable::read(&mut Cursor::new(&encoded_announcement));
+		assert_eq!(res, Err(msgs::DecodeError::InvalidValue));
+	}
+
 	fn do_encoding_channel_update(direction: bool, disable: bool, htlc_maximum_msat: bool, excess_data: bool) {
 		let secp_ctx = Secp256k1::new();
 		let (privkey_1, _) = get_keys_from!("0101010101010101010101010101010101010101010101010101010101010101", secp_ctx);
@@ -35,6 +35,7 @@ use bitcoin::secp256k1::key::SecretKey;
 use io;
 use prelude::*;
 use core::default::Default;
+use core::sync::atomic::Ordering;
 
 use ln::functional_test_utils::*;
 
@@ -295,6 +296,25 @@ fn test_fee_failures() {
 	claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_preimage_success);
 }
 
+#[test]
+fn test_failed_ecdh_fails_htlc_back() {
+	// If our signer is unable to compute the onion shared secret for an incoming HTLC we cannot
+	// decode it, but should still fail it back rather than panicking. The failure cannot be
+	// encrypted for the sender, so it arrives as an unreadable error which cannot be attributed.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1, InitFeatures::known(), InitFeatures::known());
+	create_announced_chan_between_nodes(&nodes, 1, 2, InitFeatures::known(), InitFeatures::known());
+
+	let (route, payment_hash, _, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[2], 40_000);
+	run_onion_failure_test("ecdh_unavailable", 0, &nodes, &route, &payment_hash, &payment_secret, |_| {}, || {
+		nodes[1].keys_manager.disable_ecdh.store(true, Ordering::Release);
+	}, false, None, None, None);
+	nodes[1].keys_manager.disable_ecdh.store(false, Ordering::Release);
+}
+
 #[test]
 fn test_onion_failure() {
 	// When we check for amount_below_minimum below, we want to test that we're using the *right*
@@ -18,7 +18,7 @@ use ln::channelmanager::{BREAKDOWN_TIMEOUT, ChannelManager, ChannelManagerReadAr
 use ln::features::{InitFeatures, InvoiceFeatures};
 use ln::msgs;
 use ln::msgs::ChannelMessageHandler;
-use routing::router::{PaymentParameters, get_route};
+use routing::router::{PaymentParameters, find_rebalance_route, get_route};
 use util::events::{ClosureReason, Event, MessageSendEvent, MessageSendEventsProvider};
 use util::test_utils;
 use util::errors::APIError;
@@ -738,3 +738,204 @@ fn get_ldk_payment_preimage() {
 	pass_along_path(&nodes[0], &[&nodes[1]], amt_msat, payment_hash, Some(payment_secret), events.pop().unwrap(), true, Some(payment_preimage));
 	claim_payment_along_route(&nodes[0], &[&[&nodes[1]]], false, payment_preimage);
 }
+
+#[test]
+fn rebalance_through_circular_route() {
+	// Move liquidity from our channel with nodes[1] to our channel with nodes[2] by paying
+	// ourselves along nodes[0] -> nodes[1] -> nodes[2] -> nodes[0], checking that the payment is
+	// claimed automatically and reported via `Event::RebalanceCompleted`.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+
+	let chan_1 = create_announced_chan_between_nodes(&nodes, 0, 1, InitFeatures::known(), InitFeatures::known());
+	create_announced_chan_between_nodes(&nodes, 1, 2, InitFeatures::known(), InitFeatures::known());
+	let chan_3 = create_announced_chan_between_nodes(&nodes, 2, 0, InitFeatures::known(), InitFeatures::known());
+
+	// Learn the fees nodes[2] charges to forward over the channel back to us.
+	nodes[0].node.handle_channel_update(&nodes[2].node.get_our_node_id(), &chan_3.0);
+
+	let usable_channels = nodes[0].node.list_usable_channels();
+	let outbound_channel = usable_channels.iter().find(|chan| chan.channel_id == chan_1.2).unwrap();
+	let inbound_channel = usable_channels.iter().find(|chan| chan.channel_id == chan_3.2).unwrap();
+	assert!(inbound_channel.counterparty.forwarding_info.is_some());
+
+	let amt_msat = 1_000_000;
+	let scorer = test_utils::TestScorer::with_penalty(0);
+	let route = find_rebalance_route(
+		&nodes[0].node.get_our_node_id(), outbound_channel, inbound_channel, amt_msat,
+		&nodes[0].network_graph, nodes[0].logger, &scorer).unwrap();
+	assert_eq!(route.paths[0].len(), 3);
+	assert_eq!(route.get_total_fees(), 2000);
+
+	let (payment_hash, payment_id) = nodes[0].node.send_rebalance(&route).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+
+	// No PaymentReceived is generated when the payment reaches us, it is instead claimed as soon
+	// as it has been received.
+	pass_along_path(&nodes[0], &[&nodes[1], &nodes[2], &nodes[0]], amt_msat, payment_hash, None, events.pop().unwrap(), false, None);
+	check_added_monitors!(nodes[0], 1);
+	let updates = get_htlc_update_msgs!(nodes[0], nodes[2].node.get_our_node_id());
+	assert_eq!(updates.update_fulfill_htlcs.len(), 1);
+
+	nodes[2].node.handle_update_fulfill_htlc(&nodes[0].node.get_our_node_id(), &updates.update_fulfill_htlcs[0]);
+	expect_payment_forwarded!(nodes[2], Some(1000), false);
+	check_added_monitors!(nodes[2], 1);
+	let updates_2 = get_htlc_update_msgs!(nodes[2], nodes[1].node.get_our_node_id());
+	commitment_signed_dance!(nodes[2], nodes[0], updates.commitment_signed, false);
+
+	nodes[1].node.handle_update_fulfill_htlc(&nodes[2].node.get_our_node_id(), &updates_2.update_fulfill_htlcs[0]);
+	expect_payment_forwarded!(nodes[1], Some(1000), false);
+	check_added_monitors!(nodes[1], 1);
+	let updates_1 = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	commitment_signed_dance!(nodes[1], nodes[2], updates_2.commitment_signed, false);
+
+	nodes[0].node.handle_update_fulfill_htlc(&nodes[1].node.get_our_node_id(), &updates_1.update_fulfill_htlcs[0]);
+	check_added_monitors!(nodes[0], 0);
+	commitment_signed_dance!(nodes[0], nodes[1], updates_1.commitment_signed, false);
+
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 2);
+	match events[0] {
+		Event::RebalanceCompleted { payment_id: id, payment_hash: hash, fee_paid_msat } => {
+			assert_eq!(id, payment_id);
+			assert_eq!(hash, payment_hash);
+			assert_eq!(fee_paid_msat, Some(2000));
+		},
+		_ => panic!("Unexpected event"),
+	}
+	match events[1] {
+		Event::PaymentPathSuccessful { payment_id: id, .. } => assert_eq!(id, payment_id),
+		_ => panic!("Unexpected event"),
+	}
+
+	// The liquidity has moved from our channel with nodes[1] to our channel with nodes[2].
+	let usable_channels = nodes[0].node.list_usable_channels();
+	let new_outbound_channel = usable_channels.iter().find(|chan| chan.channel_id == chan_1.2).unwrap();
+	let new_inbound_channel = usable_channels.iter().find(|chan| chan.channel_id == chan_3.2).unwrap();
+	assert_eq!(new_outbound_channel.balance_msat, outbound_channel.balance_msat - amt_msat - 2000);
+	assert_eq!(new_inbound_channel.balance_msat, inbound_channel.balance_msat + amt_msat);
+}
+
+#[test]
+fn rebalance_survives_reload() {
+	// Check that a rebalance which reaches us only after a restart is still claimed automatically
+	// and reported via `Event::RebalanceCompleted`.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let persister: test_utils::TestPersister;
+	let new_chain_monitor: test_utils::TestChainMonitor;
+	let nodes_0_deserialized: ChannelManager<EnforcingSigner, &test_utils::TestChainMonitor, &test_utils::TestBroadcaster, &test_utils::TestKeysInterface, &test_utils::TestFeeEstimator, &test_utils::TestLogger>;
+	let mut nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+
+	let chan_1 = create_announced_chan_between_nodes(&nodes, 0, 1, InitFeatures::known(), InitFeatures::known());
+	create_announced_chan_between_nodes(&nodes, 1, 2, InitFeatures::known(), InitFeatures::known());
+	let chan_3 = create_announced_chan_between_nodes(&nodes, 2, 0, InitFeatures::known(), InitFeatures::known());
+	nodes[0].node.handle_channel_update(&nodes[2].node.get_our_node_id(), &chan_3.0);
+
+	let usable_channels = nodes[0].node.list_usable_channels();
+	let outbound_channel = usable_channels.iter().find(|chan| chan.channel_id == chan_1.2).unwrap();
+	let inbound_channel = usable_channels.iter().find(|chan| chan.channel_id == chan_3.2).unwrap();
+	let amt_msat = 1_000_000;
+	let scorer = test_utils::TestScorer::with_penalty(0);
+	let route = find_rebalance_route(
+		&nodes[0].node.get_our_node_id(), outbound_channel, inbound_channel, amt_msat,
+		&nodes[0].network_graph, nodes[0].logger, &scorer).unwrap();
+
+	let (payment_hash, payment_id) = nodes[0].node.send_rebalance(&route).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+
+	// Relay the payment all the way back to us, but restart before we process it.
+	pass_along_path(&nodes[0], &[&nodes[1], &nodes[2]], amt_msat, payment_hash, None, events.pop().unwrap(), false, None);
+	check_added_monitors!(nodes[2], 1);
+	let payment_event = SendEvent::from_node(&nodes[2]);
+	nodes[0].node.handle_update_add_htlc(&nodes[2].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[0], nodes[2], payment_event.commitment_msg, false);
+	expect_pending_htlcs_forwardable_ignore!(nodes[0]);
+
+	let nodes_0_serialized = nodes[0].node.encode();
+	let mut chan_1_monitor_serialized = test_utils::TestVecWriter(Vec::new());
+	get_monitor!(nodes[0], chan_1.2).write(&mut chan_1_monitor_serialized).unwrap();
+	let mut chan_3_monitor_serialized = test_utils::TestVecWriter(Vec::new());
+	get_monitor!(nodes[0], chan_3.2).write(&mut chan_3_monitor_serialized).unwrap();
+
+	persister = test_utils::TestPersister::new();
+	let keys_manager = &chanmon_cfgs[0].keys_manager;
+	new_chain_monitor = test_utils::TestChainMonitor::new(Some(nodes[0].chain_source), nodes[0].tx_broadcaster.clone(), nodes[0].logger, node_cfgs[0].fee_estimator, &persister, keys_manager);
+	nodes[0].chain_monitor = &new_chain_monitor;
+	let (_, mut chan_1_monitor) = <(BlockHash, ChannelMonitor<EnforcingSigner>)>::read(
+		&mut &chan_1_monitor_serialized.0[..], keys_manager).unwrap();
+	let (_, mut chan_3_monitor) = <(BlockHash, ChannelMonitor<EnforcingSigner>)>::read(
+		&mut &chan_3_monitor_serialized.0[..], keys_manager).unwrap();
+
+	let mut nodes_0_read = &nodes_0_serialized[..];
+	let (_, nodes_0_deserialized_tmp) = {
+		let mut channel_monitors = HashMap::new();
+		channel_monitors.insert(chan_1_monitor.get_funding_txo().0, &mut chan_1_monitor);
+		channel_monitors.insert(chan_3_monitor.get_funding_txo().0, &mut chan_3_monitor);
+		<(BlockHash, ChannelManager<EnforcingSigner, &test_utils::TestChainMonitor, &test_utils::TestBroadcaster, &test_utils::TestKeysInterface, &test_utils::TestFeeEstimator, &test_utils::TestLogger>)>::read(&mut nodes_0_read, ChannelManagerReadArgs {
+			default_config: test_default_channel_config(),
+			keys_manager,
+			fee_estimator: node_cfgs[0].fee_estimator,
+			chain_monitor: nodes[0].chain_monitor,
+			tx_broadcaster: nodes[0].tx_broadcaster.clone(),
+			logger: nodes[0].logger,
+			channel_monitors,
+		}).unwrap()
+	};
+	nodes_0_deserialized = nodes_0_deserialized_tmp;
+	assert!(nodes_0_read.is_empty());
+
+	assert!(nodes[0].chain_monitor.watch_channel(chan_1_monitor.get_funding_txo().0, chan_1_monitor).is_ok());
+	assert!(nodes[0].chain_monitor.watch_channel(chan_3_monitor.get_funding_txo().0, chan_3_monitor).is_ok());
+	nodes[0].node = &nodes_0_deserialized;
+	check_added_monitors!(nodes[0], 2);
+
+	nodes[1].node.peer_disconnected(&nodes[0].node.get_our_node_id(), false);
+	nodes[2].node.peer_disconnected(&nodes[0].node.get_our_node_id(), false);
+	reconnect_nodes(&nodes[0], &nodes[1], (false, false), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (false, false));
+	reconnect_nodes(&nodes[0], &nodes[2], (false, false), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (false, false));
+
+	// The payment is still claimed without generating an `Event::PaymentReceived`.
+	expect_pending_htlcs_forwardable!(nodes[0]);
+	assert!(nodes[0].node.get_and_clear_pending_events().is_empty());
+	check_added_monitors!(nodes[0], 1);
+	let updates = get_htlc_update_msgs!(nodes[0], nodes[2].node.get_our_node_id());
+	assert_eq!(updates.update_fulfill_htlcs.len(), 1);
+
+	nodes[2].node.handle_update_fulfill_htlc(&nodes[0].node.get_our_node_id(), &updates.update_fulfill_htlcs[0]);
+	expect_payment_forwarded!(nodes[2], Some(1000), false);
+	check_added_monitors!(nodes[2], 1);
+	let updates_2 = get_htlc_update_msgs!(nodes[2], nodes[1].node.get_our_node_id());
+	commitment_signed_dance!(nodes[2], nodes[0], updates.commitment_signed, false);
+
+	nodes[1].node.handle_update_fulfill_htlc(&nodes[2].node.get_our_node_id(), &updates_2.update_fulfill_htlcs[0]);
+	expect_payment_forwarded!(nodes[1], Some(1000), false);
+	check_added_monitors!(nodes[1], 1);
+	let updates_1 = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	commitment_signed_dance!(nodes[1], nodes[2], updates_2.commitment_signed, false);
+
+	nodes[0].node.handle_update_fulfill_htlc(&nodes[1].node.get_our_node_id(), &updates_1.update_fulfill_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], updates_1.commitment_signed, false);
+
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 2);
+	match events[0] {
+		Event::RebalanceCompleted { payment_id: id, payment_hash: hash, fee_paid_msat } => {
+			assert_eq!(id, payment_id);
+			assert_eq!(hash, payment_hash);
+			assert_eq!(fee_paid_msat, Some(2000));
+		},
+		_ => panic!("Unexpected event"),
+	}
+	match events[1] {
+		Event::PaymentPathSuccessful { payment_id: id, .. } => assert_eq!(id, payment_id),
+		_ => panic!("Unexpected event"),
+	}
+}
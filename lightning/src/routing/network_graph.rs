@@ -37,7 +37,7 @@ use io;
 use prelude::*;
 use alloc::collections::{BTreeMap, btree_map::Entry as BtreeEntry};
 use core::{cmp, fmt};
-use sync::{RwLock, RwLockReadGuard};
+use sync::{Arc, RwLock, RwLockReadGuard};
 use core::sync::atomic::{AtomicUsize, Ordering};
 use sync::Mutex;
 use core::ops::Deref;
@@ -127,16 +127,21 @@ pub struct NetworkGraph {
 	// Lock order: channels -> nodes
 	channels: RwLock<BTreeMap<u64, ChannelInfo>>,
 	nodes: RwLock<BTreeMap<NodeId, NodeInfo>>,
+	/// The unix timestamp provided by the most recent rapid gossip sync.
+	/// It will be set by the rapid sync process after every sync completion.
+	last_rapid_gossip_sync_timestamp: Mutex<Option<u32>>,
 }
 
 impl Clone for NetworkGraph {
 	fn clone(&self) -> Self {
 		let channels = self.channels.read().unwrap();
 		let nodes = self.nodes.read().unwrap();
+		let last_rapid_gossip_sync_timestamp = self.get_last_rapid_gossip_sync_timestamp();
 		Self {
 			genesis_hash: self.genesis_hash.clone(),
 			channels: RwLock::new(channels.clone()),
 			nodes: RwLock::new(nodes.clone()),
+			last_rapid_gossip_sync_timestamp: Mutex::new(last_rapid_gossip_sync_timestamp),
 		}
 	}
 }
@@ -147,6 +152,71 @@ pub struct ReadOnlyNetworkGraph<'a> {
 	nodes: RwLockReadGuard<'a, BTreeMap<NodeId, NodeInfo>>,
 }
 
+/// An immutable snapshot of the parts of a [`NetworkGraph`] needed for routing, taken with
+/// [`NetworkGraph::snapshot`].
+///
+/// Unlike a [`ReadOnlyNetworkGraph`], a snapshot doesn't hold the graph's locks, so routing with
+/// [`find_route_from_snapshot`] doesn't block gossip from being applied to the graph in the
+/// meantime. Snapshots are cheap to clone and may be shared across threads, and should be retaken
+/// periodically to pick up changes to the graph.
+///
+/// Rather than the maps the [`NetworkGraph`] uses, a snapshot stores its nodes and channels in
+/// flat arrays sorted by id, with each node's channels listed as indices into the channel array.
+/// Thus the router walks from node to channel to node by index, and only looks ids up when
+/// starting from the payee or a route hint. Only the data needed for routing is kept, leaving out
+/// stored gossip messages as well as node aliases and addresses. Taking a snapshot costs time and
+/// memory linear in the size of the graph.
+///
+/// Snapshots may be serialized, e.g. to hand them to a separate routing process, and read back
+/// with [`Readable`].
+///
+/// [`find_route_from_snapshot`]: crate::routing::router::find_route_from_snapshot
+#[derive(Clone)]
+pub struct NetworkGraphSnapshot {
+	genesis_hash: BlockHash,
+	graph: Arc<CompactGraph>,
+}
+
+#[derive(PartialEq)]
+struct CompactGraph {
+	/// The short channel id of each channel in `channels`, in ascending order.
+	short_channel_ids: Vec<u64>,
+	channels: Vec<ChannelInfo>,
+	/// The id of each node in `nodes`, in ascending order.
+	node_ids: Vec<NodeId>,
+	nodes: Vec<CompactNode>,
+	/// The channels of every node, as indices into `channels`, grouped by node.
+	node_channels: Vec<u32>,
+}
+
+#[derive(PartialEq)]
+struct CompactNode {
+	/// The features from the node's announcement, if we've received one.
+	features: Option<NodeFeatures>,
+	lowest_inbound_channel_fees: Option<RoutingFees>,
+	/// The range of `CompactGraph::node_channels` listing the node's channels.
+	channels_start: u32,
+	channels_end: u32,
+}
+
+/// An iterator over a node's channels in a [`NetworkGraphSnapshot`], along with their short
+/// channel ids.
+pub(crate) struct SnapshotNodeChannels<'a> {
+	graph: &'a CompactGraph,
+	channel_indices: core::slice::Iter<'a, u32>,
+}
+
+impl<'a> Iterator for SnapshotNodeChannels<'a> {
+	type Item = (u64, &'a ChannelInfo);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		self.channel_indices.next().map(|idx| {
+			let idx = *idx as usize;
+			(self.graph.short_channel_ids[idx], &self.graph.channels[idx])
+		})
+	}
+}
+
 /// Update to the [`NetworkGraph`] based on payment failure information conveyed via the Onion
 /// return packet by a node along the route. See [BOLT #4] for details.
 ///
@@ -674,6 +744,16 @@ impl ChannelInfo {
 		};
 		Some((DirectedChannelInfo { channel: self, direction }, source))
 	}
+
+	/// Returns a [`ChannelUpdateInfo`] based on the direction implied by the channel_flag.
+	pub fn get_directional_info(&self, channel_flags: u8) -> Option<&ChannelUpdateInfo> {
+		let direction = channel_flags & 1u8;
+		if direction == 0 {
+			self.one_to_two.as_ref()
+		} else {
+			self.two_to_one.as_ref()
+		}
+	}
 }
 
 impl fmt::Display for ChannelInfo {
@@ -919,7 +999,10 @@ impl Writeable for NetworkGraph {
 			node_info.write(writer)?;
 		}
 
-		write_tlv_fields!(writer, {});
+		let last_rapid_gossip_sync_timestamp = self.get_last_rapid_gossip_sync_timestamp();
+		write_tlv_fields!(writer, {
+			(1, last_rapid_gossip_sync_timestamp, option),
+		});
 		Ok(())
 	}
 }
@@ -943,12 +1026,17 @@ impl Readable for NetworkGraph {
 			let node_info = Readable::read(reader)?;
 			nodes.insert(node_id, node_info);
 		}
-		read_tlv_fields!(reader, {});
+
+		let mut last_rapid_gossip_sync_timestamp: Option<u32> = None;
+		read_tlv_fields!(reader, {
+			(1, last_rapid_gossip_sync_timestamp, option),
+		});
 
 		Ok(NetworkGraph {
 			genesis_hash,
 			channels: RwLock::new(channels),
 			nodes: RwLock::new(nodes),
+			last_rapid_gossip_sync_timestamp: Mutex::new(last_rapid_gossip_sync_timestamp),
 		})
 	}
 }
@@ -971,7 +1059,8 @@ impl PartialEq for NetworkGraph {
 	fn eq(&self, other: &Self) -> bool {
 		self.genesis_hash == other.genesis_hash &&
 			*self.channels.read().unwrap() == *other.channels.read().unwrap() &&
-			*self.nodes.read().unwrap() == *other.nodes.read().unwrap()
+			*self.nodes.read().unwrap() == *other.nodes.read().unwrap() &&
+			self.get_last_rapid_gossip_sync_timestamp() == other.get_last_rapid_gossip_sync_timestamp()
 	}
 }
 
@@ -982,6 +1071,7 @@ impl NetworkGraph {
 			genesis_hash,
 			channels: RwLock::new(BTreeMap::new()),
 			nodes: RwLock::new(BTreeMap::new()),
+			last_rapid_gossip_sync_timestamp: Mutex::new(None),
 		}
 	}
 
@@ -995,6 +1085,76 @@ impl NetworkGraph {
 		}
 	}
 
+	/// Returns an immutable, compact snapshot of the network graph for routing. The graph's locks
+	/// are only held while copying it.
+	pub fn snapshot(&self) -> NetworkGraphSnapshot {
+		let channels = self.channels.read().unwrap();
+		let nodes = self.nodes.read().unwrap();
+		let snapshot_update_info = |info: &ChannelUpdateInfo| ChannelUpdateInfo {
+			last_update: info.last_update,
+			enabled: info.enabled,
+			cltv_expiry_delta: info.cltv_expiry_delta,
+			htlc_minimum_msat: info.htlc_minimum_msat,
+			htlc_maximum_msat: info.htlc_maximum_msat,
+			fees: info.fees,
+			last_update_message: None,
+		};
+		// Both maps iterate in ascending order of their keys, so the arrays come out sorted.
+		let short_channel_ids: Vec<u64> = channels.keys().cloned().collect();
+		let snapshot_channels = channels.values().map(|channel| ChannelInfo {
+			features: channel.features.clone(),
+			node_one: channel.node_one,
+			one_to_two: channel.one_to_two.as_ref().map(snapshot_update_info),
+			node_two: channel.node_two,
+			two_to_one: channel.two_to_one.as_ref().map(snapshot_update_info),
+			capacity_sats: channel.capacity_sats,
+			announcement_message: None,
+			announcement_received_time: channel.announcement_received_time,
+		}).collect();
+		let mut node_channels = Vec::new();
+		let snapshot_nodes = nodes.values().map(|node| {
+			let channels_start = node_channels.len() as u32;
+			for short_channel_id in node.channels.iter() {
+				if let Ok(idx) = short_channel_ids.binary_search(short_channel_id) {
+					node_channels.push(idx as u32);
+				}
+			}
+			CompactNode {
+				features: node.announcement_info.as_ref().map(|info| info.features.clone()),
+				lowest_inbound_channel_fees: node.lowest_inbound_channel_fees,
+				channels_start,
+				channels_end: node_channels.len() as u32,
+			}
+		}).collect();
+		NetworkGraphSnapshot {
+			genesis_hash: self.genesis_hash,
+			graph: Arc::new(CompactGraph {
+				short_channel_ids,
+				channels: snapshot_channels,
+				node_ids: nodes.keys().cloned().collect(),
+				nodes: snapshot_nodes,
+				node_channels,
+			}),
+		}
+	}
+
+	/// The unix timestamp provided by the most recent rapid gossip sync.
+	/// It will be set by the rapid sync process after every sync completion.
+	pub fn get_last_rapid_gossip_sync_timestamp(&self) -> Option<u32> {
+		self.last_rapid_gossip_sync_timestamp.lock().unwrap().clone()
+	}
+
+	/// Update the unix timestamp provided by the most recent rapid gossip sync.
+	/// This should be done automatically by the rapid sync process after every sync completion.
+	pub fn set_last_rapid_gossip_sync_timestamp(&self, last_rapid_gossip_sync_timestamp: u32) {
+		self.last_rapid_gossip_sync_timestamp.lock().unwrap().replace(last_rapid_gossip_sync_timestamp);
+	}
+
+	/// Returns the genesis hash of the chain this graph tracks.
+	pub fn get_genesis_hash(&self) -> BlockHash {
+		self.genesis_hash
+	}
+
 	/// For an already known node (from channel announcements), update its stored properties from a
 	/// given node announcement.
 	///
@@ -1144,9 +1304,42 @@ impl NetworkGraph {
 				announcement_received_time,
 			};
 
+		self.add_channel_between_nodes(msg.short_channel_id, chan_info, utxo_value)
+	}
+
+	/// Store or update channel info from a partial channel announcement, as provided by a rapid
+	/// gossip sync server rather than the lightning P2P protocol.
+	///
+	/// Because the announcement comes without any signatures, its contents cannot be verified and
+	/// it will never be relayed to our peers. `timestamp` is used in place of the time at which we
+	/// received the announcement when determining whether the channel is stale.
+	pub fn add_channel_from_partial_announcement(&self, short_channel_id: u64, timestamp: u64, features: ChannelFeatures, node_id_1: PublicKey, node_id_2: PublicKey) -> Result<(), LightningError> {
+		if node_id_1 == node_id_2 {
+			return Err(LightningError{err: "Channel announcement node had a channel with itself".to_owned(), action: ErrorAction::IgnoreError});
+		};
+
+		let chan_info = ChannelInfo {
+			features,
+			node_one: NodeId::from_pubkey(&node_id_1),
+			one_to_two: None,
+			node_two: NodeId::from_pubkey(&node_id_2),
+			two_to_one: None,
+			capacity_sats: None,
+			announcement_message: None,
+			announcement_received_time: timestamp,
+		};
+
+		self.add_channel_between_nodes(short_channel_id, chan_info, None)
+	}
+
+	fn add_channel_between_nodes(&self, short_channel_id: u64, chan_info: ChannelInfo, utxo_value: Option<u64>) -> Result<(), LightningError> {
 		let mut channels = self.channels.write().unwrap();
 		let mut nodes = self.nodes.write().unwrap();
-		match channels.entry(msg.short_channel_id) {
+
+		let node_id_a = chan_info.node_one.clone();
+		let node_id_b = chan_info.node_two.clone();
+
+		match channels.entry(short_channel_id) {
 			BtreeEntry::Occupied(mut entry) => {
 				//TODO: because asking the blockchain if short_channel_id is valid is only optional
 				//in the blockchain API, we need to handle it smartly here, though it's unclear
@@ -1160,7 +1353,7 @@ impl NetworkGraph {
 					// b) we don't track UTXOs of channels we know about and remove them if they
 					//    get reorg'd out.
 					// c) it's unclear how to do so without exposing ourselves to massive DoS risk.
-					Self::remove_channel_in_nodes(&mut nodes, &entry.get(), msg.short_channel_id);
+					Self::remove_channel_in_nodes(&mut nodes, &entry.get(), short_channel_id);
 					*entry.get_mut() = chan_info;
 				} else {
 					return Err(LightningError{err: "Already have knowledge of channel".to_owned(), action: ErrorAction::IgnoreDuplicateGossip});
@@ -1171,25 +1364,20 @@ impl NetworkGraph {
 			}
 		};
 
-		macro_rules! add_channel_to_node {
-			( $node_id: expr ) => {
-				match nodes.entry($node_id) {
-					BtreeEntry::Occupied(node_entry) => {
-						node_entry.into_mut().channels.push(msg.short_channel_id);
-					},
-					BtreeEntry::Vacant(node_entry) => {
-						node_entry.insert(NodeInfo {
-							channels: vec!(msg.short_channel_id),
-							lowest_inbound_channel_fees: None,
-							announcement_info: None,
-						});
-					}
+		for current_node_id in [node_id_a, node_id_b].iter() {
+			match nodes.entry(current_node_id.clone()) {
+				BtreeEntry::Occupied(node_entry) => {
+					node_entry.into_mut().channels.push(short_channel_id);
+				},
+				BtreeEntry::Vacant(node_entry) => {
+					node_entry.insert(NodeInfo {
+						channels: vec!(short_channel_id),
+						lowest_inbound_channel_fees: None,
+						announcement_info: None,
+					});
 				}
 			};
-		}
-
-		add_channel_to_node!(NodeId::from_pubkey(&msg.node_id_1));
-		add_channel_to_node!(NodeId::from_pubkey(&msg.node_id_2));
+		};
 
 		Ok(())
 	}
@@ -1488,6 +1676,10 @@ impl ReadOnlyNetworkGraph<'_> {
 	/// Get network addresses by node id.
 	/// Returns None if the requested node is completely unknown,
 	/// or if node announcement for the node was never received.
+	///
+	/// Addresses are returned as announced, which may include Tor onion addresses and DNS
+	/// hostnames. See [`NetAddress::to_socket_addr`] and [`NetAddress::to_host_string`] for
+	/// converting them into something which can be connected to.
 	pub fn get_addresses(&self, pubkey: &PublicKey) -> Option<Vec<NetAddress>> {
 		if let Some(node) = self.nodes.get(&NodeId::from_pubkey(&pubkey)) {
 			if let Some(node_info) = node.announcement_info.as_ref() {
@@ -1498,13 +1690,154 @@ impl ReadOnlyNetworkGraph<'_> {
 	}
 }
 
+impl Writeable for NetworkGraphSnapshot {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+		write_ver_prefix!(writer, SERIALIZATION_VERSION, MIN_SERIALIZATION_VERSION);
+
+		self.genesis_hash.write(writer)?;
+		let graph = &*self.graph;
+		(graph.channels.len() as u64).write(writer)?;
+		for (short_channel_id, chan_info) in graph.short_channel_ids.iter().zip(graph.channels.iter()) {
+			short_channel_id.write(writer)?;
+			chan_info.write(writer)?;
+		}
+		(graph.nodes.len() as u64).write(writer)?;
+		for (node_id, node) in graph.node_ids.iter().zip(graph.nodes.iter()) {
+			node_id.write(writer)?;
+			node.features.write(writer)?;
+			node.lowest_inbound_channel_fees.write(writer)?;
+			let channel_indices = &graph.node_channels[node.channels_start as usize..node.channels_end as usize];
+			(channel_indices.len() as u32).write(writer)?;
+			for idx in channel_indices.iter() {
+				idx.write(writer)?;
+			}
+		}
+
+		write_tlv_fields!(writer, {});
+		Ok(())
+	}
+}
+
+impl Readable for NetworkGraphSnapshot {
+	fn read<R: io::Read>(reader: &mut R) -> Result<NetworkGraphSnapshot, DecodeError> {
+		let _ver = read_ver_prefix!(reader, SERIALIZATION_VERSION);
+
+		let genesis_hash: BlockHash = Readable::read(reader)?;
+		let channels_count: u64 = Readable::read(reader)?;
+		if channels_count > u32::max_value() as u64 { return Err(DecodeError::InvalidValue); }
+		let mut short_channel_ids = Vec::with_capacity(cmp::min(channels_count as usize, 128));
+		let mut channels: Vec<ChannelInfo> = Vec::with_capacity(cmp::min(channels_count as usize, 128));
+		for _ in 0..channels_count {
+			let short_channel_id: u64 = Readable::read(reader)?;
+			if short_channel_ids.last() >= Some(&short_channel_id) {
+				return Err(DecodeError::InvalidValue);
+			}
+			short_channel_ids.push(short_channel_id);
+			channels.push(Readable::read(reader)?);
+		}
+		let nodes_count: u64 = Readable::read(reader)?;
+		let mut node_ids: Vec<NodeId> = Vec::with_capacity(cmp::min(nodes_count as usize, 128));
+		let mut nodes = Vec::with_capacity(cmp::min(nodes_count as usize, 128));
+		let mut node_channels = Vec::new();
+		for _ in 0..nodes_count {
+			let node_id: NodeId = Readable::read(reader)?;
+			if node_ids.last() >= Some(&node_id) {
+				return Err(DecodeError::InvalidValue);
+			}
+			let features = Readable::read(reader)?;
+			let lowest_inbound_channel_fees = Readable::read(reader)?;
+			let channels_start = node_channels.len() as u32;
+			let node_channels_count: u32 = Readable::read(reader)?;
+			for _ in 0..node_channels_count {
+				let idx: u32 = Readable::read(reader)?;
+				// Each index must refer to one of the node's own channels.
+				match channels.get(idx as usize) {
+					Some(channel) if channel.node_one == node_id || channel.node_two == node_id => {},
+					_ => return Err(DecodeError::InvalidValue),
+				}
+				node_channels.push(idx);
+			}
+			node_ids.push(node_id);
+			nodes.push(CompactNode {
+				features,
+				lowest_inbound_channel_fees,
+				channels_start,
+				channels_end: node_channels.len() as u32,
+			});
+		}
+
+		read_tlv_fields!(reader, {});
+
+		Ok(NetworkGraphSnapshot {
+			genesis_hash,
+			graph: Arc::new(CompactGraph { short_channel_ids, channels, node_ids, nodes, node_channels }),
+		})
+	}
+}
+
+impl NetworkGraphSnapshot {
+	/// Returns the genesis hash of the chain the snapshotted graph tracks.
+	pub fn get_genesis_hash(&self) -> BlockHash {
+		self.genesis_hash
+	}
+
+	/// Returns the short channel ids of all channels in the snapshot, in ascending order.
+	pub fn short_channel_ids(&self) -> &[u64] {
+		&self.graph.short_channel_ids
+	}
+
+	/// Returns the channel with the given short channel id, if it is in the snapshot. Its stored
+	/// gossip messages are always `None`.
+	pub fn channel(&self, short_channel_id: u64) -> Option<&ChannelInfo> {
+		self.graph.short_channel_ids.binary_search(&short_channel_id).ok()
+			.map(|idx| &self.graph.channels[idx])
+	}
+
+	/// Returns the ids of all nodes in the snapshot, in ascending order.
+	pub fn node_ids(&self) -> &[NodeId] {
+		&self.graph.node_ids
+	}
+
+	/// Returns the features from the given node's announcement, if the node is in the snapshot and
+	/// we had received an announcement for it.
+	pub fn node_features(&self, node_id: &NodeId) -> Option<&NodeFeatures> {
+		self.node_index(node_id).and_then(|idx| self.node_features_at(idx))
+	}
+
+	/// Returns the short channel ids of the given node's channels, if the node is in the snapshot.
+	pub fn node_channels(&self, node_id: &NodeId) -> Option<Vec<u64>> {
+		self.node_index(node_id)
+			.map(|idx| self.node_channels_at(idx).map(|(short_channel_id, _)| short_channel_id).collect())
+	}
+
+	pub(crate) fn node_index(&self, node_id: &NodeId) -> Option<u32> {
+		self.graph.node_ids.binary_search(node_id).ok().map(|idx| idx as u32)
+	}
+
+	pub(crate) fn node_features_at(&self, node_idx: u32) -> Option<&NodeFeatures> {
+		self.graph.nodes[node_idx as usize].features.as_ref()
+	}
+
+	pub(crate) fn lowest_inbound_channel_fees_at(&self, node_idx: u32) -> Option<RoutingFees> {
+		self.graph.nodes[node_idx as usize].lowest_inbound_channel_fees
+	}
+
+	pub(crate) fn node_channels_at(&self, node_idx: u32) -> SnapshotNodeChannels<'_> {
+		let node = &self.graph.nodes[node_idx as usize];
+		SnapshotNodeChannels {
+			graph: &self.graph,
+			channel_indices: self.graph.node_channels[node.channels_start as usize..node.channels_end as usize].iter(),
+		}
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use chain;
 	use ln::PaymentHash;
 	use ln::features::{ChannelFeatures, InitFeatures, NodeFeatures};
-	use routing::network_graph::{NetGraphMsgHandler, NetworkGraph, NetworkUpdate, MAX_EXCESS_BYTES_FOR_RELAY};
-	use ln::msgs::{Init, OptionalField, RoutingMessageHandler, UnsignedNodeAnnouncement, NodeAnnouncement,
+	use routing::network_graph::{NetGraphMsgHandler, NetworkGraph, NetworkGraphSnapshot, NetworkUpdate, NodeId, MAX_EXCESS_BYTES_FOR_RELAY};
+	use ln::msgs::{DecodeError, ErrorAction, Init, LightningError, OptionalField, RoutingMessageHandler, UnsignedNodeAnnouncement, NodeAnnouncement,
 		UnsignedChannelAnnouncement, ChannelAnnouncement, UnsignedChannelUpdate, ChannelUpdate, 
 		ReplyChannelRange, ReplyShortChannelIdsEnd, QueryChannelRange, QueryShortChannelIds, MAX_VALUE_MSAT};
 	use util::test_utils;
@@ -2242,6 +2575,103 @@ mod tests {
 		assert!(<NetworkGraph>::read(&mut io::Cursor::new(&w.0)).unwrap() == network_graph);
 	}
 
+	#[test]
+	fn network_graph_tlv_serialization() {
+		let network_graph = create_network_graph();
+		network_graph.set_last_rapid_gossip_sync_timestamp(42);
+
+		let mut w = test_utils::TestVecWriter(Vec::new());
+		network_graph.write(&mut w).unwrap();
+		let reassembled_network_graph: NetworkGraph = Readable::read(&mut io::Cursor::new(&w.0)).unwrap();
+		assert!(reassembled_network_graph == network_graph);
+		assert_eq!(reassembled_network_graph.get_last_rapid_gossip_sync_timestamp().unwrap(), 42);
+	}
+
+	#[test]
+	fn network_graph_snapshot() {
+		let network_graph = create_network_graph();
+		let (secp_ctx, net_graph_msg_handler) = create_net_graph_msg_handler(&network_graph);
+
+		let node_1_privkey = &SecretKey::from_slice(&[42; 32]).unwrap();
+		let node_2_privkey = &SecretKey::from_slice(&[41; 32]).unwrap();
+
+		let valid_announcement = get_signed_channel_announcement(|_| {}, node_1_privkey, node_2_privkey, &secp_ctx);
+		assert!(net_graph_msg_handler.handle_channel_announcement(&valid_announcement).unwrap());
+		let valid_announcement = get_signed_node_announcement(|_| {}, node_1_privkey, &secp_ctx);
+		net_graph_msg_handler.handle_node_announcement(&valid_announcement).unwrap();
+		let valid_channel_update = get_signed_channel_update(|_| {}, node_1_privkey, &secp_ctx);
+		assert!(net_graph_msg_handler.handle_channel_update(&valid_channel_update).unwrap());
+
+		// The snapshot has the same channels and nodes, but without their gossip messages.
+		let snapshot = network_graph.snapshot();
+		assert_eq!(snapshot.get_genesis_hash(), network_graph.get_genesis_hash());
+		{
+			let read_only_graph = network_graph.read_only();
+			let channel = read_only_graph.channels().get(&0).unwrap();
+			let snapshot_channel = snapshot.channel(0).unwrap();
+			assert!(channel.announcement_message.is_some());
+			assert!(snapshot_channel.announcement_message.is_none());
+			assert!(channel.one_to_two.as_ref().unwrap().last_update_message.is_some());
+			assert!(snapshot_channel.one_to_two.as_ref().unwrap().last_update_message.is_none());
+			assert_eq!(snapshot_channel.one_to_two.as_ref().unwrap().fees, channel.one_to_two.as_ref().unwrap().fees);
+			assert_eq!(snapshot_channel.node_one, channel.node_one);
+			assert_eq!(snapshot_channel.node_two, channel.node_two);
+
+			let node_id = NodeId::from_pubkey(&PublicKey::from_secret_key(&secp_ctx, node_1_privkey));
+			let node = read_only_graph.nodes().get(&node_id).unwrap();
+			assert_eq!(snapshot.node_channels(&node_id).unwrap(), node.channels);
+			assert_eq!(snapshot.node_features(&node_id), Some(&node.announcement_info.as_ref().unwrap().features));
+
+			// Nodes we haven't received an announcement for are present, but without features.
+			let node_2_id = NodeId::from_pubkey(&PublicKey::from_secret_key(&secp_ctx, node_2_privkey));
+			assert_eq!(snapshot.node_channels(&node_2_id).unwrap(), vec![0]);
+			assert!(snapshot.node_features(&node_2_id).is_none());
+		}
+
+		// Later changes to the graph aren't reflected in an existing snapshot.
+		network_graph.close_channel_from_update(0, true);
+		assert!(network_graph.read_only().channels().is_empty());
+		assert_eq!(snapshot.short_channel_ids(), &[0]);
+		assert_eq!(snapshot.clone().node_ids().len(), 2);
+
+		// A snapshot survives a serialization round-trip.
+		let read_snapshot: NetworkGraphSnapshot = Readable::read(&mut io::Cursor::new(&snapshot.encode())).unwrap();
+		assert_eq!(read_snapshot.get_genesis_hash(), snapshot.get_genesis_hash());
+		assert!(read_snapshot.graph == snapshot.graph);
+
+		// A snapshot whose nodes refer to channels it does not contain is rejected.
+		let mut encoded_snapshot = snapshot.encode();
+		let last_channel_idx_pos = encoded_snapshot.len() - 5;
+		assert_eq!(encoded_snapshot[last_channel_idx_pos..last_channel_idx_pos + 4], [0; 4]);
+		encoded_snapshot[last_channel_idx_pos + 3] = 1;
+		let res: Result<NetworkGraphSnapshot, _> = Readable::read(&mut io::Cursor::new(&encoded_snapshot));
+		assert_eq!(res.err(), Some(DecodeError::InvalidValue));
+	}
+
+	#[test]
+	fn handling_partial_channel_announcements() {
+		let network_graph = create_network_graph();
+		let secp_ctx = Secp256k1::new();
+		let node_1_pubkey = PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[42; 32]).unwrap());
+		let node_2_pubkey = PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[41; 32]).unwrap());
+
+		// A channel with ourselves is never accepted.
+		assert!(network_graph.add_channel_from_partial_announcement(42, 0, ChannelFeatures::empty(), node_1_pubkey, node_1_pubkey).is_err());
+
+		network_graph.add_channel_from_partial_announcement(42, 0, ChannelFeatures::empty(), node_1_pubkey, node_2_pubkey).unwrap();
+		match network_graph.add_channel_from_partial_announcement(42, 0, ChannelFeatures::empty(), node_1_pubkey, node_2_pubkey) {
+			Err(LightningError { action: ErrorAction::IgnoreDuplicateGossip, .. }) => {},
+			_ => panic!(),
+		}
+
+		let read_only_graph = network_graph.read_only();
+		let channel = read_only_graph.channels().get(&42).unwrap();
+		assert!(channel.announcement_message.is_none());
+		assert!(channel.get_directional_info(0).is_none());
+		assert_eq!(read_only_graph.nodes().get(&NodeId::from_pubkey(&node_1_pubkey)).unwrap().channels, vec![42]);
+		assert_eq!(read_only_graph.nodes().get(&NodeId::from_pubkey(&node_2_pubkey)).unwrap().channels, vec![42]);
+	}
+
 	#[test]
 	fn calling_sync_routing_table() {
 		let network_graph = create_network_graph();
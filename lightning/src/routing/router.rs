@@ -14,17 +14,17 @@
 
 use bitcoin::secp256k1::key::PublicKey;
 
-use ln::channelmanager::ChannelDetails;
+use ln::channelmanager::{ChannelDetails, MIN_FINAL_CLTV_EXPIRY};
 use ln::features::{ChannelFeatures, InvoiceFeatures, NodeFeatures};
 use ln::msgs::{DecodeError, ErrorAction, LightningError, MAX_VALUE_MSAT};
-use routing::scoring::Score;
-use routing::network_graph::{DirectedChannelInfoWithUpdate, EffectiveCapacity, NetworkGraph, NodeId, RoutingFees};
+use routing::scoring::{ChannelUsage, Score};
+use routing::network_graph::{ChannelInfo, DirectedChannelInfoWithUpdate, EffectiveCapacity, NetworkGraph, NetworkGraphSnapshot, NodeId, NodeInfo, ReadOnlyNetworkGraph, RoutingFees, SnapshotNodeChannels};
 use util::ser::{Writeable, Readable};
 use util::logger::{Level, Logger};
 
 use io;
 use prelude::*;
-use alloc::collections::BinaryHeap;
+use alloc::collections::{BinaryHeap, BTreeMap};
 use core::cmp;
 use core::ops::Deref;
 
@@ -176,6 +176,21 @@ impl_writeable_tlv_based!(RouteParameters, {
 /// Maximum total CTLV difference we allow for a full payment path.
 pub const DEFAULT_MAX_TOTAL_CLTV_EXPIRY_DELTA: u32 = 1008;
 
+/// Maximum number of paths we split a multi-path payment into by default.
+pub const DEFAULT_MAX_PATH_COUNT: u8 = 10;
+
+/// Default power-of-1/2 fraction of a channel's total capacity a single payment path may use, i.e.
+/// by default a path is limited to a quarter of a channel's capacity.
+pub const DEFAULT_MAX_CHANNEL_SATURATION_POWER_OF_HALF: u8 = 2;
+
+/// Maximum number of hops we allow in a payment path by default. This is the most hops a legacy
+/// onion packet can hold, so larger limits are rejected when routing.
+pub const DEFAULT_MAX_PATH_LENGTH: u8 = 20;
+
+/// Multiple of the payment amount we collect across paths by default before selecting the best
+/// combination of them for a multi-path payment.
+pub const DEFAULT_ROUTE_CAPACITY_PROVISION_FACTOR: u8 = 3;
+
 /// The recipient of a payment.
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub struct PaymentParameters {
@@ -198,14 +213,72 @@ pub struct PaymentParameters {
 
 	/// The maximum total CLTV delta we accept for the route.
 	pub max_total_cltv_expiry_delta: u32,
+
+	/// The maximum number of paths that may be used by MPP payments. A value of 1 disables MPP.
+	pub max_path_count: u8,
+
+	/// Selects the maximum share of a channel's total capacity which will be sent over a channel,
+	/// as a power of 1/2. A higher value prefers to send the payment using more MPP parts whereas
+	/// a lower value prefers to send larger MPP parts, potentially saturating channels and
+	/// increasing failure probability for those paths.
+	///
+	/// Only applies to channels whose total capacity is known from their funding output. If no
+	/// route can be found while respecting this limit, routing is retried ignoring it.
+	///
+	/// A value of 0 will allow payments up to and including a channel's total announced capacity,
+	/// a value of 1 will only use up to half its capacity, two 1/4, etc.
+	pub max_channel_saturation_power_of_half: u8,
+
+	/// The maximum number of hops we accept in each path of the route. May not exceed
+	/// [`DEFAULT_MAX_PATH_LENGTH`], the most hops an onion packet can hold.
+	pub max_path_length: u8,
+
+	/// When no single path can carry the full payment, we keep collecting paths until their
+	/// combined value reaches this multiple of the payment amount, and then select the best
+	/// combination of them. A higher value considers more paths at the cost of routing time. Must
+	/// be at least 1.
+	pub route_capacity_provision_factor: u8,
+
+	/// Nodes which the route must not pass through. Neither we nor the payee are affected.
+	pub excluded_node_ids: Vec<PublicKey>,
+
+	/// Channels which the route must not use, including our own first hops and channels from
+	/// [`Self::route_hints`].
+	pub excluded_short_channel_ids: Vec<u64>,
+
+	/// Node feature bits which every node the route passes through must have set. Nodes we have
+	/// not received a node announcement for are considered to have no features set. Neither we
+	/// nor the payee are affected.
+	pub required_node_feature_bits: Vec<u16>,
+
+	/// Node feature bits which no node the route passes through may have set. Neither we nor the
+	/// payee are affected.
+	pub forbidden_node_feature_bits: Vec<u16>,
+
+	/// The maximum total fee, in msat, we are willing to pay for the route, if any.
+	pub max_total_routing_fee_msat: Option<u64>,
+
+	/// The maximum total fee we are willing to pay for the route, in millionths of the amount
+	/// sent, if any. Applies in addition to [`Self::max_total_routing_fee_msat`].
+	pub max_routing_fee_proportional_millionths: Option<u32>,
 }
 
 impl_writeable_tlv_based!(PaymentParameters, {
 	(0, payee_pubkey, required),
 	(1, max_total_cltv_expiry_delta, (default_value, DEFAULT_MAX_TOTAL_CLTV_EXPIRY_DELTA)),
 	(2, features, option),
+	(3, max_path_count, (default_value, DEFAULT_MAX_PATH_COUNT)),
 	(4, route_hints, vec_type),
+	(5, max_channel_saturation_power_of_half, (default_value, DEFAULT_MAX_CHANNEL_SATURATION_POWER_OF_HALF)),
 	(6, expiry_time, option),
+	(7, max_path_length, (default_value, DEFAULT_MAX_PATH_LENGTH)),
+	(9, excluded_node_ids, vec_type),
+	(11, excluded_short_channel_ids, vec_type),
+	(13, required_node_feature_bits, vec_type),
+	(15, forbidden_node_feature_bits, vec_type),
+	(17, max_total_routing_fee_msat, option),
+	(19, max_routing_fee_proportional_millionths, option),
+	(21, route_capacity_provision_factor, (default_value, DEFAULT_ROUTE_CAPACITY_PROVISION_FACTOR)),
 });
 
 impl PaymentParameters {
@@ -217,6 +290,16 @@ impl PaymentParameters {
 			route_hints: vec![],
 			expiry_time: None,
 			max_total_cltv_expiry_delta: DEFAULT_MAX_TOTAL_CLTV_EXPIRY_DELTA,
+			max_path_count: DEFAULT_MAX_PATH_COUNT,
+			max_channel_saturation_power_of_half: DEFAULT_MAX_CHANNEL_SATURATION_POWER_OF_HALF,
+			max_path_length: DEFAULT_MAX_PATH_LENGTH,
+			route_capacity_provision_factor: DEFAULT_ROUTE_CAPACITY_PROVISION_FACTOR,
+			excluded_node_ids: vec![],
+			excluded_short_channel_ids: vec![],
+			required_node_feature_bits: vec![],
+			forbidden_node_feature_bits: vec![],
+			max_total_routing_fee_msat: None,
+			max_routing_fee_proportional_millionths: None,
 		}
 	}
 
@@ -252,6 +335,94 @@ impl PaymentParameters {
 	pub fn with_max_total_cltv_expiry_delta(self, max_total_cltv_expiry_delta: u32) -> Self {
 		Self { max_total_cltv_expiry_delta, ..self }
 	}
+
+	/// Includes a limit for the number of paths a multi-path payment may be split into.
+	///
+	/// (C-not exported) since bindings don't support move semantics
+	pub fn with_max_path_count(self, max_path_count: u8) -> Self {
+		Self { max_path_count, ..self }
+	}
+
+	/// Includes a limit for the share of a channel's total capacity a single path may use, as a
+	/// power of 1/2. See [`Self::max_channel_saturation_power_of_half`].
+	///
+	/// (C-not exported) since bindings don't support move semantics
+	pub fn with_max_channel_saturation_power_of_half(self, max_channel_saturation_power_of_half: u8) -> Self {
+		Self { max_channel_saturation_power_of_half, ..self }
+	}
+
+	/// Includes a limit for the number of hops in each path of the route.
+	///
+	/// (C-not exported) since bindings don't support move semantics
+	pub fn with_max_path_length(self, max_path_length: u8) -> Self {
+		Self { max_path_length, ..self }
+	}
+
+	/// Includes the multiple of the payment amount to collect across paths before selecting the
+	/// best of them. See [`Self::route_capacity_provision_factor`].
+	///
+	/// (C-not exported) since bindings don't support move semantics
+	pub fn with_route_capacity_provision_factor(self, route_capacity_provision_factor: u8) -> Self {
+		Self { route_capacity_provision_factor, ..self }
+	}
+
+	/// Includes nodes which the route must not pass through.
+	///
+	/// (C-not exported) since bindings don't support move semantics
+	pub fn with_excluded_node_ids(self, excluded_node_ids: Vec<PublicKey>) -> Self {
+		Self { excluded_node_ids, ..self }
+	}
+
+	/// Includes channels which the route must not use.
+	///
+	/// (C-not exported) since bindings don't support move semantics
+	pub fn with_excluded_short_channel_ids(self, excluded_short_channel_ids: Vec<u64>) -> Self {
+		Self { excluded_short_channel_ids, ..self }
+	}
+
+	/// Includes node feature bits which every node the route passes through must have set.
+	///
+	/// (C-not exported) since bindings don't support move semantics
+	pub fn with_required_node_feature_bits(self, required_node_feature_bits: Vec<u16>) -> Self {
+		Self { required_node_feature_bits, ..self }
+	}
+
+	/// Includes node feature bits which no node the route passes through may have set.
+	///
+	/// (C-not exported) since bindings don't support move semantics
+	pub fn with_forbidden_node_feature_bits(self, forbidden_node_feature_bits: Vec<u16>) -> Self {
+		Self { forbidden_node_feature_bits, ..self }
+	}
+
+	/// Includes a limit for the total fee paid for the route, in msat.
+	///
+	/// (C-not exported) since bindings don't support move semantics
+	pub fn with_max_total_routing_fee_msat(self, max_total_routing_fee_msat: u64) -> Self {
+		Self { max_total_routing_fee_msat: Some(max_total_routing_fee_msat), ..self }
+	}
+
+	/// Includes a limit for the total fee paid for the route, in millionths of the amount sent.
+	///
+	/// (C-not exported) since bindings don't support move semantics
+	pub fn with_max_routing_fee_proportional_millionths(self, max_routing_fee_proportional_millionths: u32) -> Self {
+		Self { max_routing_fee_proportional_millionths: Some(max_routing_fee_proportional_millionths), ..self }
+	}
+
+	/// Returns the most we are willing to pay in fees to send `final_value_msat`, considering both
+	/// [`Self::max_total_routing_fee_msat`] and [`Self::max_routing_fee_proportional_millionths`].
+	fn max_routing_fee_msat(&self, final_value_msat: u64) -> u64 {
+		let proportional_limit_msat = self.max_routing_fee_proportional_millionths
+			.map(|proportional_millionths| final_value_msat.saturating_mul(proportional_millionths as u64) / 1_000_000)
+			.unwrap_or(u64::max_value());
+		cmp::min(self.max_total_routing_fee_msat.unwrap_or(u64::max_value()), proportional_limit_msat)
+	}
+
+	/// Returns whether a node with the given features may be routed through, considering
+	/// [`Self::required_node_feature_bits`] and [`Self::forbidden_node_feature_bits`].
+	fn allows_node_features(&self, features: &NodeFeatures) -> bool {
+		let is_set = |bit: &u16| features.supports_bit(*bit);
+		self.required_node_feature_bits.iter().all(is_set) && !self.forbidden_node_feature_bits.iter().any(is_set)
+	}
 }
 
 /// A list of hops along a payment path terminating with a channel to the recipient.
@@ -306,12 +477,58 @@ impl_writeable_tlv_based!(RouteHintHop, {
 	(6, cltv_expiry_delta, required),
 });
 
+/// Tracks the liquidity used by HTLCs we have in flight over channels in the network, so that new
+/// paths may avoid relying on the same liquidity.
+///
+/// May be built from a [`ChannelManager`] using [`ChannelManager::compute_inflight_htlcs`] and
+/// passed to [`find_route_with_inflight_htlcs`].
+///
+/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+/// [`ChannelManager::compute_inflight_htlcs`]: crate::ln::channelmanager::ChannelManager::compute_inflight_htlcs
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InFlightHtlcs(
+	// The in-flight amount in msat, keyed by short channel id and whether the HTLCs are travelling
+	// from the lesser to the greater of the channel's counterparties' node ids.
+	HashMap<(u64, bool), u64>
+);
+
+impl InFlightHtlcs {
+	/// Creates an empty set of in-flight HTLCs.
+	pub fn new() -> Self {
+		Self(HashMap::new())
+	}
+
+	/// Adds the liquidity used by an HTLC sent by `payer_node_id` along `path`.
+	pub fn process_path(&mut self, path: &[RouteHop], payer_node_id: PublicKey) {
+		// The value sent over each channel is the final hop's amount plus the fees of each
+		// following hop, so walk the path backwards paired with each hop's previous node.
+		let previous_node_ids = path.iter().rev().skip(1)
+			.map(|hop| NodeId::from_pubkey(&hop.pubkey))
+			.chain(core::iter::once(NodeId::from_pubkey(&payer_node_id)));
+		let mut amount_msat: u64 = 0;
+		for (hop, source) in path.iter().rev().zip(previous_node_ids) {
+			amount_msat = amount_msat.saturating_add(hop.fee_msat);
+			let target = NodeId::from_pubkey(&hop.pubkey);
+			let used_liquidity_msat = self.0.entry((hop.short_channel_id, source < target)).or_insert(0);
+			*used_liquidity_msat = used_liquidity_msat.saturating_add(amount_msat);
+		}
+	}
+
+	/// Returns the liquidity in msat used by in-flight HTLCs over the given channel in the
+	/// direction from `source` to `target`, if any.
+	pub fn used_liquidity_msat(&self, source: &NodeId, target: &NodeId, short_channel_id: u64) -> Option<u64> {
+		self.0.get(&(short_channel_id, source < target)).cloned()
+	}
+}
+
 #[derive(Eq, PartialEq)]
 struct RouteGraphNode {
 	node_id: NodeId,
 	lowest_fee_to_peer_through_node: u64,
 	lowest_fee_to_node: u64,
 	total_cltv_delta: u32,
+	/// The number of hops from this node to the destination.
+	path_length_to_node: u8,
 	// The maximum value a yet-to-be-constructed payment path might flow through this node.
 	// This value is upper-bounded by us by:
 	// - how much is needed for a path being constructed
@@ -398,6 +615,14 @@ impl<'a> CandidateRouteHop<'a> {
 		}
 	}
 
+	fn htlc_maximum_msat(&self) -> Option<u64> {
+		match self {
+			CandidateRouteHop::FirstHop { .. } => None,
+			CandidateRouteHop::PublicHop { info, .. } => info.direction().htlc_maximum_msat,
+			CandidateRouteHop::PrivateHop { hint } => hint.htlc_maximum_msat,
+		}
+	}
+
 	fn fees(&self) -> RoutingFees {
 		match self {
 			CandidateRouteHop::FirstHop { .. } => RoutingFees {
@@ -417,6 +642,48 @@ impl<'a> CandidateRouteHop<'a> {
 			CandidateRouteHop::PrivateHop { .. } => EffectiveCapacity::Infinite,
 		}
 	}
+
+	/// Returns the effective capacity less any liquidity used by our `inflight_htlcs` over the
+	/// channel in the direction from `source` to `target`. The outbound liquidity of our first
+	/// hops already accounts for our pending HTLCs.
+	fn available_capacity_msat(
+		&self, source: &NodeId, target: &NodeId, inflight_htlcs: &InFlightHtlcs
+	) -> u64 {
+		let capacity_msat = self.effective_capacity().as_msat();
+		match self {
+			CandidateRouteHop::FirstHop { .. } => capacity_msat,
+			_ => {
+				let used_liquidity_msat = inflight_htlcs
+					.used_liquidity_msat(source, target, self.short_channel_id())
+					.unwrap_or(0);
+				capacity_msat.saturating_sub(used_liquidity_msat)
+			},
+		}
+	}
+
+	/// Returns the [`ChannelUsage`] given to the [`Score`] when sending `amount_msat` over this
+	/// hop with `capacity_msat` available, where `path_cltv_expiry_delta` includes this hop's delta.
+	fn usage(&self, amount_msat: u64, capacity_msat: u64, path_cltv_expiry_delta: u32) -> ChannelUsage {
+		ChannelUsage {
+			amount_msat,
+			capacity_msat,
+			htlc_minimum_msat: self.htlc_minimum_msat(),
+			htlc_maximum_msat: self.htlc_maximum_msat(),
+			fees: self.fees(),
+			cltv_expiry_delta: self.cltv_expiry_delta() as u16,
+			path_cltv_expiry_delta,
+		}
+	}
+
+	/// Returns the most a single path may send over this hop, limiting channels whose total
+	/// capacity is known to the given power-of-1/2 fraction of it.
+	fn max_path_liquidity_msat(&self, max_channel_saturation_power_of_half: u8) -> u64 {
+		match self.effective_capacity() {
+			EffectiveCapacity::Total { capacity_msat } =>
+				capacity_msat.checked_shr(max_channel_saturation_power_of_half as u32).unwrap_or(0),
+			capacity => capacity.as_msat(),
+		}
+	}
 }
 
 /// It's useful to keep track of the hops associated with the fees required to use them,
@@ -616,11 +883,325 @@ where L::Target: Logger {
 	)
 }
 
+/// Finds a route from us (payer) to the given target node (payee), as in [`find_route`], while
+/// accounting for the liquidity used by our own `inflight_htlcs`.
+///
+/// The liquidity used by in-flight HTLCs over channels beyond our first hops is subtracted from
+/// those channels' [`EffectiveCapacity`], both when deciding how much value a channel may carry
+/// and when scoring it. The outbound capacity given in `first_hops` already accounts for our
+/// pending HTLCs and is thus used as-is.
+///
+/// Useful when paying multiple payments or retrying paths of a multi-path payment concurrently.
+/// See [`ChannelManager::compute_inflight_htlcs`].
+///
+/// [`ChannelManager::compute_inflight_htlcs`]: crate::ln::channelmanager::ChannelManager::compute_inflight_htlcs
+pub fn find_route_with_inflight_htlcs<L: Deref, S: Score>(
+	our_node_pubkey: &PublicKey, route_params: &RouteParameters, network: &NetworkGraph,
+	first_hops: Option<&[&ChannelDetails]>, inflight_htlcs: &InFlightHtlcs, logger: L, scorer: &S
+) -> Result<Route, LightningError>
+where L::Target: Logger {
+	let network_graph = network.read_only();
+	get_route_internal(
+		our_node_pubkey, &route_params.payment_params, &network_graph, first_hops,
+		route_params.final_value_msat, route_params.final_cltv_expiry_delta, inflight_htlcs,
+		logger, scorer
+	)
+}
+
+/// Finds a route from us (payer) to the given target node (payee), as in
+/// [`find_route_with_inflight_htlcs`], but using a [`NetworkGraphSnapshot`] rather than the live
+/// [`NetworkGraph`].
+///
+/// As the snapshot holds no locks on the [`NetworkGraph`], gossip may continue to be applied to
+/// the graph while the route is computed. The route only reflects the graph at the time the
+/// snapshot was taken, while `inflight_htlcs` should reflect our HTLCs at the time of routing.
+pub fn find_route_from_snapshot<L: Deref, S: Score>(
+	our_node_pubkey: &PublicKey, route_params: &RouteParameters, snapshot: &NetworkGraphSnapshot,
+	first_hops: Option<&[&ChannelDetails]>, inflight_htlcs: &InFlightHtlcs, logger: L, scorer: &S
+) -> Result<Route, LightningError>
+where L::Target: Logger {
+	get_route_internal(
+		our_node_pubkey, &route_params.payment_params, snapshot, first_hops,
+		route_params.final_value_msat, route_params.final_cltv_expiry_delta, inflight_htlcs, logger, scorer
+	)
+}
+
+/// Builds a [`Route`] from us (payer) through the given `hops` to the payee of `route_params`,
+/// computing the fee and CLTV expiry delta of each hop for the amount being sent.
+///
+/// `hops` lists the node id of each hop in order, ending with the payee. A channel must exist
+/// between us and the first hop and between each pair of consecutive hops, either in
+/// `first_hops` (for our own channels, see [`find_route`]) or in the [`NetworkGraph`]. Where
+/// multiple usable channels connect two hops, the cheapest one is used.
+///
+/// The returned [`Route`] may be paid with [`ChannelManager::send_payment`].
+///
+/// [`ChannelManager::send_payment`]: crate::ln::channelmanager::ChannelManager::send_payment
+pub fn build_route_from_hops<L: Deref>(
+	our_node_pubkey: &PublicKey, hops: &[PublicKey], route_params: &RouteParameters, network: &NetworkGraph,
+	first_hops: Option<&[&ChannelDetails]>, logger: L
+) -> Result<Route, LightningError>
+where L::Target: Logger {
+	if hops.last() != Some(&route_params.payment_params.payee_pubkey) {
+		return Err(LightningError{err: "The last hop must be the payee".to_owned(), action: ErrorAction::IgnoreError});
+	}
+
+	// Only allow channels between consecutive hops, so that the only path found is the requested
+	// one, and don't split the payment across multiple paths.
+	let scorer = HopScorer {
+		our_node_id: NodeId::from_pubkey(our_node_pubkey),
+		hop_ids: hops.iter().map(|pubkey| NodeId::from_pubkey(pubkey)).collect(),
+	};
+	let payment_params = route_params.payment_params.clone().with_max_path_count(1);
+	let network_graph = network.read_only();
+	let mut route = get_route_internal(
+		our_node_pubkey, &payment_params, &network_graph, first_hops,
+		route_params.final_value_msat, route_params.final_cltv_expiry_delta, &InFlightHtlcs::new(),
+		logger, &scorer
+	)?;
+	route.payment_params = Some(route_params.payment_params.clone());
+	Ok(route)
+}
+
+/// A [`Score`] used by [`build_route_from_hops`] which only allows channels between consecutive
+/// hops of the requested path.
+struct HopScorer {
+	our_node_id: NodeId,
+	hop_ids: Vec<NodeId>,
+}
+
+impl Score for HopScorer {
+	fn channel_penalty_msat(&self, _short_channel_id: u64, source: &NodeId, target: &NodeId, _usage: ChannelUsage) -> u64 {
+		let mut prev_hop_id = &self.our_node_id;
+		for hop_id in self.hop_ids.iter() {
+			if prev_hop_id == source && hop_id == target {
+				return 0;
+			}
+			prev_hop_id = hop_id;
+		}
+		u64::max_value()
+	}
+
+	fn payment_path_failed(&mut self, _path: &[&RouteHop], _short_channel_id: u64) {}
+
+	fn payment_path_successful(&mut self, _path: &[&RouteHop]) {}
+}
+
+#[cfg(c_bindings)]
+impl Writeable for HopScorer {
+	fn write<W: ::util::ser::Writer>(&self, _w: &mut W) -> Result<(), io::Error> {
+		unreachable!();
+	}
+}
+
+/// Finds a circular route which moves `amount_msat` of liquidity out of `outbound_channel` and
+/// back into `inbound_channel`, paying ourselves.
+///
+/// The route leaves us over `outbound_channel`, is routed through the [`NetworkGraph`] to the
+/// counterparty of `inbound_channel` and returns to us over `inbound_channel`, charging the fees
+/// the counterparty advertised for it. As [`find_route`] refuses to route to ourselves, this is
+/// the way to compute a route for [`ChannelManager::send_rebalance`].
+///
+/// Both channels must have a `short_channel_id` and `inbound_channel` must have had its
+/// counterparty's forwarding parameters announced to us.
+///
+/// The returned [`Route`] has no [`PaymentParameters`] set and thus can't be retried.
+///
+/// [`ChannelManager::send_rebalance`]: crate::ln::channelmanager::ChannelManager::send_rebalance
+pub fn find_rebalance_route<L: Deref, S: Score>(
+	our_node_pubkey: &PublicKey, outbound_channel: &ChannelDetails, inbound_channel: &ChannelDetails,
+	amount_msat: u64, network: &NetworkGraph, logger: L, scorer: &S
+) -> Result<Route, LightningError>
+where L::Target: Logger {
+	let outbound_scid = outbound_channel.short_channel_id.ok_or_else(|| LightningError {
+		err: "The outbound channel must have a short_channel_id".to_owned(), action: ErrorAction::IgnoreError
+	})?;
+	let inbound_scid = inbound_channel.short_channel_id.ok_or_else(|| LightningError {
+		err: "The inbound channel must have a short_channel_id".to_owned(), action: ErrorAction::IgnoreError
+	})?;
+	if outbound_scid == inbound_scid {
+		return Err(LightningError{err: "Cannot rebalance a channel into itself".to_owned(), action: ErrorAction::IgnoreError});
+	}
+	if inbound_channel.inbound_capacity_msat < amount_msat {
+		return Err(LightningError{err: "The inbound channel doesn't have enough inbound capacity".to_owned(), action: ErrorAction::IgnoreError});
+	}
+	let forwarding_info = inbound_channel.counterparty.forwarding_info.as_ref().ok_or_else(|| LightningError {
+		err: "The inbound channel's counterparty has not announced its forwarding parameters".to_owned(),
+		action: ErrorAction::IgnoreError
+	})?;
+	let inbound_fee_msat = compute_fees(amount_msat, RoutingFees {
+		base_msat: forwarding_info.fee_base_msat,
+		proportional_millionths: forwarding_info.fee_proportional_millionths,
+	}).ok_or_else(|| LightningError{err: "Cannot route the given amount back into the inbound channel".to_owned(), action: ErrorAction::IgnoreError})?;
+	let value_to_counterparty_msat = amount_msat.checked_add(inbound_fee_msat)
+		.ok_or_else(|| LightningError{err: "Cannot route the given amount back into the inbound channel".to_owned(), action: ErrorAction::IgnoreError})?;
+
+	// Route to the inbound channel's counterparty, having it forward to us as its final hop. The
+	// counterparty's forwarding CLTV delta is used as the final CLTV delta so that it ends up on
+	// its hop in the returned route.
+	let payment_params = PaymentParameters::from_node_id(inbound_channel.counterparty.node_id)
+		.with_max_path_count(1)
+		.with_excluded_short_channel_ids(vec![inbound_scid]);
+	let mut route = get_route(
+		our_node_pubkey, &payment_params, network, Some(&[outbound_channel]), value_to_counterparty_msat,
+		forwarding_info.cltv_expiry_delta as u32, logger, scorer
+	)?;
+	debug_assert_eq!(route.paths.len(), 1);
+
+	let path = &mut route.paths[0];
+	path.last_mut().unwrap().fee_msat = inbound_fee_msat;
+	path.push(RouteHop {
+		pubkey: *our_node_pubkey,
+		node_features: NodeFeatures::known(),
+		short_channel_id: inbound_scid,
+		channel_features: inbound_channel.counterparty.features.to_context(),
+		fee_msat: amount_msat,
+		cltv_expiry_delta: MIN_FINAL_CLTV_EXPIRY,
+	});
+	route.payment_params = None;
+	Ok(route)
+}
+
 pub(crate) fn get_route<L: Deref, S: Score>(
 	our_node_pubkey: &PublicKey, payment_params: &PaymentParameters, network: &NetworkGraph,
 	first_hops: Option<&[&ChannelDetails]>, final_value_msat: u64, final_cltv_expiry_delta: u32, 
 	logger: L, scorer: &S
 ) -> Result<Route, LightningError>
+where L::Target: Logger {
+	let network_graph = network.read_only();
+	get_route_internal(
+		our_node_pubkey, payment_params, &network_graph, first_hops,
+		final_value_msat, final_cltv_expiry_delta, &InFlightHtlcs::new(), logger, scorer
+	)
+}
+
+/// The view of the network graph searched by the router, so that routes can be found both in a
+/// [`ReadOnlyNetworkGraph`] and in a [`NetworkGraphSnapshot`].
+trait RoutingGraph<'a>: Copy {
+	/// A node in the graph, as returned by [`Self::node`].
+	type Node: Copy;
+	/// An iterator over a node's channels, along with their short channel ids.
+	type NodeChannels: Iterator<Item = (u64, &'a ChannelInfo)>;
+
+	fn node_count(self) -> usize;
+	fn node(self, node_id: &NodeId) -> Option<Self::Node>;
+	/// Returns the features from the node's announcement, if we've received one.
+	fn node_features(self, node: Self::Node) -> Option<&'a NodeFeatures>;
+	fn lowest_inbound_channel_fees(self, node: Self::Node) -> Option<RoutingFees>;
+	fn node_channels(self, node: Self::Node) -> Self::NodeChannels;
+	fn channel(self, short_channel_id: u64) -> Option<&'a ChannelInfo>;
+}
+
+/// An iterator over a node's channels in a [`ReadOnlyNetworkGraph`].
+struct GraphNodeChannels<'a> {
+	channels: &'a BTreeMap<u64, ChannelInfo>,
+	short_channel_ids: core::slice::Iter<'a, u64>,
+}
+
+impl<'a> Iterator for GraphNodeChannels<'a> {
+	type Item = (u64, &'a ChannelInfo);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let channels = self.channels;
+		self.short_channel_ids.next().map(|short_channel_id| {
+			(*short_channel_id, channels.get(short_channel_id).expect("inconsistent NetworkGraph"))
+		})
+	}
+}
+
+impl<'a, 'b> RoutingGraph<'a> for &'a ReadOnlyNetworkGraph<'b> {
+	type Node = &'a NodeInfo;
+	type NodeChannels = GraphNodeChannels<'a>;
+
+	fn node_count(self) -> usize {
+		self.nodes().len()
+	}
+
+	fn node(self, node_id: &NodeId) -> Option<&'a NodeInfo> {
+		self.nodes().get(node_id)
+	}
+
+	fn node_features(self, node: &'a NodeInfo) -> Option<&'a NodeFeatures> {
+		node.announcement_info.as_ref().map(|info| &info.features)
+	}
+
+	fn lowest_inbound_channel_fees(self, node: &'a NodeInfo) -> Option<RoutingFees> {
+		node.lowest_inbound_channel_fees
+	}
+
+	fn node_channels(self, node: &'a NodeInfo) -> GraphNodeChannels<'a> {
+		GraphNodeChannels {
+			channels: self.channels(),
+			short_channel_ids: node.channels.iter(),
+		}
+	}
+
+	fn channel(self, short_channel_id: u64) -> Option<&'a ChannelInfo> {
+		self.channels().get(&short_channel_id)
+	}
+}
+
+impl<'a> RoutingGraph<'a> for &'a NetworkGraphSnapshot {
+	type Node = u32;
+	type NodeChannels = SnapshotNodeChannels<'a>;
+
+	fn node_count(self) -> usize {
+		self.node_ids().len()
+	}
+
+	fn node(self, node_id: &NodeId) -> Option<u32> {
+		self.node_index(node_id)
+	}
+
+	fn node_features(self, node: u32) -> Option<&'a NodeFeatures> {
+		self.node_features_at(node)
+	}
+
+	fn lowest_inbound_channel_fees(self, node: u32) -> Option<RoutingFees> {
+		self.lowest_inbound_channel_fees_at(node)
+	}
+
+	fn node_channels(self, node: u32) -> SnapshotNodeChannels<'a> {
+		self.node_channels_at(node)
+	}
+
+	fn channel(self, short_channel_id: u64) -> Option<&'a ChannelInfo> {
+		NetworkGraphSnapshot::channel(self, short_channel_id)
+	}
+}
+
+fn get_route_internal<'a, G: RoutingGraph<'a>, L: Deref, S: Score>(
+	our_node_pubkey: &PublicKey, payment_params: &PaymentParameters, network_graph: G,
+	first_hops: Option<&[&ChannelDetails]>, final_value_msat: u64, final_cltv_expiry_delta: u32,
+	inflight_htlcs: &InFlightHtlcs, logger: L, scorer: &S
+) -> Result<Route, LightningError>
+where L::Target: Logger {
+	let result = build_route(
+		our_node_pubkey, payment_params, network_graph, first_hops, final_value_msat,
+		final_cltv_expiry_delta, inflight_htlcs, &*logger, scorer
+	);
+	if result.is_ok() || payment_params.max_channel_saturation_power_of_half == 0 {
+		return result;
+	}
+
+	// Limiting how much of each channel's capacity a path may use may leave us unable to collect
+	// enough value, so retry allowing paths to saturate channels. The returned route still carries
+	// the original parameters so that retries of the payment start out respecting them again.
+	log_trace!(logger, "Failed to find a route while limiting channel saturation, retrying without the limit");
+	let unsaturated_params = payment_params.clone().with_max_channel_saturation_power_of_half(0);
+	let mut route = build_route(
+		our_node_pubkey, &unsaturated_params, network_graph, first_hops, final_value_msat,
+		final_cltv_expiry_delta, inflight_htlcs, &*logger, scorer
+	)?;
+	route.payment_params = Some(payment_params.clone());
+	Ok(route)
+}
+
+fn build_route<'a, G: RoutingGraph<'a>, L: Deref, S: Score>(
+	our_node_pubkey: &PublicKey, payment_params: &PaymentParameters, network_graph: G,
+	first_hops: Option<&[&ChannelDetails]>, final_value_msat: u64, final_cltv_expiry_delta: u32,
+	inflight_htlcs: &InFlightHtlcs, logger: L, scorer: &S
+) -> Result<Route, LightningError>
 where L::Target: Logger {
 	let payee_node_id = NodeId::from_pubkey(&payment_params.payee_pubkey);
 	let our_node_id = NodeId::from_pubkey(&our_node_pubkey);
@@ -637,6 +1218,18 @@ where L::Target: Logger {
 		return Err(LightningError{err: "Cannot send a payment of 0 msat".to_owned(), action: ErrorAction::IgnoreError});
 	}
 
+	if payment_params.max_path_count == 0 {
+		return Err(LightningError{err: "Can't find a route with no paths allowed.".to_owned(), action: ErrorAction::IgnoreError});
+	}
+
+	if payment_params.max_path_length > DEFAULT_MAX_PATH_LENGTH {
+		return Err(LightningError{err: "Can't find a route with more hops than fit in an onion packet.".to_owned(), action: ErrorAction::IgnoreError});
+	}
+
+	if payment_params.route_capacity_provision_factor == 0 {
+		return Err(LightningError{err: "Can't find a route with a capacity provision factor of zero.".to_owned(), action: ErrorAction::IgnoreError});
+	}
+
 	for route in payment_params.route_hints.iter() {
 		for hop in &route.0 {
 			if hop.src_node_id == payment_params.payee_pubkey {
@@ -705,19 +1298,16 @@ where L::Target: Logger {
 	// to use as the A* heuristic beyond just the cost to get one node further than the current
 	// one.
 
-	let network_graph = network.read_only();
-	let network_channels = network_graph.channels();
-	let network_nodes = network_graph.nodes();
 
 	// Allow MPP only if we have a features set from somewhere that indicates the payee supports
 	// it. If the payee supports it they're supposed to include it in the invoice, so that should
 	// work reliably.
-	let allow_mpp = if let Some(features) = &payment_params.features {
+	let allow_mpp = if payment_params.max_path_count == 1 {
+		false
+	} else if let Some(features) = &payment_params.features {
+		features.supports_basic_mpp()
+	} else if let Some(features) = network_graph.node(&payee_node_id).and_then(|node| network_graph.node_features(node)) {
 		features.supports_basic_mpp()
-	} else if let Some(node) = network_nodes.get(&payee_node_id) {
-		if let Some(node_info) = node.announcement_info.as_ref() {
-			node_info.features.supports_basic_mpp()
-		} else { false }
 	} else { false };
 	log_trace!(logger, "Searching for a route from payer {} to payee {} {} MPP and {} first hops {}overriding the network graph", our_node_pubkey,
 		payment_params.payee_pubkey, if allow_mpp { "with" } else { "without" },
@@ -754,7 +1344,7 @@ where L::Target: Logger {
 
 	// Map from node_id to information about the best current path to that node, including feerate
 	// information.
-	let mut dist = HashMap::with_capacity(network_nodes.len());
+	let mut dist = HashMap::with_capacity(network_graph.node_count());
 
 	// During routing, if we ignore a path due to an htlc_minimum_msat limit, we set this,
 	// indicating that we may wish to try again with a higher value, potentially paying to meet an
@@ -763,17 +1353,18 @@ where L::Target: Logger {
 
 	// When arranging a route, we select multiple paths so that we can make a multi-path payment.
 	// We start with a path_value of the exact amount we want, and if that generates a route we may
-	// return it immediately. Otherwise, we don't stop searching for paths until we have 3x the
-	// amount we want in total across paths, selecting the best subset at the end.
-	const ROUTE_CAPACITY_PROVISION_FACTOR: u64 = 3;
-	let recommended_value_msat = final_value_msat * ROUTE_CAPACITY_PROVISION_FACTOR as u64;
+	// return it immediately. Otherwise, we don't stop searching for paths until we have
+	// `route_capacity_provision_factor` times the amount we want in total across paths, selecting
+	// the best subset at the end.
+	let recommended_value_msat =
+		final_value_msat.saturating_mul(payment_params.route_capacity_provision_factor as u64);
 	let mut path_value_msat = final_value_msat;
 
 	// We don't want multiple paths (as per MPP) share liquidity of the same channels.
 	// This map allows paths to be aware of the channel use by other paths in the same call.
 	// This would help to make a better path finding decisions and not "overbook" channels.
 	// It is unaware of the directions (except for `outbound_capacity_msat` in `first_hops`).
-	let mut bookkept_channels_liquidity_available_msat = HashMap::with_capacity(network_nodes.len());
+	let mut bookkept_channels_liquidity_available_msat = HashMap::with_capacity(network_graph.node_count());
 
 	// Keeping track of how much value we already collected across other paths. Helps to decide:
 	// - how much a new path should be transferring (upper bound);
@@ -782,6 +1373,29 @@ where L::Target: Logger {
 	// - when we want to stop looking for new paths.
 	let mut already_collected_value_msat = 0;
 
+	// Channels and nodes we were asked to avoid, and the most we may pay in fees for the route.
+	let excluded_short_channel_ids: HashSet<u64> = payment_params.excluded_short_channel_ids.iter().cloned().collect();
+	let excluded_node_ids: HashSet<NodeId> = payment_params.excluded_node_ids.iter()
+		.map(|pubkey| NodeId::from_pubkey(pubkey)).collect();
+	let filters_node_features = !payment_params.required_node_feature_bits.is_empty() ||
+		!payment_params.forbidden_node_feature_bits.is_empty();
+	let is_node_allowed = |node_id: &NodeId| -> bool {
+		if *node_id == our_node_id || *node_id == payee_node_id {
+			return true;
+		}
+		if excluded_node_ids.contains(node_id) {
+			return false;
+		}
+		if !filters_node_features {
+			return true;
+		}
+		match network_graph.node(node_id).and_then(|node| network_graph.node_features(node)) {
+			Some(features) => payment_params.allows_node_features(features),
+			None => payment_params.allows_node_features(&NodeFeatures::empty()),
+		}
+	};
+	let max_routing_fee_msat = payment_params.max_routing_fee_msat(final_value_msat);
+
 	log_trace!(logger, "Building path from {} (payee) to {} (us/payer) for value {} msat.", payment_params.payee_pubkey, our_node_pubkey, final_value_msat);
 
 	macro_rules! add_entry {
@@ -791,18 +1405,21 @@ where L::Target: Logger {
 		// Returns whether this channel caused an update to `targets`.
 		( $candidate: expr, $src_node_id: expr, $dest_node_id: expr, $next_hops_fee_msat: expr,
 		   $next_hops_value_contribution: expr, $next_hops_path_htlc_minimum_msat: expr,
-		   $next_hops_path_penalty_msat: expr, $next_hops_cltv_delta: expr ) => { {
+		   $next_hops_path_penalty_msat: expr, $next_hops_cltv_delta: expr, $next_hops_path_length: expr ) => { {
 			// We "return" whether we updated the path at the end, via this:
 			let mut did_add_update_path_to_src_node = false;
 			// Channels to self should not be used. This is more of belt-and-suspenders, because in
 			// practice these cases should be caught earlier:
 			// - for regular channels at channel announcement (TODO)
 			// - for first and last hops early in get_route
-			if $src_node_id != $dest_node_id {
+			// Also skip channels and nodes we were asked to avoid.
+			if $src_node_id != $dest_node_id && !excluded_short_channel_ids.contains(&$candidate.short_channel_id()) &&
+				is_node_allowed(&$src_node_id)
+			{
 				let short_channel_id = $candidate.short_channel_id();
 				let available_liquidity_msat = bookkept_channels_liquidity_available_msat
 					.entry(short_channel_id)
-					.or_insert_with(|| $candidate.effective_capacity().as_msat());
+					.or_insert_with(|| $candidate.available_capacity_msat(&$src_node_id, &$dest_node_id, inflight_htlcs));
 
 				// It is tricky to substract $next_hops_fee_msat from available liquidity here.
 				// It may be misleading because we might later choose to reduce the value transferred
@@ -811,7 +1428,10 @@ where L::Target: Logger {
 				// fees caused by one expensive channel, but then this channel could have been used
 				// if the amount being transferred over this path is lower.
 				// We do this for now, but this is a subject for removal.
-				if let Some(available_value_contribution_msat) = available_liquidity_msat.checked_sub($next_hops_fee_msat) {
+				// A single path may only use up to the configured share of a channel's capacity.
+				let max_path_liquidity_msat = cmp::min(*available_liquidity_msat,
+					$candidate.max_path_liquidity_msat(payment_params.max_channel_saturation_power_of_half));
+				if let Some(available_value_contribution_msat) = max_path_liquidity_msat.checked_sub($next_hops_fee_msat) {
 
 					// Routing Fragmentation Mitigation heuristic:
 					//
@@ -843,6 +1463,11 @@ where L::Target: Logger {
 						.unwrap_or(u32::max_value());
 					let doesnt_exceed_cltv_delta_limit = hop_total_cltv_delta <= max_total_cltv_expiry_delta;
 
+					// Do not consider candidates that exceed the maximum path length.
+					let path_length_to_node = ($next_hops_path_length as u8).saturating_add(1);
+					let doesnt_exceed_path_limits = doesnt_exceed_cltv_delta_limit &&
+						path_length_to_node <= payment_params.max_path_length;
+
 					let value_contribution_msat = cmp::min(available_value_contribution_msat, $next_hops_value_contribution);
 					// Includes paying fees for the use of the following channels.
 					let amount_to_transfer_over_msat: u64 = match value_contribution_msat.checked_add($next_hops_fee_msat) {
@@ -859,9 +1484,9 @@ where L::Target: Logger {
 					// Since we're choosing amount_to_transfer_over_msat as maximum possible, it can
 					// be only reduced later (not increased), so this channel should just be skipped
 					// as not sufficient.
-					if !over_path_minimum_msat && doesnt_exceed_cltv_delta_limit {
+					if !over_path_minimum_msat && doesnt_exceed_path_limits {
 						hit_minimum_limit = true;
-					} else if contributes_sufficient_value && doesnt_exceed_cltv_delta_limit {
+					} else if contributes_sufficient_value && doesnt_exceed_path_limits {
 						// Note that low contribution here (limited by available_liquidity_msat)
 						// might violate htlc_minimum_msat on the hops which are next along the
 						// payment path (upstream to the payee). To avoid that, we recompute
@@ -879,7 +1504,7 @@ where L::Target: Logger {
 							// as a way to reach the $dest_node_id.
 							let mut fee_base_msat = u32::max_value();
 							let mut fee_proportional_millionths = u32::max_value();
-							if let Some(Some(fees)) = network_nodes.get(&$src_node_id).map(|node| node.lowest_inbound_channel_fees) {
+							if let Some(Some(fees)) = network_graph.node(&$src_node_id).map(|node| network_graph.lowest_inbound_channel_fees(node)) {
 								fee_base_msat = fees.base_msat;
 								fee_proportional_millionths = fees.proportional_millionths;
 							}
@@ -947,14 +1572,16 @@ where L::Target: Logger {
 								}
 							}
 
+							let channel_usage = $candidate.usage(amount_to_transfer_over_msat, *available_liquidity_msat, hop_total_cltv_delta);
 							let path_penalty_msat = $next_hops_path_penalty_msat.checked_add(
-								scorer.channel_penalty_msat(short_channel_id, amount_to_transfer_over_msat, *available_liquidity_msat,
-									&$src_node_id, &$dest_node_id)).unwrap_or_else(|| u64::max_value());
+								scorer.channel_penalty_msat(short_channel_id, &$src_node_id, &$dest_node_id, channel_usage))
+								.unwrap_or_else(|| u64::max_value());
 							let new_graph_node = RouteGraphNode {
 								node_id: $src_node_id,
 								lowest_fee_to_peer_through_node: total_fee_msat,
 								lowest_fee_to_node: $next_hops_fee_msat as u64 + hop_use_fee_msat,
 								total_cltv_delta: hop_total_cltv_delta,
+								path_length_to_node,
 								value_contribution_msat: value_contribution_msat,
 								path_htlc_minimum_msat,
 								path_penalty_msat,
@@ -983,7 +1610,10 @@ where L::Target: Logger {
 								.checked_add(path_penalty_msat)
 								.unwrap_or_else(|| u64::max_value());
 
-							if !old_entry.was_processed && new_cost < old_cost {
+							// Don't bother with paths which alone already exceed our fee limit.
+							let exceeds_max_routing_fee = ($next_hops_fee_msat as u64).saturating_add(hop_use_fee_msat) > max_routing_fee_msat;
+
+							if !old_entry.was_processed && new_cost < old_cost && !exceeds_max_routing_fee {
 								targets.push(new_graph_node);
 								old_entry.next_hops_fee_msat = $next_hops_fee_msat;
 								old_entry.hop_use_fee_msat = hop_use_fee_msat;
@@ -998,7 +1628,7 @@ where L::Target: Logger {
 									old_entry.value_contribution_msat = value_contribution_msat;
 								}
 								did_add_update_path_to_src_node = true;
-							} else if old_entry.was_processed && new_cost < old_cost {
+							} else if old_entry.was_processed && new_cost < old_cost && !exceeds_max_routing_fee {
 								#[cfg(all(not(feature = "_bench_unstable"), any(test, fuzzing)))]
 								{
 									// If we're skipping processing a node which was previously
@@ -1042,7 +1672,7 @@ where L::Target: Logger {
 	// meaning how much will be paid in fees after this node (to the best of our knowledge).
 	// This data can later be helpful to optimize routing (pay lower fees).
 	macro_rules! add_entries_to_cheapest_to_target_node {
-		( $node: expr, $node_id: expr, $fee_to_target_msat: expr, $next_hops_value_contribution: expr, $next_hops_path_htlc_minimum_msat: expr, $next_hops_path_penalty_msat: expr, $next_hops_cltv_delta: expr ) => {
+		( $node: expr, $node_id: expr, $fee_to_target_msat: expr, $next_hops_value_contribution: expr, $next_hops_path_htlc_minimum_msat: expr, $next_hops_path_penalty_msat: expr, $next_hops_cltv_delta: expr, $next_hops_path_length: expr ) => {
 			let skip_node = if let Some(elem) = dist.get_mut(&$node_id) {
 				let was_processed = elem.was_processed;
 				elem.was_processed = true;
@@ -1059,19 +1689,14 @@ where L::Target: Logger {
 				if let Some(first_channels) = first_hop_targets.get(&$node_id) {
 					for details in first_channels {
 						let candidate = CandidateRouteHop::FirstHop { details };
-						add_entry!(candidate, our_node_id, $node_id, $fee_to_target_msat, $next_hops_value_contribution, $next_hops_path_htlc_minimum_msat, $next_hops_path_penalty_msat, $next_hops_cltv_delta);
+						add_entry!(candidate, our_node_id, $node_id, $fee_to_target_msat, $next_hops_value_contribution, $next_hops_path_htlc_minimum_msat, $next_hops_path_penalty_msat, $next_hops_cltv_delta, $next_hops_path_length);
 					}
 				}
 
-				let features = if let Some(node_info) = $node.announcement_info.as_ref() {
-					&node_info.features
-				} else {
-					&empty_node_features
-				};
+				let features = network_graph.node_features($node).unwrap_or(&empty_node_features);
 
 				if !features.requires_unknown_bits() {
-					for chan_id in $node.channels.iter() {
-						let chan = network_channels.get(chan_id).unwrap();
+					for (chan_id, chan) in network_graph.node_channels($node) {
 						if !chan.features.requires_unknown_bits() {
 							let (directed_channel, source) =
 								chan.as_directed_to(&$node_id).expect("inconsistent NetworkGraph");
@@ -1080,9 +1705,9 @@ where L::Target: Logger {
 									if direction.enabled {
 										let candidate = CandidateRouteHop::PublicHop {
 											info: directed_channel.with_update().unwrap(),
-											short_channel_id: *chan_id,
+											short_channel_id: chan_id,
 										};
-										add_entry!(candidate, *source, $node_id, $fee_to_target_msat, $next_hops_value_contribution, $next_hops_path_htlc_minimum_msat, $next_hops_path_penalty_msat, $next_hops_cltv_delta);
+										add_entry!(candidate, *source, $node_id, $fee_to_target_msat, $next_hops_value_contribution, $next_hops_path_htlc_minimum_msat, $next_hops_path_penalty_msat, $next_hops_cltv_delta, $next_hops_path_length);
 									}
 								}
 							}
@@ -1109,21 +1734,21 @@ where L::Target: Logger {
 		if let Some(first_channels) = first_hop_targets.get(&payee_node_id) {
 			for details in first_channels {
 				let candidate = CandidateRouteHop::FirstHop { details };
-				let added = add_entry!(candidate, our_node_id, payee_node_id, 0, path_value_msat, 0, 0u64, 0);
+				let added = add_entry!(candidate, our_node_id, payee_node_id, 0, path_value_msat, 0, 0u64, 0, 0);
 				log_trace!(logger, "{} direct route to payee via SCID {}", if added { "Added" } else { "Skipped" }, candidate.short_channel_id());
 			}
 		}
 
 		// Add the payee as a target, so that the payee-to-payer
 		// search algorithm knows what to start with.
-		match network_nodes.get(&payee_node_id) {
+		match network_graph.node(&payee_node_id) {
 			// The payee is not in our network graph, so nothing to add here.
 			// There is still a chance of reaching them via last_hops though,
 			// so don't yet fail the payment here.
 			// If not, targets.pop() will not even let us enter the loop in step 2.
 			None => {},
 			Some(node) => {
-				add_entries_to_cheapest_to_target_node!(node, payee_node_id, 0, path_value_msat, 0, 0u64, 0);
+				add_entries_to_cheapest_to_target_node!(node, payee_node_id, 0, path_value_msat, 0, 0u64, 0, 0);
 			},
 		}
 
@@ -1138,7 +1763,7 @@ where L::Target: Logger {
 				// we have a direct channel to the first hop or the first hop is
 				// in the regular network graph.
 				first_hop_targets.get(&NodeId::from_pubkey(&first_hop_in_route.src_node_id)).is_some() ||
-				network_nodes.get(&NodeId::from_pubkey(&first_hop_in_route.src_node_id)).is_some();
+				network_graph.node(&NodeId::from_pubkey(&first_hop_in_route.src_node_id)).is_some();
 			if have_hop_src_in_graph {
 				// We start building the path from reverse, i.e., from payee
 				// to the first RouteHintHop in the path.
@@ -1150,12 +1775,13 @@ where L::Target: Logger {
 				let mut aggregate_next_hops_path_htlc_minimum_msat: u64 = 0;
 				let mut aggregate_next_hops_path_penalty_msat: u64 = 0;
 				let mut aggregate_next_hops_cltv_delta: u32 = 0;
+				let mut aggregate_next_hops_path_length: u8 = 0;
 
 				for (idx, (hop, prev_hop_id)) in hop_iter.zip(prev_hop_iter).enumerate() {
 					let source = NodeId::from_pubkey(&hop.src_node_id);
 					let target = NodeId::from_pubkey(&prev_hop_id);
-					let candidate = network_channels
-						.get(&hop.short_channel_id)
+					let candidate = network_graph
+						.channel(hop.short_channel_id)
 						.and_then(|channel| channel.as_directed_to(&target))
 						.and_then(|(channel, _)| channel.with_update())
 						.map(|info| CandidateRouteHop::PublicHop {
@@ -1163,16 +1789,17 @@ where L::Target: Logger {
 							short_channel_id: hop.short_channel_id,
 						})
 						.unwrap_or_else(|| CandidateRouteHop::PrivateHop { hint: hop });
-					let capacity_msat = candidate.effective_capacity().as_msat();
-					aggregate_next_hops_path_penalty_msat = aggregate_next_hops_path_penalty_msat
-						.checked_add(scorer.channel_penalty_msat(hop.short_channel_id, final_value_msat, capacity_msat, &source, &target))
-						.unwrap_or_else(|| u64::max_value());
-
 					aggregate_next_hops_cltv_delta = aggregate_next_hops_cltv_delta
 						.checked_add(hop.cltv_expiry_delta as u32)
 						.unwrap_or_else(|| u32::max_value());
 
-					if !add_entry!(candidate, source, target, aggregate_next_hops_fee_msat, path_value_msat, aggregate_next_hops_path_htlc_minimum_msat, aggregate_next_hops_path_penalty_msat, aggregate_next_hops_cltv_delta) {
+					let capacity_msat = candidate.available_capacity_msat(&source, &target, inflight_htlcs);
+					let channel_usage = candidate.usage(final_value_msat, capacity_msat, aggregate_next_hops_cltv_delta);
+					aggregate_next_hops_path_penalty_msat = aggregate_next_hops_path_penalty_msat
+						.checked_add(scorer.channel_penalty_msat(hop.short_channel_id, &source, &target, channel_usage))
+						.unwrap_or_else(|| u64::max_value());
+
+					if !add_entry!(candidate, source, target, aggregate_next_hops_fee_msat, path_value_msat, aggregate_next_hops_path_htlc_minimum_msat, aggregate_next_hops_path_penalty_msat, aggregate_next_hops_cltv_delta, aggregate_next_hops_path_length) {
 						// If this hop was not used then there is no use checking the preceding hops
 						// in the RouteHint. We can break by just searching for a direct channel between
 						// last checked hop and first_hop_targets
@@ -1183,7 +1810,7 @@ where L::Target: Logger {
 					if let Some(first_channels) = first_hop_targets.get(&NodeId::from_pubkey(&prev_hop_id)) {
 						for details in first_channels {
 							let candidate = CandidateRouteHop::FirstHop { details };
-							add_entry!(candidate, our_node_id, NodeId::from_pubkey(&prev_hop_id), aggregate_next_hops_fee_msat, path_value_msat, aggregate_next_hops_path_htlc_minimum_msat, aggregate_next_hops_path_penalty_msat, aggregate_next_hops_cltv_delta);
+							add_entry!(candidate, our_node_id, NodeId::from_pubkey(&prev_hop_id), aggregate_next_hops_fee_msat, path_value_msat, aggregate_next_hops_path_htlc_minimum_msat, aggregate_next_hops_path_penalty_msat, aggregate_next_hops_cltv_delta, aggregate_next_hops_path_length);
 						}
 					}
 
@@ -1191,6 +1818,10 @@ where L::Target: Logger {
 						break;
 					}
 
+					aggregate_next_hops_path_length = aggregate_next_hops_path_length
+						.checked_add(1)
+						.unwrap_or_else(|| u8::max_value());
+
 					// In the next values of the iterator, the aggregate fees already reflects
 					// the sum of value sent from payer (final_value_msat) and routing fees
 					// for the last node in the RouteHint. We need to just add the fees to
@@ -1219,7 +1850,7 @@ where L::Target: Logger {
 						if let Some(first_channels) = first_hop_targets.get(&NodeId::from_pubkey(&hop.src_node_id)) {
 							for details in first_channels {
 								let candidate = CandidateRouteHop::FirstHop { details };
-								add_entry!(candidate, our_node_id, NodeId::from_pubkey(&hop.src_node_id), aggregate_next_hops_fee_msat, path_value_msat, aggregate_next_hops_path_htlc_minimum_msat, aggregate_next_hops_path_penalty_msat, aggregate_next_hops_cltv_delta);
+								add_entry!(candidate, our_node_id, NodeId::from_pubkey(&hop.src_node_id), aggregate_next_hops_fee_msat, path_value_msat, aggregate_next_hops_path_htlc_minimum_msat, aggregate_next_hops_path_penalty_msat, aggregate_next_hops_cltv_delta, aggregate_next_hops_path_length);
 							}
 						}
 					}
@@ -1242,7 +1873,7 @@ where L::Target: Logger {
 		// Both these cases (and other cases except reaching recommended_value_msat) mean that
 		// paths_collection will be stopped because found_new_path==false.
 		// This is not necessarily a routing failure.
-		'path_construction: while let Some(RouteGraphNode { node_id, lowest_fee_to_node, total_cltv_delta, value_contribution_msat, path_htlc_minimum_msat, path_penalty_msat, .. }) = targets.pop() {
+		'path_construction: while let Some(RouteGraphNode { node_id, lowest_fee_to_node, total_cltv_delta, path_length_to_node, value_contribution_msat, path_htlc_minimum_msat, path_penalty_msat, .. }) = targets.pop() {
 
 			// Since we're going payee-to-payer, hitting our node as a target means we should stop
 			// traversing the graph and arrange the path out of what we found.
@@ -1262,12 +1893,9 @@ where L::Target: Logger {
 						}
 					}
 					if !features_set {
-						if let Some(node) = network_nodes.get(&ordered_hops.last().unwrap().0.node_id) {
-							if let Some(node_info) = node.announcement_info.as_ref() {
-								ordered_hops.last_mut().unwrap().1 = node_info.features.clone();
-							} else {
-								ordered_hops.last_mut().unwrap().1 = NodeFeatures::empty();
-							}
+						if let Some(node) = network_graph.node(&ordered_hops.last().unwrap().0.node_id) {
+							ordered_hops.last_mut().unwrap().1 = network_graph.node_features(node)
+								.cloned().unwrap_or_else(NodeFeatures::empty);
 						} else {
 							// We should be able to fill in features for everything except the last
 							// hop, if the last hop was provided via a BOLT 11 invoice (though we
@@ -1363,10 +1991,10 @@ where L::Target: Logger {
 			// Otherwise, since the current target node is not us,
 			// keep "unrolling" the payment graph from payee to payer by
 			// finding a way to reach the current target from the payer side.
-			match network_nodes.get(&node_id) {
+			match network_graph.node(&node_id) {
 				None => {},
 				Some(node) => {
-					add_entries_to_cheapest_to_target_node!(node, node_id, lowest_fee_to_node, value_contribution_msat, path_htlc_minimum_msat, path_penalty_msat, total_cltv_delta);
+					add_entries_to_cheapest_to_target_node!(node, node_id, lowest_fee_to_node, value_contribution_msat, path_htlc_minimum_msat, path_penalty_msat, total_cltv_delta, path_length_to_node);
 				},
 			}
 		}
@@ -1428,6 +2056,9 @@ where L::Target: Logger {
 
 		// Step (7).
 		for payment_path in cur_payment_paths {
+			if cur_route.len() >= payment_params.max_path_count as usize {
+				break;
+			}
 			cur_route.push(payment_path.clone());
 			aggregate_route_value_msat += payment_path.get_value_msat();
 			if aggregate_route_value_msat > final_value_msat {
@@ -1479,12 +2110,24 @@ where L::Target: Logger {
 				break;
 			}
 		}
+		// If we ran out of paths we're allowed to use before collecting enough value, this
+		// combination of paths can't be used.
+		if aggregate_route_value_msat < final_value_msat {
+			continue;
+		}
 		drawn_routes.push(cur_route);
 	}
 
+	if drawn_routes.is_empty() {
+		return Err(LightningError{err: "Failed to find a sufficient route to the given destination within the maximum path count".to_owned(), action: ErrorAction::IgnoreError});
+	}
+
 	// Step (9).
-	// Select the best route by lowest total fee.
+	// Select the best route by lowest total fee, as long as it is within our fee limit.
 	drawn_routes.sort_by_key(|paths| paths.iter().map(|path| path.get_total_fee_paid_msat()).sum::<u64>());
+	if drawn_routes.first().unwrap().iter().map(|path| path.get_total_fee_paid_msat()).sum::<u64>() > max_routing_fee_msat {
+		return Err(LightningError{err: "Failed to find a route to the given destination within the maximum routing fee".to_owned(), action: ErrorAction::IgnoreError});
+	}
 	let mut selected_paths = Vec::<Vec<Result<RouteHop, LightningError>>>::new();
 	for payment_path in drawn_routes.first().unwrap() {
 		let mut path = payment_path.hops.iter().map(|(payment_hop, node_features)| {
@@ -1523,9 +2166,10 @@ where L::Target: Logger {
 
 #[cfg(test)]
 mod tests {
-	use routing::scoring::{ProbabilisticScorer, ProbabilisticScoringParameters, Score};
+	use routing::scoring::{ChannelUsage, ProbabilisticScorer, ProbabilisticScoringParameters, Score};
 	use routing::network_graph::{NetworkGraph, NetGraphMsgHandler, NodeId};
-	use routing::router::{get_route, PaymentParameters, Route, RouteHint, RouteHintHop, RouteHop, RoutingFees};
+	use routing::router::{build_route_from_hops, find_rebalance_route, find_route, find_route_from_snapshot, find_route_with_inflight_htlcs, get_route, InFlightHtlcs, PaymentParameters, Route,
+		RouteHint, RouteHintHop, RouteHop, RouteParameters, RoutingFees, DEFAULT_MAX_PATH_LENGTH};
 	use chain::transaction::OutPoint;
 	use ln::features::{ChannelFeatures, InitFeatures, InvoiceFeatures, NodeFeatures};
 	use ln::msgs::{ErrorAction, LightningError, OptionalField, UnsignedChannelAnnouncement, ChannelAnnouncement, RoutingMessageHandler,
@@ -2024,6 +2668,273 @@ mod tests {
 		assert_eq!(route.paths[0][1].channel_features.le_flags(), &id_to_feature_flags(4));
 	}
 
+	#[test]
+	fn avoids_liquidity_used_by_inflight_htlcs() {
+		let (secp_ctx, network_graph, _, _, logger) = build_graph();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let route_params = RouteParameters {
+			payment_params: PaymentParameters::from_node_id(nodes[2]),
+			final_value_msat: 100,
+			final_cltv_expiry_delta: 42,
+		};
+		let scorer = test_utils::TestScorer::with_penalty(0);
+
+		// Without any HTLCs in flight, the route to 2 goes via 1.
+		let route = find_route_with_inflight_htlcs(&our_id, &route_params, &network_graph, None, &InFlightHtlcs::new(), Arc::clone(&logger), &scorer).unwrap();
+		assert_eq!(route.paths[0].len(), 2);
+		assert_eq!(route.paths[0][0].short_channel_id, 2);
+		assert_eq!(route.paths[0][1].short_channel_id, 4);
+
+		// Account for an HTLC using all of the (unknown, thus assumed) capacity of channel 4.
+		let mut inflight_path = route.paths[0].clone();
+		inflight_path[1].fee_msat = 250_000_000;
+		let mut inflight_htlcs = InFlightHtlcs::new();
+		inflight_htlcs.process_path(&inflight_path, our_id);
+		let our_node_id = NodeId::from_pubkey(&our_id);
+		let node_1_id = NodeId::from_pubkey(&nodes[1]);
+		let node_2_id = NodeId::from_pubkey(&nodes[2]);
+		assert_eq!(inflight_htlcs.used_liquidity_msat(&our_node_id, &node_1_id, 2), Some(250_000_100));
+		assert_eq!(inflight_htlcs.used_liquidity_msat(&node_1_id, &node_2_id, 4), Some(250_000_000));
+		assert_eq!(inflight_htlcs.used_liquidity_msat(&node_2_id, &node_1_id, 4), None);
+
+		// The route now avoids the used liquidity and goes via 7 instead.
+		let route = find_route_with_inflight_htlcs(&our_id, &route_params, &network_graph, None, &inflight_htlcs, Arc::clone(&logger), &scorer).unwrap();
+		assert_eq!(route.paths[0].len(), 2);
+		assert_eq!(route.paths[0][0].short_channel_id, 12);
+		assert_eq!(route.paths[0][1].short_channel_id, 13);
+	}
+
+	#[test]
+	fn routes_from_graph_snapshot() {
+		let (secp_ctx, network_graph, net_graph_msg_handler, _, logger) = build_graph();
+		let (_, our_id, privkeys, nodes) = get_nodes(&secp_ctx);
+		let scorer = test_utils::TestScorer::with_penalty(0);
+		let route_params = RouteParameters {
+			payment_params: PaymentParameters::from_node_id(nodes[2]),
+			final_value_msat: 100,
+			final_cltv_expiry_delta: 42,
+		};
+
+		let snapshot = network_graph.snapshot();
+		let route = find_route_from_snapshot(&our_id, &route_params, &snapshot, None, &InFlightHtlcs::new(), Arc::clone(&logger), &scorer).unwrap();
+		assert!(route == find_route(&our_id, &route_params, &network_graph, None, Arc::clone(&logger), &scorer).unwrap());
+		assert_eq!(route.paths[0][0].short_channel_id, 2);
+		assert_eq!(route.paths[0][1].short_channel_id, 4);
+
+		// Routes to every other node, including via route hints, match those over the live graph.
+		for node in nodes.iter() {
+			let route_params = RouteParameters {
+				payment_params: PaymentParameters::from_node_id(*node).with_route_hints(last_hops(&nodes)),
+				final_value_msat: 100_000,
+				final_cltv_expiry_delta: 42,
+			};
+			let snapshot_route = find_route_from_snapshot(&our_id, &route_params, &snapshot, None, &InFlightHtlcs::new(), Arc::clone(&logger), &scorer);
+			let route = find_route(&our_id, &route_params, &network_graph, None, Arc::clone(&logger), &scorer);
+			match (snapshot_route, route) {
+				(Ok(snapshot_route), Ok(route)) => assert!(snapshot_route == route),
+				(Err(snapshot_err), Err(err)) => assert_eq!(snapshot_err.err, err.err),
+				_ => panic!("Routing over the snapshot and the live graph gave different results"),
+			}
+		}
+
+		// HTLCs in flight are accounted for as when routing over the live graph.
+		let mut inflight_path = route.paths[0].clone();
+		inflight_path[1].fee_msat = 250_000_000;
+		let mut inflight_htlcs = InFlightHtlcs::new();
+		inflight_htlcs.process_path(&inflight_path, our_id);
+		let inflight_route = find_route_from_snapshot(&our_id, &route_params, &snapshot, None, &inflight_htlcs, Arc::clone(&logger), &scorer).unwrap();
+		assert!(inflight_route == find_route_with_inflight_htlcs(&our_id, &route_params, &network_graph, None, &inflight_htlcs, Arc::clone(&logger), &scorer).unwrap());
+		assert_eq!(inflight_route.paths[0][0].short_channel_id, 12);
+		assert_eq!(inflight_route.paths[0][1].short_channel_id, 13);
+
+		// Disabling channel 4 in the graph doesn't affect the existing snapshot.
+		update_channel(&net_graph_msg_handler, &secp_ctx, &privkeys[1], UnsignedChannelUpdate {
+			chain_hash: genesis_block(Network::Testnet).header.block_hash(),
+			short_channel_id: 4,
+			timestamp: 2,
+			flags: 2, // to disable
+			cltv_expiry_delta: 0,
+			htlc_minimum_msat: 0,
+			htlc_maximum_msat: OptionalField::Absent,
+			fee_base_msat: 0,
+			fee_proportional_millionths: 0,
+			excess_data: Vec::new()
+		});
+		let route = find_route_from_snapshot(&our_id, &route_params, &snapshot, None, &InFlightHtlcs::new(), Arc::clone(&logger), &scorer).unwrap();
+		assert_eq!(route.paths[0][1].short_channel_id, 4);
+
+		let route = find_route_from_snapshot(&our_id, &route_params, &network_graph.snapshot(), None, &InFlightHtlcs::new(), Arc::clone(&logger), &scorer).unwrap();
+		assert_ne!(route.paths[0][1].short_channel_id, 4);
+	}
+
+	#[test]
+	fn builds_route_from_hops() {
+		let (secp_ctx, network_graph, _, _, logger) = build_graph();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let route_params = RouteParameters {
+			payment_params: PaymentParameters::from_node_id(nodes[2]),
+			final_value_msat: 100,
+			final_cltv_expiry_delta: 42,
+		};
+
+		// The cheapest route to 2 goes via 1, but we can still ask to go via 7.
+		let route = build_route_from_hops(&our_id, &[nodes[7], nodes[2]], &route_params, &network_graph, None, Arc::clone(&logger)).unwrap();
+		assert_eq!(route.paths.len(), 1);
+		assert_eq!(route.paths[0].len(), 2);
+
+		assert_eq!(route.paths[0][0].pubkey, nodes[7]);
+		assert_eq!(route.paths[0][0].short_channel_id, 12);
+		assert_eq!(route.paths[0][0].fee_msat, 200);
+		assert_eq!(route.paths[0][0].cltv_expiry_delta, (13 << 4) | 1);
+
+		assert_eq!(route.paths[0][1].pubkey, nodes[2]);
+		assert_eq!(route.paths[0][1].short_channel_id, 13);
+		assert_eq!(route.paths[0][1].fee_msat, 100);
+		assert_eq!(route.paths[0][1].cltv_expiry_delta, 42);
+		assert!(route.payment_params.unwrap() == route_params.payment_params);
+
+		// Our channel to 0 is disabled, so a route via 0 can't be built.
+		match build_route_from_hops(&our_id, &[nodes[0], nodes[2]], &route_params, &network_graph, None, Arc::clone(&logger)) {
+			Err(LightningError { err, .. }) => assert_eq!(err, "Failed to find a path to the given destination"),
+			Ok(_) => panic!("Expected error"),
+		}
+
+		match build_route_from_hops(&our_id, &[nodes[7]], &route_params, &network_graph, None, Arc::clone(&logger)) {
+			Err(LightningError { err, .. }) => assert_eq!(err, "The last hop must be the payee"),
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[test]
+	fn finds_rebalance_route() {
+		let (secp_ctx, network_graph, _, _, logger) = build_graph();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let scorer = test_utils::TestScorer::with_penalty(0);
+
+		// Move liquidity out of a channel with 1 and back in through a channel with 2.
+		let outbound_channel = get_channel_details(Some(2), nodes[1], InitFeatures::from_le_bytes(vec![0b11]), 100000);
+		let mut inbound_channel = get_channel_details(Some(42), nodes[2], InitFeatures::from_le_bytes(vec![0b11]), 0);
+		inbound_channel.inbound_capacity_msat = 100000;
+
+		match find_rebalance_route(&our_id, &outbound_channel, &inbound_channel, 100, &network_graph, Arc::clone(&logger), &scorer) {
+			Err(LightningError { err, .. }) => assert_eq!(err, "The inbound channel's counterparty has not announced its forwarding parameters"),
+			Ok(_) => panic!("Expected error"),
+		}
+
+		inbound_channel.counterparty.forwarding_info = Some(channelmanager::CounterpartyForwardingInfo {
+			fee_base_msat: 10,
+			fee_proportional_millionths: 0,
+			cltv_expiry_delta: 40,
+		});
+		let route = find_rebalance_route(&our_id, &outbound_channel, &inbound_channel, 100, &network_graph, Arc::clone(&logger), &scorer).unwrap();
+		assert_eq!(route.paths.len(), 1);
+		assert_eq!(route.paths[0].len(), 3);
+		assert!(route.payment_params.is_none());
+
+		assert_eq!(route.paths[0][0].pubkey, nodes[1]);
+		assert_eq!(route.paths[0][0].short_channel_id, 2);
+		assert_eq!(route.paths[0][0].fee_msat, 110);
+		assert_eq!(route.paths[0][0].cltv_expiry_delta, (4 << 4) | 1);
+
+		assert_eq!(route.paths[0][1].pubkey, nodes[2]);
+		assert_eq!(route.paths[0][1].short_channel_id, 4);
+		assert_eq!(route.paths[0][1].fee_msat, 10);
+		assert_eq!(route.paths[0][1].cltv_expiry_delta, 40);
+
+		assert_eq!(route.paths[0][2].pubkey, our_id);
+		assert_eq!(route.paths[0][2].short_channel_id, 42);
+		assert_eq!(route.paths[0][2].fee_msat, 100);
+		assert_eq!(route.paths[0][2].cltv_expiry_delta, channelmanager::MIN_FINAL_CLTV_EXPIRY);
+		assert_eq!(route.get_total_fees(), 120);
+
+		match find_rebalance_route(&our_id, &outbound_channel, &outbound_channel, 100, &network_graph, Arc::clone(&logger), &scorer) {
+			Err(LightningError { err, .. }) => assert_eq!(err, "Cannot rebalance a channel into itself"),
+			Ok(_) => panic!("Expected error"),
+		}
+
+		match find_rebalance_route(&our_id, &outbound_channel, &inbound_channel, 100001, &network_graph, Arc::clone(&logger), &scorer) {
+			Err(LightningError { err, .. }) => assert_eq!(err, "The inbound channel doesn't have enough inbound capacity"),
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[test]
+	fn avoids_excluded_channels_and_nodes() {
+		let (secp_ctx, network_graph, _, _, logger) = build_graph();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let scorer = test_utils::TestScorer::with_penalty(0);
+		let route_via = |payment_params: &PaymentParameters| {
+			get_route(&our_id, payment_params, &network_graph, None, 100, 42, Arc::clone(&logger), &scorer)
+				.map(|route| route.paths[0].iter().map(|hop| hop.short_channel_id).collect::<Vec<_>>())
+		};
+
+		// Without any exclusions, the route to 2 goes via 1.
+		let payment_params = PaymentParameters::from_node_id(nodes[2]);
+		assert_eq!(route_via(&payment_params).unwrap(), vec![2, 4]);
+
+		// Excluding either channel 4 or node 1 moves the route to go via 7.
+		let payment_params = PaymentParameters::from_node_id(nodes[2]).with_excluded_short_channel_ids(vec![4]);
+		assert_eq!(route_via(&payment_params).unwrap(), vec![12, 13]);
+		let payment_params = PaymentParameters::from_node_id(nodes[2]).with_excluded_node_ids(vec![nodes[1]]);
+		assert_eq!(route_via(&payment_params).unwrap(), vec![12, 13]);
+
+		// Excluding our own channel to 7 as well leaves no route.
+		let payment_params = PaymentParameters::from_node_id(nodes[2])
+			.with_excluded_node_ids(vec![nodes[1]]).with_excluded_short_channel_ids(vec![12]);
+		match route_via(&payment_params) {
+			Err(LightningError { err, .. }) => assert_eq!(err, "Failed to find a path to the given destination"),
+			Ok(_) => panic!("Expected error"),
+		}
+
+		// Excluding the payee itself has no effect.
+		let payment_params = PaymentParameters::from_node_id(nodes[2]).with_excluded_node_ids(vec![nodes[2]]);
+		assert_eq!(route_via(&payment_params).unwrap(), vec![2, 4]);
+	}
+
+	#[test]
+	fn filters_nodes_by_feature_bits() {
+		let (secp_ctx, network_graph, _, _, logger) = build_graph();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let scorer = test_utils::TestScorer::with_penalty(0);
+		let route_via = |payment_params: &PaymentParameters| {
+			get_route(&our_id, payment_params, &network_graph, None, 100, 42, Arc::clone(&logger), &scorer)
+				.map(|route| route.paths[0].iter().map(|hop| hop.short_channel_id).collect::<Vec<_>>())
+		};
+
+		// Node 1 announces feature bit 3 and node 7 feature bit 7.
+		let payment_params = PaymentParameters::from_node_id(nodes[2]).with_required_node_feature_bits(vec![3]);
+		assert_eq!(route_via(&payment_params).unwrap(), vec![2, 4]);
+		let payment_params = PaymentParameters::from_node_id(nodes[2]).with_forbidden_node_feature_bits(vec![3]);
+		assert_eq!(route_via(&payment_params).unwrap(), vec![12, 13]);
+		let payment_params = PaymentParameters::from_node_id(nodes[2]).with_required_node_feature_bits(vec![7]);
+		assert_eq!(route_via(&payment_params).unwrap(), vec![12, 13]);
+
+		let payment_params = PaymentParameters::from_node_id(nodes[2])
+			.with_required_node_feature_bits(vec![7]).with_forbidden_node_feature_bits(vec![7]);
+		assert!(route_via(&payment_params).is_err());
+	}
+
+	#[test]
+	fn limits_routing_fee() {
+		let (secp_ctx, network_graph, _, _, logger) = build_graph();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let scorer = test_utils::TestScorer::with_penalty(0);
+
+		// The cheapest route to 2 goes via 1, charging a 100% fee.
+		let payment_params = PaymentParameters::from_node_id(nodes[2]).with_max_total_routing_fee_msat(100);
+		let route = get_route(&our_id, &payment_params, &network_graph, None, 100, 42, Arc::clone(&logger), &scorer).unwrap();
+		assert_eq!(route.get_total_fees(), 100);
+		let payment_params = PaymentParameters::from_node_id(nodes[2]).with_max_routing_fee_proportional_millionths(1_000_000);
+		let route = get_route(&our_id, &payment_params, &network_graph, None, 100, 42, Arc::clone(&logger), &scorer).unwrap();
+		assert_eq!(route.get_total_fees(), 100);
+
+		// Any lower limit leaves no route.
+		let payment_params = PaymentParameters::from_node_id(nodes[2]).with_max_total_routing_fee_msat(99);
+		assert!(get_route(&our_id, &payment_params, &network_graph, None, 100, 42, Arc::clone(&logger), &scorer).is_err());
+		let payment_params = PaymentParameters::from_node_id(nodes[2]).with_max_routing_fee_proportional_millionths(999_999);
+		assert!(get_route(&our_id, &payment_params, &network_graph, None, 100, 42, Arc::clone(&logger), &scorer).is_err());
+	}
+
 	#[test]
 	fn invalid_first_hop_test() {
 		let (secp_ctx, network_graph, _, _, logger) = build_graph();
@@ -3614,6 +4525,25 @@ mod tests {
 			}
 			assert_eq!(total_amount_paid_msat, 290_000);
 		}
+
+		{
+			// No two of the paths can carry 250 sats, so limiting the route to two paths fails.
+			let payment_params = payment_params.clone().with_max_path_count(2);
+			if let Err(LightningError{err, action: ErrorAction::IgnoreError}) = get_route(
+					&our_id, &payment_params, &network_graph, None, 250_000, 42, Arc::clone(&logger), &scorer) {
+				assert_eq!(err, "Failed to find a sufficient route to the given destination within the maximum path count");
+			} else { panic!(); }
+		}
+
+		{
+			// Limiting the route to a single path disables MPP, leaving only the path via node1.
+			let payment_params = payment_params.clone().with_max_path_count(1);
+			let route = get_route(&our_id, &payment_params, &network_graph, None, 150_000, 42, Arc::clone(&logger), &scorer).unwrap();
+			assert_eq!(route.paths.len(), 1);
+			assert_eq!(route.paths[0][0].short_channel_id, 2);
+			assert_eq!(route.paths[0][1].short_channel_id, 4);
+			assert_eq!(route.payment_params.unwrap().max_path_count, 1);
+		}
 	}
 
 	#[test]
@@ -4706,7 +5636,7 @@ mod tests {
 		fn write<W: Writer>(&self, _w: &mut W) -> Result<(), ::io::Error> { unimplemented!() }
 	}
 	impl Score for BadChannelScorer {
-		fn channel_penalty_msat(&self, short_channel_id: u64, _send_amt: u64, _capacity_msat: u64, _source: &NodeId, _target: &NodeId) -> u64 {
+		fn channel_penalty_msat(&self, short_channel_id: u64, _source: &NodeId, _target: &NodeId, _usage: ChannelUsage) -> u64 {
 			if short_channel_id == self.short_channel_id { u64::max_value() } else { 0 }
 		}
 
@@ -4724,7 +5654,7 @@ mod tests {
 	}
 
 	impl Score for BadNodeScorer {
-		fn channel_penalty_msat(&self, _short_channel_id: u64, _send_amt: u64, _capacity_msat: u64, _source: &NodeId, target: &NodeId) -> u64 {
+		fn channel_penalty_msat(&self, _short_channel_id: u64, _source: &NodeId, target: &NodeId, _usage: ChannelUsage) -> u64 {
 			if *target == self.node_id { u64::max_value() } else { 0 }
 		}
 
@@ -4874,6 +5804,164 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn limits_path_length() {
+		let (secp_ctx, network_graph, _, _, logger) = build_graph();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+
+		let scorer = test_utils::TestScorer::with_penalty(0);
+
+		// Every path to node2 takes two hops.
+		let payment_params = PaymentParameters::from_node_id(nodes[2]).with_max_path_length(2);
+		let route = get_route(&our_id, &payment_params, &network_graph, None, 100, 42, Arc::clone(&logger), &scorer).unwrap();
+		assert_eq!(route.paths.len(), 1);
+		assert_eq!(route.paths[0].len(), 2);
+
+		let payment_params = PaymentParameters::from_node_id(nodes[2]).with_max_path_length(1);
+		match get_route(&our_id, &payment_params, &network_graph, None, 100, 42, Arc::clone(&logger), &scorer) {
+			Err(LightningError { err, .. } ) => {
+				assert_eq!(err, "Failed to find a path to the given destination");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+
+		// Paths longer than an onion packet can hold are never allowed.
+		let payment_params = PaymentParameters::from_node_id(nodes[2]).with_max_path_length(DEFAULT_MAX_PATH_LENGTH + 1);
+		match get_route(&our_id, &payment_params, &network_graph, None, 100, 42, Arc::clone(&logger), &scorer) {
+			Err(LightningError { err, .. } ) => {
+				assert_eq!(err, "Can't find a route with more hops than fit in an onion packet.");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[test]
+	fn limits_channel_saturation_by_default() {
+		// By default, a single path may only use a quarter of a channel's total capacity, if known,
+		// splitting payments which would previously have been sent over one path.
+		let (secp_ctx, network_graph, mut net_graph_msg_handler, chain_monitor, logger) = build_graph();
+		let (our_privkey, our_id, privkeys, nodes) = get_nodes(&secp_ctx);
+		let scorer = test_utils::TestScorer::with_penalty(0);
+		let payment_params = PaymentParameters::from_node_id(nodes[2]).with_features(InvoiceFeatures::known());
+		assert_eq!(payment_params.max_channel_saturation_power_of_half, 2);
+
+		// Disable the existing paths to node2 and replace them with channels via node0 and node1,
+		// both of which have a capacity of 1_000 sats.
+		update_channel(&net_graph_msg_handler, &secp_ctx, &our_privkey, UnsignedChannelUpdate {
+			chain_hash: genesis_block(Network::Testnet).header.block_hash(),
+			short_channel_id: 1,
+			timestamp: 2,
+			flags: 0,
+			cltv_expiry_delta: 0,
+			htlc_minimum_msat: 0,
+			htlc_maximum_msat: OptionalField::Absent,
+			fee_base_msat: 0,
+			fee_proportional_millionths: 0,
+			excess_data: Vec::new()
+		});
+		update_channel(&net_graph_msg_handler, &secp_ctx, &our_privkey, UnsignedChannelUpdate {
+			chain_hash: genesis_block(Network::Testnet).header.block_hash(),
+			short_channel_id: 12,
+			timestamp: 2,
+			flags: 2,
+			cltv_expiry_delta: 0,
+			htlc_minimum_msat: 0,
+			htlc_maximum_msat: OptionalField::Absent,
+			fee_base_msat: 0,
+			fee_proportional_millionths: 0,
+			excess_data: Vec::new()
+		});
+		update_channel(&net_graph_msg_handler, &secp_ctx, &privkeys[0], UnsignedChannelUpdate {
+			chain_hash: genesis_block(Network::Testnet).header.block_hash(),
+			short_channel_id: 3,
+			timestamp: 2,
+			flags: 2,
+			cltv_expiry_delta: 0,
+			htlc_minimum_msat: 0,
+			htlc_maximum_msat: OptionalField::Absent,
+			fee_base_msat: 0,
+			fee_proportional_millionths: 0,
+			excess_data: Vec::new()
+		});
+		update_channel(&net_graph_msg_handler, &secp_ctx, &privkeys[1], UnsignedChannelUpdate {
+			chain_hash: genesis_block(Network::Testnet).header.block_hash(),
+			short_channel_id: 4,
+			timestamp: 2,
+			flags: 2,
+			cltv_expiry_delta: 0,
+			htlc_minimum_msat: 0,
+			htlc_maximum_msat: OptionalField::Absent,
+			fee_base_msat: 0,
+			fee_proportional_millionths: 0,
+			excess_data: Vec::new()
+		});
+
+		net_graph_msg_handler.add_chain_access(Some(chain_monitor.clone()));
+		for (privkey, short_channel_id) in [(&privkeys[0], 333), (&privkeys[1], 444)].iter() {
+			let good_script = Builder::new().push_opcode(opcodes::all::OP_PUSHNUM_2)
+			.push_slice(&PublicKey::from_secret_key(&secp_ctx, privkey).serialize())
+			.push_slice(&PublicKey::from_secret_key(&secp_ctx, &privkeys[2]).serialize())
+			.push_opcode(opcodes::all::OP_PUSHNUM_2)
+			.push_opcode(opcodes::all::OP_CHECKMULTISIG).into_script().to_v0_p2wsh();
+			*chain_monitor.utxo_ret.lock().unwrap() = Ok(TxOut { value: 1_000, script_pubkey: good_script });
+
+			add_channel(&net_graph_msg_handler, &secp_ctx, privkey, &privkeys[2], ChannelFeatures::from_le_bytes(id_to_feature_flags(3)), *short_channel_id);
+			update_channel(&net_graph_msg_handler, &secp_ctx, privkey, UnsignedChannelUpdate {
+				chain_hash: genesis_block(Network::Testnet).header.block_hash(),
+				short_channel_id: *short_channel_id,
+				timestamp: 1,
+				flags: 0,
+				cltv_expiry_delta: 0,
+				htlc_minimum_msat: 0,
+				htlc_maximum_msat: OptionalField::Absent,
+				fee_base_msat: 0,
+				fee_proportional_millionths: 0,
+				excess_data: Vec::new()
+			});
+		}
+
+		// Without the limit, the whole payment fits in either channel.
+		let unsaturated_params = payment_params.clone().with_max_channel_saturation_power_of_half(0);
+		let route = get_route(&our_id, &unsaturated_params, &network_graph, None, 400_000, 42, Arc::clone(&logger), &scorer).unwrap();
+		assert_eq!(route.paths.len(), 1);
+		assert_eq!(route.get_total_amount(), 400_000);
+
+		// By default, only a quarter of it may be used, with the rest sent via the other channel.
+		let route = get_route(&our_id, &payment_params, &network_graph, None, 400_000, 42, Arc::clone(&logger), &scorer).unwrap();
+		assert_eq!(route.paths.len(), 2);
+		for path in route.paths.iter() {
+			assert!(path.last().unwrap().fee_msat <= 250_000);
+		}
+		assert_eq!(route.get_total_amount(), 400_000);
+		assert_eq!(route.payment_params, Some(payment_params.clone()));
+
+		// If the limit leaves us without enough value, we fall back to saturating channels but still
+		// hand back the original parameters.
+		let route = get_route(&our_id, &payment_params, &network_graph, None, 1_500_000, 42, Arc::clone(&logger), &scorer).unwrap();
+		assert_eq!(route.paths.len(), 2);
+		assert_eq!(route.get_total_amount(), 1_500_000);
+		assert_eq!(route.payment_params, Some(payment_params));
+	}
+
+	#[test]
+	fn limits_route_capacity_provision_factor() {
+		let (secp_ctx, network_graph, _, _, logger) = build_graph();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let scorer = test_utils::TestScorer::with_penalty(0);
+
+		let payment_params = PaymentParameters::from_node_id(nodes[2]).with_route_capacity_provision_factor(1);
+		let route = get_route(&our_id, &payment_params, &network_graph, None, 100, 42, Arc::clone(&logger), &scorer).unwrap();
+		assert_eq!(route.get_total_amount(), 100);
+
+		let payment_params = PaymentParameters::from_node_id(nodes[2]).with_route_capacity_provision_factor(0);
+		match get_route(&our_id, &payment_params, &network_graph, None, 100, 42, Arc::clone(&logger), &scorer) {
+			Err(LightningError { err, .. } ) => {
+				assert_eq!(err, "Can't find a route with a capacity provision factor of zero.");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
 	#[cfg(not(feature = "no-std"))]
 	pub(super) fn random_init_seed() -> u64 {
 		// Because the default HashMap in std pulls OS randomness, we can use it as a (bad) RNG.
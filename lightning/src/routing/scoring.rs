@@ -53,7 +53,7 @@
 //! [`find_route`]: crate::routing::router::find_route
 
 use ln::msgs::DecodeError;
-use routing::network_graph::{NetworkGraph, NodeId};
+use routing::network_graph::{NetworkGraph, NodeId, RoutingFees};
 use routing::router::RouteHop;
 use util::ser::{Readable, ReadableArgs, Writeable, Writer};
 
@@ -80,15 +80,15 @@ macro_rules! define_score { ($($supertrait: path)*) => {
 ///
 ///	Scoring is in terms of fees willing to be paid in order to avoid routing through a channel.
 pub trait Score $(: $supertrait)* {
-	/// Returns the fee in msats willing to be paid to avoid routing `send_amt_msat` through the
-	/// given channel in the direction from `source` to `target`.
+	/// Returns the fee in msats willing to be paid to avoid routing [`ChannelUsage::amount_msat`]
+	/// through the given channel in the direction from `source` to `target`.
 	///
-	/// The channel's capacity (less any other MPP parts that are also being considered for use in
-	/// the same payment) is given by `capacity_msat`. It may be determined from various sources
-	/// such as a chain data, network gossip, or invoice hints. For invoice hints, a capacity near
-	/// [`u64::max_value`] is given to indicate sufficient capacity for the invoice's full amount.
-	/// Thus, implementations should be overflow-safe.
-	fn channel_penalty_msat(&self, short_channel_id: u64, send_amt_msat: u64, capacity_msat: u64, source: &NodeId, target: &NodeId) -> u64;
+	/// The channel's capacity and what is known about its forwarding parameters in that direction
+	/// are given by `usage`. Capacities may be determined from various sources such as chain data,
+	/// network gossip, or invoice hints. For invoice hints, a capacity near [`u64::max_value`] is
+	/// given to indicate sufficient capacity for the invoice's full amount. Thus, implementations
+	/// should be overflow-safe.
+	fn channel_penalty_msat(&self, short_channel_id: u64, source: &NodeId, target: &NodeId, usage: ChannelUsage) -> u64;
 
 	/// Handles updating channel penalties after failing to route through a channel.
 	fn payment_path_failed(&mut self, path: &[&RouteHop], short_channel_id: u64);
@@ -98,8 +98,8 @@ pub trait Score $(: $supertrait)* {
 }
 
 impl<S: Score, T: DerefMut<Target=S> $(+ $supertrait)*> Score for T {
-	fn channel_penalty_msat(&self, short_channel_id: u64, send_amt_msat: u64, capacity_msat: u64, source: &NodeId, target: &NodeId) -> u64 {
-		self.deref().channel_penalty_msat(short_channel_id, send_amt_msat, capacity_msat, source, target)
+	fn channel_penalty_msat(&self, short_channel_id: u64, source: &NodeId, target: &NodeId, usage: ChannelUsage) -> u64 {
+		self.deref().channel_penalty_msat(short_channel_id, source, target, usage)
 	}
 
 	fn payment_path_failed(&mut self, path: &[&RouteHop], short_channel_id: u64) {
@@ -117,6 +117,41 @@ define_score!(Writeable);
 #[cfg(not(c_bindings))]
 define_score!();
 
+/// Information about a channel considered for use along a path, as given to
+/// [`Score::channel_penalty_msat`].
+///
+/// Describes the channel in the direction it would be used. For our own channels and channels only
+/// known from invoice hints, values which are unknown are given as the least restrictive values.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelUsage {
+	/// The amount to send through the channel, denominated in millisatoshis.
+	pub amount_msat: u64,
+
+	/// The channel's capacity, less any other MPP parts that are also being considered for use in
+	/// the same payment and any of our HTLCs known to be in flight over it (see
+	/// [`InFlightHtlcs`]), denominated in millisatoshis.
+	///
+	/// [`InFlightHtlcs`]: crate::routing::router::InFlightHtlcs
+	pub capacity_msat: u64,
+
+	/// The minimum value of an HTLC the channel will relay, denominated in millisatoshis.
+	pub htlc_minimum_msat: u64,
+
+	/// The maximum value of an HTLC the channel will relay, denominated in millisatoshis, if
+	/// advertised.
+	pub htlc_maximum_msat: Option<u64>,
+
+	/// The fees charged to relay over the channel.
+	pub fees: RoutingFees,
+
+	/// The CLTV expiry delta required when relaying over the channel, in blocks.
+	pub cltv_expiry_delta: u16,
+
+	/// The sum of the CLTV expiry deltas of this channel and of every channel after it on the path
+	/// to the payee, excluding the final CLTV expiry delta, in blocks.
+	pub path_cltv_expiry_delta: u32,
+}
+
 /// A scorer that is accessed under a lock.
 ///
 /// Needed so that calls to [`Score::channel_penalty_msat`] in [`find_route`] can be made while
@@ -150,6 +185,17 @@ impl<'a, T: 'a + Score> LockableScore<'a> for RefCell<T> {
 	}
 }
 
+/// Refers to a scorer that is accessible under lock and also writeable to disk.
+///
+/// This allows a scorer shared with [`find_route`] to be handed to a [`Persister`] which may
+/// write it out periodically.
+///
+/// [`find_route`]: crate::routing::router::find_route
+/// [`Persister`]: crate::util::persist::Persister
+pub trait WriteableScore<'a>: LockableScore<'a> + Writeable {}
+
+impl<'a, T> WriteableScore<'a> for T where T: LockableScore<'a> + Writeable {}
+
 #[cfg(c_bindings)]
 /// A concrete implementation of [`LockableScore`] which supports multi-threading.
 pub struct MultiThreadedLockableScore<S: Score> {
@@ -165,6 +211,13 @@ impl<'a, T: Score + 'a> LockableScore<'a> for MultiThreadedLockableScore<T> {
 	}
 }
 
+#[cfg(c_bindings)]
+impl<T: Score + Writeable> Writeable for MultiThreadedLockableScore<T> {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+		self.score.lock().unwrap().write(writer)
+	}
+}
+
 #[cfg(c_bindings)]
 impl<T: Score> MultiThreadedLockableScore<T> {
 	/// Creates a new [`MultiThreadedLockableScore`] given an underlying [`Score`].
@@ -206,7 +259,7 @@ impl FixedPenaltyScorer {
 }
 
 impl Score for FixedPenaltyScorer {
-	fn channel_penalty_msat(&self, _: u64, _: u64, _: u64, _: &NodeId, _: &NodeId) -> u64 {
+	fn channel_penalty_msat(&self, _: u64, _: &NodeId, _: &NodeId, _: ChannelUsage) -> u64 {
 		self.penalty_msat
 	}
 
@@ -379,14 +432,14 @@ impl Default for ScoringParameters {
 
 impl<T: Time> Score for ScorerUsingTime<T> {
 	fn channel_penalty_msat(
-		&self, short_channel_id: u64, send_amt_msat: u64, capacity_msat: u64, _source: &NodeId, _target: &NodeId
+		&self, short_channel_id: u64, _source: &NodeId, _target: &NodeId, usage: ChannelUsage
 	) -> u64 {
 		let failure_penalty_msat = self.channel_failures
 			.get(&short_channel_id)
 			.map_or(0, |value| value.decayed_penalty_msat(self.params.failure_penalty_half_life));
 
 		let mut penalty_msat = self.params.base_penalty_msat + failure_penalty_msat;
-		let send_1024ths = send_amt_msat.checked_mul(1024).unwrap_or(u64::max_value()) / capacity_msat;
+		let send_1024ths = usage.amount_msat.checked_mul(1024).unwrap_or(u64::max_value()) / usage.capacity_msat;
 		if send_1024ths > self.params.overuse_penalty_start_1024th as u64 {
 			penalty_msat = penalty_msat.checked_add(
 					(send_1024ths - self.params.overuse_penalty_start_1024th as u64)
@@ -482,6 +535,11 @@ impl<T: Time> Readable for ChannelFailure<T> {
 /// with high payment volume or that actively probe the [`NetworkGraph`]. Nodes with low payment
 /// volume are more likely to experience failed payment paths, which would need to be retried.
 ///
+/// Additionally, the scorer may keep a longer-lived history of where the liquidity bounds of each
+/// channel have been observed, allowing knowledge to be retained well after the bounds themselves
+/// have decayed. See [`ProbabilisticScoringParameters::historical_liquidity_penalty_multiplier_msat`]
+/// for details.
+///
 /// # Note
 ///
 /// Mixing the `no-std` feature between serialization and deserialization results in undefined
@@ -533,13 +591,183 @@ pub struct ProbabilisticScoringParameters {
 	/// When built with the `no-std` feature, time will never elapse. Therefore, the channel
 	/// liquidity knowledge will never decay except when the bounds cross.
 	pub liquidity_offset_half_life: Duration,
+
+	/// A multiplier used in conjunction with the negative `log10` of the channel's success
+	/// probability for a payment, as determined from the history of the channel's liquidity
+	/// bounds, to determine the amount in msats willing to be paid to avoid routing through the
+	/// channel.
+	///
+	/// Each time a payment or probe succeeds or fails through a channel, the position of the
+	/// resulting liquidity bounds is recorded in one of eight buckets, each covering an eighth of
+	/// the channel's capacity. Older datapoints are given gradually less weight as new ones are
+	/// recorded. The success probability is then the average over all recorded combinations of
+	/// lower and upper bounds, weighted by how often each was observed. Unlike the bounds used by
+	/// [`liquidity_penalty_multiplier_msat`], this history is only forgotten once no new datapoints
+	/// have been recorded for a while, see [`historical_no_updates_half_life`].
+	///
+	/// If no history has been recorded for a channel, the success probability used by
+	/// [`liquidity_penalty_multiplier_msat`] is used instead.
+	///
+	/// Default value: 0 msat (i.e., the historical penalty is disabled)
+	///
+	/// [`liquidity_penalty_multiplier_msat`]: Self::liquidity_penalty_multiplier_msat
+	/// [`historical_no_updates_half_life`]: Self::historical_no_updates_half_life
+	pub historical_liquidity_penalty_multiplier_msat: u64,
+
+	/// The time required to elapse without any new datapoints being recorded for a channel before
+	/// its liquidity history, as used by [`historical_liquidity_penalty_multiplier_msat`], is cut
+	/// in half.
+	///
+	/// Default value: 14 days
+	///
+	/// # Note
+	///
+	/// When built with the `no-std` feature, time will never elapse. Therefore, the channel
+	/// liquidity history will only decay as new datapoints are recorded.
+	///
+	/// [`historical_liquidity_penalty_multiplier_msat`]: Self::historical_liquidity_penalty_multiplier_msat
+	pub historical_no_updates_half_life: Duration,
+
+	/// A multiplier used in conjunction with a channel's CLTV expiry delta and the amount sent
+	/// through it to determine the amount in msats willing to be paid to avoid routing through the
+	/// channel, accounting for the time value of funds which would be locked up should the payment
+	/// get stuck.
+	///
+	/// The penalty is the multiplier times the channel's [`ChannelUsage::cltv_expiry_delta`] in
+	/// blocks, times the amount sent in units of 1,000,000 msat (1,000 sat). As the penalties of
+	/// each channel along a path are added up, only the channel's own delta is used rather than
+	/// the [`ChannelUsage::path_cltv_expiry_delta`].
+	///
+	/// Default value: 0 msat (i.e., the CLTV expiry delta is not penalized)
+	pub cltv_expiry_delta_penalty_multiplier_msat: u64,
+
+	/// A penalty in msats applied when sending a channel's full advertised `htlc_maximum_msat`
+	/// through it, scaled down linearly for smaller amounts.
+	///
+	/// Nodes commonly lower the maximum HTLC value of a channel to reflect the liquidity they have
+	/// available, so amounts close to it are less likely to succeed. Channels which don't
+	/// advertise a maximum HTLC value are not penalized.
+	///
+	/// Default value: 0 msat (i.e., the maximum HTLC value is not penalized)
+	pub htlc_maximum_penalty_msat: u64,
+
+	/// A penalty in msats applied when sending exactly a channel's advertised `htlc_minimum_msat`
+	/// through it, scaled down proportionally as the amount grows larger than the minimum.
+	///
+	/// Channels with minimum HTLC values close to the amount being sent are more likely to reject
+	/// the HTLC should the amount be adjusted, e.g., when retrying part of a multi-path payment.
+	///
+	/// Default value: 0 msat (i.e., the minimum HTLC value is not penalized)
+	pub htlc_minimum_penalty_msat: u64,
 }
 
 impl_writeable_tlv_based!(ProbabilisticScoringParameters, {
 	(0, liquidity_penalty_multiplier_msat, required),
+	(1, cltv_expiry_delta_penalty_multiplier_msat, (default_value, 0)),
 	(2, liquidity_offset_half_life, required),
+	(3, htlc_maximum_penalty_msat, (default_value, 0)),
+	(4, historical_liquidity_penalty_multiplier_msat, (default_value, 0)),
+	(5, htlc_minimum_penalty_msat, (default_value, 0)),
+	(6, historical_no_updates_half_life, (default_value, Duration::from_secs(60 * 60 * 24 * 14))),
 });
 
+impl ProbabilisticScoringParameters {
+	/// Returns the penalty for the funds locked up by the channel's CLTV expiry delta.
+	fn cltv_expiry_delta_penalty_msat(&self, usage: &ChannelUsage) -> u64 {
+		let penalty_msat = self.cltv_expiry_delta_penalty_multiplier_msat as u128
+			* usage.cltv_expiry_delta as u128 * usage.amount_msat as u128 / 1_000_000;
+		core::cmp::min(penalty_msat, u64::max_value() as u128) as u64
+	}
+
+	/// Returns the penalty for sending an amount close to the channel's HTLC limits.
+	fn htlc_limits_penalty_msat(&self, usage: &ChannelUsage) -> u64 {
+		let amount_msat = usage.amount_msat;
+		let htlc_maximum_penalty_msat = match usage.htlc_maximum_msat {
+			Some(htlc_maximum_msat) if htlc_maximum_msat > 0 => {
+				let amount_msat = core::cmp::min(amount_msat, htlc_maximum_msat);
+				(self.htlc_maximum_penalty_msat as u128 * amount_msat as u128 / htlc_maximum_msat as u128) as u64
+			},
+			_ => 0,
+		};
+		let htlc_minimum_penalty_msat = if amount_msat > 0 {
+			let htlc_minimum_msat = core::cmp::min(usage.htlc_minimum_msat, amount_msat);
+			(self.htlc_minimum_penalty_msat as u128 * htlc_minimum_msat as u128 / amount_msat as u128) as u64
+		} else {
+			0
+		};
+		htlc_maximum_penalty_msat.saturating_add(htlc_minimum_penalty_msat)
+	}
+}
+
+/// The number of buckets tracked by a [`HistoricalBucketRangeTracker`].
+const LIQUIDITY_HISTORY_BUCKETS: usize = 8;
+
+/// A decaying histogram of the positions a liquidity bound has been observed in, relative to the
+/// channel's capacity.
+///
+/// Each bucket is a fixed-point number with a 5-bit fractional part. Recording a datapoint first
+/// decays all buckets by a factor of 2047/2048 and then adds 1.0 (i.e., 32) to the bucket the bound
+/// falls in, so that older datapoints are given gradually less weight than newer ones while still
+/// fitting in 16 bits.
+#[derive(Clone, Copy)]
+struct HistoricalBucketRangeTracker {
+	buckets: [u16; LIQUIDITY_HISTORY_BUCKETS],
+}
+
+impl HistoricalBucketRangeTracker {
+	fn new() -> Self {
+		Self { buckets: [0; LIQUIDITY_HISTORY_BUCKETS] }
+	}
+
+	/// Records the bound at `offset_msat` from its origin for a channel of `capacity_msat`.
+	fn track_datapoint(&mut self, offset_msat: u64, capacity_msat: u64) {
+		if capacity_msat == 0 {
+			return;
+		}
+		let bucket = offset_msat as u128 * LIQUIDITY_HISTORY_BUCKETS as u128 / capacity_msat as u128;
+		let bucket = core::cmp::min(bucket as usize, LIQUIDITY_HISTORY_BUCKETS - 1);
+		for value in self.buckets.iter_mut() {
+			*value = ((*value as u32) * 2047 / 2048) as u16;
+		}
+		self.buckets[bucket] = self.buckets[bucket].saturating_add(32);
+	}
+
+	/// Returns the buckets after halving them the given number of times.
+	fn decayed_buckets(&self, half_lives: u32) -> [u16; LIQUIDITY_HISTORY_BUCKETS] {
+		let mut buckets = self.buckets;
+		for value in buckets.iter_mut() {
+			*value = value.checked_shr(half_lives).unwrap_or(0);
+		}
+		buckets
+	}
+
+	/// Halves the buckets the given number of times.
+	fn decay(&mut self, half_lives: u32) {
+		self.buckets = self.decayed_buckets(half_lives);
+	}
+}
+
+impl Writeable for HistoricalBucketRangeTracker {
+	#[inline]
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		for value in self.buckets.iter() {
+			value.write(w)?;
+		}
+		Ok(())
+	}
+}
+
+impl Readable for HistoricalBucketRangeTracker {
+	#[inline]
+	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let mut buckets = [0; LIQUIDITY_HISTORY_BUCKETS];
+		for value in buckets.iter_mut() {
+			*value = Readable::read(r)?;
+		}
+		Ok(Self { buckets })
+	}
+}
+
 /// Accounting for channel liquidity balance uncertainty.
 ///
 /// Direction is defined in terms of [`NodeId`] partial ordering, where the source node is the
@@ -552,19 +780,29 @@ struct ChannelLiquidity<T: Time> {
 	/// Upper channel liquidity bound in terms of an offset from the effective capacity.
 	max_liquidity_offset_msat: u64,
 
+	/// History of the lower channel liquidity bound, in terms of offsets from zero.
+	min_liquidity_offset_history: HistoricalBucketRangeTracker,
+
+	/// History of the upper channel liquidity bound, in terms of offsets from the effective
+	/// capacity.
+	max_liquidity_offset_history: HistoricalBucketRangeTracker,
+
 	/// Time when the liquidity bounds were last modified.
 	last_updated: T,
 }
 
 /// A snapshot of [`ChannelLiquidity`] in one direction assuming a certain channel capacity and
-/// decayed with a given half life.
-struct DirectedChannelLiquidity<L: Deref<Target = u64>, T: Time, U: Deref<Target = T>> {
+/// decayed with the half lives given in [`ProbabilisticScoringParameters`].
+struct DirectedChannelLiquidity<L: Deref<Target = u64>, H: Deref<Target = HistoricalBucketRangeTracker>, T: Time, U: Deref<Target = T>> {
 	min_liquidity_offset_msat: L,
 	max_liquidity_offset_msat: L,
+	min_liquidity_offset_history: H,
+	max_liquidity_offset_history: H,
 	capacity_msat: u64,
 	last_updated: U,
 	now: T,
 	half_life: Duration,
+	history_half_life: Duration,
 }
 
 impl<G: Deref<Target = NetworkGraph>, T: Time> ProbabilisticScorerUsingTime<G, T> {
@@ -590,6 +828,11 @@ impl Default for ProbabilisticScoringParameters {
 		Self {
 			liquidity_penalty_multiplier_msat: 10_000,
 			liquidity_offset_half_life: Duration::from_secs(3600),
+			historical_liquidity_penalty_multiplier_msat: 0,
+			historical_no_updates_half_life: Duration::from_secs(60 * 60 * 24 * 14),
+			cltv_expiry_delta_penalty_multiplier_msat: 0,
+			htlc_maximum_penalty_msat: 0,
+			htlc_minimum_penalty_msat: 0,
 		}
 	}
 }
@@ -600,6 +843,8 @@ impl<T: Time> ChannelLiquidity<T> {
 		Self {
 			min_liquidity_offset_msat: 0,
 			max_liquidity_offset_msat: 0,
+			min_liquidity_offset_history: HistoricalBucketRangeTracker::new(),
+			max_liquidity_offset_history: HistoricalBucketRangeTracker::new(),
 			last_updated: T::now(),
 		}
 	}
@@ -607,61 +852,133 @@ impl<T: Time> ChannelLiquidity<T> {
 	/// Returns a view of the channel liquidity directed from `source` to `target` assuming
 	/// `capacity_msat`.
 	fn as_directed(
-		&self, source: &NodeId, target: &NodeId, capacity_msat: u64, half_life: Duration
-	) -> DirectedChannelLiquidity<&u64, T, &T> {
+		&self, source: &NodeId, target: &NodeId, capacity_msat: u64,
+		params: &ProbabilisticScoringParameters
+	) -> DirectedChannelLiquidity<&u64, &HistoricalBucketRangeTracker, T, &T> {
 		let (min_liquidity_offset_msat, max_liquidity_offset_msat) = if source < target {
 			(&self.min_liquidity_offset_msat, &self.max_liquidity_offset_msat)
 		} else {
 			(&self.max_liquidity_offset_msat, &self.min_liquidity_offset_msat)
 		};
+		let (min_liquidity_offset_history, max_liquidity_offset_history) = if source < target {
+			(&self.min_liquidity_offset_history, &self.max_liquidity_offset_history)
+		} else {
+			(&self.max_liquidity_offset_history, &self.min_liquidity_offset_history)
+		};
 
 		DirectedChannelLiquidity {
 			min_liquidity_offset_msat,
 			max_liquidity_offset_msat,
+			min_liquidity_offset_history,
+			max_liquidity_offset_history,
 			capacity_msat,
 			last_updated: &self.last_updated,
 			now: T::now(),
-			half_life,
+			half_life: params.liquidity_offset_half_life,
+			history_half_life: params.historical_no_updates_half_life,
 		}
 	}
 
 	/// Returns a mutable view of the channel liquidity directed from `source` to `target` assuming
 	/// `capacity_msat`.
 	fn as_directed_mut(
-		&mut self, source: &NodeId, target: &NodeId, capacity_msat: u64, half_life: Duration
-	) -> DirectedChannelLiquidity<&mut u64, T, &mut T> {
+		&mut self, source: &NodeId, target: &NodeId, capacity_msat: u64,
+		params: &ProbabilisticScoringParameters
+	) -> DirectedChannelLiquidity<&mut u64, &mut HistoricalBucketRangeTracker, T, &mut T> {
 		let (min_liquidity_offset_msat, max_liquidity_offset_msat) = if source < target {
 			(&mut self.min_liquidity_offset_msat, &mut self.max_liquidity_offset_msat)
 		} else {
 			(&mut self.max_liquidity_offset_msat, &mut self.min_liquidity_offset_msat)
 		};
+		let (min_liquidity_offset_history, max_liquidity_offset_history) = if source < target {
+			(&mut self.min_liquidity_offset_history, &mut self.max_liquidity_offset_history)
+		} else {
+			(&mut self.max_liquidity_offset_history, &mut self.min_liquidity_offset_history)
+		};
 
 		DirectedChannelLiquidity {
 			min_liquidity_offset_msat,
 			max_liquidity_offset_msat,
+			min_liquidity_offset_history,
+			max_liquidity_offset_history,
 			capacity_msat,
 			last_updated: &mut self.last_updated,
 			now: T::now(),
-			half_life,
+			half_life: params.liquidity_offset_half_life,
+			history_half_life: params.historical_no_updates_half_life,
 		}
 	}
 }
 
-impl<L: Deref<Target = u64>, T: Time, U: Deref<Target = T>> DirectedChannelLiquidity<L, T, U> {
+/// Returns the probability of successfully routing `amount_msat` through a channel whose liquidity
+/// is uniformly distributed between `min_liquidity_msat` and `max_liquidity_msat`.
+fn uniform_success_probability(amount_msat: u64, min_liquidity_msat: u64, max_liquidity_msat: u64) -> f64 {
+	let min_liquidity_msat = core::cmp::min(min_liquidity_msat, max_liquidity_msat);
+	if amount_msat > max_liquidity_msat {
+		0.0
+	} else if amount_msat <= min_liquidity_msat {
+		1.0
+	} else {
+		let numerator = max_liquidity_msat - amount_msat + 1;
+		let denominator = max_liquidity_msat - min_liquidity_msat + 1;
+		numerator as f64 / denominator as f64
+	}
+}
+
+impl<L: Deref<Target = u64>, H: Deref<Target = HistoricalBucketRangeTracker>, T: Time, U: Deref<Target = T>> DirectedChannelLiquidity<L, H, T, U> {
 	/// Returns the success probability of routing the given HTLC `amount_msat` through the channel
 	/// in this direction.
 	fn success_probability(&self, amount_msat: u64) -> f64 {
-		let max_liquidity_msat = self.max_liquidity_msat();
-		let min_liquidity_msat = core::cmp::min(self.min_liquidity_msat(), max_liquidity_msat);
-		if amount_msat > max_liquidity_msat {
-			0.0
-		} else if amount_msat <= min_liquidity_msat {
-			1.0
+		uniform_success_probability(amount_msat, self.min_liquidity_msat(), self.max_liquidity_msat())
+			.max(0.01) // Lower bound the success probability to ensure some channel is selected.
+	}
+
+	/// Returns the success probability of routing the given HTLC `amount_msat` through the channel
+	/// in this direction as determined by the history of its liquidity bounds, or `None` if no
+	/// history has been recorded or it has fully decayed.
+	fn historical_success_probability(&self, amount_msat: u64) -> Option<f64> {
+		let half_lives = self.history_half_lives();
+		let min_buckets = self.min_liquidity_offset_history.decayed_buckets(half_lives);
+		let max_buckets = self.max_liquidity_offset_history.decayed_buckets(half_lives);
+		let bucket_msat = |bucket: usize| {
+			(self.capacity_msat as u128 * bucket as u128 / LIQUIDITY_HISTORY_BUCKETS as u128) as u64
+		};
+
+		// Walk each combination of lower and upper bound buckets where the lower bound is below the
+		// upper bound, weighting the success probability within those bounds by how often the
+		// combination was observed.
+		let mut total_weight = 0;
+		let mut weighted_success_probability = 0.0;
+		for (min_bucket, min_weight) in min_buckets.iter().enumerate() {
+			let max_buckets = max_buckets.iter().enumerate().take(LIQUIDITY_HISTORY_BUCKETS - min_bucket);
+			for (max_bucket, max_weight) in max_buckets {
+				let weight = *min_weight as u64 * *max_weight as u64;
+				if weight == 0 {
+					continue;
+				}
+				let min_liquidity_msat = bucket_msat(min_bucket);
+				let max_liquidity_msat = bucket_msat(LIQUIDITY_HISTORY_BUCKETS - max_bucket);
+				total_weight += weight;
+				weighted_success_probability += weight as f64 *
+					uniform_success_probability(amount_msat, min_liquidity_msat, max_liquidity_msat);
+			}
+		}
+
+		if total_weight == 0 {
+			None
 		} else {
-			let numerator = max_liquidity_msat + 1 - amount_msat;
-			let denominator = max_liquidity_msat + 1 - min_liquidity_msat;
-			numerator as f64 / denominator as f64
-		}.max(0.01) // Lower bound the success probability to ensure some channel is selected.
+			// Lower bound the success probability to ensure some channel is selected.
+			Some((weighted_success_probability / total_weight as f64).max(0.01))
+		}
+	}
+
+	/// Returns the number of times the liquidity history should be halved given the time since
+	/// the liquidity bounds were last modified.
+	fn history_half_lives(&self) -> u32 {
+		self.now.duration_since(*self.last_updated).as_secs()
+			.checked_div(self.history_half_life.as_secs())
+			.map(|half_lives| core::cmp::min(half_lives, u32::max_value() as u64) as u32)
+			.unwrap_or(u32::max_value())
 	}
 
 	/// Returns the lower bound of the channel liquidity balance in this direction.
@@ -684,12 +1001,13 @@ impl<L: Deref<Target = u64>, T: Time, U: Deref<Target = T>> DirectedChannelLiqui
 	}
 }
 
-impl<L: DerefMut<Target = u64>, T: Time, U: DerefMut<Target = T>> DirectedChannelLiquidity<L, T, U> {
+impl<L: DerefMut<Target = u64>, H: DerefMut<Target = HistoricalBucketRangeTracker>, T: Time, U: DerefMut<Target = T>> DirectedChannelLiquidity<L, H, T, U> {
 	/// Adjusts the channel liquidity balance bounds when failing to route `amount_msat`.
 	fn failed_at_channel(&mut self, amount_msat: u64) {
 		if amount_msat < self.max_liquidity_msat() {
 			self.set_max_liquidity_msat(amount_msat);
 		}
+		self.track_liquidity_history();
 	}
 
 	/// Adjusts the channel liquidity balance bounds when failing to route `amount_msat` downstream.
@@ -697,12 +1015,14 @@ impl<L: DerefMut<Target = u64>, T: Time, U: DerefMut<Target = T>> DirectedChanne
 		if amount_msat > self.min_liquidity_msat() {
 			self.set_min_liquidity_msat(amount_msat);
 		}
+		self.track_liquidity_history();
 	}
 
 	/// Adjusts the channel liquidity balance bounds when successfully routing `amount_msat`.
 	fn successful(&mut self, amount_msat: u64) {
 		let max_liquidity_msat = self.max_liquidity_msat().checked_sub(amount_msat).unwrap_or(0);
 		self.set_max_liquidity_msat(max_liquidity_msat);
+		self.track_liquidity_history();
 	}
 
 	/// Adjusts the lower bound of the channel liquidity balance in this direction.
@@ -713,6 +1033,7 @@ impl<L: DerefMut<Target = u64>, T: Time, U: DerefMut<Target = T>> DirectedChanne
 		} else {
 			self.decayed_offset_msat(*self.max_liquidity_offset_msat)
 		};
+		self.decay_liquidity_history();
 		*self.last_updated = self.now;
 	}
 
@@ -724,31 +1045,62 @@ impl<L: DerefMut<Target = u64>, T: Time, U: DerefMut<Target = T>> DirectedChanne
 		} else {
 			self.decayed_offset_msat(*self.min_liquidity_offset_msat)
 		};
+		self.decay_liquidity_history();
 		*self.last_updated = self.now;
 	}
+
+	/// Applies any decay of the liquidity history owed since the liquidity bounds were last
+	/// modified. Must be called before updating `last_updated`.
+	fn decay_liquidity_history(&mut self) {
+		let half_lives = self.history_half_lives();
+		self.min_liquidity_offset_history.decay(half_lives);
+		self.max_liquidity_offset_history.decay(half_lives);
+	}
+
+	/// Records the current liquidity bounds in the liquidity history.
+	fn track_liquidity_history(&mut self) {
+		let min_liquidity_offset_msat = self.min_liquidity_msat();
+		let max_liquidity_offset_msat = self.capacity_msat.saturating_sub(self.max_liquidity_msat());
+		self.min_liquidity_offset_history.track_datapoint(min_liquidity_offset_msat, self.capacity_msat);
+		self.max_liquidity_offset_history.track_datapoint(max_liquidity_offset_msat, self.capacity_msat);
+	}
 }
 
 impl<G: Deref<Target = NetworkGraph>, T: Time> Score for ProbabilisticScorerUsingTime<G, T> {
 	fn channel_penalty_msat(
-		&self, short_channel_id: u64, amount_msat: u64, capacity_msat: u64, source: &NodeId,
-		target: &NodeId
+		&self, short_channel_id: u64, source: &NodeId, target: &NodeId, usage: ChannelUsage
 	) -> u64 {
+		let amount_msat = usage.amount_msat;
 		let liquidity_penalty_multiplier_msat = self.params.liquidity_penalty_multiplier_msat;
-		let liquidity_offset_half_life = self.params.liquidity_offset_half_life;
-		let success_probability = self.channel_liquidities
+		let historical_liquidity_penalty_multiplier_msat =
+			self.params.historical_liquidity_penalty_multiplier_msat;
+		let default_liquidity = ChannelLiquidity::new();
+		let liquidity = self.channel_liquidities
 			.get(&short_channel_id)
-			.unwrap_or(&ChannelLiquidity::new())
-			.as_directed(source, target, capacity_msat, liquidity_offset_half_life)
-			.success_probability(amount_msat);
+			.unwrap_or(&default_liquidity)
+			.as_directed(source, target, usage.capacity_msat, &self.params);
+		let success_probability = liquidity.success_probability(amount_msat);
 		// NOTE: If success_probability is ever changed to return 0.0, log10 is undefined so return
 		// u64::max_value instead.
 		debug_assert!(success_probability > core::f64::EPSILON);
-		(-(success_probability.log10()) * liquidity_penalty_multiplier_msat as f64) as u64
+		let mut penalty_msat = (-(success_probability.log10()) * liquidity_penalty_multiplier_msat as f64) as u64;
+
+		if historical_liquidity_penalty_multiplier_msat != 0 {
+			let historical_success_probability = liquidity.historical_success_probability(amount_msat)
+				.unwrap_or(success_probability);
+			debug_assert!(historical_success_probability > core::f64::EPSILON);
+			let historical_penalty_msat = (-(historical_success_probability.log10()) *
+				historical_liquidity_penalty_multiplier_msat as f64) as u64;
+			penalty_msat = penalty_msat.saturating_add(historical_penalty_msat);
+		}
+
+		penalty_msat
+			.saturating_add(self.params.cltv_expiry_delta_penalty_msat(&usage))
+			.saturating_add(self.params.htlc_limits_penalty_msat(&usage))
 	}
 
 	fn payment_path_failed(&mut self, path: &[&RouteHop], short_channel_id: u64) {
 		let amount_msat = path.split_last().map(|(hop, _)| hop.fee_msat).unwrap_or(0);
-		let liquidity_offset_half_life = self.params.liquidity_offset_half_life;
 		let network_graph = self.network_graph.read_only();
 		for hop in path {
 			let target = NodeId::from_pubkey(&hop.pubkey);
@@ -763,7 +1115,7 @@ impl<G: Deref<Target = NetworkGraph>, T: Time> Score for ProbabilisticScorerUsin
 					self.channel_liquidities
 						.entry(hop.short_channel_id)
 						.or_insert_with(ChannelLiquidity::new)
-						.as_directed_mut(source, &target, capacity_msat, liquidity_offset_half_life)
+						.as_directed_mut(source, &target, capacity_msat, &self.params)
 						.failed_at_channel(amount_msat);
 					break;
 				}
@@ -771,7 +1123,7 @@ impl<G: Deref<Target = NetworkGraph>, T: Time> Score for ProbabilisticScorerUsin
 				self.channel_liquidities
 					.entry(hop.short_channel_id)
 					.or_insert_with(ChannelLiquidity::new)
-					.as_directed_mut(source, &target, capacity_msat, liquidity_offset_half_life)
+					.as_directed_mut(source, &target, capacity_msat, &self.params)
 					.failed_downstream(amount_msat);
 			}
 		}
@@ -779,7 +1131,6 @@ impl<G: Deref<Target = NetworkGraph>, T: Time> Score for ProbabilisticScorerUsin
 
 	fn payment_path_successful(&mut self, path: &[&RouteHop]) {
 		let amount_msat = path.split_last().map(|(hop, _)| hop.fee_msat).unwrap_or(0);
-		let liquidity_offset_half_life = self.params.liquidity_offset_half_life;
 		let network_graph = self.network_graph.read_only();
 		for hop in path {
 			let target = NodeId::from_pubkey(&hop.pubkey);
@@ -793,7 +1144,7 @@ impl<G: Deref<Target = NetworkGraph>, T: Time> Score for ProbabilisticScorerUsin
 				self.channel_liquidities
 					.entry(hop.short_channel_id)
 					.or_insert_with(ChannelLiquidity::new)
-					.as_directed_mut(source, &target, capacity_msat, liquidity_offset_half_life)
+					.as_directed_mut(source, &target, capacity_msat, &self.params)
 					.successful(amount_msat);
 			}
 		}
@@ -838,7 +1189,9 @@ impl<T: Time> Writeable for ChannelLiquidity<T> {
 		let duration_since_epoch = T::duration_since_epoch() - self.last_updated.elapsed();
 		write_tlv_fields!(w, {
 			(0, self.min_liquidity_offset_msat, required),
+			(1, self.min_liquidity_offset_history, required),
 			(2, self.max_liquidity_offset_msat, required),
+			(3, self.max_liquidity_offset_history, required),
 			(4, duration_since_epoch, required),
 		});
 		Ok(())
@@ -850,15 +1203,21 @@ impl<T: Time> Readable for ChannelLiquidity<T> {
 	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
 		let mut min_liquidity_offset_msat = 0;
 		let mut max_liquidity_offset_msat = 0;
+		let mut min_liquidity_offset_history = HistoricalBucketRangeTracker::new();
+		let mut max_liquidity_offset_history = HistoricalBucketRangeTracker::new();
 		let mut duration_since_epoch = Duration::from_secs(0);
 		read_tlv_fields!(r, {
 			(0, min_liquidity_offset_msat, required),
+			(1, min_liquidity_offset_history, (default_value, HistoricalBucketRangeTracker::new())),
 			(2, max_liquidity_offset_msat, required),
+			(3, max_liquidity_offset_history, (default_value, HistoricalBucketRangeTracker::new())),
 			(4, duration_since_epoch, required),
 		});
 		Ok(Self {
 			min_liquidity_offset_msat,
 			max_liquidity_offset_msat,
+			min_liquidity_offset_history,
+			max_liquidity_offset_history,
 			last_updated: T::now() - (T::duration_since_epoch() - duration_since_epoch),
 		})
 	}
@@ -939,13 +1298,13 @@ pub(crate) use self::time::Time;
 
 #[cfg(test)]
 mod tests {
-	use super::{ChannelLiquidity, ProbabilisticScoringParameters, ProbabilisticScorerUsingTime, ScoringParameters, ScorerUsingTime, Time};
+	use super::{ChannelLiquidity, ChannelUsage, HistoricalBucketRangeTracker, ProbabilisticScoringParameters, ProbabilisticScorerUsingTime, ScoringParameters, ScorerUsingTime, Time};
 	use super::time::Eternity;
 
 	use ln::features::{ChannelFeatures, NodeFeatures};
 	use ln::msgs::{ChannelAnnouncement, ChannelUpdate, OptionalField, UnsignedChannelAnnouncement, UnsignedChannelUpdate};
 	use routing::scoring::Score;
-	use routing::network_graph::{NetworkGraph, NodeId};
+	use routing::network_graph::{NetworkGraph, NodeId, RoutingFees};
 	use routing::router::RouteHop;
 	use util::ser::{Readable, ReadableArgs, Writeable};
 
@@ -1057,6 +1416,18 @@ mod tests {
 		NodeId::from_pubkey(&target_pubkey())
 	}
 
+	fn usage(amount_msat: u64, capacity_msat: u64) -> ChannelUsage {
+		ChannelUsage {
+			amount_msat,
+			capacity_msat,
+			htlc_minimum_msat: 0,
+			htlc_maximum_msat: None,
+			fees: RoutingFees { base_msat: 0, proportional_millionths: 0 },
+			cltv_expiry_delta: 0,
+			path_cltv_expiry_delta: 0,
+		}
+	}
+
 	#[test]
 	fn penalizes_without_channel_failures() {
 		let scorer = Scorer::new(ScoringParameters {
@@ -1068,10 +1439,10 @@ mod tests {
 		});
 		let source = source_node_id();
 		let target = target_node_id();
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_000);
 
 		SinceEpoch::advance(Duration::from_secs(1));
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_000);
 	}
 
 	#[test]
@@ -1085,16 +1456,16 @@ mod tests {
 		});
 		let source = source_node_id();
 		let target = target_node_id();
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_000);
 
 		scorer.payment_path_failed(&[], 42);
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_064);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_064);
 
 		scorer.payment_path_failed(&[], 42);
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_128);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_128);
 
 		scorer.payment_path_failed(&[], 42);
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_192);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_192);
 	}
 
 	#[test]
@@ -1108,25 +1479,25 @@ mod tests {
 		});
 		let source = source_node_id();
 		let target = target_node_id();
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_000);
 
 		scorer.payment_path_failed(&[], 42);
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_512);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_512);
 
 		SinceEpoch::advance(Duration::from_secs(9));
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_512);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_512);
 
 		SinceEpoch::advance(Duration::from_secs(1));
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_256);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_256);
 
 		SinceEpoch::advance(Duration::from_secs(10 * 8));
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_001);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_001);
 
 		SinceEpoch::advance(Duration::from_secs(10));
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_000);
 
 		SinceEpoch::advance(Duration::from_secs(10));
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_000);
 	}
 
 	#[test]
@@ -1140,18 +1511,18 @@ mod tests {
 		});
 		let source = source_node_id();
 		let target = target_node_id();
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_000);
 
 		scorer.payment_path_failed(&[], 42);
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_512);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_512);
 
 		// An unchecked right shift 64 bits or more in ChannelFailure::decayed_penalty_msat would
 		// cause an overflow.
 		SinceEpoch::advance(Duration::from_secs(10 * 64));
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_000);
 
 		SinceEpoch::advance(Duration::from_secs(10));
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_000);
 	}
 
 	#[test]
@@ -1165,19 +1536,19 @@ mod tests {
 		});
 		let source = source_node_id();
 		let target = target_node_id();
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_000);
 
 		scorer.payment_path_failed(&[], 42);
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_512);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_512);
 
 		SinceEpoch::advance(Duration::from_secs(10));
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_256);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_256);
 
 		scorer.payment_path_failed(&[], 42);
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_768);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_768);
 
 		SinceEpoch::advance(Duration::from_secs(10));
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_384);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_384);
 	}
 
 	#[test]
@@ -1191,13 +1562,13 @@ mod tests {
 		});
 		let source = source_node_id();
 		let target = target_node_id();
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_000);
 
 		scorer.payment_path_failed(&[], 42);
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_512);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_512);
 
 		SinceEpoch::advance(Duration::from_secs(10));
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_256);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_256);
 
 		let hop = RouteHop {
 			pubkey: PublicKey::from_slice(target.as_slice()).unwrap(),
@@ -1208,10 +1579,10 @@ mod tests {
 			cltv_expiry_delta: 18,
 		};
 		scorer.payment_path_successful(&[&hop]);
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_128);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_128);
 
 		SinceEpoch::advance(Duration::from_secs(10));
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_064);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_064);
 	}
 
 	#[test]
@@ -1227,20 +1598,20 @@ mod tests {
 		let target = target_node_id();
 
 		scorer.payment_path_failed(&[], 42);
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_512);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_512);
 
 		SinceEpoch::advance(Duration::from_secs(10));
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_256);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_256);
 
 		scorer.payment_path_failed(&[], 43);
-		assert_eq!(scorer.channel_penalty_msat(43, 1, 1, &source, &target), 1_512);
+		assert_eq!(scorer.channel_penalty_msat(43, &source, &target, usage(1, 1)), 1_512);
 
 		let mut serialized_scorer = Vec::new();
 		scorer.write(&mut serialized_scorer).unwrap();
 
 		let deserialized_scorer = <Scorer>::read(&mut io::Cursor::new(&serialized_scorer)).unwrap();
-		assert_eq!(deserialized_scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_256);
-		assert_eq!(deserialized_scorer.channel_penalty_msat(43, 1, 1, &source, &target), 1_512);
+		assert_eq!(deserialized_scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_256);
+		assert_eq!(deserialized_scorer.channel_penalty_msat(43, &source, &target, usage(1, 1)), 1_512);
 	}
 
 	#[test]
@@ -1256,7 +1627,7 @@ mod tests {
 		let target = target_node_id();
 
 		scorer.payment_path_failed(&[], 42);
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_512);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_512);
 
 		let mut serialized_scorer = Vec::new();
 		scorer.write(&mut serialized_scorer).unwrap();
@@ -1264,10 +1635,10 @@ mod tests {
 		SinceEpoch::advance(Duration::from_secs(10));
 
 		let deserialized_scorer = <Scorer>::read(&mut io::Cursor::new(&serialized_scorer)).unwrap();
-		assert_eq!(deserialized_scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_256);
+		assert_eq!(deserialized_scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_256);
 
 		SinceEpoch::advance(Duration::from_secs(10));
-		assert_eq!(deserialized_scorer.channel_penalty_msat(42, 1, 1, &source, &target), 1_128);
+		assert_eq!(deserialized_scorer.channel_penalty_msat(42, &source, &target, usage(1, 1)), 1_128);
 	}
 
 	#[test]
@@ -1282,11 +1653,11 @@ mod tests {
 		let source = source_node_id();
 		let target = target_node_id();
 
-		assert_eq!(scorer.channel_penalty_msat(42, 1_000, 1_024_000, &source, &target), 0);
-		assert_eq!(scorer.channel_penalty_msat(42, 256_999, 1_024_000, &source, &target), 0);
-		assert_eq!(scorer.channel_penalty_msat(42, 257_000, 1_024_000, &source, &target), 100);
-		assert_eq!(scorer.channel_penalty_msat(42, 258_000, 1_024_000, &source, &target), 200);
-		assert_eq!(scorer.channel_penalty_msat(42, 512_000, 1_024_000, &source, &target), 256 * 100);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1_000, 1_024_000)), 0);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(256_999, 1_024_000)), 0);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(257_000, 1_024_000)), 100);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(258_000, 1_024_000)), 200);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(512_000, 1_024_000)), 256 * 100);
 	}
 
 	// `ProbabilisticScorer` tests
@@ -1424,11 +1795,15 @@ mod tests {
 		let mut scorer = ProbabilisticScorer::new(params, &network_graph)
 			.with_channel(42,
 				ChannelLiquidity {
-					min_liquidity_offset_msat: 700, max_liquidity_offset_msat: 100, last_updated
+					min_liquidity_offset_msat: 700, max_liquidity_offset_msat: 100, last_updated,
+					min_liquidity_offset_history: HistoricalBucketRangeTracker::new(),
+					max_liquidity_offset_history: HistoricalBucketRangeTracker::new(),
 				})
 			.with_channel(43,
 				ChannelLiquidity {
-					min_liquidity_offset_msat: 700, max_liquidity_offset_msat: 100, last_updated
+					min_liquidity_offset_msat: 700, max_liquidity_offset_msat: 100, last_updated,
+					min_liquidity_offset_history: HistoricalBucketRangeTracker::new(),
+					max_liquidity_offset_history: HistoricalBucketRangeTracker::new(),
 				});
 		let source = source_node_id();
 		let target = target_node_id();
@@ -1438,54 +1813,53 @@ mod tests {
 
 		// Update minimum liquidity.
 
-		let liquidity_offset_half_life = scorer.params.liquidity_offset_half_life;
 		let liquidity = scorer.channel_liquidities.get(&42).unwrap()
-			.as_directed(&source, &target, 1_000, liquidity_offset_half_life);
+			.as_directed(&source, &target, 1_000, &params);
 		assert_eq!(liquidity.min_liquidity_msat(), 100);
 		assert_eq!(liquidity.max_liquidity_msat(), 300);
 
 		let liquidity = scorer.channel_liquidities.get(&42).unwrap()
-			.as_directed(&target, &source, 1_000, liquidity_offset_half_life);
+			.as_directed(&target, &source, 1_000, &params);
 		assert_eq!(liquidity.min_liquidity_msat(), 700);
 		assert_eq!(liquidity.max_liquidity_msat(), 900);
 
 		scorer.channel_liquidities.get_mut(&42).unwrap()
-			.as_directed_mut(&source, &target, 1_000, liquidity_offset_half_life)
+			.as_directed_mut(&source, &target, 1_000, &params)
 			.set_min_liquidity_msat(200);
 
 		let liquidity = scorer.channel_liquidities.get(&42).unwrap()
-			.as_directed(&source, &target, 1_000, liquidity_offset_half_life);
+			.as_directed(&source, &target, 1_000, &params);
 		assert_eq!(liquidity.min_liquidity_msat(), 200);
 		assert_eq!(liquidity.max_liquidity_msat(), 300);
 
 		let liquidity = scorer.channel_liquidities.get(&42).unwrap()
-			.as_directed(&target, &source, 1_000, liquidity_offset_half_life);
+			.as_directed(&target, &source, 1_000, &params);
 		assert_eq!(liquidity.min_liquidity_msat(), 700);
 		assert_eq!(liquidity.max_liquidity_msat(), 800);
 
 		// Update maximum liquidity.
 
 		let liquidity = scorer.channel_liquidities.get(&43).unwrap()
-			.as_directed(&target, &recipient, 1_000, liquidity_offset_half_life);
+			.as_directed(&target, &recipient, 1_000, &params);
 		assert_eq!(liquidity.min_liquidity_msat(), 700);
 		assert_eq!(liquidity.max_liquidity_msat(), 900);
 
 		let liquidity = scorer.channel_liquidities.get(&43).unwrap()
-			.as_directed(&recipient, &target, 1_000, liquidity_offset_half_life);
+			.as_directed(&recipient, &target, 1_000, &params);
 		assert_eq!(liquidity.min_liquidity_msat(), 100);
 		assert_eq!(liquidity.max_liquidity_msat(), 300);
 
 		scorer.channel_liquidities.get_mut(&43).unwrap()
-			.as_directed_mut(&target, &recipient, 1_000, liquidity_offset_half_life)
+			.as_directed_mut(&target, &recipient, 1_000, &params)
 			.set_max_liquidity_msat(200);
 
 		let liquidity = scorer.channel_liquidities.get(&43).unwrap()
-			.as_directed(&target, &recipient, 1_000, liquidity_offset_half_life);
+			.as_directed(&target, &recipient, 1_000, &params);
 		assert_eq!(liquidity.min_liquidity_msat(), 0);
 		assert_eq!(liquidity.max_liquidity_msat(), 200);
 
 		let liquidity = scorer.channel_liquidities.get(&43).unwrap()
-			.as_directed(&recipient, &target, 1_000, liquidity_offset_half_life);
+			.as_directed(&recipient, &target, 1_000, &params);
 		assert_eq!(liquidity.min_liquidity_msat(), 800);
 		assert_eq!(liquidity.max_liquidity_msat(), 1000);
 	}
@@ -1498,51 +1872,52 @@ mod tests {
 		let mut scorer = ProbabilisticScorer::new(params, &network_graph)
 			.with_channel(42,
 				ChannelLiquidity {
-					min_liquidity_offset_msat: 200, max_liquidity_offset_msat: 400, last_updated
+					min_liquidity_offset_msat: 200, max_liquidity_offset_msat: 400, last_updated,
+					min_liquidity_offset_history: HistoricalBucketRangeTracker::new(),
+					max_liquidity_offset_history: HistoricalBucketRangeTracker::new(),
 				});
 		let source = source_node_id();
 		let target = target_node_id();
 		assert!(source > target);
 
 		// Check initial bounds.
-		let liquidity_offset_half_life = scorer.params.liquidity_offset_half_life;
 		let liquidity = scorer.channel_liquidities.get(&42).unwrap()
-			.as_directed(&source, &target, 1_000, liquidity_offset_half_life);
+			.as_directed(&source, &target, 1_000, &params);
 		assert_eq!(liquidity.min_liquidity_msat(), 400);
 		assert_eq!(liquidity.max_liquidity_msat(), 800);
 
 		let liquidity = scorer.channel_liquidities.get(&42).unwrap()
-			.as_directed(&target, &source, 1_000, liquidity_offset_half_life);
+			.as_directed(&target, &source, 1_000, &params);
 		assert_eq!(liquidity.min_liquidity_msat(), 200);
 		assert_eq!(liquidity.max_liquidity_msat(), 600);
 
 		// Reset from source to target.
 		scorer.channel_liquidities.get_mut(&42).unwrap()
-			.as_directed_mut(&source, &target, 1_000, liquidity_offset_half_life)
+			.as_directed_mut(&source, &target, 1_000, &params)
 			.set_min_liquidity_msat(900);
 
 		let liquidity = scorer.channel_liquidities.get(&42).unwrap()
-			.as_directed(&source, &target, 1_000, liquidity_offset_half_life);
+			.as_directed(&source, &target, 1_000, &params);
 		assert_eq!(liquidity.min_liquidity_msat(), 900);
 		assert_eq!(liquidity.max_liquidity_msat(), 1_000);
 
 		let liquidity = scorer.channel_liquidities.get(&42).unwrap()
-			.as_directed(&target, &source, 1_000, liquidity_offset_half_life);
+			.as_directed(&target, &source, 1_000, &params);
 		assert_eq!(liquidity.min_liquidity_msat(), 0);
 		assert_eq!(liquidity.max_liquidity_msat(), 100);
 
 		// Reset from target to source.
 		scorer.channel_liquidities.get_mut(&42).unwrap()
-			.as_directed_mut(&target, &source, 1_000, liquidity_offset_half_life)
+			.as_directed_mut(&target, &source, 1_000, &params)
 			.set_min_liquidity_msat(400);
 
 		let liquidity = scorer.channel_liquidities.get(&42).unwrap()
-			.as_directed(&source, &target, 1_000, liquidity_offset_half_life);
+			.as_directed(&source, &target, 1_000, &params);
 		assert_eq!(liquidity.min_liquidity_msat(), 0);
 		assert_eq!(liquidity.max_liquidity_msat(), 600);
 
 		let liquidity = scorer.channel_liquidities.get(&42).unwrap()
-			.as_directed(&target, &source, 1_000, liquidity_offset_half_life);
+			.as_directed(&target, &source, 1_000, &params);
 		assert_eq!(liquidity.min_liquidity_msat(), 400);
 		assert_eq!(liquidity.max_liquidity_msat(), 1_000);
 	}
@@ -1555,51 +1930,52 @@ mod tests {
 		let mut scorer = ProbabilisticScorer::new(params, &network_graph)
 			.with_channel(42,
 				ChannelLiquidity {
-					min_liquidity_offset_msat: 200, max_liquidity_offset_msat: 400, last_updated
+					min_liquidity_offset_msat: 200, max_liquidity_offset_msat: 400, last_updated,
+					min_liquidity_offset_history: HistoricalBucketRangeTracker::new(),
+					max_liquidity_offset_history: HistoricalBucketRangeTracker::new(),
 				});
 		let source = source_node_id();
 		let target = target_node_id();
 		assert!(source > target);
 
 		// Check initial bounds.
-		let liquidity_offset_half_life = scorer.params.liquidity_offset_half_life;
 		let liquidity = scorer.channel_liquidities.get(&42).unwrap()
-			.as_directed(&source, &target, 1_000, liquidity_offset_half_life);
+			.as_directed(&source, &target, 1_000, &params);
 		assert_eq!(liquidity.min_liquidity_msat(), 400);
 		assert_eq!(liquidity.max_liquidity_msat(), 800);
 
 		let liquidity = scorer.channel_liquidities.get(&42).unwrap()
-			.as_directed(&target, &source, 1_000, liquidity_offset_half_life);
+			.as_directed(&target, &source, 1_000, &params);
 		assert_eq!(liquidity.min_liquidity_msat(), 200);
 		assert_eq!(liquidity.max_liquidity_msat(), 600);
 
 		// Reset from source to target.
 		scorer.channel_liquidities.get_mut(&42).unwrap()
-			.as_directed_mut(&source, &target, 1_000, liquidity_offset_half_life)
+			.as_directed_mut(&source, &target, 1_000, &params)
 			.set_max_liquidity_msat(300);
 
 		let liquidity = scorer.channel_liquidities.get(&42).unwrap()
-			.as_directed(&source, &target, 1_000, liquidity_offset_half_life);
+			.as_directed(&source, &target, 1_000, &params);
 		assert_eq!(liquidity.min_liquidity_msat(), 0);
 		assert_eq!(liquidity.max_liquidity_msat(), 300);
 
 		let liquidity = scorer.channel_liquidities.get(&42).unwrap()
-			.as_directed(&target, &source, 1_000, liquidity_offset_half_life);
+			.as_directed(&target, &source, 1_000, &params);
 		assert_eq!(liquidity.min_liquidity_msat(), 700);
 		assert_eq!(liquidity.max_liquidity_msat(), 1_000);
 
 		// Reset from target to source.
 		scorer.channel_liquidities.get_mut(&42).unwrap()
-			.as_directed_mut(&target, &source, 1_000, liquidity_offset_half_life)
+			.as_directed_mut(&target, &source, 1_000, &params)
 			.set_max_liquidity_msat(600);
 
 		let liquidity = scorer.channel_liquidities.get(&42).unwrap()
-			.as_directed(&source, &target, 1_000, liquidity_offset_half_life);
+			.as_directed(&source, &target, 1_000, &params);
 		assert_eq!(liquidity.min_liquidity_msat(), 400);
 		assert_eq!(liquidity.max_liquidity_msat(), 1_000);
 
 		let liquidity = scorer.channel_liquidities.get(&42).unwrap()
-			.as_directed(&target, &source, 1_000, liquidity_offset_half_life);
+			.as_directed(&target, &source, 1_000, &params);
 		assert_eq!(liquidity.min_liquidity_msat(), 0);
 		assert_eq!(liquidity.max_liquidity_msat(), 600);
 	}
@@ -1614,18 +1990,18 @@ mod tests {
 		let source = source_node_id();
 		let target = target_node_id();
 
-		assert_eq!(scorer.channel_penalty_msat(42, 100, 100_000, &source, &target), 0);
-		assert_eq!(scorer.channel_penalty_msat(42, 1_000, 100_000, &source, &target), 4);
-		assert_eq!(scorer.channel_penalty_msat(42, 10_000, 100_000, &source, &target), 45);
-		assert_eq!(scorer.channel_penalty_msat(42, 100_000, 100_000, &source, &target), 2_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(100, 100_000)), 0);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1_000, 100_000)), 4);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(10_000, 100_000)), 45);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(100_000, 100_000)), 2_000);
 
-		assert_eq!(scorer.channel_penalty_msat(42, 125, 1_000, &source, &target), 57);
-		assert_eq!(scorer.channel_penalty_msat(42, 250, 1_000, &source, &target), 124);
-		assert_eq!(scorer.channel_penalty_msat(42, 375, 1_000, &source, &target), 203);
-		assert_eq!(scorer.channel_penalty_msat(42, 500, 1_000, &source, &target), 300);
-		assert_eq!(scorer.channel_penalty_msat(42, 625, 1_000, &source, &target), 425);
-		assert_eq!(scorer.channel_penalty_msat(42, 750, 1_000, &source, &target), 600);
-		assert_eq!(scorer.channel_penalty_msat(42, 875, 1_000, &source, &target), 900);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(125, 1_000)), 57);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(250, 1_000)), 124);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(375, 1_000)), 203);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(500, 1_000)), 300);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(625, 1_000)), 425);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(750, 1_000)), 600);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(875, 1_000)), 900);
 	}
 
 	#[test]
@@ -1638,15 +2014,17 @@ mod tests {
 		let scorer = ProbabilisticScorer::new(params, &network_graph)
 			.with_channel(42,
 				ChannelLiquidity {
-					min_liquidity_offset_msat: 40, max_liquidity_offset_msat: 40, last_updated
+					min_liquidity_offset_msat: 40, max_liquidity_offset_msat: 40, last_updated,
+					min_liquidity_offset_history: HistoricalBucketRangeTracker::new(),
+					max_liquidity_offset_history: HistoricalBucketRangeTracker::new(),
 				});
 		let source = source_node_id();
 		let target = target_node_id();
 
-		assert_eq!(scorer.channel_penalty_msat(42, 39, 100, &source, &target), 0);
-		assert_ne!(scorer.channel_penalty_msat(42, 50, 100, &source, &target), 0);
-		assert_ne!(scorer.channel_penalty_msat(42, 50, 100, &source, &target), 2_000);
-		assert_eq!(scorer.channel_penalty_msat(42, 61, 100, &source, &target), 2_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(39, 100)), 0);
+		assert_ne!(scorer.channel_penalty_msat(42, &source, &target, usage(50, 100)), 0);
+		assert_ne!(scorer.channel_penalty_msat(42, &source, &target, usage(50, 100)), 2_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(61, 100)), 2_000);
 	}
 
 	#[test]
@@ -1661,13 +2039,13 @@ mod tests {
 		let failed_path = payment_path_for_amount(500);
 		let successful_path = payment_path_for_amount(200);
 
-		assert_eq!(scorer.channel_penalty_msat(41, 500, 1_000, &sender, &source), 300);
+		assert_eq!(scorer.channel_penalty_msat(41, &sender, &source, usage(500, 1_000)), 300);
 
 		scorer.payment_path_failed(&failed_path.iter().collect::<Vec<_>>(), 41);
-		assert_eq!(scorer.channel_penalty_msat(41, 500, 1_000, &sender, &source), 300);
+		assert_eq!(scorer.channel_penalty_msat(41, &sender, &source, usage(500, 1_000)), 300);
 
 		scorer.payment_path_successful(&successful_path.iter().collect::<Vec<_>>());
-		assert_eq!(scorer.channel_penalty_msat(41, 500, 1_000, &sender, &source), 300);
+		assert_eq!(scorer.channel_penalty_msat(41, &sender, &source, usage(500, 1_000)), 300);
 	}
 
 	#[test]
@@ -1681,15 +2059,15 @@ mod tests {
 		let target = target_node_id();
 		let path = payment_path_for_amount(500);
 
-		assert_eq!(scorer.channel_penalty_msat(42, 250, 1_000, &source, &target), 124);
-		assert_eq!(scorer.channel_penalty_msat(42, 500, 1_000, &source, &target), 300);
-		assert_eq!(scorer.channel_penalty_msat(42, 750, 1_000, &source, &target), 600);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(250, 1_000)), 124);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(500, 1_000)), 300);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(750, 1_000)), 600);
 
 		scorer.payment_path_failed(&path.iter().collect::<Vec<_>>(), 43);
 
-		assert_eq!(scorer.channel_penalty_msat(42, 250, 1_000, &source, &target), 0);
-		assert_eq!(scorer.channel_penalty_msat(42, 500, 1_000, &source, &target), 0);
-		assert_eq!(scorer.channel_penalty_msat(42, 750, 1_000, &source, &target), 300);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(250, 1_000)), 0);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(500, 1_000)), 0);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(750, 1_000)), 300);
 	}
 
 	#[test]
@@ -1703,15 +2081,15 @@ mod tests {
 		let target = target_node_id();
 		let path = payment_path_for_amount(500);
 
-		assert_eq!(scorer.channel_penalty_msat(42, 250, 1_000, &source, &target), 124);
-		assert_eq!(scorer.channel_penalty_msat(42, 500, 1_000, &source, &target), 300);
-		assert_eq!(scorer.channel_penalty_msat(42, 750, 1_000, &source, &target), 600);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(250, 1_000)), 124);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(500, 1_000)), 300);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(750, 1_000)), 600);
 
 		scorer.payment_path_failed(&path.iter().collect::<Vec<_>>(), 42);
 
-		assert_eq!(scorer.channel_penalty_msat(42, 250, 1_000, &source, &target), 300);
-		assert_eq!(scorer.channel_penalty_msat(42, 500, 1_000, &source, &target), 2_000);
-		assert_eq!(scorer.channel_penalty_msat(42, 750, 1_000, &source, &target), 2_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(250, 1_000)), 300);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(500, 1_000)), 2_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(750, 1_000)), 2_000);
 	}
 
 	#[test]
@@ -1727,15 +2105,15 @@ mod tests {
 		let recipient = recipient_node_id();
 		let path = payment_path_for_amount(500);
 
-		assert_eq!(scorer.channel_penalty_msat(41, 250, 1_000, &sender, &source), 124);
-		assert_eq!(scorer.channel_penalty_msat(42, 250, 1_000, &source, &target), 124);
-		assert_eq!(scorer.channel_penalty_msat(43, 250, 1_000, &target, &recipient), 124);
+		assert_eq!(scorer.channel_penalty_msat(41, &sender, &source, usage(250, 1_000)), 124);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(250, 1_000)), 124);
+		assert_eq!(scorer.channel_penalty_msat(43, &target, &recipient, usage(250, 1_000)), 124);
 
 		scorer.payment_path_successful(&path.iter().collect::<Vec<_>>());
 
-		assert_eq!(scorer.channel_penalty_msat(41, 250, 1_000, &sender, &source), 124);
-		assert_eq!(scorer.channel_penalty_msat(42, 250, 1_000, &source, &target), 300);
-		assert_eq!(scorer.channel_penalty_msat(43, 250, 1_000, &target, &recipient), 300);
+		assert_eq!(scorer.channel_penalty_msat(41, &sender, &source, usage(250, 1_000)), 124);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(250, 1_000)), 300);
+		assert_eq!(scorer.channel_penalty_msat(43, &target, &recipient, usage(250, 1_000)), 300);
 	}
 
 	#[test]
@@ -1744,49 +2122,50 @@ mod tests {
 		let params = ProbabilisticScoringParameters {
 			liquidity_penalty_multiplier_msat: 1_000,
 			liquidity_offset_half_life: Duration::from_secs(10),
+			..Default::default()
 		};
 		let mut scorer = ProbabilisticScorer::new(params, &network_graph);
 		let source = source_node_id();
 		let target = target_node_id();
 
-		assert_eq!(scorer.channel_penalty_msat(42, 0, 1_024, &source, &target), 0);
-		assert_eq!(scorer.channel_penalty_msat(42, 1_024, 1_024, &source, &target), 2_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(0, 1_024)), 0);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1_024, 1_024)), 2_000);
 
 		scorer.payment_path_failed(&payment_path_for_amount(768).iter().collect::<Vec<_>>(), 42);
 		scorer.payment_path_failed(&payment_path_for_amount(128).iter().collect::<Vec<_>>(), 43);
 
-		assert_eq!(scorer.channel_penalty_msat(42, 128, 1_024, &source, &target), 0);
-		assert_eq!(scorer.channel_penalty_msat(42, 256, 1_024, &source, &target), 92);
-		assert_eq!(scorer.channel_penalty_msat(42, 768, 1_024, &source, &target), 1_424);
-		assert_eq!(scorer.channel_penalty_msat(42, 896, 1_024, &source, &target), 2_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(128, 1_024)), 0);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(256, 1_024)), 92);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(768, 1_024)), 1_424);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(896, 1_024)), 2_000);
 
 		SinceEpoch::advance(Duration::from_secs(9));
-		assert_eq!(scorer.channel_penalty_msat(42, 128, 1_024, &source, &target), 0);
-		assert_eq!(scorer.channel_penalty_msat(42, 256, 1_024, &source, &target), 92);
-		assert_eq!(scorer.channel_penalty_msat(42, 768, 1_024, &source, &target), 1_424);
-		assert_eq!(scorer.channel_penalty_msat(42, 896, 1_024, &source, &target), 2_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(128, 1_024)), 0);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(256, 1_024)), 92);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(768, 1_024)), 1_424);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(896, 1_024)), 2_000);
 
 		SinceEpoch::advance(Duration::from_secs(1));
-		assert_eq!(scorer.channel_penalty_msat(42, 64, 1_024, &source, &target), 0);
-		assert_eq!(scorer.channel_penalty_msat(42, 128, 1_024, &source, &target), 34);
-		assert_eq!(scorer.channel_penalty_msat(42, 896, 1_024, &source, &target), 1_812);
-		assert_eq!(scorer.channel_penalty_msat(42, 960, 1_024, &source, &target), 2_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(64, 1_024)), 0);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(128, 1_024)), 34);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(896, 1_024)), 1_812);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(960, 1_024)), 2_000);
 
 		// Fully decay liquidity lower bound.
 		SinceEpoch::advance(Duration::from_secs(10 * 7));
-		assert_eq!(scorer.channel_penalty_msat(42, 0, 1_024, &source, &target), 0);
-		assert_eq!(scorer.channel_penalty_msat(42, 1, 1_024, &source, &target), 0);
-		assert_eq!(scorer.channel_penalty_msat(42, 1_023, 1_024, &source, &target), 2_000);
-		assert_eq!(scorer.channel_penalty_msat(42, 1_024, 1_024, &source, &target), 2_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(0, 1_024)), 0);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1, 1_024)), 0);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1_023, 1_024)), 2_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1_024, 1_024)), 2_000);
 
 		// Fully decay liquidity upper bound.
 		SinceEpoch::advance(Duration::from_secs(10));
-		assert_eq!(scorer.channel_penalty_msat(42, 0, 1_024, &source, &target), 0);
-		assert_eq!(scorer.channel_penalty_msat(42, 1_024, 1_024, &source, &target), 2_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(0, 1_024)), 0);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1_024, 1_024)), 2_000);
 
 		SinceEpoch::advance(Duration::from_secs(10));
-		assert_eq!(scorer.channel_penalty_msat(42, 0, 1_024, &source, &target), 0);
-		assert_eq!(scorer.channel_penalty_msat(42, 1_024, 1_024, &source, &target), 2_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(0, 1_024)), 0);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(1_024, 1_024)), 2_000);
 	}
 
 	#[test]
@@ -1795,22 +2174,23 @@ mod tests {
 		let params = ProbabilisticScoringParameters {
 			liquidity_penalty_multiplier_msat: 1_000,
 			liquidity_offset_half_life: Duration::from_secs(10),
+			..Default::default()
 		};
 		let mut scorer = ProbabilisticScorer::new(params, &network_graph);
 		let source = source_node_id();
 		let target = target_node_id();
-		assert_eq!(scorer.channel_penalty_msat(42, 256, 1_024, &source, &target), 124);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(256, 1_024)), 124);
 
 		scorer.payment_path_failed(&payment_path_for_amount(512).iter().collect::<Vec<_>>(), 42);
-		assert_eq!(scorer.channel_penalty_msat(42, 256, 1_024, &source, &target), 281);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(256, 1_024)), 281);
 
 		// An unchecked right shift 64 bits or more in DirectedChannelLiquidity::decayed_offset_msat
 		// would cause an overflow.
 		SinceEpoch::advance(Duration::from_secs(10 * 64));
-		assert_eq!(scorer.channel_penalty_msat(42, 256, 1_024, &source, &target), 124);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(256, 1_024)), 124);
 
 		SinceEpoch::advance(Duration::from_secs(10));
-		assert_eq!(scorer.channel_penalty_msat(42, 256, 1_024, &source, &target), 124);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(256, 1_024)), 124);
 	}
 
 	#[test]
@@ -1819,35 +2199,36 @@ mod tests {
 		let params = ProbabilisticScoringParameters {
 			liquidity_penalty_multiplier_msat: 1_000,
 			liquidity_offset_half_life: Duration::from_secs(10),
+			..Default::default()
 		};
 		let mut scorer = ProbabilisticScorer::new(params, &network_graph);
 		let source = source_node_id();
 		let target = target_node_id();
 
-		assert_eq!(scorer.channel_penalty_msat(42, 512, 1_024, &source, &target), 300);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(512, 1_024)), 300);
 
 		// More knowledge gives higher confidence (256, 768), meaning a lower penalty.
 		scorer.payment_path_failed(&payment_path_for_amount(768).iter().collect::<Vec<_>>(), 42);
 		scorer.payment_path_failed(&payment_path_for_amount(256).iter().collect::<Vec<_>>(), 43);
-		assert_eq!(scorer.channel_penalty_msat(42, 512, 1_024, &source, &target), 281);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(512, 1_024)), 281);
 
 		// Decaying knowledge gives less confidence (128, 896), meaning a higher penalty.
 		SinceEpoch::advance(Duration::from_secs(10));
-		assert_eq!(scorer.channel_penalty_msat(42, 512, 1_024, &source, &target), 293);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(512, 1_024)), 293);
 
 		// Reducing the upper bound gives more confidence (128, 832) that the payment amount (512)
 		// is closer to the upper bound, meaning a higher penalty.
 		scorer.payment_path_successful(&payment_path_for_amount(64).iter().collect::<Vec<_>>());
-		assert_eq!(scorer.channel_penalty_msat(42, 512, 1_024, &source, &target), 333);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(512, 1_024)), 333);
 
 		// Increasing the lower bound gives more confidence (256, 832) that the payment amount (512)
 		// is closer to the lower bound, meaning a lower penalty.
 		scorer.payment_path_failed(&payment_path_for_amount(256).iter().collect::<Vec<_>>(), 43);
-		assert_eq!(scorer.channel_penalty_msat(42, 512, 1_024, &source, &target), 247);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(512, 1_024)), 247);
 
 		// Further decaying affects the lower bound more than the upper bound (128, 928).
 		SinceEpoch::advance(Duration::from_secs(10));
-		assert_eq!(scorer.channel_penalty_msat(42, 512, 1_024, &source, &target), 280);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(512, 1_024)), 280);
 	}
 
 	#[test]
@@ -1856,19 +2237,20 @@ mod tests {
 		let params = ProbabilisticScoringParameters {
 			liquidity_penalty_multiplier_msat: 1_000,
 			liquidity_offset_half_life: Duration::from_secs(10),
+			..Default::default()
 		};
 		let mut scorer = ProbabilisticScorer::new(params, &network_graph);
 		let source = source_node_id();
 		let target = target_node_id();
 
 		scorer.payment_path_failed(&payment_path_for_amount(500).iter().collect::<Vec<_>>(), 42);
-		assert_eq!(scorer.channel_penalty_msat(42, 500, 1_000, &source, &target), 2_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(500, 1_000)), 2_000);
 
 		SinceEpoch::advance(Duration::from_secs(10));
-		assert_eq!(scorer.channel_penalty_msat(42, 500, 1_000, &source, &target), 475);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(500, 1_000)), 475);
 
 		scorer.payment_path_failed(&payment_path_for_amount(250).iter().collect::<Vec<_>>(), 43);
-		assert_eq!(scorer.channel_penalty_msat(42, 500, 1_000, &source, &target), 300);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(500, 1_000)), 300);
 
 		let mut serialized_scorer = Vec::new();
 		scorer.write(&mut serialized_scorer).unwrap();
@@ -1876,7 +2258,7 @@ mod tests {
 		let mut serialized_scorer = io::Cursor::new(&serialized_scorer);
 		let deserialized_scorer =
 			<ProbabilisticScorer>::read(&mut serialized_scorer, (params, &network_graph)).unwrap();
-		assert_eq!(deserialized_scorer.channel_penalty_msat(42, 500, 1_000, &source, &target), 300);
+		assert_eq!(deserialized_scorer.channel_penalty_msat(42, &source, &target, usage(500, 1_000)), 300);
 	}
 
 	#[test]
@@ -1885,13 +2267,14 @@ mod tests {
 		let params = ProbabilisticScoringParameters {
 			liquidity_penalty_multiplier_msat: 1_000,
 			liquidity_offset_half_life: Duration::from_secs(10),
+			..Default::default()
 		};
 		let mut scorer = ProbabilisticScorer::new(params, &network_graph);
 		let source = source_node_id();
 		let target = target_node_id();
 
 		scorer.payment_path_failed(&payment_path_for_amount(500).iter().collect::<Vec<_>>(), 42);
-		assert_eq!(scorer.channel_penalty_msat(42, 500, 1_000, &source, &target), 2_000);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(500, 1_000)), 2_000);
 
 		let mut serialized_scorer = Vec::new();
 		scorer.write(&mut serialized_scorer).unwrap();
@@ -1901,12 +2284,94 @@ mod tests {
 		let mut serialized_scorer = io::Cursor::new(&serialized_scorer);
 		let deserialized_scorer =
 			<ProbabilisticScorer>::read(&mut serialized_scorer, (params, &network_graph)).unwrap();
-		assert_eq!(deserialized_scorer.channel_penalty_msat(42, 500, 1_000, &source, &target), 475);
+		assert_eq!(deserialized_scorer.channel_penalty_msat(42, &source, &target, usage(500, 1_000)), 475);
 
 		scorer.payment_path_failed(&payment_path_for_amount(250).iter().collect::<Vec<_>>(), 43);
-		assert_eq!(scorer.channel_penalty_msat(42, 500, 1_000, &source, &target), 300);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(500, 1_000)), 300);
 
 		SinceEpoch::advance(Duration::from_secs(10));
-		assert_eq!(deserialized_scorer.channel_penalty_msat(42, 500, 1_000, &source, &target), 367);
+		assert_eq!(deserialized_scorer.channel_penalty_msat(42, &source, &target, usage(500, 1_000)), 367);
+	}
+
+	#[test]
+	fn remembers_historical_liquidity_after_bounds_decay() {
+		let network_graph = network_graph();
+		let params = ProbabilisticScoringParameters {
+			liquidity_penalty_multiplier_msat: 1_000,
+			liquidity_offset_half_life: Duration::from_secs(10),
+			historical_liquidity_penalty_multiplier_msat: 1_000,
+			..ProbabilisticScoringParameters::default()
+		};
+		let mut scorer = ProbabilisticScorer::new(params, &network_graph);
+		let source = source_node_id();
+		let target = target_node_id();
+
+		// Without any history, the current liquidity bounds are used for the historical penalty.
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(500, 1_000)), 600);
+
+		scorer.payment_path_failed(&payment_path_for_amount(500).iter().collect::<Vec<_>>(), 42);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(250, 1_000)), 300 + 300);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(500, 1_000)), 2_000 + 2_000);
+
+		// Fully decaying the liquidity bounds leaves the history intact.
+		SinceEpoch::advance(Duration::from_secs(10 * 64));
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(500, 1_000)), 300 + 2_000);
+
+		let mut serialized_scorer = Vec::new();
+		scorer.write(&mut serialized_scorer).unwrap();
+
+		let mut serialized_scorer = io::Cursor::new(&serialized_scorer);
+		let deserialized_scorer =
+			<ProbabilisticScorer>::read(&mut serialized_scorer, (params, &network_graph)).unwrap();
+		assert_eq!(deserialized_scorer.channel_penalty_msat(42, &source, &target, usage(500, 1_000)), 300 + 2_000);
+
+		// Once the history has fully decayed, the current liquidity bounds are used again.
+		SinceEpoch::advance(Duration::from_secs(60 * 60 * 24 * 14 * 6));
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage(500, 1_000)), 600);
+		assert_eq!(deserialized_scorer.channel_penalty_msat(42, &source, &target, usage(500, 1_000)), 600);
+	}
+
+	#[test]
+	fn penalizes_cltv_expiry_delta_and_htlc_limits() {
+		let network_graph = network_graph();
+		let params = ProbabilisticScoringParameters {
+			liquidity_penalty_multiplier_msat: 0,
+			..ProbabilisticScoringParameters::default()
+		};
+		let scorer = ProbabilisticScorer::new(params, &network_graph);
+		let source = source_node_id();
+		let target = target_node_id();
+		let usage = ChannelUsage {
+			cltv_expiry_delta: 40,
+			path_cltv_expiry_delta: 120,
+			htlc_minimum_msat: 500_000,
+			htlc_maximum_msat: Some(8_000_000),
+			..usage(2_000_000, 10_000_000)
+		};
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage), 0);
+
+		let params = ProbabilisticScoringParameters {
+			cltv_expiry_delta_penalty_multiplier_msat: 10,
+			..params
+		};
+		let scorer = ProbabilisticScorer::new(params, &network_graph);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage), 800);
+		let usage_with_more_blocks = ChannelUsage { cltv_expiry_delta: 80, ..usage };
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage_with_more_blocks), 1_600);
+
+		let params = ProbabilisticScoringParameters {
+			cltv_expiry_delta_penalty_multiplier_msat: 0,
+			htlc_maximum_penalty_msat: 1_000,
+			htlc_minimum_penalty_msat: 1_000,
+			..params
+		};
+		let scorer = ProbabilisticScorer::new(params, &network_graph);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage), 250 + 250);
+		let usage_at_htlc_maximum = ChannelUsage { amount_msat: 8_000_000, ..usage };
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage_at_htlc_maximum), 1_000 + 62);
+		let usage_at_htlc_minimum = ChannelUsage { amount_msat: 500_000, ..usage };
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage_at_htlc_minimum), 62 + 1_000);
+		let usage_without_htlc_maximum = ChannelUsage { htlc_maximum_msat: None, ..usage };
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage_without_htlc_maximum), 250);
 	}
 }
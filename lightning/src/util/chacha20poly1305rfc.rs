@@ -36,10 +36,8 @@ mod real_chachapoly {
 			assert!(key.len() == 16 || key.len() == 32);
 			assert!(nonce.len() == 12);
 
-			// Ehh, I'm too lazy to *also* tweak ChaCha20 to make it RFC-compliant
-			assert!(nonce[0] == 0 && nonce[1] == 0 && nonce[2] == 0 && nonce[3] == 0);
-
-			let mut cipher = ChaCha20::new(key, &nonce[4..]);
+			// With a 12-byte nonce ChaCha20 uses a 32-bit block counter, as specified in RFC 8439.
+			let mut cipher = ChaCha20::new(key, nonce);
 			let mut mac_key = [0u8; 64];
 			let zero_key = [0u8; 64];
 			cipher.process(&zero_key, &mut mac_key);
@@ -109,9 +107,6 @@ mod fuzzy_chachapoly {
 			assert!(key.len() == 16 || key.len() == 32);
 			assert!(nonce.len() == 12);
 
-			// Ehh, I'm too lazy to *also* tweak ChaCha20 to make it RFC-compliant
-			assert!(nonce[0] == 0 && nonce[1] == 0 && nonce[2] == 0 && nonce[3] == 0);
-
 			let mut tag = [0; 16];
 			tag.copy_from_slice(&key[0..16]);
 
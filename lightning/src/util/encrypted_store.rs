@@ -0,0 +1,304 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! A [`KVStore`] wrapper which encrypts all data before handing it to an underlying [`KVStore`].
+//!
+//! Serialized [`ChannelMonitor`]s and [`ChannelManager`]s contain revocation secrets and payment
+//! preimages, which users may not want to store in plaintext.
+//!
+//! [`ChannelMonitor`]: crate::chain::channelmonitor::ChannelMonitor
+//! [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+
+use chain::keysinterface::KeysInterface;
+use util::chacha20poly1305rfc::ChaCha20Poly1305RFC;
+use util::crypto::hkdf_extract_expand_twice;
+use util::persist::{KVStore, CHANNEL_MANAGER_PERSISTENCE_NAMESPACE, CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, ARCHIVED_CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, CHANNEL_MONITOR_UPDATE_PERSISTENCE_NAMESPACE};
+
+use core::ops::Deref;
+use io;
+use prelude::*;
+
+/// The version of the header prepended to each encrypted value.
+const ENCRYPTION_VERSION: u8 = 1;
+/// The length of the header: the version, a key id and a nonce.
+const HEADER_LEN: usize = 1 + KEY_ID_LEN + NONCE_LEN;
+const KEY_ID_LEN: usize = 4;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// The keys derived from a single user-supplied secret.
+struct EncryptionKeys {
+	encryption_key: [u8; 32],
+	key_id: [u8; KEY_ID_LEN],
+}
+
+impl EncryptionKeys {
+	fn from_secret(secret: &[u8; 32]) -> Self {
+		let (encryption_key, key_id_material) = hkdf_extract_expand_twice(b"LDK KVStore encryption", secret);
+		let mut key_id = [0; KEY_ID_LEN];
+		key_id.copy_from_slice(&key_id_material[..KEY_ID_LEN]);
+		Self { encryption_key, key_id }
+	}
+}
+
+/// Builds the additional authenticated data for a value, binding the ciphertext to its header
+/// and to the location it is stored at so that values cannot be swapped between keys.
+fn authenticated_data(header: &[u8], namespace: &str, key: &str) -> Vec<u8> {
+	let mut aad = Vec::with_capacity(header.len() + 4 + namespace.len() + key.len());
+	aad.extend_from_slice(header);
+	aad.extend_from_slice(&(namespace.len() as u16).to_be_bytes());
+	aad.extend_from_slice(namespace.as_bytes());
+	aad.extend_from_slice(&(key.len() as u16).to_be_bytes());
+	aad.extend_from_slice(key.as_bytes());
+	aad
+}
+
+/// A [`KVStore`] which authenticated-encrypts every value with ChaCha20Poly1305 before writing it
+/// to the wrapped [`KVStore`], and decrypts (and authenticates) it again when it is read.
+///
+/// As it is itself a [`KVStore`] it can be used anywhere the wrapped store could be, e.g. wrapped
+/// in a [`KVStorePersister`] or a [`MonitorUpdatingPersister`].
+///
+/// Encryption keys are derived from a user-supplied 32-byte secret. Each value is prefixed with a
+/// header holding a version byte, an identifier of the key it was encrypted with and a random
+/// 96-bit nonce, drawn from [`KeysInterface::get_secure_random_bytes`] on each write. The ciphertext is bound to its namespace and key, so values cannot be moved between
+/// keys without detection. Note that namespaces and keys themselves (e.g. channel funding
+/// outpoints) are stored in plaintext.
+///
+/// To rotate keys, construct the store with the new secret, passing the old one(s) as
+/// `previous_secrets`. Values encrypted under any of the given secrets can be read, while all
+/// writes use the new secret. Once every value has been re-written (e.g. via
+/// [`Self::reencrypt_persisted_data`]), the previous secrets may be dropped.
+///
+/// [`KVStorePersister`]: crate::util::persist::KVStorePersister
+/// [`MonitorUpdatingPersister`]: crate::util::persist::MonitorUpdatingPersister
+pub struct EncryptedKVStore<K: Deref, ES: Deref> where K::Target: KVStore, ES::Target: KeysInterface {
+	kv_store: K,
+	entropy_source: ES,
+	current_keys: EncryptionKeys,
+	previous_keys: Vec<EncryptionKeys>,
+}
+
+impl<K: Deref, ES: Deref> EncryptedKVStore<K, ES> where K::Target: KVStore, ES::Target: KeysInterface {
+	/// Constructs a new [`EncryptedKVStore`] which stores data in `kv_store`, encrypted with keys
+	/// derived from `secret`. Nonces are drawn from `entropy_source`.
+	///
+	/// Values which were encrypted using keys derived from any of the `previous_secrets` can still
+	/// be read. The `secret` and all `previous_secrets` should be generated randomly and must be
+	/// kept as safe as the node's seed.
+	pub fn new(kv_store: K, entropy_source: ES, secret: [u8; 32], previous_secrets: Vec<[u8; 32]>) -> Self {
+		Self {
+			kv_store,
+			entropy_source,
+			current_keys: EncryptionKeys::from_secret(&secret),
+			previous_keys: previous_secrets.iter().map(|secret| EncryptionKeys::from_secret(secret)).collect(),
+		}
+	}
+
+	/// Re-writes all data stored by a [`KVStorePersister`] or [`MonitorUpdatingPersister`],
+	/// encrypting any values which were encrypted with one of the previous secrets with the
+	/// current one.
+	///
+	/// This covers the [`ChannelManager`], network graph and scorer, all live and archived
+	/// [`ChannelMonitor`]s, as well as the per-channel namespaces holding pending
+	/// [`ChannelMonitorUpdate`]s. Other namespaces must be re-encrypted via
+	/// [`Self::reencrypt_namespace`].
+	///
+	/// [`KVStorePersister`]: crate::util::persist::KVStorePersister
+	/// [`MonitorUpdatingPersister`]: crate::util::persist::MonitorUpdatingPersister
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	/// [`ChannelMonitor`]: crate::chain::channelmonitor::ChannelMonitor
+	/// [`ChannelMonitorUpdate`]: crate::chain::channelmonitor::ChannelMonitorUpdate
+	pub fn reencrypt_persisted_data(&self) -> Result<(), io::Error> {
+		self.reencrypt_namespace(CHANNEL_MANAGER_PERSISTENCE_NAMESPACE)?;
+		for namespace in [CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, ARCHIVED_CHANNEL_MONITOR_PERSISTENCE_NAMESPACE].iter() {
+			for monitor_key in self.kv_store.list(namespace)? {
+				self.reencrypt_namespace(&format!("{}_{}", CHANNEL_MONITOR_UPDATE_PERSISTENCE_NAMESPACE, monitor_key))?;
+			}
+			self.reencrypt_namespace(namespace)?;
+		}
+		Ok(())
+	}
+
+	/// Re-writes all values in `namespace`, encrypting any which were encrypted with one of the
+	/// previous secrets with the current one.
+	pub fn reencrypt_namespace(&self, namespace: &str) -> Result<(), io::Error> {
+		for key in self.kv_store.list(namespace)? {
+			let stored_data = self.kv_store.read(namespace, &key)?;
+			if stored_data.len() > HEADER_LEN && stored_data[1..1 + KEY_ID_LEN] == self.current_keys.key_id {
+				continue;
+			}
+			let data = self.decrypt(namespace, &key, &stored_data)?;
+			self.write(namespace, &key, &data)?;
+		}
+		Ok(())
+	}
+
+	fn encrypt(&self, namespace: &str, key: &str, data: &[u8]) -> Vec<u8> {
+		let keys = &self.current_keys;
+
+		let mut nonce = [0; NONCE_LEN];
+		nonce.copy_from_slice(&self.entropy_source.get_secure_random_bytes()[..NONCE_LEN]);
+
+		let mut res = Vec::with_capacity(HEADER_LEN + data.len() + TAG_LEN);
+		res.push(ENCRYPTION_VERSION);
+		res.extend_from_slice(&keys.key_id);
+		res.extend_from_slice(&nonce);
+
+		let mut chacha = ChaCha20Poly1305RFC::new(&keys.encryption_key, &nonce, &authenticated_data(&res, namespace, key));
+		let mut ciphertext = vec![0; data.len()];
+		let mut tag = [0; TAG_LEN];
+		chacha.encrypt(data, &mut ciphertext, &mut tag);
+		res.extend_from_slice(&ciphertext);
+		res.extend_from_slice(&tag);
+		res
+	}
+
+	fn decrypt(&self, namespace: &str, key: &str, stored_data: &[u8]) -> Result<Vec<u8>, io::Error> {
+		if stored_data.len() < HEADER_LEN + TAG_LEN {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "Encrypted data is too short"));
+		}
+		if stored_data[0] != ENCRYPTION_VERSION {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown encryption version"));
+		}
+		let (header, ciphertext_and_tag) = stored_data.split_at(HEADER_LEN);
+		let key_id = &header[1..1 + KEY_ID_LEN];
+		let keys = match core::iter::once(&self.current_keys).chain(self.previous_keys.iter()).find(|keys| keys.key_id == key_id) {
+			Some(keys) => keys,
+			None => return Err(io::Error::new(io::ErrorKind::InvalidData, "Data was encrypted with an unknown key")),
+		};
+		let nonce = &header[1 + KEY_ID_LEN..];
+		let (ciphertext, tag) = ciphertext_and_tag.split_at(ciphertext_and_tag.len() - TAG_LEN);
+
+		let mut chacha = ChaCha20Poly1305RFC::new(&keys.encryption_key, nonce, &authenticated_data(header, namespace, key));
+		let mut data = vec![0; ciphertext.len()];
+		if !chacha.decrypt(ciphertext, &mut data, tag) {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "Failed to decrypt data"));
+		}
+		Ok(data)
+	}
+}
+
+impl<K: Deref, ES: Deref> KVStore for EncryptedKVStore<K, ES> where K::Target: KVStore, ES::Target: KeysInterface {
+	fn read(&self, namespace: &str, key: &str) -> Result<Vec<u8>, io::Error> {
+		let stored_data = self.kv_store.read(namespace, key)?;
+		self.decrypt(namespace, key, &stored_data)
+	}
+
+	fn write(&self, namespace: &str, key: &str, buf: &[u8]) -> Result<(), io::Error> {
+		self.kv_store.write(namespace, key, &self.encrypt(namespace, key, buf))
+	}
+
+	fn remove(&self, namespace: &str, key: &str) -> Result<(), io::Error> {
+		self.kv_store.remove(namespace, key)
+	}
+
+	fn list(&self, namespace: &str) -> Result<Vec<String>, io::Error> {
+		self.kv_store.list(namespace)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use chain::keysinterface::KeysManager;
+	use chain::transaction::OutPoint;
+	use util::persist::{channel_monitor_key, channel_monitor_update_namespace};
+	use util::test_utils::TestStore;
+
+	#[test]
+	fn test_encrypted_kv_store() {
+		let store = TestStore::new(false);
+		let keys_manager = KeysManager::new(&[42; 32], 42, 42);
+		let encrypted_store = EncryptedKVStore::new(&store, &keys_manager, [42; 32], Vec::new());
+		let data = [0xab; 100];
+
+		encrypted_store.write("namespace", "key", &data).unwrap();
+		assert_eq!(encrypted_store.read("namespace", "key").unwrap(), data.to_vec());
+		assert_eq!(encrypted_store.list("namespace").unwrap(), vec!["key".to_string()]);
+
+		// Re-writing the same data uses a fresh nonce.
+		let stored_data = store.read("namespace", "key").unwrap();
+		encrypted_store.write("namespace", "key", &data).unwrap();
+		assert_ne!(store.read("namespace", "key").unwrap(), stored_data);
+		assert_eq!(encrypted_store.read("namespace", "key").unwrap(), data.to_vec());
+
+		// The stored data is not the plaintext and is tied to the key it was written under.
+		let stored_data = store.read("namespace", "key").unwrap();
+		assert_eq!(stored_data.len(), HEADER_LEN + data.len() + TAG_LEN);
+		assert!(!stored_data.windows(16).any(|window| window == &data[..16]));
+		store.write("namespace", "other_key", &stored_data).unwrap();
+		assert_eq!(encrypted_store.read("namespace", "other_key").unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+		// Tampering with the header or ciphertext is detected.
+		for i in [0, 1, 5, 8, HEADER_LEN - 1, HEADER_LEN, stored_data.len() - 1].iter() {
+			let mut tampered_data = stored_data.clone();
+			tampered_data[*i] ^= 1;
+			store.write("namespace", "key", &tampered_data).unwrap();
+			assert_eq!(encrypted_store.read("namespace", "key").unwrap_err().kind(), io::ErrorKind::InvalidData);
+		}
+		store.write("namespace", "key", &stored_data[..HEADER_LEN]).unwrap();
+		assert_eq!(encrypted_store.read("namespace", "key").unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+		// Data encrypted with a different secret cannot be read.
+		store.write("namespace", "key", &stored_data).unwrap();
+		let other_store = EncryptedKVStore::new(&store, &keys_manager, [43; 32], Vec::new());
+		assert_eq!(other_store.read("namespace", "key").unwrap_err().kind(), io::ErrorKind::InvalidData);
+	}
+
+	#[test]
+	fn test_encrypted_kv_store_key_rotation() {
+		let store = TestStore::new(false);
+		let keys_manager = KeysManager::new(&[42; 32], 42, 42);
+		let old_store = EncryptedKVStore::new(&store, &keys_manager, [1; 32], Vec::new());
+		old_store.write("namespace", "key", &[1; 10]).unwrap();
+		old_store.write("namespace", "other_key", &[2; 10]).unwrap();
+
+		// After rotating, data written with the previous secret can still be read.
+		let new_store = EncryptedKVStore::new(&store, &keys_manager, [2; 32], vec![[1; 32]]);
+		assert_eq!(new_store.read("namespace", "key").unwrap(), vec![1; 10]);
+		new_store.write("namespace", "key", &[3; 10]).unwrap();
+		assert_eq!(new_store.read("namespace", "key").unwrap(), vec![3; 10]);
+		assert!(old_store.read("namespace", "key").is_err());
+		assert_eq!(old_store.read("namespace", "other_key").unwrap(), vec![2; 10]);
+
+		// Once re-encrypted, the previous secret is no longer needed.
+		new_store.reencrypt_namespace("namespace").unwrap();
+		let rotated_store = EncryptedKVStore::new(&store, &keys_manager, [2; 32], Vec::new());
+		assert_eq!(rotated_store.read("namespace", "key").unwrap(), vec![3; 10]);
+		assert_eq!(rotated_store.read("namespace", "other_key").unwrap(), vec![2; 10]);
+	}
+
+	#[test]
+	fn test_reencrypt_persisted_data() {
+		// Check that all data written by the persisters, including the per-channel namespaces
+		// holding `ChannelMonitorUpdate`s, is re-encrypted.
+		let store = TestStore::new(false);
+		let keys_manager = KeysManager::new(&[42; 32], 42, 42);
+		let old_store = EncryptedKVStore::new(&store, &keys_manager, [1; 32], Vec::new());
+		let funding_txo = OutPoint { txid: Default::default(), index: 1 };
+		let update_namespace = channel_monitor_update_namespace(&funding_txo);
+		let locations = [
+			(CHANNEL_MANAGER_PERSISTENCE_NAMESPACE.to_string(), "manager".to_string()),
+			(CHANNEL_MONITOR_PERSISTENCE_NAMESPACE.to_string(), channel_monitor_key(&funding_txo)),
+			(update_namespace.clone(), "3".to_string()),
+			(ARCHIVED_CHANNEL_MONITOR_PERSISTENCE_NAMESPACE.to_string(), channel_monitor_key(&OutPoint { txid: Default::default(), index: 2 })),
+		];
+		for (namespace, key) in locations.iter() {
+			old_store.write(namespace, key, &[1; 10]).unwrap();
+		}
+
+		let new_store = EncryptedKVStore::new(&store, &keys_manager, [2; 32], vec![[1; 32]]);
+		new_store.reencrypt_persisted_data().unwrap();
+		let rotated_store = EncryptedKVStore::new(&store, &keys_manager, [2; 32], Vec::new());
+		for (namespace, key) in locations.iter() {
+			assert_eq!(rotated_store.read(namespace, key).unwrap(), vec![1; 10]);
+		}
+	}
+}
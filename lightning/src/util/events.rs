@@ -430,6 +430,29 @@ pub enum Event {
 		/// Our starting balance in the channel if the request is accepted, in milli-satoshi.
 		push_msat: u64,
 	},
+	/// Indicates a circular payment sent to ourselves via [`ChannelManager::send_rebalance`] has
+	/// been claimed, moving liquidity from the channels used by its first hops to the channels
+	/// used by its last hops.
+	///
+	/// This is generated in place of [`Event::PaymentSent`]. As with it, an
+	/// [`Event::PaymentPathSuccessful`] will follow for each path.
+	///
+	/// [`ChannelManager::send_rebalance`]: crate::ln::channelmanager::ChannelManager::send_rebalance
+	RebalanceCompleted {
+		/// The id returned by [`ChannelManager::send_rebalance`].
+		///
+		/// [`ChannelManager::send_rebalance`]: crate::ln::channelmanager::ChannelManager::send_rebalance
+		payment_id: PaymentId,
+		/// The hash returned by [`ChannelManager::send_rebalance`].
+		///
+		/// [`ChannelManager::send_rebalance`]: crate::ln::channelmanager::ChannelManager::send_rebalance
+		payment_hash: PaymentHash,
+		/// The total fee which was paid to intermediate hops to move the liquidity, across all
+		/// paths.
+		///
+		/// See [`Event::PaymentSent::fee_paid_msat`] for more details.
+		fee_paid_msat: Option<u64>,
+	},
 }
 
 impl Writeable for Event {
@@ -547,6 +570,14 @@ impl Writeable for Event {
 				// We never write the OpenChannelRequest events as, upon disconnection, peers
 				// drop any channels which have not yet exchanged funding_signed.
 			},
+			&Event::RebalanceCompleted { ref payment_id, ref payment_hash, ref fee_paid_msat } => {
+				19u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, payment_id, required),
+					(2, payment_hash, required),
+					(4, fee_paid_msat, option),
+				})
+			},
 			// Note that, going forward, all new events must only write data inside of
 			// `write_tlv_fields`. Versions 0.0.101+ will ignore odd-numbered events that write
 			// data via `write_tlv_fields`.
@@ -743,6 +774,24 @@ impl MaybeReadable for Event {
 				// Value 17 is used for `Event::OpenChannelRequest`.
 				Ok(None)
 			},
+			19u8 => {
+				let f = || {
+					let mut payment_id = PaymentId([0; 32]);
+					let mut payment_hash = PaymentHash([0; 32]);
+					let mut fee_paid_msat = None;
+					read_tlv_fields!(reader, {
+						(0, payment_id, required),
+						(2, payment_hash, required),
+						(4, fee_paid_msat, option),
+					});
+					Ok(Some(Event::RebalanceCompleted {
+						payment_id,
+						payment_hash,
+						fee_paid_msat,
+					}))
+				};
+				f()
+			},
 			// Versions prior to 0.0.100 did not ignore odd types, instead returning InvalidValue.
 			// Version 0.0.100 failed to properly ignore odd types, possibly resulting in corrupt
 			// reads.
@@ -20,6 +20,9 @@ pub mod errors;
 pub mod ser;
 pub mod message_signing;
 pub mod invoice;
+pub mod encrypted_store;
+pub mod validating_signer;
+pub mod wakers;
 
 pub(crate) mod atomic_counter;
 pub(crate) mod byte_utils;
@@ -44,6 +47,7 @@ pub(crate) mod crypto;
 // These have to come after macro_logger to build
 pub mod logger;
 pub mod config;
+pub mod persist;
 
 #[cfg(any(test, fuzzing, feature = "_test_utils"))]
 pub mod test_utils;
@@ -0,0 +1,545 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Traits for persisting the [`ChannelManager`], [`NetworkGraph`] and scorer state which a node
+//! keeps in memory and writes out in the background, as well as a [`KVStore`] abstraction through
+//! which all of it (and [`ChannelMonitor`]s) can be stored.
+//!
+//! [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+//! [`ChannelMonitor`]: crate::chain::channelmonitor::ChannelMonitor
+
+use core::ops::Deref;
+use bitcoin::hash_types::{BlockHash, Txid};
+use bitcoin::hashes::hex::{FromHex, ToHex};
+
+use chain;
+use chain::chaininterface::{BroadcasterInterface, FeeEstimator};
+use chain::chainmonitor::{MonitorUpdateId, Persist};
+use chain::channelmonitor::{ChannelMonitor, ChannelMonitorUpdate, CLOSED_CHANNEL_UPDATE_ID};
+use chain::keysinterface::{KeysInterface, Sign};
+use chain::transaction::OutPoint;
+use ln::channelmanager::ChannelManager;
+use routing::network_graph::NetworkGraph;
+use routing::scoring::WriteableScore;
+use util::logger::Logger;
+use util::ser::{Readable, ReadableArgs, Writeable};
+
+use io;
+use prelude::*;
+
+/// The namespace under which the [`ChannelManager`] is persisted by the [`Persister`]
+/// implementation for [`KVStore`]s.
+///
+/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+pub const CHANNEL_MANAGER_PERSISTENCE_NAMESPACE: &str = "";
+/// The key under which the [`ChannelManager`] is persisted by the [`Persister`] implementation
+/// for [`KVStore`]s.
+///
+/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+pub const CHANNEL_MANAGER_PERSISTENCE_KEY: &str = "manager";
+
+/// The namespace under which [`ChannelMonitor`]s are persisted by the [`Persist`] implementation
+/// for [`KVStore`]s. Each monitor is stored under the key `<funding txid>_<funding output index>`.
+pub const CHANNEL_MONITOR_PERSISTENCE_NAMESPACE: &str = "monitors";
+
+/// The namespace to which [`ChannelMonitor`]s of fully resolved channels are moved when archived
+/// by the [`Persist`] implementation for [`KVStore`]s, keyed like those in
+/// [`CHANNEL_MONITOR_PERSISTENCE_NAMESPACE`]. Monitors in this namespace are not read on startup.
+pub const ARCHIVED_CHANNEL_MONITOR_PERSISTENCE_NAMESPACE: &str = "archived_monitors";
+
+/// The prefix of the namespaces under which [`ChannelMonitorUpdate`]s are persisted by a
+/// [`MonitorUpdatingPersister`]. The updates for each channel are stored in the namespace
+/// `monitor_updates_<funding txid>_<funding output index>`, each keyed by its update id.
+pub const CHANNEL_MONITOR_UPDATE_PERSISTENCE_NAMESPACE: &str = "monitor_updates";
+
+/// The namespace under which the [`NetworkGraph`] is persisted by the [`Persister`]
+/// implementation for [`KVStore`]s.
+pub const NETWORK_GRAPH_PERSISTENCE_NAMESPACE: &str = "";
+/// The key under which the [`NetworkGraph`] is persisted by the [`Persister`] implementation for
+/// [`KVStore`]s.
+pub const NETWORK_GRAPH_PERSISTENCE_KEY: &str = "network_graph";
+
+/// The namespace under which the scorer is persisted by the [`Persister`] implementation for
+/// [`KVStore`]s.
+pub const SCORER_PERSISTENCE_NAMESPACE: &str = "";
+/// The key under which the scorer is persisted by the [`Persister`] implementation for
+/// [`KVStore`]s.
+pub const SCORER_PERSISTENCE_KEY: &str = "scorer";
+
+/// Provides an interface that allows storage and retrieval of persisted values that are associated
+/// with given keys.
+///
+/// Keys are grouped into namespaces, where the empty namespace holds top-level objects such as the
+/// [`ChannelManager`]. Both namespaces and keys are restricted to ASCII alphanumeric characters and
+/// `_`, so implementations may map them directly onto, e.g., directory and file names or table
+/// and row identifiers.
+///
+/// Wrapping an implementation of this trait in a [`KVStorePersister`] gives implementations of
+/// [`Persister`] and [`Persist`], storing each object under the keys given by the
+/// `*_PERSISTENCE_NAMESPACE` and `*_PERSISTENCE_KEY` constants in this module.
+/// [`read_channel_monitors`] reads the stored [`ChannelMonitor`]s back out on startup.
+///
+/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+pub trait KVStore {
+	/// Returns the data stored for the given `namespace` and `key`.
+	///
+	/// Returns an [`ErrorKind::NotFound`] error if the given `key` could not be found in the given
+	/// `namespace`.
+	///
+	/// [`ErrorKind::NotFound`]: io::ErrorKind::NotFound
+	fn read(&self, namespace: &str, key: &str) -> Result<Vec<u8>, io::Error>;
+
+	/// Persists the given data under the given `key` in the given `namespace`, replacing any data
+	/// previously stored there.
+	///
+	/// Will create the given `namespace` if not already present in the store. The write must be
+	/// durable (and atomic with respect to the previously stored data) before `Ok` is returned.
+	fn write(&self, namespace: &str, key: &str, buf: &[u8]) -> Result<(), io::Error>;
+
+	/// Removes any data that had previously been persisted under the given `key` in the given
+	/// `namespace`.
+	///
+	/// Returns `Ok` if the `key` did not exist in the first place.
+	fn remove(&self, namespace: &str, key: &str) -> Result<(), io::Error>;
+
+	/// Returns a list of keys that are stored under the given `namespace`, in no particular order.
+	///
+	/// Returns an empty list if the `namespace` is unknown.
+	fn list(&self, namespace: &str) -> Result<Vec<String>, io::Error>;
+}
+
+/// Trait that handles persisting a [`ChannelManager`], [`NetworkGraph`], and [`WriteableScore`]
+/// to disk.
+///
+/// Unlike [`ChannelMonitor`]s, which are persisted via [`chainmonitor::Persist`], none of these
+/// objects must be persisted before we proceed with any particular operation. Instead they are
+/// typically written out periodically, and once more at shutdown, eg by
+/// `lightning-background-processor`.
+///
+/// [`ChannelMonitor`]: crate::chain::channelmonitor::ChannelMonitor
+/// [`chainmonitor::Persist`]: crate::chain::chainmonitor::Persist
+pub trait Persister<'a, Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref, S: WriteableScore<'a>>
+	where M::Target: 'static + chain::Watch<Signer>,
+		T::Target: 'static + BroadcasterInterface,
+		K::Target: 'static + KeysInterface<Signer = Signer>,
+		F::Target: 'static + FeeEstimator,
+		L::Target: 'static + Logger,
+{
+	/// Persist the given [`ChannelManager`] to disk, returning an error if persistence failed.
+	///
+	/// If [`ChannelManager`] persistence fails and the persisted manager becomes out-of-date,
+	/// there is a risk of channels force-closing on startup when the manager realizes it's
+	/// outdated.
+	fn persist_manager(&self, channel_manager: &ChannelManager<Signer, M, T, K, F, L>) -> Result<(), io::Error>;
+
+	/// Persist the given [`NetworkGraph`] to disk, returning an error if persistence failed.
+	///
+	/// Losing the [`NetworkGraph`] only requires it to be re-synced from the network, thus
+	/// implementations may wish to simply log failures rather than returning them.
+	fn persist_graph(&self, network_graph: &NetworkGraph) -> Result<(), io::Error>;
+
+	/// Persist the given [`WriteableScore`] to disk, returning an error if persistence failed.
+	fn persist_scorer(&self, scorer: &S) -> Result<(), io::Error>;
+}
+
+/// Implements [`Persister`] and [`Persist`] on top of a [`KVStore`], storing each object under the
+/// keys given by the `*_PERSISTENCE_NAMESPACE` and `*_PERSISTENCE_KEY` constants in this module.
+///
+/// [`ChannelMonitor`]s are re-written in full on each update. See [`MonitorUpdatingPersister`]
+/// for a [`Persist`] implementation which stores [`ChannelMonitorUpdate`]s individually instead.
+pub struct KVStorePersister<K: Deref> where K::Target: KVStore {
+	kv_store: K,
+}
+
+impl<K: Deref> KVStorePersister<K> where K::Target: KVStore {
+	/// Constructs a new [`KVStorePersister`] storing data in `kv_store`.
+	pub fn new(kv_store: K) -> Self {
+		Self { kv_store }
+	}
+
+	/// Gets a reference to the wrapped [`KVStore`].
+	pub fn kv_store(&self) -> &K::Target {
+		&*self.kv_store
+	}
+}
+
+impl<'a, KV: Deref, Signer: Sign, M: Deref, T: Deref, K: Deref, F: Deref, L: Deref, S: WriteableScore<'a>> Persister<'a, Signer, M, T, K, F, L, S> for KVStorePersister<KV>
+	where KV::Target: KVStore,
+		M::Target: 'static + chain::Watch<Signer>,
+		T::Target: 'static + BroadcasterInterface,
+		K::Target: 'static + KeysInterface<Signer = Signer>,
+		F::Target: 'static + FeeEstimator,
+		L::Target: 'static + Logger,
+{
+	fn persist_manager(&self, channel_manager: &ChannelManager<Signer, M, T, K, F, L>) -> Result<(), io::Error> {
+		self.kv_store.write(CHANNEL_MANAGER_PERSISTENCE_NAMESPACE, CHANNEL_MANAGER_PERSISTENCE_KEY, &channel_manager.encode())
+	}
+
+	fn persist_graph(&self, network_graph: &NetworkGraph) -> Result<(), io::Error> {
+		self.kv_store.write(NETWORK_GRAPH_PERSISTENCE_NAMESPACE, NETWORK_GRAPH_PERSISTENCE_KEY, &network_graph.encode())
+	}
+
+	fn persist_scorer(&self, scorer: &S) -> Result<(), io::Error> {
+		self.kv_store.write(SCORER_PERSISTENCE_NAMESPACE, SCORER_PERSISTENCE_KEY, &scorer.encode())
+	}
+}
+
+/// Gets the key under which the [`ChannelMonitor`] for the channel funded by `funding_txo` is
+/// stored in [`CHANNEL_MONITOR_PERSISTENCE_NAMESPACE`].
+pub fn channel_monitor_key(funding_txo: &OutPoint) -> String {
+	format!("{}_{}", funding_txo.txid.to_hex(), funding_txo.index)
+}
+
+impl<ChannelSigner: Sign, K: Deref> Persist<ChannelSigner> for KVStorePersister<K> where K::Target: KVStore {
+	// TODO: We really need a way for the persister to inform the user that its time to crash/shut
+	// down once these start returning failure.
+	// A PermanentFailure implies we need to shut down since we're force-closing channels without
+	// even broadcasting!
+
+	fn persist_new_channel(&self, funding_txo: OutPoint, monitor: &ChannelMonitor<ChannelSigner>, _update_id: MonitorUpdateId) -> Result<(), chain::ChannelMonitorUpdateErr> {
+		self.kv_store.write(CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, &channel_monitor_key(&funding_txo), &monitor.encode())
+			.map_err(|_| chain::ChannelMonitorUpdateErr::PermanentFailure)
+	}
+
+	fn update_persisted_channel(&self, funding_txo: OutPoint, _update: &Option<ChannelMonitorUpdate>, monitor: &ChannelMonitor<ChannelSigner>, _update_id: MonitorUpdateId) -> Result<(), chain::ChannelMonitorUpdateErr> {
+		self.kv_store.write(CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, &channel_monitor_key(&funding_txo), &monitor.encode())
+			.map_err(|_| chain::ChannelMonitorUpdateErr::PermanentFailure)
+	}
+
+	fn archive_persisted_channel(&self, funding_txo: OutPoint, monitor: &ChannelMonitor<ChannelSigner>) {
+		let key = channel_monitor_key(&funding_txo);
+		if self.kv_store.write(ARCHIVED_CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, &key, &monitor.encode()).is_ok() {
+			let _ = self.kv_store.remove(CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, &key);
+		}
+	}
+}
+
+/// Reads all [`ChannelMonitor`]s stored in the given [`KVStore`] by a [`KVStorePersister`], checking that each was stored under the key matching its funding outpoint.
+pub fn read_channel_monitors<KV: Deref, Signer: Sign, K: Deref>(
+	kv_store: KV, keys_manager: K
+) -> Result<Vec<(BlockHash, ChannelMonitor<Signer>)>, io::Error>
+	where KV::Target: KVStore,
+		K::Target: KeysInterface<Signer = Signer> + Sized,
+{
+	let mut res = Vec::new();
+	for stored_key in kv_store.list(CHANNEL_MONITOR_PERSISTENCE_NAMESPACE)? {
+		if !stored_key.is_ascii() || stored_key.len() < 66 {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid ChannelMonitor key"));
+		}
+
+		let txid = match Txid::from_hex(stored_key.split_at(64).0) {
+			Ok(txid) => txid,
+			Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid tx ID in key")),
+		};
+
+		let index: u16 = match stored_key.split_at(65).1.parse() {
+			Ok(index) => index,
+			Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid tx index in key")),
+		};
+
+		let contents = kv_store.read(CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, &stored_key)?;
+		match <(BlockHash, ChannelMonitor<Signer>)>::read(&mut io::Cursor::new(&contents), &*keys_manager) {
+			Ok((block_hash, channel_monitor)) => {
+				if channel_monitor.get_funding_txo().0.txid != txid || channel_monitor.get_funding_txo().0.index != index {
+					return Err(io::Error::new(io::ErrorKind::InvalidData, "ChannelMonitor was stored under the wrong key"));
+				}
+				res.push((block_hash, channel_monitor));
+			},
+			Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "Failed to deserialize ChannelMonitor")),
+		}
+	}
+	Ok(res)
+}
+
+/// Gets the namespace in which a [`MonitorUpdatingPersister`] stores the pending
+/// [`ChannelMonitorUpdate`]s for the channel funded by `funding_txo`.
+pub fn channel_monitor_update_namespace(funding_txo: &OutPoint) -> String {
+	format!("{}_{}", CHANNEL_MONITOR_UPDATE_PERSISTENCE_NAMESPACE, channel_monitor_key(funding_txo))
+}
+
+/// A [`Persist`] implementation which, rather than re-writing the full [`ChannelMonitor`] on each
+/// update, stores each [`ChannelMonitorUpdate`] individually in the wrapped [`KVStore`].
+///
+/// The size of a [`ChannelMonitor`] grows with the number of state updates its channel has seen,
+/// whereas each [`ChannelMonitorUpdate`] is small, thus for busy channels this substantially
+/// reduces the amount of data written per payment.
+///
+/// Full [`ChannelMonitor`]s are still written under [`CHANNEL_MONITOR_PERSISTENCE_NAMESPACE`]:
+/// - when the channel is first persisted,
+/// - on every `maximum_pending_updates`th update (i.e. whenever the update id is divisible by
+///   it),
+/// - on updates which do not come with a [`ChannelMonitorUpdate`], e.g. during chain sync, and
+/// - on updates to closed channels (with an update id of [`CLOSED_CHANNEL_UPDATE_ID`]).
+///
+/// After each full write, all updates which were consolidated into it are removed. Updates are
+/// stored in the namespace given by [`channel_monitor_update_namespace`] and are replayed onto the
+/// last full [`ChannelMonitor`] by [`Self::read_channelmonitors`], which must be used instead of
+/// [`read_channel_monitors`] on startup.
+///
+/// Note that this only handles [`ChannelMonitor`]s - the wrapped [`KVStore`] may be wrapped in a
+/// [`KVStorePersister`] to persist the [`ChannelManager`], [`NetworkGraph`] and scorer.
+///
+/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+pub struct MonitorUpdatingPersister<K: Deref> where K::Target: KVStore {
+	kv_store: K,
+	maximum_pending_updates: u64,
+}
+
+impl<K: Deref> MonitorUpdatingPersister<K> where K::Target: KVStore {
+	/// Constructs a new [`MonitorUpdatingPersister`] storing data in `kv_store`.
+	///
+	/// A full [`ChannelMonitor`] is written (and pending updates consolidated) every
+	/// `maximum_pending_updates` updates. Setting it to 0 or 1 results in the full
+	/// [`ChannelMonitor`] being written on every update.
+	pub fn new(kv_store: K, maximum_pending_updates: u64) -> Self {
+		Self { kv_store, maximum_pending_updates }
+	}
+
+	/// Reads all [`ChannelMonitor`]s stored in the wrapped [`KVStore`], applying any pending
+	/// [`ChannelMonitorUpdate`]s to them, in order of their update ids.
+	///
+	/// The `broadcaster`, `fee_estimator` and `logger` are passed to
+	/// [`ChannelMonitor::update_monitor`] when applying updates.
+	pub fn read_channelmonitors<Signer: Sign, KM: Deref, B: Deref, F: Deref, L: Deref>(
+		&self, keys_manager: KM, broadcaster: B, fee_estimator: F, logger: L
+	) -> Result<Vec<(BlockHash, ChannelMonitor<Signer>)>, io::Error>
+		where KM::Target: KeysInterface<Signer = Signer> + Sized,
+			B::Target: BroadcasterInterface,
+			F::Target: FeeEstimator,
+			L::Target: Logger,
+	{
+		let monitors = read_channel_monitors(&*self.kv_store, keys_manager)?;
+		for (_, monitor) in monitors.iter() {
+			let namespace = channel_monitor_update_namespace(&monitor.get_funding_txo().0);
+			let mut update_ids = Vec::new();
+			for stored_key in self.kv_store.list(&namespace)? {
+				match stored_key.parse::<u64>() {
+					Ok(update_id) => update_ids.push(update_id),
+					Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid ChannelMonitorUpdate key")),
+				}
+			}
+			update_ids.sort_unstable();
+			for update_id in update_ids {
+				// Updates which were already consolidated into the full monitor may be left behind
+				// if we crashed before removing them.
+				if update_id <= monitor.get_latest_update_id() {
+					continue;
+				}
+				let contents = self.kv_store.read(&namespace, &update_id.to_string())?;
+				let update: ChannelMonitorUpdate = match Readable::read(&mut io::Cursor::new(&contents)) {
+					Ok(update) => update,
+					Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "Failed to deserialize ChannelMonitorUpdate")),
+				};
+				if update.update_id != update_id || update_id != monitor.get_latest_update_id() + 1 {
+					return Err(io::Error::new(io::ErrorKind::InvalidData, "Missing or mislabeled ChannelMonitorUpdate"));
+				}
+				// `update_monitor` returns an `Err` for updates which were applied after the funding
+				// output was spent (e.g. the `ChannelForceClosed` update on a force-close), exactly as
+				// it did when the update was first applied and persisted. Thus, as long as the update
+				// was applied we carry on replaying.
+				if monitor.update_monitor(&update, &broadcaster, &fee_estimator, &logger).is_err() {
+					if monitor.get_latest_update_id() != update_id {
+						return Err(io::Error::new(io::ErrorKind::InvalidData, "Failed to apply ChannelMonitorUpdate"));
+					}
+					log_debug!(logger, "Replayed ChannelMonitorUpdate {} for channel {} returned an error, continuing", update_id, log_bytes!(monitor.get_funding_txo().0.to_channel_id()));
+				}
+			}
+		}
+		Ok(monitors)
+	}
+
+	/// Writes the full `monitor`, then removes any updates which have been consolidated into it.
+	fn persist_full_monitor<ChannelSigner: Sign>(&self, funding_txo: &OutPoint, monitor: &ChannelMonitor<ChannelSigner>) -> Result<(), chain::ChannelMonitorUpdateErr> {
+		self.kv_store.write(CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, &channel_monitor_key(funding_txo), &monitor.encode())
+			.map_err(|_| chain::ChannelMonitorUpdateErr::PermanentFailure)?;
+
+		// Once the full monitor is durably written any stale updates are ignored on read, thus we
+		// don't fail the persistence if we're unable to clean them up here.
+		let namespace = channel_monitor_update_namespace(funding_txo);
+		if let Ok(stored_keys) = self.kv_store.list(&namespace) {
+			for stored_key in stored_keys {
+				match stored_key.parse::<u64>() {
+					Ok(update_id) if update_id <= monitor.get_latest_update_id() => {
+						let _ = self.kv_store.remove(&namespace, &stored_key);
+					},
+					_ => {},
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+impl<ChannelSigner: Sign, K: Deref> Persist<ChannelSigner> for MonitorUpdatingPersister<K> where K::Target: KVStore {
+	fn persist_new_channel(&self, funding_txo: OutPoint, monitor: &ChannelMonitor<ChannelSigner>, _update_id: MonitorUpdateId) -> Result<(), chain::ChannelMonitorUpdateErr> {
+		self.persist_full_monitor(&funding_txo, monitor)
+	}
+
+	fn update_persisted_channel(&self, funding_txo: OutPoint, update: &Option<ChannelMonitorUpdate>, monitor: &ChannelMonitor<ChannelSigner>, _update_id: MonitorUpdateId) -> Result<(), chain::ChannelMonitorUpdateErr> {
+		match update {
+			Some(update) if update.update_id != CLOSED_CHANNEL_UPDATE_ID &&
+				self.maximum_pending_updates > 1 && update.update_id % self.maximum_pending_updates != 0 =>
+			{
+				self.kv_store.write(&channel_monitor_update_namespace(&funding_txo), &update.update_id.to_string(), &update.encode())
+					.map_err(|_| chain::ChannelMonitorUpdateErr::PermanentFailure)
+			},
+			_ => self.persist_full_monitor(&funding_txo, monitor),
+		}
+	}
+
+	fn archive_persisted_channel(&self, funding_txo: OutPoint, monitor: &ChannelMonitor<ChannelSigner>) {
+		let key = channel_monitor_key(&funding_txo);
+		if self.kv_store.write(ARCHIVED_CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, &key, &monitor.encode()).is_err() {
+			return;
+		}
+		if self.kv_store.remove(CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, &key).is_ok() {
+			let namespace = channel_monitor_update_namespace(&funding_txo);
+			if let Ok(stored_keys) = self.kv_store.list(&namespace) {
+				for stored_key in stored_keys {
+					let _ = self.kv_store.remove(&namespace, &stored_key);
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bitcoin::blockdata::block::{Block, BlockHeader};
+	use ::{check_added_monitors, check_closed_broadcast, check_closed_event};
+	use chain::ChannelMonitorUpdateErr;
+	use ln::features::InitFeatures;
+	use ln::functional_test_utils::*;
+	use util::events::{ClosureReason, MessageSendEventsProvider};
+	use util::test_utils::{self, TestStore};
+	use sync::{Arc, Mutex};
+
+	#[test]
+	fn test_kv_store_monitor_persistence() {
+		// Check that monitors written via the `Persist` implementation for `KVStore`s are stored
+		// under the expected key and can be read back out.
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+		let chan = create_announced_chan_between_nodes(&nodes, 0, 1, InitFeatures::known(), InitFeatures::known());
+		nodes[1].node.force_close_channel(&chan.2).unwrap();
+		check_closed_event!(nodes[1], 1, ClosureReason::HolderForceClosed);
+		let mut added_monitors = nodes[1].chain_monitor.added_monitors.lock().unwrap();
+		let update_map = nodes[1].chain_monitor.latest_monitor_update_id.lock().unwrap();
+		let update_id = update_map.get(&added_monitors[0].0.to_channel_id()).unwrap();
+		let funding_txo = added_monitors[0].0;
+
+		let store = TestStore::new(false);
+		let persister = KVStorePersister::new(&store);
+		assert!(read_channel_monitors(&store, nodes[1].keys_manager).unwrap().is_empty());
+		persister.persist_new_channel(funding_txo, &added_monitors[0].1, update_id.2).unwrap();
+		assert_eq!(store.list(CHANNEL_MONITOR_PERSISTENCE_NAMESPACE).unwrap(), vec![channel_monitor_key(&funding_txo)]);
+		persister.update_persisted_channel(funding_txo, &None, &added_monitors[0].1, update_id.2).unwrap();
+
+		let read_monitors = read_channel_monitors(&store, nodes[1].keys_manager).unwrap();
+		assert_eq!(read_monitors.len(), 1);
+		assert_eq!(read_monitors[0].1.get_funding_txo().0, funding_txo);
+		assert_eq!(read_monitors[0].1.get_latest_update_id(), added_monitors[0].1.get_latest_update_id());
+
+		// A monitor stored under some other channel's key is rejected on read.
+		let data = store.read(CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, &channel_monitor_key(&funding_txo)).unwrap();
+		store.remove(CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, &channel_monitor_key(&funding_txo)).unwrap();
+		let wrong_txo = OutPoint { txid: funding_txo.txid, index: funding_txo.index + 1 };
+		store.write(CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, &channel_monitor_key(&wrong_txo), &data).unwrap();
+		match read_channel_monitors(&store, nodes[1].keys_manager) {
+			Err(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidData),
+			Ok(_) => panic!("Read a monitor stored under the wrong key"),
+		}
+
+		// Failing writes result in a permanent failure.
+		let failing_store = TestStore::new(true);
+		match KVStorePersister::new(&failing_store).persist_new_channel(funding_txo, &added_monitors[0].1, update_id.2) {
+			Err(ChannelMonitorUpdateErr::PermanentFailure) => {},
+			_ => panic!("unexpected result from persisting new channel")
+		}
+
+		nodes[1].node.get_and_clear_pending_msg_events();
+		added_monitors.clear();
+	}
+
+	#[test]
+	fn test_monitor_updating_persister() {
+		// Check that the `MonitorUpdatingPersister` stores updates rather than full monitors,
+		// consolidates them every `maximum_pending_updates` updates, and replays any pending
+		// updates when reading the monitors back out.
+		let store_0 = TestStore::new(false);
+		let store_1 = TestStore::new(false);
+		let persister_0 = MonitorUpdatingPersister::new(&store_0, 3);
+		let persister_1 = MonitorUpdatingPersister::new(&store_1, 3);
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let mut node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let chain_mon_0 = test_utils::TestChainMonitor::new(Some(&chanmon_cfgs[0].chain_source), &chanmon_cfgs[0].tx_broadcaster, &chanmon_cfgs[0].logger, &chanmon_cfgs[0].fee_estimator, &persister_0, &node_cfgs[0].keys_manager);
+		let chain_mon_1 = test_utils::TestChainMonitor::new(Some(&chanmon_cfgs[1].chain_source), &chanmon_cfgs[1].tx_broadcaster, &chanmon_cfgs[1].logger, &chanmon_cfgs[1].fee_estimator, &persister_1, &node_cfgs[1].keys_manager);
+		node_cfgs[0].chain_monitor = chain_mon_0;
+		node_cfgs[1].chain_monitor = chain_mon_1;
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+		// Replaying updates may re-broadcast transactions, so we don't use the nodes' broadcasters.
+		let broadcaster = test_utils::TestBroadcaster { txn_broadcasted: Mutex::new(Vec::new()), blocks: Arc::new(Mutex::new(Vec::new())) };
+
+		macro_rules! check_persisted_data {
+			($expected_update_id: expr, $expected_full_update_id: expr) => {
+				for (i, (store, persister)) in [(&store_0, &persister_0), (&store_1, &persister_1)].iter().enumerate() {
+					let persisted_chan_data = persister.read_channelmonitors(nodes[i].keys_manager, &broadcaster, &chanmon_cfgs[i].fee_estimator, &chanmon_cfgs[i].logger).unwrap();
+					assert_eq!(persisted_chan_data.len(), 1);
+					assert_eq!(persisted_chan_data[0].1.get_latest_update_id(), $expected_update_id);
+
+					let full_monitors = read_channel_monitors(*store, nodes[i].keys_manager).unwrap();
+					assert_eq!(full_monitors[0].1.get_latest_update_id(), $expected_full_update_id);
+					let update_namespace = channel_monitor_update_namespace(&full_monitors[0].1.get_funding_txo().0);
+					assert_eq!(store.list(&update_namespace).unwrap().len() as u64, $expected_update_id - $expected_full_update_id);
+				}
+			}
+		}
+
+		let _ = create_announced_chan_between_nodes(&nodes, 0, 1, InitFeatures::known(), InitFeatures::known());
+		check_persisted_data!(0, 0);
+
+		send_payment(&nodes[0], &vec!(&nodes[1])[..], 8000000);
+		check_persisted_data!(5, 3);
+		send_payment(&nodes[1], &vec!(&nodes[0])[..], 4000000);
+		check_persisted_data!(10, 9);
+
+		nodes[0].node.force_close_channel(&nodes[0].node.list_channels()[0].channel_id).unwrap();
+		check_closed_event!(nodes[0], 1, ClosureReason::HolderForceClosed);
+		check_closed_broadcast!(nodes[0], true);
+		check_added_monitors!(nodes[0], 1);
+
+		let commitment_tx = {
+			let node_txn = nodes[0].tx_broadcaster.txn_broadcasted.lock().unwrap();
+			assert_eq!(node_txn.len(), 1);
+			node_txn[0].clone()
+		};
+
+		// Connecting a block results in a chain sync persistence of the full monitor, consolidating
+		// the pending update, before the `ChannelManager` closes the channel, adding a new update.
+		let header = BlockHeader { version: 0x20000000, prev_blockhash: nodes[1].best_block_hash(), merkle_root: Default::default(), time: 42, bits: 42, nonce: 42 };
+		connect_block(&nodes[1], &Block { header, txdata: vec![commitment_tx.clone(), commitment_tx]});
+		check_closed_broadcast!(nodes[1], true);
+		check_closed_event!(nodes[1], 1, ClosureReason::CommitmentTxConfirmed);
+		check_added_monitors!(nodes[1], 1);
+
+		let persisted_chan_data = persister_0.read_channelmonitors(nodes[0].keys_manager, &broadcaster, &chanmon_cfgs[0].fee_estimator, &chanmon_cfgs[0].logger).unwrap();
+		assert_eq!(persisted_chan_data[0].1.get_latest_update_id(), 11);
+		let persisted_chan_data = persister_1.read_channelmonitors(nodes[1].keys_manager, &broadcaster, &chanmon_cfgs[1].fee_estimator, &chanmon_cfgs[1].logger).unwrap();
+		assert_eq!(persisted_chan_data[0].1.get_latest_update_id(), 11);
+		let full_monitors = read_channel_monitors(&store_1, nodes[1].keys_manager).unwrap();
+		assert_eq!(full_monitors[0].1.get_latest_update_id(), 10);
+		let update_namespace = channel_monitor_update_namespace(&full_monitors[0].1.get_funding_txo().0);
+		assert_eq!(store_1.list(&update_namespace).unwrap(), vec!["11".to_string()]);
+	}
+}
@@ -25,6 +25,8 @@ use routing::scoring::FixedPenaltyScorer;
 use util::enforcing_trait_impls::{EnforcingSigner, EnforcementState};
 use util::events;
 use util::logger::{Logger, Level, Record};
+use util::message_signing;
+use util::persist::KVStore;
 use util::ser::{Readable, ReadableArgs, Writer, Writeable};
 
 use bitcoin::blockdata::constants::genesis_block;
@@ -35,7 +37,11 @@ use bitcoin::blockdata::block::BlockHeader;
 use bitcoin::network::constants::Network;
 use bitcoin::hash_types::{BlockHash, Txid};
 
+use bitcoin::hashes::Hash;
+use bitcoin::hashes::sha256d::Hash as Sha256dHash;
+use bitcoin::secp256k1;
 use bitcoin::secp256k1::{SecretKey, PublicKey, Secp256k1, Signature};
+use bitcoin::secp256k1::ecdh::SharedSecret;
 use bitcoin::secp256k1::recovery::RecoverableSignature;
 
 use regex;
@@ -70,10 +76,11 @@ pub struct OnlyReadsKeysInterface {}
 impl keysinterface::KeysInterface for OnlyReadsKeysInterface {
 	type Signer = EnforcingSigner;
 
-	fn get_node_secret(&self, _recipient: Recipient) -> Result<SecretKey, ()> { unreachable!(); }
+	fn get_node_id(&self, _recipient: Recipient) -> Result<PublicKey, ()> { unreachable!(); }
+	fn ecdh(&self, _recipient: Recipient, _other_key: &PublicKey) -> Result<SharedSecret, ()> { unreachable!(); }
 	fn get_inbound_payment_key_material(&self) -> KeyMaterial { unreachable!(); }
-	fn get_destination_script(&self) -> Script { unreachable!(); }
-	fn get_shutdown_scriptpubkey(&self) -> ShutdownScript { unreachable!(); }
+	fn get_destination_script(&self) -> Result<Script, ()> { unreachable!(); }
+	fn get_shutdown_scriptpubkey(&self) -> Result<ShutdownScript, ()> { unreachable!(); }
 	fn get_channel_signer(&self, _inbound: bool, _channel_value_satoshis: u64) -> EnforcingSigner { unreachable!(); }
 	fn get_secure_random_bytes(&self) -> [u8; 32] { [0; 32] }
 
@@ -89,6 +96,50 @@ impl keysinterface::KeysInterface for OnlyReadsKeysInterface {
 		))
 	}
 	fn sign_invoice(&self, _hrp_bytes: &[u8], _invoice_data: &[u5], _recipient: Recipient) -> Result<RecoverableSignature, ()> { unreachable!(); }
+	fn sign_gossip_message(&self, _msg: msgs::UnsignedGossipMessage) -> Result<Signature, ()> { unreachable!(); }
+	fn sign_message(&self, _msg: &[u8]) -> Result<String, ()> { unreachable!(); }
+}
+
+/// A [`keysinterface::KeysInterface`] which only supports the operations using the node secret
+/// key, for tests which need a specific node secret key.
+pub struct TestNodeSigner {
+	node_secret: SecretKey,
+	secp_ctx: Secp256k1<secp256k1::All>,
+}
+impl TestNodeSigner {
+	pub fn new(node_secret: SecretKey) -> Self {
+		Self { node_secret, secp_ctx: Secp256k1::new() }
+	}
+}
+impl keysinterface::KeysInterface for TestNodeSigner {
+	type Signer = EnforcingSigner;
+
+	fn get_node_id(&self, recipient: Recipient) -> Result<PublicKey, ()> {
+		match recipient {
+			Recipient::Node => Ok(PublicKey::from_secret_key(&self.secp_ctx, &self.node_secret)),
+			Recipient::PhantomNode => Err(()),
+		}
+	}
+	fn ecdh(&self, recipient: Recipient, other_key: &PublicKey) -> Result<SharedSecret, ()> {
+		match recipient {
+			Recipient::Node => Ok(SharedSecret::new(other_key, &self.node_secret)),
+			Recipient::PhantomNode => Err(()),
+		}
+	}
+	fn get_inbound_payment_key_material(&self) -> KeyMaterial { unreachable!(); }
+	fn get_destination_script(&self) -> Result<Script, ()> { unreachable!(); }
+	fn get_shutdown_scriptpubkey(&self) -> Result<ShutdownScript, ()> { unreachable!(); }
+	fn get_channel_signer(&self, _inbound: bool, _channel_value_satoshis: u64) -> EnforcingSigner { unreachable!(); }
+	fn get_secure_random_bytes(&self) -> [u8; 32] { unreachable!(); }
+	fn read_chan_signer(&self, _reader: &[u8]) -> Result<Self::Signer, msgs::DecodeError> { unreachable!(); }
+	fn sign_invoice(&self, _hrp_bytes: &[u8], _invoice_data: &[u5], _recipient: Recipient) -> Result<RecoverableSignature, ()> { unreachable!(); }
+	fn sign_gossip_message(&self, msg: msgs::UnsignedGossipMessage) -> Result<Signature, ()> {
+		Ok(self.secp_ctx.sign(&hash_to_message!(&Sha256dHash::hash(&msg.encode()[..])[..]), &self.node_secret))
+	}
+
+	fn sign_message(&self, msg: &[u8]) -> Result<String, ()> {
+		message_signing::sign(msg, &self.node_secret).map_err(|_| ())
+	}
 }
 
 pub struct TestChainMonitor<'a> {
@@ -177,6 +228,9 @@ pub struct TestPersister {
 	/// When we get an update_persisted_channel call *with* a ChannelMonitorUpdate, we insert the
 	/// MonitorUpdateId here.
 	pub offchain_monitor_updates: Mutex<HashMap<OutPoint, HashSet<MonitorUpdateId>>>,
+	/// When we get an archive_persisted_channel call, we insert the channel's funding outpoint
+	/// here.
+	pub archived_channels: Mutex<HashSet<OutPoint>>,
 }
 impl TestPersister {
 	pub fn new() -> Self {
@@ -185,6 +239,7 @@ impl TestPersister {
 			next_update_ret: Mutex::new(None),
 			chain_sync_monitor_persistences: Mutex::new(HashMap::new()),
 			offchain_monitor_updates: Mutex::new(HashMap::new()),
+			archived_channels: Mutex::new(HashSet::new()),
 		}
 	}
 
@@ -217,6 +272,10 @@ impl<Signer: keysinterface::Sign> chainmonitor::Persist<Signer> for TestPersiste
 		}
 		ret
 	}
+
+	fn archive_persisted_channel(&self, funding_txo: OutPoint, _data: &channelmonitor::ChannelMonitor<Signer>) {
+		self.archived_channels.lock().unwrap().insert(funding_txo);
+	}
 }
 
 pub struct TestBroadcaster {
@@ -474,6 +533,8 @@ pub struct TestKeysInterface {
 	pub override_session_priv: Mutex<Option<[u8; 32]>>,
 	pub override_channel_id_priv: Mutex<Option<[u8; 32]>>,
 	pub disable_revocation_policy_check: bool,
+	/// If set, [`keysinterface::KeysInterface::ecdh`] fails as an unavailable signer would.
+	pub disable_ecdh: AtomicBool,
 	enforcement_states: Mutex<HashMap<[u8;32], Arc<Mutex<EnforcementState>>>>,
 	expectations: Mutex<Option<VecDeque<OnGetShutdownScriptpubkey>>>,
 }
@@ -481,20 +542,24 @@ pub struct TestKeysInterface {
 impl keysinterface::KeysInterface for TestKeysInterface {
 	type Signer = EnforcingSigner;
 
-	fn get_node_secret(&self, recipient: Recipient) -> Result<SecretKey, ()> {
-		self.backing.get_node_secret(recipient)
+	fn get_node_id(&self, recipient: Recipient) -> Result<PublicKey, ()> {
+		self.backing.get_node_id(recipient)
+	}
+	fn ecdh(&self, recipient: Recipient, other_key: &PublicKey) -> Result<SharedSecret, ()> {
+		if self.disable_ecdh.load(Ordering::Acquire) { return Err(()); }
+		self.backing.ecdh(recipient, other_key)
 	}
 	fn get_inbound_payment_key_material(&self) -> keysinterface::KeyMaterial {
 		self.backing.get_inbound_payment_key_material()
 	}
-	fn get_destination_script(&self) -> Script { self.backing.get_destination_script() }
+	fn get_destination_script(&self) -> Result<Script, ()> { self.backing.get_destination_script() }
 
-	fn get_shutdown_scriptpubkey(&self) -> ShutdownScript {
+	fn get_shutdown_scriptpubkey(&self) -> Result<ShutdownScript, ()> {
 		match &mut *self.expectations.lock().unwrap() {
 			None => self.backing.get_shutdown_scriptpubkey(),
 			Some(expectations) => match expectations.pop_front() {
 				None => panic!("Unexpected get_shutdown_scriptpubkey"),
-				Some(expectation) => expectation.returns,
+				Some(expectation) => Ok(expectation.returns),
 			},
 		}
 	}
@@ -521,9 +586,7 @@ impl keysinterface::KeysInterface for TestKeysInterface {
 	}
 
 	fn read_chan_signer(&self, buffer: &[u8]) -> Result<Self::Signer, msgs::DecodeError> {
-		let mut reader = io::Cursor::new(buffer);
-
-		let inner: InMemorySigner = ReadableArgs::read(&mut reader, self.get_node_secret(Recipient::Node).unwrap())?;
+		let inner = self.backing.read_chan_signer(buffer)?;
 		let state = self.make_enforcement_state_cell(inner.commitment_seed);
 
 		Ok(EnforcingSigner::new_with_revoked(
@@ -536,6 +599,14 @@ impl keysinterface::KeysInterface for TestKeysInterface {
 	fn sign_invoice(&self, hrp_bytes: &[u8], invoice_data: &[u5], recipient: Recipient) -> Result<RecoverableSignature, ()> {
 		self.backing.sign_invoice(hrp_bytes, invoice_data, recipient)
 	}
+
+	fn sign_gossip_message(&self, msg: msgs::UnsignedGossipMessage) -> Result<Signature, ()> {
+		self.backing.sign_gossip_message(msg)
+	}
+
+	fn sign_message(&self, msg: &[u8]) -> Result<String, ()> {
+		self.backing.sign_message(msg)
+	}
 }
 
 impl TestKeysInterface {
@@ -546,6 +617,7 @@ impl TestKeysInterface {
 			override_session_priv: Mutex::new(None),
 			override_channel_id_priv: Mutex::new(None),
 			disable_revocation_policy_check: false,
+			disable_ecdh: AtomicBool::new(false),
 			enforcement_states: Mutex::new(HashMap::new()),
 			expectations: Mutex::new(None),
 		}
@@ -728,3 +800,43 @@ impl core::fmt::Debug for OnRegisterOutput {
 
 /// A scorer useful in testing, when the passage of time isn't a concern.
 pub type TestScorer = FixedPenaltyScorer;
+
+/// A [`KVStore`] which keeps everything in memory, optionally failing all writes.
+pub struct TestStore {
+	pub entries: Mutex<HashMap<(String, String), Vec<u8>>>,
+	pub fail_writes: bool,
+}
+
+impl TestStore {
+	pub fn new(fail_writes: bool) -> Self {
+		Self { entries: Mutex::new(HashMap::new()), fail_writes }
+	}
+}
+
+impl KVStore for TestStore {
+	fn read(&self, namespace: &str, key: &str) -> Result<Vec<u8>, io::Error> {
+		match self.entries.lock().unwrap().get(&(namespace.to_string(), key.to_string())) {
+			Some(data) => Ok(data.clone()),
+			None => Err(io::Error::new(io::ErrorKind::NotFound, "Key not found")),
+		}
+	}
+
+	fn write(&self, namespace: &str, key: &str, buf: &[u8]) -> Result<(), io::Error> {
+		if self.fail_writes {
+			return Err(io::Error::new(io::ErrorKind::Other, "Write failed"));
+		}
+		self.entries.lock().unwrap().insert((namespace.to_string(), key.to_string()), buf.to_vec());
+		Ok(())
+	}
+
+	fn remove(&self, namespace: &str, key: &str) -> Result<(), io::Error> {
+		self.entries.lock().unwrap().remove(&(namespace.to_string(), key.to_string()));
+		Ok(())
+	}
+
+	fn list(&self, namespace: &str) -> Result<Vec<String>, io::Error> {
+		Ok(self.entries.lock().unwrap().keys()
+			.filter(|(entry_namespace, _)| entry_namespace == namespace)
+			.map(|(_, key)| key.clone()).collect())
+	}
+}
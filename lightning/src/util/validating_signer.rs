@@ -0,0 +1,646 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! A [`Sign`] wrapper which enforces a set of signing policies, independently of the
+//! [`ChannelManager`] and [`ChannelMonitor`]s asking for signatures, and a [`KeysInterface`]
+//! wrapper handing out such signers.
+//!
+//! [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+//! [`ChannelMonitor`]: crate::chain::channelmonitor::ChannelMonitor
+
+use bitcoin::bech32::u5;
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::Transaction;
+use bitcoin::hashes::hex::ToHex;
+use bitcoin::secp256k1;
+use bitcoin::secp256k1::ecdh::SharedSecret;
+use bitcoin::secp256k1::key::{PublicKey, SecretKey};
+use bitcoin::secp256k1::recovery::RecoverableSignature;
+use bitcoin::secp256k1::{Secp256k1, Signature};
+
+use chain::keysinterface::{BaseSign, KeyMaterial, KeysInterface, Recipient, Sign, SignerError};
+use ln::chan_utils::{ChannelPublicKeys, ChannelTransactionParameters, ClosingTransaction, CommitmentTransaction, HolderCommitmentTransaction, HTLCOutputInCommitment};
+use ln::msgs::{DecodeError, UnsignedChannelAnnouncement, UnsignedGossipMessage};
+use ln::script::ShutdownScript;
+use ln::PaymentPreimage;
+use util::persist::KVStore;
+use util::ser::{Readable, Writeable, Writer};
+
+use core::ops::Deref;
+use io;
+use prelude::*;
+use sync::{Arc, Mutex};
+
+/// The namespace under which [`ValidatingSigner`]s persist their state, keyed by the hex-encoded
+/// [`BaseSign::channel_keys_id`] of the channel.
+pub const VALIDATING_SIGNER_STATE_PERSISTENCE_NAMESPACE: &str = "signer_state";
+
+/// The namespace under which a [`ValidatingKeysManager`] persists the destination and shutdown
+/// scripts it has handed out, keyed by the hex-encoded script.
+pub const VALIDATING_SIGNER_SCRIPTS_PERSISTENCE_NAMESPACE: &str = "signer_scripts";
+
+/// One more than the highest commitment number, used as the initial value of the backwards
+/// counting commitment numbers tracked in [`ValidationState`].
+const INITIAL_STATE_COMMITMENT_NUMBER: u64 = 1 << 48;
+
+/// The limits enforced by a [`ValidatingSigner`] on the transactions it signs.
+#[derive(Clone, Debug)]
+pub struct SigningPolicy {
+	/// The maximum feerate, in satoshis per 1000 weight units, of holder and counterparty
+	/// commitment transactions which will be validated or signed.
+	///
+	/// Default value: 100,000.
+	pub max_feerate_per_kw: u32,
+	/// The maximum fee, in satoshis, which a cooperative closing transaction may pay.
+	///
+	/// Default value: 100,000.
+	pub max_closing_fee_satoshis: u64,
+	/// The maximum value, in millisatoshis, of any single HTLC in a holder or counterparty
+	/// commitment transaction which will be validated or signed.
+	///
+	/// Default value: 16,777,215,000 (the largest value a channel without `option_support_large_channel`
+	/// may hold).
+	pub max_htlc_value_msat: u64,
+	/// Scripts, in addition to the destination and shutdown scripts handed out by the
+	/// [`ValidatingKeysManager`], which our outputs in closing, justice and HTLC-claiming
+	/// transactions may pay to.
+	///
+	/// This should include any `shutdown_script` passed to [`ChannelManager::close_channel`] or
+	/// configured as an upfront shutdown script.
+	///
+	/// Default value: empty.
+	///
+	/// [`ChannelManager::close_channel`]: crate::ln::channelmanager::ChannelManager::close_channel
+	pub allowed_destination_scripts: Vec<Script>,
+}
+
+impl Default for SigningPolicy {
+	fn default() -> Self {
+		Self {
+			max_feerate_per_kw: 100_000,
+			max_closing_fee_satoshis: 100_000,
+			max_htlc_value_msat: ((1 << 24) - 1) * 1000,
+			allowed_destination_scripts: Vec::new(),
+		}
+	}
+}
+
+/// The per-channel state a [`ValidatingSigner`] needs to enforce its policies. All commitment
+/// numbers are backwards counting, starting at `1 << 48`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationState {
+	/// The value of the channel, in satoshis.
+	pub channel_value_satoshis: u64,
+	/// The last counterparty commitment number we signed.
+	pub last_counterparty_commitment: u64,
+	/// The last counterparty commitment number the counterparty revoked.
+	pub last_counterparty_revoked_commitment: u64,
+	/// The last holder commitment number we revoked.
+	pub last_holder_revoked_commitment: u64,
+	/// The last holder commitment number we validated.
+	pub last_holder_commitment: u64,
+}
+
+impl ValidationState {
+	fn new(channel_value_satoshis: u64) -> Self {
+		Self {
+			channel_value_satoshis,
+			last_counterparty_commitment: INITIAL_STATE_COMMITMENT_NUMBER,
+			last_counterparty_revoked_commitment: INITIAL_STATE_COMMITMENT_NUMBER,
+			last_holder_revoked_commitment: INITIAL_STATE_COMMITMENT_NUMBER,
+			last_holder_commitment: INITIAL_STATE_COMMITMENT_NUMBER,
+		}
+	}
+}
+
+impl_writeable_tlv_based!(ValidationState, {
+	(0, channel_value_satoshis, required),
+	(2, last_counterparty_commitment, required),
+	(4, last_counterparty_revoked_commitment, required),
+	(6, last_holder_revoked_commitment, required),
+	(8, last_holder_commitment, required),
+});
+
+/// A [`Sign`] implementation wrapping another [`Sign`] which refuses to sign anything violating
+/// its [`SigningPolicy`] or the channel's state machine. Specifically, it:
+///
+/// - never signs a holder commitment transaction which has been revoked, and never revokes a
+///   holder commitment transaction which is the latest validated one,
+/// - only validates and signs holder and counterparty commitment transactions in order, and
+///   never signs a new counterparty commitment transaction while the counterparty holds two
+///   unrevoked ones,
+/// - only signs commitment transactions within the policy's feerate and HTLC value limits,
+/// - only signs closing transactions paying our balance to one of our scripts and within the
+///   policy's fee limit, and
+/// - only signs justice and HTLC-claiming transactions paying exclusively to our scripts.
+///
+/// The state needed for this is persisted to a [`KVStore`] before any signature is handed out,
+/// so a signer never relies on the [`ChannelManager`] or [`ChannelMonitor`]s to tell it which
+/// states have been signed or revoked, even across restarts.
+///
+/// Note that, as this wraps an arbitrary [`Sign`], the transactions passed to it are not
+/// re-built from the channel parameters and are checked as given. Wrapped signers which do not
+/// trust the pre-built transactions should verify them themselves, e.g. using
+/// [`CommitmentTransaction::verify`].
+///
+/// Obtained from a [`ValidatingKeysManager`].
+///
+/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+/// [`ChannelMonitor`]: crate::chain::channelmonitor::ChannelMonitor
+#[derive(Clone)]
+pub struct ValidatingSigner<S: Sign, K: Deref + Clone> where K::Target: KVStore {
+	inner: S,
+	state: Arc<Mutex<ValidationState>>,
+	policy: Arc<SigningPolicy>,
+	allowed_scripts: Arc<Mutex<HashSet<Script>>>,
+	kv_store: K,
+}
+
+impl<S: Sign, K: Deref + Clone> ValidatingSigner<S, K> where K::Target: KVStore {
+	/// Gets the signer wrapped by this [`ValidatingSigner`].
+	pub fn inner(&self) -> &S { &self.inner }
+
+	/// Gets a copy of the current [`ValidationState`] of this channel.
+	pub fn state(&self) -> ValidationState { self.state.lock().unwrap().clone() }
+
+	/// Applies `f` to a copy of the current state, persisting and adopting the result if `f`
+	/// succeeds and changed anything.
+	fn update_state<F: FnOnce(&mut ValidationState) -> Result<(), ()>>(&self, f: F) -> Result<(), ()> {
+		let mut state = self.state.lock().unwrap();
+		let mut new_state = state.clone();
+		f(&mut new_state)?;
+		if new_state != *state {
+			self.kv_store.write(VALIDATING_SIGNER_STATE_PERSISTENCE_NAMESPACE, &self.inner.channel_keys_id()[..].to_hex(), &new_state.encode())
+				.map_err(|_| ())?;
+			*state = new_state;
+		}
+		Ok(())
+	}
+
+	fn check_commitment_limits(&self, commitment_tx: &CommitmentTransaction) -> Result<(), ()> {
+		if commitment_tx.feerate_per_kw() > self.policy.max_feerate_per_kw {
+			return Err(());
+		}
+		if commitment_tx.htlcs().iter().any(|htlc| htlc.amount_msat > self.policy.max_htlc_value_msat) {
+			return Err(());
+		}
+		Ok(())
+	}
+
+	fn check_pays_to_us(&self, tx: &Transaction) -> Result<(), ()> {
+		let allowed_scripts = self.allowed_scripts.lock().unwrap();
+		if tx.output.is_empty() || !tx.output.iter().all(|output| allowed_scripts.contains(&output.script_pubkey)) {
+			return Err(());
+		}
+		Ok(())
+	}
+}
+
+impl<S: Sign, K: Deref + Clone> BaseSign for ValidatingSigner<S, K> where K::Target: KVStore {
+	fn get_per_commitment_point(&self, idx: u64, secp_ctx: &Secp256k1<secp256k1::All>) -> PublicKey {
+		self.inner.get_per_commitment_point(idx, secp_ctx)
+	}
+
+	fn release_commitment_secret(&self, idx: u64) -> Result<[u8; 32], ()> {
+		self.update_state(|state| {
+			// We may only revoke the current or next unrevoked commitment, and never the latest
+			// validated one, lest we be left without a broadcastable commitment transaction.
+			if idx != state.last_holder_revoked_commitment && idx != state.last_holder_revoked_commitment - 1 {
+				return Err(());
+			}
+			if idx <= state.last_holder_commitment {
+				return Err(());
+			}
+			state.last_holder_revoked_commitment = idx;
+			Ok(())
+		})?;
+		self.inner.release_commitment_secret(idx)
+	}
+
+	fn validate_holder_commitment(&self, holder_tx: &HolderCommitmentTransaction, preimages: Vec<PaymentPreimage>) -> Result<(), ()> {
+		self.check_commitment_limits(holder_tx)?;
+		let idx = holder_tx.commitment_number();
+		self.update_state(|state| {
+			if idx != state.last_holder_commitment && idx != state.last_holder_commitment - 1 {
+				return Err(());
+			}
+			state.last_holder_commitment = idx;
+			Ok(())
+		})?;
+		self.inner.validate_holder_commitment(holder_tx, preimages)
+	}
+
+	fn pubkeys(&self) -> &ChannelPublicKeys { self.inner.pubkeys() }
+	fn channel_keys_id(&self) -> [u8; 32] { self.inner.channel_keys_id() }
+
+	fn sign_counterparty_commitment(&self, commitment_tx: &CommitmentTransaction, preimages: Vec<PaymentPreimage>, secp_ctx: &Secp256k1<secp256k1::All>) -> Result<(Signature, Vec<Signature>), SignerError> {
+		self.check_commitment_limits(commitment_tx).map_err(|()| SignerError::Rejected)?;
+		let idx = commitment_tx.commitment_number();
+		self.update_state(|state| {
+			if idx != state.last_counterparty_commitment && idx != state.last_counterparty_commitment - 1 {
+				return Err(());
+			}
+			// The counterparty may hold at most two unrevoked commitment transactions - the
+			// previous one and the one we're signing now.
+			if idx < state.last_counterparty_revoked_commitment - 2 {
+				return Err(());
+			}
+			state.last_counterparty_commitment = idx;
+			Ok(())
+		}).map_err(|()| SignerError::Rejected)?;
+		self.inner.sign_counterparty_commitment(commitment_tx, preimages, secp_ctx)
+	}
+
+	fn validate_counterparty_revocation(&self, idx: u64, secret: &SecretKey) -> Result<(), ()> {
+		self.update_state(|state| {
+			if idx != state.last_counterparty_revoked_commitment && idx != state.last_counterparty_revoked_commitment - 1 {
+				return Err(());
+			}
+			// The counterparty may only revoke a commitment transaction once we've signed a newer
+			// one for it.
+			if idx <= state.last_counterparty_commitment {
+				return Err(());
+			}
+			state.last_counterparty_revoked_commitment = idx;
+			Ok(())
+		})?;
+		self.inner.validate_counterparty_revocation(idx, secret)
+	}
+
+	fn sign_holder_commitment_and_htlcs(&self, commitment_tx: &HolderCommitmentTransaction, secp_ctx: &Secp256k1<secp256k1::All>) -> Result<(Signature, Vec<Signature>), ()> {
+		if commitment_tx.commitment_number() >= self.state.lock().unwrap().last_holder_revoked_commitment {
+			return Err(());
+		}
+		self.inner.sign_holder_commitment_and_htlcs(commitment_tx, secp_ctx)
+	}
+
+	#[cfg(any(test,feature = "unsafe_revoked_tx_signing"))]
+	fn unsafe_sign_holder_commitment_and_htlcs(&self, commitment_tx: &HolderCommitmentTransaction, secp_ctx: &Secp256k1<secp256k1::All>) -> Result<(Signature, Vec<Signature>), ()> {
+		self.inner.unsafe_sign_holder_commitment_and_htlcs(commitment_tx, secp_ctx)
+	}
+
+	fn sign_justice_revoked_output(&self, justice_tx: &Transaction, input: usize, amount: u64, per_commitment_key: &SecretKey, secp_ctx: &Secp256k1<secp256k1::All>) -> Result<Signature, ()> {
+		self.check_pays_to_us(justice_tx)?;
+		self.inner.sign_justice_revoked_output(justice_tx, input, amount, per_commitment_key, secp_ctx)
+	}
+
+	fn sign_justice_revoked_htlc(&self, justice_tx: &Transaction, input: usize, amount: u64, per_commitment_key: &SecretKey, htlc: &HTLCOutputInCommitment, secp_ctx: &Secp256k1<secp256k1::All>) -> Result<Signature, ()> {
+		self.check_pays_to_us(justice_tx)?;
+		self.inner.sign_justice_revoked_htlc(justice_tx, input, amount, per_commitment_key, htlc, secp_ctx)
+	}
+
+	fn sign_counterparty_htlc_transaction(&self, htlc_tx: &Transaction, input: usize, amount: u64, per_commitment_point: &PublicKey, htlc: &HTLCOutputInCommitment, secp_ctx: &Secp256k1<secp256k1::All>) -> Result<Signature, ()> {
+		self.check_pays_to_us(htlc_tx)?;
+		self.inner.sign_counterparty_htlc_transaction(htlc_tx, input, amount, per_commitment_point, htlc, secp_ctx)
+	}
+
+	fn sign_closing_transaction(&self, closing_tx: &ClosingTransaction, secp_ctx: &Secp256k1<secp256k1::All>) -> Result<Signature, ()> {
+		if closing_tx.to_holder_value_sat() > 0 && !self.allowed_scripts.lock().unwrap().contains(closing_tx.to_holder_script()) {
+			return Err(());
+		}
+		let channel_value_satoshis = self.state.lock().unwrap().channel_value_satoshis;
+		let fee_satoshis = channel_value_satoshis
+			.checked_sub(closing_tx.to_holder_value_sat())
+			.and_then(|value| value.checked_sub(closing_tx.to_counterparty_value_sat()))
+			.ok_or(())?;
+		if fee_satoshis > self.policy.max_closing_fee_satoshis {
+			return Err(());
+		}
+		self.inner.sign_closing_transaction(closing_tx, secp_ctx)
+	}
+
+	fn sign_channel_announcement(&self, msg: &UnsignedChannelAnnouncement, secp_ctx: &Secp256k1<secp256k1::All>)
+	-> Result<(Signature, Signature), ()> {
+		self.inner.sign_channel_announcement(msg, secp_ctx)
+	}
+
+	fn ready_channel(&mut self, channel_parameters: &ChannelTransactionParameters) {
+		self.inner.ready_channel(channel_parameters)
+	}
+}
+
+impl<S: Sign, K: Deref + Clone> Sign for ValidatingSigner<S, K> where K::Target: KVStore {}
+
+impl<S: Sign, K: Deref + Clone> Writeable for ValidatingSigner<S, K> where K::Target: KVStore {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+		// The ValidationState is persisted separately, to the KVStore, whenever it changes and
+		// is loaded from there again in ValidatingKeysManager::read_chan_signer.
+		self.inner.write(writer)
+	}
+}
+
+/// A [`KeysInterface`] wrapping another [`KeysInterface`], handing out [`ValidatingSigner`]s
+/// wrapping its signers.
+///
+/// All copies of a channel's signer, e.g. those held by the channel and by its
+/// [`ChannelMonitor`], share the same [`ValidationState`], which is loaded from the [`KVStore`]
+/// when the signer is first deserialized.
+///
+/// [`ChannelMonitor`]: crate::chain::channelmonitor::ChannelMonitor
+pub struct ValidatingKeysManager<KI: Deref, K: Deref + Clone> where KI::Target: KeysInterface, K::Target: KVStore {
+	keys_manager: KI,
+	kv_store: K,
+	policy: Arc<SigningPolicy>,
+	allowed_scripts: Arc<Mutex<HashSet<Script>>>,
+	states: Mutex<HashMap<[u8; 32], Arc<Mutex<ValidationState>>>>,
+}
+
+impl<KI: Deref, K: Deref + Clone> ValidatingKeysManager<KI, K> where KI::Target: KeysInterface, K::Target: KVStore {
+	/// Constructs a new [`ValidatingKeysManager`] wrapping `keys_manager`, whose signers persist
+	/// their state to `kv_store` and enforce `policy`.
+	///
+	/// Fails if the scripts previously handed out by a [`ValidatingKeysManager`] using the same
+	/// `kv_store` cannot be read back.
+	pub fn new(keys_manager: KI, kv_store: K, policy: SigningPolicy) -> Result<Self, io::Error> {
+		let mut allowed_scripts: HashSet<Script> = policy.allowed_destination_scripts.iter().cloned().collect();
+		for key in kv_store.list(VALIDATING_SIGNER_SCRIPTS_PERSISTENCE_NAMESPACE)? {
+			let data = kv_store.read(VALIDATING_SIGNER_SCRIPTS_PERSISTENCE_NAMESPACE, &key)?;
+			let script: Script = Readable::read(&mut io::Cursor::new(&data))
+				.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Failed to decode allowed script"))?;
+			allowed_scripts.insert(script);
+		}
+		Ok(Self {
+			keys_manager,
+			kv_store,
+			policy: Arc::new(policy),
+			allowed_scripts: Arc::new(Mutex::new(allowed_scripts)),
+			states: Mutex::new(HashMap::new()),
+		})
+	}
+
+	/// Allows our signers to pay to `script`, persisting it first so that claims made after a
+	/// restart may still pay to it.
+	fn allow_script(&self, script: &Script) -> Result<(), ()> {
+		let mut allowed_scripts = self.allowed_scripts.lock().unwrap();
+		if allowed_scripts.contains(script) { return Ok(()); }
+		self.kv_store.write(VALIDATING_SIGNER_SCRIPTS_PERSISTENCE_NAMESPACE, &script[..].to_hex(), &script.encode())
+			.map_err(|_| ())?;
+		allowed_scripts.insert(script.clone());
+		Ok(())
+	}
+
+	fn wrap_signer(&self, inner: <KI::Target as KeysInterface>::Signer, state: Arc<Mutex<ValidationState>>) -> ValidatingSigner<<KI::Target as KeysInterface>::Signer, K> {
+		ValidatingSigner {
+			inner,
+			state,
+			policy: Arc::clone(&self.policy),
+			allowed_scripts: Arc::clone(&self.allowed_scripts),
+			kv_store: self.kv_store.clone(),
+		}
+	}
+}
+
+impl<KI: Deref, K: Deref + Clone> KeysInterface for ValidatingKeysManager<KI, K> where KI::Target: KeysInterface, K::Target: KVStore {
+	type Signer = ValidatingSigner<<KI::Target as KeysInterface>::Signer, K>;
+
+	fn get_node_id(&self, recipient: Recipient) -> Result<PublicKey, ()> {
+		self.keys_manager.get_node_id(recipient)
+	}
+
+	fn ecdh(&self, recipient: Recipient, other_key: &PublicKey) -> Result<SharedSecret, ()> {
+		self.keys_manager.ecdh(recipient, other_key)
+	}
+
+	fn get_destination_script(&self) -> Result<Script, ()> {
+		let script = self.keys_manager.get_destination_script()?;
+		self.allow_script(&script)?;
+		Ok(script)
+	}
+
+	fn get_shutdown_scriptpubkey(&self) -> Result<ShutdownScript, ()> {
+		let shutdown_script = self.keys_manager.get_shutdown_scriptpubkey()?;
+		self.allow_script(&shutdown_script.clone().into_inner())?;
+		Ok(shutdown_script)
+	}
+
+	fn get_channel_signer(&self, inbound: bool, channel_value_satoshis: u64) -> Self::Signer {
+		let inner = self.keys_manager.get_channel_signer(inbound, channel_value_satoshis);
+		let channel_keys_id = inner.channel_keys_id();
+		let state = ValidationState::new(channel_value_satoshis);
+		self.kv_store.write(VALIDATING_SIGNER_STATE_PERSISTENCE_NAMESPACE, &channel_keys_id[..].to_hex(), &state.encode())
+			.expect("Failed to persist new signer state");
+		let state = Arc::new(Mutex::new(state));
+		self.states.lock().unwrap().insert(channel_keys_id, Arc::clone(&state));
+		self.wrap_signer(inner, state)
+	}
+
+	fn get_secure_random_bytes(&self) -> [u8; 32] {
+		self.keys_manager.get_secure_random_bytes()
+	}
+
+	fn read_chan_signer(&self, reader: &[u8]) -> Result<Self::Signer, DecodeError> {
+		let inner = self.keys_manager.read_chan_signer(reader)?;
+		let channel_keys_id = inner.channel_keys_id();
+		let mut states = self.states.lock().unwrap();
+		let state = match states.get(&channel_keys_id) {
+			Some(state) => Arc::clone(state),
+			None => {
+				// A signer without persisted state would have no record of what it has signed or
+				// revoked, so refuse to load it rather than starting over.
+				let data = self.kv_store.read(VALIDATING_SIGNER_STATE_PERSISTENCE_NAMESPACE, &channel_keys_id[..].to_hex())
+					.map_err(|e| match e.kind() {
+						io::ErrorKind::NotFound => DecodeError::InvalidValue,
+						kind => DecodeError::Io(kind),
+					})?;
+				let state: ValidationState = Readable::read(&mut io::Cursor::new(&data))?;
+				let state = Arc::new(Mutex::new(state));
+				states.insert(channel_keys_id, Arc::clone(&state));
+				state
+			},
+		};
+		Ok(self.wrap_signer(inner, state))
+	}
+
+	fn sign_invoice(&self, hrp_bytes: &[u8], invoice_data: &[u5], recipient: Recipient) -> Result<RecoverableSignature, ()> {
+		self.keys_manager.sign_invoice(hrp_bytes, invoice_data, recipient)
+	}
+
+	fn sign_gossip_message(&self, msg: UnsignedGossipMessage) -> Result<Signature, ()> {
+		self.keys_manager.sign_gossip_message(msg)
+	}
+
+	fn sign_message(&self, msg: &[u8]) -> Result<String, ()> {
+		self.keys_manager.sign_message(msg)
+	}
+
+	fn get_inbound_payment_key_material(&self) -> KeyMaterial {
+		self.keys_manager.get_inbound_payment_key_material()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bitcoin::blockdata::script::Builder;
+	use bitcoin::blockdata::transaction::TxOut;
+	use chain::keysinterface::{InMemorySigner, KeysManager};
+	use chain::transaction::OutPoint;
+	use ln::chan_utils::{CounterpartyChannelTransactionParameters, TxCreationKeys};
+	use ln::channel::INITIAL_COMMITMENT_NUMBER;
+	use ln::PaymentHash;
+	use util::test_utils::TestStore;
+
+	struct TestChannel<'a> {
+		signer: ValidatingSigner<InMemorySigner, &'a TestStore>,
+		params: ChannelTransactionParameters,
+	}
+
+	fn open_channel<'a>(keys_manager: &ValidatingKeysManager<&KeysManager, &'a TestStore>) -> TestChannel<'a> {
+		let mut signer = keys_manager.get_channel_signer(false, 1_000_000);
+		let counterparty_signer = KeysManager::new(&[43; 32], 42, 42).get_channel_signer(true, 1_000_000);
+		let params = ChannelTransactionParameters {
+			holder_pubkeys: signer.pubkeys().clone(),
+			holder_selected_contest_delay: 144,
+			is_outbound_from_holder: true,
+			counterparty_parameters: Some(CounterpartyChannelTransactionParameters {
+				pubkeys: counterparty_signer.pubkeys().clone(),
+				selected_contest_delay: 144,
+			}),
+			funding_outpoint: Some(OutPoint { txid: Default::default(), index: 0 }),
+			opt_anchors: None,
+		};
+		signer.ready_channel(&params);
+		TestChannel { signer, params }
+	}
+
+	fn counterparty_commitment(channel: &TestChannel, commitment_number: u64, feerate_per_kw: u32, htlc_amount_msat: Option<u64>) -> CommitmentTransaction {
+		let secp_ctx = Secp256k1::new();
+		let params = channel.params.as_counterparty_broadcastable();
+		let per_commitment_point = PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[44; 32]).unwrap());
+		let keys = TxCreationKeys::from_channel_static_keys(&per_commitment_point, params.broadcaster_pubkeys(), params.countersignatory_pubkeys(), &secp_ctx).unwrap();
+		let mut htlcs: Vec<_> = htlc_amount_msat.into_iter().map(|amount_msat| (HTLCOutputInCommitment {
+			offered: true, amount_msat, cltv_expiry: 100, payment_hash: PaymentHash([45; 32]), transaction_output_index: None,
+		}, ())).collect();
+		CommitmentTransaction::new_with_auxiliary_htlc_data(commitment_number, 400_000, 500_000, false,
+			params.broadcaster_pubkeys().funding_pubkey, params.countersignatory_pubkeys().funding_pubkey,
+			keys, feerate_per_kw, &mut htlcs, &params)
+	}
+
+	#[test]
+	fn test_counterparty_commitment_policy() {
+		let secp_ctx = Secp256k1::new();
+		let store = TestStore::new(false);
+		let keys_manager = KeysManager::new(&[42; 32], 42, 42);
+		let policy = SigningPolicy { max_feerate_per_kw: 10_000, max_htlc_value_msat: 50_000_000, ..Default::default() };
+		let validating_keys_manager = ValidatingKeysManager::new(&keys_manager, &store, policy.clone()).unwrap();
+		let channel = open_channel(&validating_keys_manager);
+
+		// Commitments must be signed in order, and at most two may be unrevoked.
+		let first = counterparty_commitment(&channel, INITIAL_COMMITMENT_NUMBER, 253, None);
+		assert!(channel.signer.sign_counterparty_commitment(&first, Vec::new(), &secp_ctx).is_ok());
+		assert!(channel.signer.sign_counterparty_commitment(&first, Vec::new(), &secp_ctx).is_ok());
+		let third = counterparty_commitment(&channel, INITIAL_COMMITMENT_NUMBER - 2, 253, None);
+		assert!(channel.signer.sign_counterparty_commitment(&third, Vec::new(), &secp_ctx).is_err());
+		let second = counterparty_commitment(&channel, INITIAL_COMMITMENT_NUMBER - 1, 253, None);
+		assert!(channel.signer.sign_counterparty_commitment(&second, Vec::new(), &secp_ctx).is_ok());
+		assert!(channel.signer.sign_counterparty_commitment(&third, Vec::new(), &secp_ctx).is_err());
+		assert!(channel.signer.validate_counterparty_revocation(INITIAL_COMMITMENT_NUMBER - 1, &SecretKey::from_slice(&[46; 32]).unwrap()).is_err());
+		assert!(channel.signer.validate_counterparty_revocation(INITIAL_COMMITMENT_NUMBER, &SecretKey::from_slice(&[46; 32]).unwrap()).is_ok());
+		assert!(channel.signer.sign_counterparty_commitment(&third, Vec::new(), &secp_ctx).is_ok());
+
+		// The feerate and HTLC value limits are enforced.
+		let high_fee = counterparty_commitment(&channel, INITIAL_COMMITMENT_NUMBER - 3, 10_001, None);
+		assert!(channel.signer.sign_counterparty_commitment(&high_fee, Vec::new(), &secp_ctx).is_err());
+		let large_htlc = counterparty_commitment(&channel, INITIAL_COMMITMENT_NUMBER - 3, 253, Some(50_000_001));
+		assert!(channel.signer.sign_counterparty_commitment(&large_htlc, Vec::new(), &secp_ctx).is_err());
+		let fourth = counterparty_commitment(&channel, INITIAL_COMMITMENT_NUMBER - 3, 10_000, Some(50_000_000));
+		assert!(channel.signer.validate_counterparty_revocation(INITIAL_COMMITMENT_NUMBER - 1, &SecretKey::from_slice(&[46; 32]).unwrap()).is_ok());
+		assert!(channel.signer.sign_counterparty_commitment(&fourth, Vec::new(), &secp_ctx).is_ok());
+
+		// The state survives a restart, and old states are still refused afterwards.
+		let reloaded_keys_manager = ValidatingKeysManager::new(&keys_manager, &store, policy).unwrap();
+		let reloaded_signer = reloaded_keys_manager.read_chan_signer(&channel.signer.encode()).unwrap();
+		assert_eq!(reloaded_signer.state(), channel.signer.state());
+		assert_eq!(reloaded_signer.state().last_counterparty_commitment, INITIAL_COMMITMENT_NUMBER - 3);
+		assert!(reloaded_signer.sign_counterparty_commitment(&third, Vec::new(), &secp_ctx).is_err());
+
+		// Signers without persisted state are refused.
+		let other_store = TestStore::new(false);
+		let other_keys_manager = ValidatingKeysManager::new(&keys_manager, &other_store, SigningPolicy::default()).unwrap();
+		assert!(other_keys_manager.read_chan_signer(&channel.signer.encode()).is_err());
+	}
+
+	#[test]
+	fn test_holder_commitment_policy() {
+		let secp_ctx = Secp256k1::new();
+		let store = TestStore::new(false);
+		let keys_manager = KeysManager::new(&[42; 32], 42, 42);
+		let validating_keys_manager = ValidatingKeysManager::new(&keys_manager, &store, SigningPolicy::default()).unwrap();
+		let channel = open_channel(&validating_keys_manager);
+
+		let holder_commitment = |commitment_number| {
+			let params = channel.params.as_holder_broadcastable();
+			let per_commitment_point = channel.signer.get_per_commitment_point(commitment_number, &secp_ctx);
+			let keys = TxCreationKeys::from_channel_static_keys(&per_commitment_point, params.broadcaster_pubkeys(), params.countersignatory_pubkeys(), &secp_ctx).unwrap();
+			let commitment_tx = CommitmentTransaction::new_with_auxiliary_htlc_data(commitment_number, 400_000, 500_000, false,
+				params.broadcaster_pubkeys().funding_pubkey, params.countersignatory_pubkeys().funding_pubkey,
+				keys, 253, &mut Vec::<(_, ())>::new(), &params);
+			let dummy_sig = secp_ctx.sign(&secp256k1::Message::from_slice(&[42; 32]).unwrap(), &SecretKey::from_slice(&[42; 32]).unwrap());
+			HolderCommitmentTransaction::new(commitment_tx, dummy_sig, Vec::new(), &params.broadcaster_pubkeys().funding_pubkey, &params.countersignatory_pubkeys().funding_pubkey)
+		};
+
+		let first = holder_commitment(INITIAL_COMMITMENT_NUMBER);
+		let second = holder_commitment(INITIAL_COMMITMENT_NUMBER - 1);
+		assert!(channel.signer.validate_holder_commitment(&first, Vec::new()).is_ok());
+		assert!(channel.signer.validate_holder_commitment(&holder_commitment(INITIAL_COMMITMENT_NUMBER - 2), Vec::new()).is_err());
+		assert!(channel.signer.validate_holder_commitment(&second, Vec::new()).is_ok());
+		assert!(channel.signer.sign_holder_commitment_and_htlcs(&first, &secp_ctx).is_ok());
+
+		// Once revoked, a holder commitment will never be signed again.
+		assert!(channel.signer.release_commitment_secret(INITIAL_COMMITMENT_NUMBER).is_ok());
+		assert!(channel.signer.sign_holder_commitment_and_htlcs(&first, &secp_ctx).is_err());
+		assert!(channel.signer.sign_holder_commitment_and_htlcs(&second, &secp_ctx).is_ok());
+	}
+
+	#[test]
+	fn test_latest_holder_commitment_not_revoked() {
+		let store = TestStore::new(false);
+		let keys_manager = KeysManager::new(&[42; 32], 42, 42);
+		let validating_keys_manager = ValidatingKeysManager::new(&keys_manager, &store, SigningPolicy::default()).unwrap();
+		let channel = open_channel(&validating_keys_manager);
+		assert!(channel.signer.release_commitment_secret(INITIAL_COMMITMENT_NUMBER).is_err());
+		assert_eq!(channel.signer.state().last_holder_revoked_commitment, INITIAL_STATE_COMMITMENT_NUMBER);
+	}
+
+	#[test]
+	fn test_closing_and_claim_policy() {
+		let secp_ctx = Secp256k1::new();
+		let store = TestStore::new(false);
+		let keys_manager = KeysManager::new(&[42; 32], 42, 42);
+		let policy = SigningPolicy { max_closing_fee_satoshis: 1000, ..Default::default() };
+		let validating_keys_manager = ValidatingKeysManager::new(&keys_manager, &store, policy).unwrap();
+		let channel = open_channel(&validating_keys_manager);
+		let funding_outpoint = channel.params.funding_outpoint.unwrap().into_bitcoin_outpoint();
+
+		// Our scripts are only allowed once they've been handed out, even to existing signers.
+		let our_script = keys_manager.get_shutdown_scriptpubkey().unwrap().into_inner();
+		let their_script = Builder::new().push_int(0).push_slice(&[3; 20]).into_script();
+		let closing_tx = ClosingTransaction::new(400_000, 599_000, our_script.clone(), their_script.clone(), funding_outpoint);
+		assert!(channel.signer.sign_closing_transaction(&closing_tx, &secp_ctx).is_err());
+		assert_eq!(validating_keys_manager.get_shutdown_scriptpubkey().unwrap().into_inner(), our_script);
+		assert!(channel.signer.sign_closing_transaction(&closing_tx, &secp_ctx).is_ok());
+		let high_fee_closing_tx = ClosingTransaction::new(400_000, 598_999, our_script.clone(), their_script.clone(), funding_outpoint);
+		assert!(channel.signer.sign_closing_transaction(&high_fee_closing_tx, &secp_ctx).is_err());
+		let foreign_closing_tx = ClosingTransaction::new(400_000, 599_000, their_script.clone(), their_script.clone(), funding_outpoint);
+		assert!(channel.signer.sign_closing_transaction(&foreign_closing_tx, &secp_ctx).is_err());
+
+		let per_commitment_key = SecretKey::from_slice(&[47; 32]).unwrap();
+		let mut justice_tx = closing_tx.trust().built_transaction().clone();
+		justice_tx.output = vec![TxOut { value: 10_000, script_pubkey: validating_keys_manager.get_destination_script().unwrap() }];
+		assert!(channel.signer.sign_justice_revoked_output(&justice_tx, 0, 11_000, &per_commitment_key, &secp_ctx).is_ok());
+
+		// Handed out scripts remain allowed after a restart.
+		let reloaded_keys_manager = ValidatingKeysManager::new(&keys_manager, &store, SigningPolicy::default()).unwrap();
+		let reloaded_signer = reloaded_keys_manager.read_chan_signer(&channel.signer.encode()).unwrap();
+		assert!(reloaded_signer.sign_justice_revoked_output(&justice_tx, 0, 11_000, &per_commitment_key, &secp_ctx).is_ok());
+
+		justice_tx.output.push(TxOut { value: 1_000, script_pubkey: their_script });
+		assert!(channel.signer.sign_justice_revoked_output(&justice_tx, 0, 11_000, &per_commitment_key, &secp_ctx).is_err());
+	}
+}
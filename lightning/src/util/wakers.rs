@@ -0,0 +1,324 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Utilities which allow users to block on some future notification from LDK. These are
+//! specifically used by [`ChannelManager`] to allow waiting until the [`ChannelManager`] needs to
+//! be re-persisted.
+//!
+//! [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+
+use core::mem;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+#[cfg(any(test, feature = "std"))]
+use core::time::Duration;
+
+use sync::{Arc, Condvar, Mutex};
+
+use prelude::*;
+
+#[cfg(any(test, feature = "std"))]
+use std::time::Instant;
+
+/// Used to signal to one of many waiters that the condition they're waiting on has happened.
+pub(crate) struct Notifier {
+	/// Users won't access the lock directly, but rather wait on its bool using `wait_timeout` and
+	/// `wait`, or via a [`Future`] from `get_future`. The second element holds the state of the
+	/// [`Future`] most recently handed out, if it has not yet been completed.
+	notify_pending: Mutex<(bool, Option<Arc<Mutex<FutureState>>>)>,
+	condvar: Condvar,
+}
+
+impl Notifier {
+	pub(crate) fn new() -> Self {
+		Self {
+			notify_pending: Mutex::new((false, None)),
+			condvar: Condvar::new(),
+		}
+	}
+
+	pub(crate) fn wait(&self) {
+		loop {
+			let mut guard = self.notify_pending.lock().unwrap();
+			if guard.0 {
+				guard.0 = false;
+				return;
+			}
+			guard = self.condvar.wait(guard).unwrap();
+			let result = guard.0;
+			if result {
+				guard.0 = false;
+				return
+			}
+		}
+	}
+
+	#[cfg(any(test, feature = "std"))]
+	pub(crate) fn wait_timeout(&self, max_wait: Duration) -> bool {
+		let current_time = Instant::now();
+		loop {
+			let mut guard = self.notify_pending.lock().unwrap();
+			if guard.0 {
+				guard.0 = false;
+				return true;
+			}
+			guard = self.condvar.wait_timeout(guard, max_wait).unwrap().0;
+			// Due to spurious wakeups that can happen on `wait_timeout`, here we need to check if the
+			// desired wait time has actually passed, and if not then restart the loop with a reduced wait
+			// time. Note that this logic can be highly simplified through the use of
+			// `Condvar::wait_while` and `Condvar::wait_timeout_while`, if and when our MSRV is raised to
+			// 1.42.0.
+			let elapsed = current_time.elapsed();
+			let result = guard.0;
+			if result || elapsed >= max_wait {
+				guard.0 = false;
+				return result;
+			}
+			match max_wait.checked_sub(elapsed) {
+				None => return result,
+				Some(_) => continue
+			}
+		}
+	}
+
+	/// Wake waiters, tracking that wake needs to occur even if there are currently no waiters.
+	pub(crate) fn notify(&self) {
+		let mut lock = self.notify_pending.lock().unwrap();
+		let mut future_held = false;
+		if let Some(future_state) = lock.1.take() {
+			// If someone is still holding a `Future` they will see it complete the next time they
+			// poll it, so we don't need to leave a notification pending for them. Wakers left
+			// behind by a `Future` which was polled and then dropped don't count, as nothing will
+			// observe the completion.
+			future_held = Arc::strong_count(&future_state) > 1;
+			future_state.lock().unwrap().complete();
+		}
+		if !future_held {
+			lock.0 = true;
+		}
+		mem::drop(lock);
+		self.condvar.notify_all();
+	}
+
+	/// Gets a [`Future`] that will get woken up with any waiters
+	pub(crate) fn get_future(&self) -> Future {
+		let mut lock = self.notify_pending.lock().unwrap();
+		if lock.0 {
+			lock.0 = false;
+			Future {
+				state: Arc::new(Mutex::new(FutureState {
+					wakers: Vec::new(),
+					complete: true,
+				}))
+			}
+		} else if let Some(existing_state) = &lock.1 {
+			Future { state: Arc::clone(existing_state) }
+		} else {
+			let state = Arc::new(Mutex::new(FutureState {
+				wakers: Vec::new(),
+				complete: false,
+			}));
+			lock.1 = Some(Arc::clone(&state));
+			Future { state }
+		}
+	}
+
+	#[cfg(any(test, feature = "_test_utils"))]
+	pub(crate) fn notify_pending(&self) -> bool {
+		self.notify_pending.lock().unwrap().0
+	}
+}
+
+pub(crate) struct FutureState {
+	wakers: Vec<Waker>,
+	complete: bool,
+}
+
+impl FutureState {
+	/// Marks the future as complete, waking any registered wakers.
+	fn complete(&mut self) {
+		for waker in self.wakers.drain(..) {
+			waker.wake();
+		}
+		self.complete = true;
+	}
+}
+
+/// A simple future which can complete once, and calls some callback(s) when it does so.
+///
+/// It implements [`core::future::Future`] and thus can be `.await`ed from any async runtime.
+pub struct Future {
+	state: Arc<Mutex<FutureState>>,
+}
+
+impl core::future::Future for Future {
+	type Output = ();
+	fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+		let mut state = self.state.lock().unwrap();
+		if state.complete {
+			Poll::Ready(())
+		} else {
+			state.wakers.push(cx.waker().clone());
+			Poll::Pending
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::future::Future as FutureTrait;
+	use core::sync::atomic::{AtomicBool, Ordering};
+	use core::task::{RawWaker, RawWakerVTable};
+
+	#[cfg(feature = "std")]
+	#[test]
+	fn test_wait_timeout() {
+		use std::thread;
+
+		let persistence_notifier = Arc::new(Notifier::new());
+		let thread_notifier = Arc::clone(&persistence_notifier);
+
+		let exit_thread = Arc::new(AtomicBool::new(false));
+		let exit_thread_clone = exit_thread.clone();
+		thread::spawn(move || {
+			loop {
+				let mut lock = thread_notifier.notify_pending.lock().unwrap();
+				lock.0 = true;
+				thread_notifier.condvar.notify_all();
+
+				if exit_thread_clone.load(Ordering::SeqCst) {
+					break
+				}
+			}
+		});
+
+		// Check that we can block indefinitely until updates are available.
+		let _ = persistence_notifier.wait();
+
+		// Check that the Notifier will return after the given duration if updates are
+		// available.
+		loop {
+			if persistence_notifier.wait_timeout(Duration::from_millis(100)) {
+				break
+			}
+		}
+
+		exit_thread.store(true, Ordering::SeqCst);
+
+		// Check that the Notifier will return after the given duration even if no updates
+		// are available.
+		loop {
+			if !persistence_notifier.wait_timeout(Duration::from_millis(100)) {
+				break
+			}
+		}
+	}
+
+	// Rather annoyingly, there's no safe way in Rust std to construct a Waker despite it being
+	// totally possible to construct from a trait implementation (though somewhat less effecient
+	// compared to a raw VTable). Instead, we have to write out a lot of boilerplate to build a
+	// waker, which we do here with a trivial Arc<AtomicBool> data element to track woke-ness.
+	const WAKER_V_TABLE: RawWakerVTable = RawWakerVTable::new(waker_clone, wake, wake_by_ref, drop);
+	unsafe fn wake_by_ref(ptr: *const ()) { let p = ptr as *const Arc<AtomicBool>; assert!(!(*p).fetch_or(true, Ordering::SeqCst)); }
+	unsafe fn drop(ptr: *const ()) { let p = ptr as *mut Arc<AtomicBool>; let _freed = Box::from_raw(p); }
+	unsafe fn wake(ptr: *const ()) { wake_by_ref(ptr); drop(ptr); }
+	unsafe fn waker_clone(ptr: *const ()) -> RawWaker {
+		let p = ptr as *const Arc<AtomicBool>;
+		RawWaker::new(Box::into_raw(Box::new(Arc::clone(&*p))) as *const (), &WAKER_V_TABLE)
+	}
+
+	fn create_waker() -> (Arc<AtomicBool>, Waker) {
+		let a = Arc::new(AtomicBool::new(false));
+		let waker = unsafe { Waker::from_raw(waker_clone((&a as *const Arc<AtomicBool>) as *const ())) };
+		(a, waker)
+	}
+
+	#[test]
+	fn test_future() {
+		let mut future = Future {
+			state: Arc::new(Mutex::new(FutureState {
+				wakers: Vec::new(),
+				complete: false,
+			}))
+		};
+		let mut second_future = Future { state: Arc::clone(&future.state) };
+
+		let (woken, waker) = create_waker();
+		assert_eq!(Pin::new(&mut future).poll(&mut Context::from_waker(&waker)), Poll::Pending);
+		assert!(!woken.load(Ordering::SeqCst));
+
+		let (second_woken, second_waker) = create_waker();
+		assert_eq!(Pin::new(&mut second_future).poll(&mut Context::from_waker(&second_waker)), Poll::Pending);
+		assert!(!second_woken.load(Ordering::SeqCst));
+
+		future.state.lock().unwrap().complete();
+		assert!(woken.load(Ordering::SeqCst));
+		assert!(second_woken.load(Ordering::SeqCst));
+		assert_eq!(Pin::new(&mut future).poll(&mut Context::from_waker(&waker)), Poll::Ready(()));
+		assert_eq!(Pin::new(&mut second_future).poll(&mut Context::from_waker(&second_waker)), Poll::Ready(()));
+	}
+
+	#[test]
+	fn test_notifier_future() {
+		// Check that a notification which arrives before a future is fetched completes the next
+		// future immediately, and that one which arrives while a future is held wakes it.
+		let notifier = Notifier::new();
+		notifier.notify();
+		let mut future = notifier.get_future();
+		let (woken, waker) = create_waker();
+		assert_eq!(Pin::new(&mut future).poll(&mut Context::from_waker(&waker)), Poll::Ready(()));
+		assert!(!woken.load(Ordering::SeqCst));
+		assert!(!notifier.notify_pending());
+
+		let mut future = notifier.get_future();
+		assert_eq!(Pin::new(&mut future).poll(&mut Context::from_waker(&waker)), Poll::Pending);
+		notifier.notify();
+		assert!(woken.load(Ordering::SeqCst));
+		assert!(!notifier.notify_pending());
+		assert_eq!(Pin::new(&mut future).poll(&mut Context::from_waker(&waker)), Poll::Ready(()));
+
+		// Once the future has completed, a new one is handed out for the next notification.
+		let mut future = notifier.get_future();
+		let (woken, waker) = create_waker();
+		assert_eq!(Pin::new(&mut future).poll(&mut Context::from_waker(&waker)), Poll::Pending);
+		assert!(!woken.load(Ordering::SeqCst));
+	}
+
+	#[test]
+	fn test_dropped_future_doesnt_count() {
+		// Tests that if a Future gets drop'd before it is poll()ed `Ready` it doesn't count as
+		// having been woken, leaving the notify-required flag set.
+		let notifier = Notifier::new();
+		notifier.notify();
+
+		// If we get a future and don't touch it we're definitely still notify-required.
+		notifier.get_future();
+		notifier.notify();
+		assert!(notifier.notify_pending());
+		notifier.wait();
+		assert!(!notifier.notify_pending());
+
+		// Getting a future, dropping it and only then notifying leaves the notification pending.
+		mem::drop(notifier.get_future());
+		notifier.notify();
+		assert!(notifier.notify_pending());
+		notifier.wait();
+
+		// Even if the future registered a waker by being polled before it was dropped.
+		let mut future = notifier.get_future();
+		let (woken, waker) = create_waker();
+		assert_eq!(Pin::new(&mut future).poll(&mut Context::from_waker(&waker)), Poll::Pending);
+		mem::drop(future);
+		notifier.notify();
+		assert!(woken.load(Ordering::SeqCst));
+		assert!(notifier.notify_pending());
+		assert_eq!(Pin::new(&mut notifier.get_future()).poll(&mut Context::from_waker(&waker)), Poll::Ready(()));
+	}
+}